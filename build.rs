@@ -0,0 +1,37 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// Embeds everything under ./static into the binary, see src/server/assets.rs
+fn main() {
+    let root = Path::new(env!("CARGO_MANIFEST_DIR")).join("static");
+    println!("cargo:rerun-if-changed={}", root.display());
+    // sqlx::migrate! embeds ./migrations, a new migration needs a rebuild
+    println!("cargo:rerun-if-changed=migrations");
+
+    let mut files = Vec::new();
+    collect_files(&root, &mut files);
+    files.sort();
+
+    let entries = files
+        .iter()
+        .map(|path| {
+            let name = path.strip_prefix(&root).unwrap().to_string_lossy().replace('\\', "/");
+            println!("cargo:rerun-if-changed={}", path.display());
+            format!("    ({:?}, include_bytes!({:?})),\n", name, path.display().to_string())
+        })
+        .collect::<String>();
+
+    let output = format!("pub static STATIC_ASSETS: &[(&str, &[u8])] = &[\n{}];\n", entries);
+    let out_dir = PathBuf::from(std::env::var("OUT_DIR").unwrap());
+    fs::write(out_dir.join("static_assets.rs"), output).unwrap();
+}
+
+fn collect_files(dir: &Path, files: &mut Vec<PathBuf>) {
+    for entry in fs::read_dir(dir).unwrap() {
+        let path = entry.unwrap().path();
+        match path.is_dir() {
+            true => collect_files(&path, files),
+            false => files.push(path),
+        }
+    }
+}
\ No newline at end of file
@@ -0,0 +1,224 @@
+-- Everything created before migrations were used. Every statement is IF NOT EXISTS (or ignores duplicates), so
+-- databases set up by older versions are brought up to date and recorded as migrated instead of failing.
+-- Never edit a migration that has been released, add a new one instead.
+
+DO $$ BEGIN
+    CREATE TYPE item_type as ENUM ('movie', 'tvshow');
+EXCEPTION
+    WHEN duplicate_object THEN null;
+END $$;
+
+CREATE TABLE IF NOT EXISTS imdb
+(
+    id                  TEXT        NOT NULL PRIMARY KEY,
+    title               TEXT        NOT NULL,
+    year                BIGINT      NOT NULL DEFAULT 0,
+    image_url           TEXT        NOT NULL,
+    rating              TEXT        NOT NULL DEFAULT 'TBD',
+    runtime             BIGINT               DEFAULT NULL,
+    video_thumbnail_url TEXT                 DEFAULT NULL,
+    video_url           TEXT                 DEFAULT NULL,
+    plot                TEXT                 DEFAULT NULL,
+    popularity_rank     INTEGER              DEFAULT NULL,
+    release_order       INTEGER              DEFAULT NULL,
+    _type               item_type   NOT NULL,
+    watchlist           BOOLEAN     NOT NULL DEFAULT FALSE,
+    created_at          TIMESTAMPTZ NOT NULL DEFAULT now(),
+    updated_at          TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+ALTER TABLE imdb ADD COLUMN IF NOT EXISTS poster_url TEXT DEFAULT NULL;
+ALTER TABLE imdb ADD COLUMN IF NOT EXISTS backdrop_url TEXT DEFAULT NULL;
+
+CREATE TABLE IF NOT EXISTS moviedb (
+    id INTEGER PRIMARY KEY NOT NULL,
+    imdb_id TEXT NOT NULL,
+    title TEXT NOT NULL,
+    plot TEXT NOT NULL,
+    release_date DATE NOT NULL,
+    image_url TEXT,
+    video_id TEXT,
+    certification TEXT,
+    runtime BIGINT,
+    popularity_rank BIGINT,
+    _type item_type NOT NULL,
+    watchlist BOOLEAN,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+ALTER TABLE moviedb ADD COLUMN IF NOT EXISTS poster_url TEXT DEFAULT NULL;
+ALTER TABLE moviedb ADD COLUMN IF NOT EXISTS backdrop_url TEXT DEFAULT NULL;
+
+CREATE TABLE IF NOT EXISTS active_downloads
+(
+    id          SERIAL PRIMARY KEY NOT NULL,
+    imdb_id     TEXT               NOT NULL,
+    season      INTEGER,
+    episode     INTEGER,
+    quality     TEXT               NOT NULL,
+    _type       item_type          NOT NULL,
+    magnet_hash TEXT               NOT NULL,
+    state       TEXT               NOT NULL DEFAULT 'Not Started',
+    progress    FLOAT              NOT NULL DEFAULT 0,
+    created_at  TIMESTAMPTZ        NOT NULL DEFAULT now(),
+    updated_at  TIMESTAMPTZ        NOT NULL DEFAULT now()
+);
+
+ALTER TABLE active_downloads ADD COLUMN IF NOT EXISTS import_state TEXT DEFAULT NULL;
+ALTER TABLE active_downloads ADD COLUMN IF NOT EXISTS completed_at TIMESTAMPTZ DEFAULT NULL;
+ALTER TABLE active_downloads ADD COLUMN IF NOT EXISTS download_speed BIGINT NOT NULL DEFAULT 0;
+ALTER TABLE active_downloads ADD COLUMN IF NOT EXISTS eta BIGINT DEFAULT NULL;
+ALTER TABLE active_downloads ADD COLUMN IF NOT EXISTS release_name TEXT DEFAULT NULL;
+ALTER TABLE active_downloads ADD COLUMN IF NOT EXISTS source TEXT DEFAULT NULL;
+ALTER TABLE active_downloads ADD COLUMN IF NOT EXISTS size BIGINT DEFAULT NULL;
+
+CREATE TABLE IF NOT EXISTS similar_titles
+(
+    source_id  TEXT        NOT NULL,
+    similar_id TEXT        NOT NULL,
+    position   INTEGER     NOT NULL DEFAULT 0,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    PRIMARY KEY (source_id, similar_id)
+);
+
+CREATE TABLE IF NOT EXISTS tv_episodes
+(
+    show_id    TEXT        NOT NULL,
+    season     INTEGER     NOT NULL,
+    episode    INTEGER     NOT NULL,
+    air_date   DATE,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    PRIMARY KEY (show_id, season, episode)
+);
+
+CREATE TABLE IF NOT EXISTS plex_auth
+(
+    id                INTEGER PRIMARY KEY NOT NULL DEFAULT 1 CHECK (id = 1),
+    client_identifier TEXT                NOT NULL,
+    token             TEXT,
+    updated_at        TIMESTAMPTZ         NOT NULL DEFAULT now()
+);
+
+CREATE TABLE IF NOT EXISTS library_items
+(
+    path       TEXT PRIMARY KEY NOT NULL,
+    title      TEXT             NOT NULL,
+    year       INTEGER,
+    season     INTEGER,
+    episode    INTEGER,
+    _type      item_type        NOT NULL,
+    size       BIGINT           NOT NULL DEFAULT 0,
+    created_at TIMESTAMPTZ      NOT NULL DEFAULT now(),
+    updated_at TIMESTAMPTZ      NOT NULL DEFAULT now()
+);
+
+CREATE INDEX IF NOT EXISTS library_items_title_idx ON library_items (title);
+
+ALTER TABLE library_items ADD COLUMN IF NOT EXISTS quality TEXT DEFAULT NULL;
+
+CREATE TABLE IF NOT EXISTS aliases
+(
+    item_id    TEXT        NOT NULL,
+    title      TEXT        NOT NULL,
+    source     TEXT        NOT NULL DEFAULT 'user',
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    PRIMARY KEY (item_id, title)
+);
+
+CREATE TABLE IF NOT EXISTS pending_upgrades
+(
+    magnet_hash TEXT PRIMARY KEY NOT NULL,
+    replaces    TEXT             NOT NULL,
+    created_at  TIMESTAMPTZ      NOT NULL DEFAULT now()
+);
+
+CREATE TABLE IF NOT EXISTS blocked_releases
+(
+    magnet_hash TEXT PRIMARY KEY NOT NULL,
+    imdb_id     TEXT             NOT NULL,
+    reason      TEXT             NOT NULL,
+    created_at  TIMESTAMPTZ      NOT NULL DEFAULT now()
+);
+
+CREATE TABLE IF NOT EXISTS download_history
+(
+    id           SERIAL PRIMARY KEY NOT NULL,
+    imdb_id      TEXT               NOT NULL,
+    season       INTEGER,
+    episode      INTEGER,
+    magnet_hash  TEXT               NOT NULL,
+    release_name TEXT,
+    source       TEXT,
+    event        TEXT               NOT NULL,
+    detail       TEXT,
+    created_at   TIMESTAMPTZ        NOT NULL DEFAULT now()
+);
+
+ALTER TABLE download_history ADD COLUMN IF NOT EXISTS size BIGINT DEFAULT NULL;
+
+CREATE INDEX IF NOT EXISTS download_history_magnet_hash ON download_history (magnet_hash);
+
+CREATE TABLE IF NOT EXISTS api_keys
+(
+    id           SERIAL PRIMARY KEY,
+    name         TEXT        NOT NULL,
+    key_hash     TEXT        NOT NULL UNIQUE,
+    created_at   TIMESTAMPTZ NOT NULL DEFAULT now(),
+    last_used_at TIMESTAMPTZ
+);
+
+-- Keys created before scopes keep full access
+ALTER TABLE api_keys ADD COLUMN IF NOT EXISTS scope TEXT NOT NULL DEFAULT 'admin';
+
+CREATE TABLE IF NOT EXISTS watchlist_checks
+(
+    id         SERIAL PRIMARY KEY NOT NULL,
+    imdb_id    TEXT               NOT NULL,
+    found      BOOLEAN            NOT NULL,
+    created_at TIMESTAMPTZ        NOT NULL DEFAULT now()
+);
+
+CREATE INDEX IF NOT EXISTS watchlist_checks_created_at ON watchlist_checks (created_at);
+
+CREATE TABLE IF NOT EXISTS settings
+(
+    key        TEXT PRIMARY KEY NOT NULL,
+    value      TEXT             NOT NULL,
+    updated_at TIMESTAMPTZ      NOT NULL DEFAULT now()
+);
+
+CREATE TABLE IF NOT EXISTS user_preferences
+(
+    client_id         TEXT PRIMARY KEY NOT NULL,
+    search_mode       TEXT             NOT NULL,
+    page_size         BIGINT           NOT NULL,
+    preferred_quality TEXT             NOT NULL DEFAULT '',
+    theme             TEXT             NOT NULL DEFAULT 'auto',
+    updated_at        TIMESTAMPTZ      NOT NULL DEFAULT now()
+);
+
+CREATE TABLE IF NOT EXISTS quality_profiles
+(
+    id              SERIAL PRIMARY KEY,
+    name            TEXT        NOT NULL UNIQUE,
+    qualities       TEXT[]      NOT NULL,
+    cutoff          TEXT,
+    min_size_mb     BIGINT,
+    max_size_mb     BIGINT,
+    preferred_terms TEXT[]      NOT NULL DEFAULT '{}',
+    rejected_terms  TEXT[]      NOT NULL DEFAULT '{}',
+    is_default      BOOLEAN     NOT NULL DEFAULT false,
+    created_at      TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+-- At most one default profile
+CREATE UNIQUE INDEX IF NOT EXISTS quality_profiles_default ON quality_profiles (is_default) WHERE is_default;
+
+-- Keyed by the id used in the watchlist, an IMDb ID or a TheMovieDB ID
+CREATE TABLE IF NOT EXISTS watchlist_profiles
+(
+    item_id    TEXT PRIMARY KEY NOT NULL,
+    profile_id INT              NOT NULL REFERENCES quality_profiles (id) ON DELETE CASCADE
+);
@@ -0,0 +1,15 @@
+-- Background work queued by the web handlers and the torrent watcher, see src/api/jobs.rs
+CREATE TABLE IF NOT EXISTS jobs
+(
+    id         BIGSERIAL PRIMARY KEY,
+    kind       TEXT        NOT NULL,
+    payload    TEXT        NOT NULL,
+    state      TEXT        NOT NULL DEFAULT 'queued',
+    attempts   INTEGER     NOT NULL DEFAULT 0,
+    last_error TEXT,
+    run_at     TIMESTAMPTZ NOT NULL DEFAULT now(),
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+CREATE INDEX IF NOT EXISTS jobs_queued ON jobs (run_at) WHERE state = 'queued';
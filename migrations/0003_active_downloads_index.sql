@@ -0,0 +1,2 @@
+-- Checking what's already downloading looks up episodes by show, season and episode
+CREATE INDEX IF NOT EXISTS active_downloads_imdb_id_season_episode ON active_downloads (imdb_id, season, episode);
@@ -0,0 +1,18 @@
+-- Every release tried for an item or episode and how it went, see src/db/attempts.rs
+CREATE TABLE IF NOT EXISTS download_attempts
+(
+    id           SERIAL PRIMARY KEY,
+    imdb_id      TEXT        NOT NULL,
+    season       INTEGER,
+    episode      INTEGER,
+    magnet_hash  TEXT        NOT NULL,
+    release_name TEXT,
+    source       TEXT,
+    outcome      TEXT        NOT NULL DEFAULT 'downloading',
+    detail       TEXT,
+    created_at   TIMESTAMPTZ NOT NULL DEFAULT now(),
+    updated_at   TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+CREATE INDEX IF NOT EXISTS download_attempts_item ON download_attempts (imdb_id, season, episode);
+CREATE INDEX IF NOT EXISTS download_attempts_magnet_hash ON download_attempts (magnet_hash);
@@ -0,0 +1,5 @@
+-- When plot, rating and runtime were last fetched, updated_at changes with every search so it can't tell
+ALTER TABLE imdb ADD COLUMN IF NOT EXISTS metadata_updated_at TIMESTAMPTZ DEFAULT NULL;
+
+-- Items that already have metadata count as fetched now, rather than all being fetched again at once
+UPDATE imdb SET metadata_updated_at = now() WHERE plot IS NOT NULL AND metadata_updated_at IS NULL;
@@ -0,0 +1,9 @@
+-- Per item watchlist overrides, the quality profile is kept in watchlist_profiles
+CREATE TABLE IF NOT EXISTS watchlist_options
+(
+    item_id             TEXT PRIMARY KEY NOT NULL,
+    -- Releases have to be tagged with this language, eg "french", NULL takes any release
+    language            TEXT,
+    -- Set when "only future seasons" is turned on, seasons that started airing before it are skipped
+    future_seasons_from DATE
+);
@@ -0,0 +1,9 @@
+-- Where each watchlist item is between being added and being in the library, keyed like active_downloads.imdb_id
+-- (TheMovieDB ids get a "tt" prefix)
+CREATE TABLE IF NOT EXISTS watchlist_states
+(
+    imdb_id    TEXT PRIMARY KEY NOT NULL,
+    state      TEXT             NOT NULL,
+    detail     TEXT,
+    updated_at TIMESTAMPTZ      NOT NULL DEFAULT now()
+);
@@ -0,0 +1,2 @@
+-- Movies that stay on the watchlist after being grabbed, replaced with better releases until the profile's cutoff
+ALTER TABLE watchlist_options ADD COLUMN IF NOT EXISTS upgrade_until_cutoff BOOLEAN NOT NULL DEFAULT false;
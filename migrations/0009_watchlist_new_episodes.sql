@@ -0,0 +1,4 @@
+-- When each item was last added to the watchlist, NULL for items added before this was recorded
+ALTER TABLE watchlist_states ADD COLUMN IF NOT EXISTS added_at TIMESTAMPTZ;
+-- Set when "only new episodes" is turned on, episodes that aired before it are skipped
+ALTER TABLE watchlist_options ADD COLUMN IF NOT EXISTS new_episodes_from DATE;
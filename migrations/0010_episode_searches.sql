@@ -0,0 +1,2 @@
+-- Set once a search has been queued for shortly after the episode airs, see schedule_episode_searches
+ALTER TABLE tv_episodes ADD COLUMN IF NOT EXISTS search_scheduled BOOLEAN NOT NULL DEFAULT false;
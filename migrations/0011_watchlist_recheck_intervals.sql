@@ -0,0 +1,5 @@
+-- Hours between watchlist checks for the item, NULL uses watchlist_recheck_interval_hours
+ALTER TABLE watchlist_options ADD COLUMN IF NOT EXISTS recheck_interval_hours INTEGER;
+
+-- The latest check of each item decides when it's due again
+CREATE INDEX IF NOT EXISTS watchlist_checks_imdb_id ON watchlist_checks (imdb_id, created_at);
@@ -0,0 +1,16 @@
+-- IMDb lists being added to the watchlist, started with POST /api/v1/watchlist/import
+CREATE TABLE IF NOT EXISTS watchlist_imports
+(
+    id         BIGSERIAL PRIMARY KEY,
+    -- The list's URL, or "csv" for an uploaded export
+    source     TEXT        NOT NULL,
+    state      TEXT        NOT NULL DEFAULT 'queued',
+    total      INTEGER     NOT NULL DEFAULT 0,
+    added      INTEGER     NOT NULL DEFAULT 0,
+    existing   INTEGER     NOT NULL DEFAULT 0,
+    -- "tt0000000: reason" for each title that couldn't be added
+    failed     TEXT[]      NOT NULL DEFAULT '{}',
+    last_error TEXT,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
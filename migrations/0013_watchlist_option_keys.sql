@@ -0,0 +1,6 @@
+-- Options for TheMovieDB items were stored under the bare id, they use the same "tt" key as watchlist_states now
+DELETE FROM watchlist_options bare
+WHERE item_id NOT LIKE 'tt%'
+  AND EXISTS (SELECT 1 FROM watchlist_options prefixed WHERE prefixed.item_id = 'tt' || bare.item_id);
+
+UPDATE watchlist_options SET item_id = 'tt' || item_id WHERE item_id NOT LIKE 'tt%';
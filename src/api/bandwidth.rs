@@ -0,0 +1,173 @@
+use anyhow::format_err;
+use chrono::{Local, NaiveTime};
+use log::{info, warn};
+use reqwest::{Client, ClientBuilder, StatusCode};
+use tokio::sync::Mutex;
+
+// A period of the day with its own global speed limits, in KiB/s with 0 being unlimited.
+// Periods where start is after end run overnight.
+#[derive(Debug, Clone)]
+pub struct SpeedSchedule {
+    start: NaiveTime,
+    end: NaiveTime,
+    download_limit_kb: i64,
+    upload_limit_kb: i64,
+}
+
+impl SpeedSchedule {
+    pub fn new(start: &str, end: &str, download_limit_kb: i64, upload_limit_kb: i64) -> anyhow::Result<Self> {
+        let start = NaiveTime::parse_from_str(start, "%H:%M")
+            .map_err(|e| format_err!("Invalid schedule start {}: {}", start, e))?;
+        let end = NaiveTime::parse_from_str(end, "%H:%M")
+            .map_err(|e| format_err!("Invalid schedule end {}: {}", end, e))?;
+
+        Ok(SpeedSchedule {
+            start,
+            end,
+            download_limit_kb,
+            upload_limit_kb,
+        })
+    }
+
+    fn is_active(&self, time: NaiveTime) -> bool {
+        match self.start <= self.end {
+            true => time >= self.start && time < self.end,
+            false => time >= self.start || time < self.end,
+        }
+    }
+}
+
+// Sets qBittorrent's global speed limits to match the schedule. The qbittorrent crate doesn't cover the
+// transfer endpoints so the WebUI API is called directly.
+pub struct BandwidthScheduler {
+    client: Client,
+    url: String,
+    username: String,
+    password: String,
+    schedules: Vec<SpeedSchedule>,
+    session: Mutex<Option<String>>,
+    // Limits last sent to qBittorrent, (download, upload) in KiB/s
+    applied: Mutex<Option<(i64, i64)>>,
+}
+
+impl BandwidthScheduler {
+    pub fn new(url: &str, username: &str, password: &str, schedules: Vec<SpeedSchedule>) -> Self {
+        let client = ClientBuilder::new().user_agent("roundup/1.0").build().unwrap();
+
+        BandwidthScheduler {
+            client,
+            url: url.trim_end_matches('/').to_string(),
+            username: username.to_string(),
+            password: password.to_string(),
+            schedules,
+            session: Mutex::new(None),
+            applied: Mutex::new(None),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        !self.schedules.is_empty()
+    }
+
+    // Limits for the current time, the first matching schedule wins and no match means unlimited
+    fn current_limits(&self) -> (i64, i64) {
+        let now = Local::now().time();
+        self.schedules
+            .iter()
+            .find(|s| s.is_active(now))
+            .map(|s| (s.download_limit_kb, s.upload_limit_kb))
+            .unwrap_or((0, 0))
+    }
+
+    // Only talks to qBittorrent when the limits have changed
+    pub async fn apply(&self) -> anyhow::Result<()> {
+        if !self.is_enabled() {
+            return Ok(());
+        }
+
+        let limits = self.current_limits();
+        let mut applied = self.applied.lock().await;
+        if *applied == Some(limits) {
+            return Ok(());
+        }
+
+        let (download, upload) = limits;
+        self.post("/api/v2/transfer/setDownloadLimit", download * 1024).await?;
+        self.post("/api/v2/transfer/setUploadLimit", upload * 1024).await?;
+        info!(
+            "Set global speed limits, download: {}, upload: {}",
+            format_limit(download),
+            format_limit(upload)
+        );
+        *applied = Some(limits);
+        Ok(())
+    }
+
+    async fn post(&self, path: &str, limit: i64) -> anyhow::Result<()> {
+        let params = [("limit", limit.to_string())];
+
+        // Sessions expire, so log in again once if qBittorrent no longer accepts the cookie
+        for _ in 0..2 {
+            let cookie = self.session().await?;
+            let resp = self
+                .client
+                .post(format!("{}{}", self.url, path))
+                .header("Cookie", &cookie)
+                .header("Referer", &self.url)
+                .form(&params)
+                .send()
+                .await?;
+            match resp.status() {
+                StatusCode::FORBIDDEN => {
+                    warn!("qBittorrent session expired, logging in again");
+                    *self.session.lock().await = None;
+                }
+                status if status.is_client_error() || status.is_server_error() => {
+                    let text = resp.text().await?;
+                    return Err(format_err!("Failed to send request, Status: {}, Body: {}", status, text));
+                }
+                _ => return Ok(()),
+            }
+        }
+
+        Err(format_err!("qBittorrent rejected the login session"))
+    }
+
+    async fn session(&self) -> anyhow::Result<String> {
+        let mut session = self.session.lock().await;
+        if let Some(t) = session.as_ref() {
+            return Ok(t.to_owned());
+        }
+
+        let params = [("username", &self.username), ("password", &self.password)];
+        let resp = self
+            .client
+            .post(format!("{}/api/v2/auth/login", self.url))
+            .header("Referer", &self.url)
+            .form(&params)
+            .send()
+            .await?;
+        let cookie = resp
+            .headers()
+            .get_all("set-cookie")
+            .iter()
+            .filter_map(|v| v.to_str().ok())
+            .find_map(|v| v.split(';').next().filter(|c| c.starts_with("SID=")))
+            .map(|c| c.to_string());
+
+        match cookie {
+            Some(t) => {
+                *session = Some(t.to_owned());
+                Ok(t)
+            }
+            None => Err(format_err!("Failed to log in to qBittorrent, Status: {}", resp.status())),
+        }
+    }
+}
+
+fn format_limit(limit_kb: i64) -> String {
+    match limit_kb {
+        0 => String::from("unlimited"),
+        t => format!("{} KiB/s", t),
+    }
+}
\ No newline at end of file
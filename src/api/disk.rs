@@ -0,0 +1,128 @@
+use std::ops::Not;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use log::{info, warn};
+
+use crate::api::notify::{DownloadEvent, Notifier};
+use crate::api::shutdown::Shutdown;
+
+static ONE_GB: u64 = 1_073_741_824;
+static CHECK_INTERVAL: Duration = Duration::from_secs(60 * 5);
+
+#[derive(Debug, Clone)]
+pub struct VolumeUsage {
+    pub path: String,
+    pub available: u64,
+    pub total: u64,
+}
+
+impl VolumeUsage {
+    pub fn available_gb(&self) -> f64 {
+        self.available as f64 / ONE_GB as f64
+    }
+
+    pub fn total_gb(&self) -> f64 {
+        self.total as f64 / ONE_GB as f64
+    }
+}
+
+// Keeps track of free space on the download and library volumes, automation is paused while any of them is low
+pub struct DiskMonitor {
+    paths: Vec<String>,
+    minimum_free: u64,
+    usage: Mutex<Vec<VolumeUsage>>,
+    low: AtomicBool,
+}
+
+impl DiskMonitor {
+    pub fn new(paths: Vec<String>, minimum_free_gb: u64) -> Self {
+        let mut paths = paths.into_iter().filter(|p| p.is_empty().not()).collect::<Vec<String>>();
+        paths.dedup();
+
+        DiskMonitor {
+            paths,
+            minimum_free: minimum_free_gb * ONE_GB,
+            usage: Mutex::new(Vec::new()),
+            low: AtomicBool::new(false),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.paths.is_empty().not()
+    }
+
+    pub fn is_low(&self) -> bool {
+        self.low.load(Ordering::Relaxed)
+    }
+
+    pub fn usage(&self) -> Vec<VolumeUsage> {
+        self.usage.lock().unwrap().clone()
+    }
+
+    // Reads the free space of every volume, returns the ones below the threshold
+    pub fn check(&self) -> Vec<VolumeUsage> {
+        let usage = self
+            .paths
+            .iter()
+            .filter_map(|path| {
+                let volume = Path::new(path);
+                let available = fs2::available_space(volume);
+                let total = fs2::total_space(volume);
+                match (available, total) {
+                    (Ok(available), Ok(total)) => Some(VolumeUsage {
+                        path: path.to_owned(),
+                        available,
+                        total,
+                    }),
+                    (Err(e), _) | (_, Err(e)) => {
+                        warn!("Failed to read free space of {}: {}", path, e);
+                        None
+                    }
+                }
+            })
+            .collect::<Vec<VolumeUsage>>();
+
+        let low = usage
+            .iter()
+            .filter(|u| u.available < self.minimum_free)
+            .cloned()
+            .collect::<Vec<VolumeUsage>>();
+
+        self.low.store(low.is_empty().not(), Ordering::Relaxed);
+        *self.usage.lock().unwrap() = usage;
+        low
+    }
+}
+
+pub async fn monitor_disk_space(disk: Arc<DiskMonitor>, notifier: Arc<Notifier>, shutdown: Arc<Shutdown>) {
+    if disk.is_enabled().not() {
+        return;
+    }
+    info!("Starting Disk Space Monitor");
+
+    loop {
+        let was_low = disk.is_low();
+        let low = disk.check();
+
+        match (was_low, low.is_empty()) {
+            (false, false) => {
+                let volumes = low
+                    .iter()
+                    .map(|u| format!("{} ({:.1} GB free)", u.path, u.available_gb()))
+                    .collect::<Vec<String>>()
+                    .join(", ");
+                warn!("Low disk space, pausing automatic downloads: {}", volumes);
+                notifier.notify(DownloadEvent::LowDiskSpace, None, None, Some(&volumes)).await;
+            }
+            (true, true) => info!("Disk space recovered, resuming automatic downloads"),
+            _ => (),
+        }
+
+        if shutdown.sleep(CHECK_INTERVAL).await {
+            return;
+        }
+    }
+}
\ No newline at end of file
@@ -137,7 +137,7 @@ impl TorrentSearch for EZTV {
                 title.push(' ');
                 title.push_str(&q_s);
 
-                TorrentItem::new(
+                let mut item = TorrentItem::new(
                     imdb_id_clone.clone(),
                     title,
                     t.magnet_url.to_string(),
@@ -146,7 +146,9 @@ impl TorrentSearch for EZTV {
                     Some(season),
                     Some(episode),
                     None,
-                )
+                );
+                item.size = t.size_bytes.parse::<i64>().ok();
+                item
             })
             .filter(|t| !matches!(t.quality, MediaQuality::Unknown))
             .collect();
@@ -180,6 +182,10 @@ impl TorrentSearch for EZTV {
 
         Ok(torrents)
     }
+
+    fn source(&self) -> &'static str {
+        "EZTV"
+    }
 }
 
 #[derive(Deserialize, Debug)]
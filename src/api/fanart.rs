@@ -0,0 +1,101 @@
+use anyhow::format_err;
+use reqwest::{Client, ClientBuilder, StatusCode};
+use reqwest::header::{HeaderMap, HeaderValue};
+use serde::Deserialize;
+
+use crate::api::imdb::ItemType;
+
+pub struct Fanart {
+    client: Client,
+    api_key: String,
+}
+
+#[derive(Debug, Default)]
+pub struct FanartArtwork {
+    pub poster_url: Option<String>,
+    pub backdrop_url: Option<String>,
+}
+
+impl Fanart {
+    pub fn new(api_key: &str) -> Self {
+        let mut headers = HeaderMap::new();
+        headers.insert("Accept", HeaderValue::from_static("application/json"));
+
+        let client = ClientBuilder::new().default_headers(headers).user_agent("roundup/1.0").build().unwrap();
+
+        Fanart {
+            client,
+            api_key: api_key.to_string(),
+        }
+    }
+
+    // Movies are looked up by IMDb/TMDB id, TV shows are only indexed by their TheTVDB id.
+    pub async fn fetch_artwork(&self, id: &str, _type: &ItemType) -> anyhow::Result<FanartArtwork> {
+        let url = match _type {
+            ItemType::Movie => format!("https://webservice.fanart.tv/v3/movies/{}", id),
+            ItemType::TvShow => format!("https://webservice.fanart.tv/v3/tv/{}", id),
+        };
+        let query = [("api_key", self.api_key.as_str())];
+
+        let resp = self.client.get(url).query(&query).send().await?;
+        if resp.status().is_client_error() || resp.status().is_server_error() {
+            return match resp.status() {
+                StatusCode::NOT_FOUND => Ok(FanartArtwork::default()), // No artwork submitted for this item
+                _ => {
+                    let status = resp.status();
+                    let text = resp.text().await?;
+                    Err(format_err!("Failed to send request, Status: {}, Body: {}", status, text))
+                },
+            };
+        }
+
+        let text = resp.text().await?;
+        let data: FanartResponse = serde_json::from_str(&text)?;
+
+        let artwork = match _type {
+            ItemType::Movie => FanartArtwork {
+                poster_url: Fanart::best_image(&data.movie_poster),
+                backdrop_url: Fanart::best_image(&data.movie_background),
+            },
+            ItemType::TvShow => FanartArtwork {
+                poster_url: Fanart::best_image(&data.tv_poster),
+                backdrop_url: Fanart::best_image(&data.show_background),
+            },
+        };
+
+        Ok(artwork)
+    }
+
+    // Prefer English (or language-less) images with the most likes
+    fn best_image(images: &[FanartImage]) -> Option<String> {
+        let likes = |x: &FanartImage| x.likes.parse::<i64>().unwrap_or(0);
+
+        images
+            .iter()
+            .filter(|x| x.lang.is_empty() || x.lang == "en" || x.lang == "00")
+            .max_by_key(|x| likes(x))
+            .or_else(|| images.iter().max_by_key(|x| likes(x)))
+            .map(|x| x.url.to_string())
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct FanartResponse {
+    #[serde(rename = "movieposter", default)]
+    movie_poster: Vec<FanartImage>,
+    #[serde(rename = "moviebackground", default)]
+    movie_background: Vec<FanartImage>,
+    #[serde(rename = "tvposter", default)]
+    tv_poster: Vec<FanartImage>,
+    #[serde(rename = "showbackground", default)]
+    show_background: Vec<FanartImage>,
+}
+
+#[derive(Debug, Deserialize)]
+struct FanartImage {
+    url: String,
+    #[serde(default)]
+    lang: String,
+    #[serde(default)]
+    likes: String,
+}
\ No newline at end of file
@@ -0,0 +1,68 @@
+use std::ops::Not;
+
+use crate::api::validation::is_subtitle_file;
+
+// Decides which files in a torrent are downloaded, the rest are set to "do not download"
+#[derive(Debug, Clone)]
+pub struct FileFilter {
+    extensions: Vec<String>,
+    // Bytes, subtitles are always kept
+    minimum_size: i64,
+    // Words in the file or folder name, eg "sample" matches "Sample/movie-sample.mkv"
+    exclude_patterns: Vec<String>,
+}
+
+impl FileFilter {
+    pub fn new(extensions: &[String], minimum_size_mb: i64, exclude_patterns: &[String]) -> Self {
+        FileFilter {
+            extensions: extensions.iter().map(|e| e.to_ascii_lowercase()).collect(),
+            minimum_size: minimum_size_mb.max(0) * 1_048_576,
+            exclude_patterns: exclude_patterns
+                .iter()
+                .map(|p| normalise_words(p))
+                .filter(|p| p.trim().is_empty().not())
+                .collect(),
+        }
+    }
+
+    pub fn wanted(&self, name: &str, size: i64) -> bool {
+        let lower = name.to_ascii_lowercase();
+        if self.extensions.iter().any(|e| lower.ends_with(e.as_str())).not() {
+            return false;
+        }
+        if size < self.minimum_size && is_subtitle_file(&lower).not() {
+            return false;
+        }
+
+        let words = normalise_words(name);
+        self.exclude_patterns.iter().any(|p| words.contains(p.as_str())).not()
+    }
+
+    // Indexes of the files to skip from (index, name, size). Nothing is skipped when no video would be
+    // left, eg releases packed in RAR archives, otherwise the torrent would never download anything
+    pub fn files_to_skip(&self, files: &[(i64, String, i64)]) -> Vec<i64> {
+        let skipped = files
+            .iter()
+            .filter(|(_, name, size)| self.wanted(name, *size).not())
+            .map(|(index, _, _)| *index)
+            .collect::<Vec<i64>>();
+
+        let kept_video = files
+            .iter()
+            .any(|(index, name, _)| skipped.contains(index).not() && is_subtitle_file(name).not());
+        match kept_video {
+            true => skipped,
+            false => Vec::new(),
+        }
+    }
+}
+
+// Lowercase words separated and surrounded by single spaces, so patterns only match whole words
+fn normalise_words(input: &str) -> String {
+    let words = input
+        .split(|c: char| c.is_alphanumeric().not())
+        .filter(|w| w.is_empty().not())
+        .map(|w| w.to_lowercase())
+        .collect::<Vec<String>>();
+    format!(" {} ", words.join(" "))
+}
\ No newline at end of file
@@ -8,6 +8,7 @@ use reqwest::header::{HeaderMap, HeaderValue};
 use reqwest::Proxy;
 use scraper::{Html, Selector};
 use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
 
 pub struct IMDB {
     search_type: SearchType,
@@ -26,14 +27,14 @@ pub enum SearchType {
     Query(String),
 }
 
-#[derive(Debug, sqlx::Type, Serialize, Clone)]
+#[derive(Debug, sqlx::Type, Serialize, Deserialize, Clone, ToSchema)]
 #[sqlx(type_name = "item_type", rename_all = "lowercase")]
 pub enum ItemType {
     Movie,
     TvShow,
 }
 
-#[derive(Debug, sqlx::FromRow, Serialize)]
+#[derive(Debug, Clone, sqlx::FromRow, Serialize, ToSchema)]
 pub struct IMDBItem {
     pub id: String,
     pub title: String,
@@ -44,6 +45,8 @@ pub struct IMDBItem {
     pub video_thumbnail_url: Option<String>,
     pub video_url: Option<String>,
     pub plot: Option<String>,
+    pub poster_url: Option<String>,
+    pub backdrop_url: Option<String>,
     pub popularity_rank: Option<i32>,
     pub release_order: Option<i32>,
     pub _type: ItemType,
@@ -59,6 +62,7 @@ pub struct IMDBEpisode {
     pub id: String,
     pub season: i32,
     pub episode: i32,
+    pub air_date: Option<chrono::NaiveDate>,
 }
 
 impl<'a> IMDB {
@@ -190,12 +194,12 @@ impl<'a> IMDB {
             .episodes
             .items
             .par_iter()
-            .map(|e| {
-                IMDBEpisode::new(
-                    e.id.clone(),
-                    e.season.parse().unwrap(),
-                    e.episode.parse().unwrap(),
-                )
+            .filter_map(|e| {
+                // "Unknown" seasons and untitled episodes don't have a number yet
+                let season = e.season.parse().ok()?;
+                let episode = e.episode.parse().ok()?;
+                let air_date = e.release_date.as_ref().and_then(|d| d.to_date());
+                Some(IMDBEpisode::new(e.id.clone(), season, episode, air_date))
             })
             .collect::<Vec<IMDBEpisode>>();
 
@@ -353,6 +357,8 @@ impl<'a> IMDB {
             video_thumbnail_url: None,
             video_url: None,
             plot,
+            poster_url: None,
+            backdrop_url: None,
             popularity_rank: None,
             release_order: None,
             _type,
@@ -363,6 +369,172 @@ impl<'a> IMDB {
 
         Ok(imdb_item)
     }
+    pub async fn fetch_more_like_this(
+        id: &str,
+        query_key: Option<String>,
+        proxy: Option<Proxy>,
+    ) -> anyhow::Result<Vec<IMDBItem>> {
+        let mut query_key = query_key;
+        if query_key.is_none() {
+            let token = IMDB::update_query_key(proxy.clone()).await?;
+            query_key = Some(token);
+        }
+
+        let mut headers = HeaderMap::new();
+        headers.insert("Accept", HeaderValue::from_static("application/json"));
+        headers.insert("DNT", HeaderValue::from_static("1"));
+        headers.insert(
+            "Referer",
+            HeaderValue::from_static("https://www.imdb.com/chart/moviemeter/"),
+        );
+        headers.insert(
+            "Accept-Language",
+            HeaderValue::from_static("en-US,en;q=0.9,en-AU;q=0.8"),
+        );
+        headers.insert("Cache-Control", HeaderValue::from_static("no-cache"));
+        headers.insert("User-Agent", HeaderValue::from_static("Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/121.0.0.0 Safari/537.36"));
+
+        let mut client = reqwest::ClientBuilder::new().default_headers(headers);
+        client = match proxy {
+            Some(p) => client.proxy(p),
+            None => client,
+        };
+        let client = client.build()?;
+
+        let url = format!(
+            "https://www.imdb.com/_next/data/{}/title/{}.json",
+            query_key.unwrap(),
+            id
+        );
+
+        let resp = client.get(url).send().await?;
+
+        let status = resp.status();
+        if status.is_server_error() || status.is_client_error() {
+            return Err(format_err!("Failed request, Status: {}", status));
+        }
+
+        let text = resp.text().await?;
+        let data: IMDBNextDataResponse = serde_json::from_str(&text)?;
+
+        let edges = match data.page_props.main_column_data {
+            Some(t) => match t.more_like_this_titles {
+                Some(t) => t.edges,
+                None => return Ok(Vec::new()),
+            },
+            None => return Ok(Vec::new()),
+        };
+
+        let output = edges
+            .into_par_iter()
+            .map(|edge| {
+                let node = edge.node;
+                let year = match node.release_year {
+                    Some(t) => t.year,
+                    None => 0,
+                };
+
+                let image_url = match node.primary_image {
+                    Some(t) => t.url.replace("._V1_", "._V1_UX200_CR0,4,200,300_"),
+                    None => "https://upload.wikimedia.org/wikipedia/commons/thumb/a/ac/No_image_available.svg/300px-No_image_available.svg.png".to_string(),
+                };
+
+                let _type = match node.title_type {
+                    Some(t) if t.can_have_episodes => ItemType::TvShow,
+                    _ => ItemType::Movie,
+                };
+
+                IMDBItem {
+                    id: node.id,
+                    title: node.title_text.text,
+                    year,
+                    image_url,
+                    rating: match node.certificate {
+                        Some(c) => c.rating,
+                        None => "TBD".to_string(),
+                    },
+                    runtime: None,
+                    video_thumbnail_url: None,
+                    video_url: None,
+                    plot: None,
+                    poster_url: None,
+                    backdrop_url: None,
+                    popularity_rank: None,
+                    release_order: None,
+                    _type,
+                    watchlist: false,
+                    created_at: Local::now(),
+                    updated_at: Local::now(),
+                }
+            })
+            .collect::<Vec<IMDBItem>>();
+
+        Ok(output)
+    }
+    // Original title and AKAs, used when matching against Plex and release names
+    pub async fn fetch_alternate_titles(
+        id: &str,
+        query_key: Option<String>,
+        proxy: Option<Proxy>,
+    ) -> anyhow::Result<Vec<String>> {
+        let mut query_key = query_key;
+        if query_key.is_none() {
+            let token = IMDB::update_query_key(proxy.clone()).await?;
+            query_key = Some(token);
+        }
+
+        let mut headers = HeaderMap::new();
+        headers.insert("Accept", HeaderValue::from_static("application/json"));
+        headers.insert("DNT", HeaderValue::from_static("1"));
+        headers.insert(
+            "Referer",
+            HeaderValue::from_static("https://www.imdb.com/chart/moviemeter/"),
+        );
+        headers.insert(
+            "Accept-Language",
+            HeaderValue::from_static("en-US,en;q=0.9,en-AU;q=0.8"),
+        );
+        headers.insert("Cache-Control", HeaderValue::from_static("no-cache"));
+        headers.insert("User-Agent", HeaderValue::from_static("Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/121.0.0.0 Safari/537.36"));
+
+        let mut client = reqwest::ClientBuilder::new().default_headers(headers);
+        client = match proxy {
+            Some(p) => client.proxy(p),
+            None => client,
+        };
+        let client = client.build()?;
+
+        let url = format!(
+            "https://www.imdb.com/_next/data/{}/title/{}.json",
+            query_key.unwrap(),
+            id
+        );
+
+        let resp = client.get(url).send().await?;
+
+        let status = resp.status();
+        if status.is_server_error() || status.is_client_error() {
+            return Err(format_err!("Failed request, Status: {}", status));
+        }
+
+        let text = resp.text().await?;
+        let data: IMDBNextDataResponse = serde_json::from_str(&text)?;
+
+        let title = data.page_props.above_the_fold_data.title_text.text;
+        let mut titles = Vec::new();
+        if let Some(t) = data.page_props.above_the_fold_data.original_title_text {
+            titles.push(t.text);
+        }
+        if let Some(akas) = data.page_props.main_column_data.and_then(|t| t.akas) {
+            titles.extend(akas.edges.into_iter().map(|e| e.node.text));
+        }
+
+        titles.retain(|t| t.ne(&title));
+        titles.sort();
+        titles.dedup();
+
+        Ok(titles)
+    }
     fn parse_json(&self, data: &str) -> anyhow::Result<Vec<IMDBItem>> {
         let resp_data: IMDBSuggestionQueryResponse = serde_json::from_str(data)?;
 
@@ -381,6 +553,8 @@ impl<'a> IMDB {
                 video_thumbnail_url: None,
                 video_url: None,
                 plot: None,
+                poster_url: None,
+                backdrop_url: None,
                 popularity_rank: None, // This is Search Query only so always None
                 release_order: None, // This is Search Query only so always None
                 _type: ItemType::from_str(item._type.as_ref().unwrap().as_str()).unwrap(),
@@ -440,6 +614,8 @@ impl<'a> IMDB {
                     video_thumbnail_url: None,
                     video_url: None,
                     plot: None,
+                    poster_url: None,
+                    backdrop_url: None,
                     popularity_rank: match self.search_type {
                         SearchType::MoviePopular => Some((i + 1) as i32),
                         SearchType::TVPopular => Some((i + 1) as i32),
@@ -496,11 +672,20 @@ impl<'a> SearchType {
 }
 
 impl IMDBEpisode {
-    fn new(id: String, season: i32, episode: i32) -> Self {
+    fn new(id: String, season: i32, episode: i32, air_date: Option<chrono::NaiveDate>) -> Self {
         Self {
             id,
             season,
             episode,
+            air_date,
+        }
+    }
+
+    // Episodes without an air date are TBA, so they aren't worth searching for yet
+    pub fn has_aired(&self) -> bool {
+        match self.air_date {
+            Some(t) => t <= Local::now().date_naive(),
+            None => false,
         }
     }
 }
@@ -730,6 +915,30 @@ pub struct IMDBTVSeasonItem {
     pub id: String,
     pub season: String,
     pub episode: String,
+    #[serde(default)]
+    pub release_date: Option<IMDBTVSeasonReleaseDate>,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct IMDBTVSeasonReleaseDate {
+    pub year: Option<i32>,
+    pub month: Option<u32>,
+    pub day: Option<u32>,
+}
+
+impl IMDBTVSeasonReleaseDate {
+    fn to_date(&self) -> Option<chrono::NaiveDate> {
+        let year = self.year?;
+        // Partial dates only count as aired once the whole month/year has passed
+        match (self.month, self.day) {
+            (Some(m), Some(d)) => chrono::NaiveDate::from_ymd_opt(year, m, d),
+            (Some(m), None) => chrono::NaiveDate::from_ymd_opt(year, m, 1)
+                .and_then(|t| t.checked_add_months(chrono::Months::new(1)))
+                .and_then(|t| t.pred_opt()),
+            _ => chrono::NaiveDate::from_ymd_opt(year, 12, 31),
+        }
+    }
 }
 
 ///////////
@@ -744,6 +953,61 @@ pub struct IMDBNextDataResponse {
 #[serde(rename_all = "camelCase")]
 struct IMDBNextDataPageProps {
     above_the_fold_data: IMDBNextDataAboveTheFoldData,
+    main_column_data: Option<IMDBNextDataMainColumnData>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct IMDBNextDataMainColumnData {
+    more_like_this_titles: Option<IMDBNextDataMoreLikeThis>,
+    akas: Option<IMDBNextDataAkas>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct IMDBNextDataAkas {
+    edges: Vec<IMDBNextDataAkasEdge>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct IMDBNextDataAkasEdge {
+    node: IMDBNextDataAkasNode,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct IMDBNextDataAkasNode {
+    text: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct IMDBNextDataMoreLikeThis {
+    edges: Vec<IMDBNextDataMoreLikeThisEdge>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct IMDBNextDataMoreLikeThisEdge {
+    node: IMDBNextDataMoreLikeThisNode,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct IMDBNextDataMoreLikeThisNode {
+    id: String,
+    title_text: TitleText,
+    title_type: Option<IMDBNextDataTitleType>,
+    primary_image: Option<PrimaryImage>,
+    release_year: Option<ReleaseYear>,
+    certificate: Option<Certificate>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct IMDBNextDataTitleType {
+    can_have_episodes: bool,
 }
 
 #[derive(Debug, Clone, Deserialize)]
@@ -752,6 +1016,7 @@ struct IMDBNextDataAboveTheFoldData {
     id: String,
     can_have_episodes: bool,
     title_text: TitleText,
+    original_title_text: Option<TitleText>,
     certificate: Option<Certificate>,
     release_year: ReleaseYear,
     runtime: Option<Runtime>,
@@ -0,0 +1,147 @@
+use std::collections::HashSet;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use anyhow::format_err;
+use log::{info, warn};
+use qbittorrent::data::{State, Torrent};
+use qbittorrent::traits::TorrentData;
+
+// EXDEV on unix, ERROR_NOT_SAME_DEVICE on windows
+#[cfg(windows)]
+const CROSS_DEVICE_ERROR: i32 = 17;
+#[cfg(not(windows))]
+const CROSS_DEVICE_ERROR: i32 = 18;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ImportMode {
+    Hardlink,
+    Copy,
+    Move,
+}
+
+impl ImportMode {
+    pub fn from_config(mode: &str) -> ImportMode {
+        match mode.to_ascii_lowercase().as_str() {
+            "copy" => ImportMode::Copy,
+            "move" => ImportMode::Move,
+            _ => ImportMode::Hardlink,
+        }
+    }
+}
+
+// Places finished downloads into the library folder
+pub struct Importer {
+    mode: ImportMode,
+    destination: PathBuf,
+    imported: Mutex<HashSet<String>>,
+}
+
+impl Importer {
+    pub fn new(mode: ImportMode, destination: &str) -> Self {
+        Importer {
+            mode,
+            destination: PathBuf::from(destination),
+            imported: Mutex::new(HashSet::new()),
+        }
+    }
+
+    pub fn destination(&self) -> &str {
+        self.destination.to_str().unwrap_or_default()
+    }
+
+    // Hardlinks and copies are made as soon as the download finishes so the torrent can keep seeding,
+    // moving has to wait until qBittorrent is done with the files.
+    pub fn ready(&self, torrent: &Torrent) -> bool {
+        if self.has_imported(torrent.hash().as_str()) {
+            return false;
+        }
+        match self.mode {
+            ImportMode::Move => matches!(torrent.state(), State::PausedUP),
+            _ => {
+                *torrent.progress() >= 1.0
+                    && matches!(
+                        torrent.state(),
+                        State::Uploading | State::StalledUP | State::QueuedUP | State::ForcedUP | State::PausedUP
+                    )
+            }
+        }
+    }
+
+    pub fn has_imported(&self, hash: &str) -> bool {
+        self.imported.lock().unwrap().contains(hash)
+    }
+
+    // Called once the torrent has been removed from qBittorrent
+    pub fn forget(&self, hash: &str) {
+        self.imported.lock().unwrap().remove(hash);
+    }
+
+    // Files are relative to the torrent's save path, the same layout is kept in the destination
+    pub async fn import(&self, torrent: &Torrent, files: &[String]) -> anyhow::Result<()> {
+        let save_path = torrent.save_path();
+        let mut failed = 0;
+        for file in files {
+            let source = Path::new(save_path).join(file);
+            let target = self.destination.join(file);
+            if let Err(e) = self.import_file(&source, &target).await {
+                warn!("Failed to import {}: {}", source.display(), e);
+                failed += 1;
+            }
+        }
+
+        match failed {
+            0 => {
+                self.imported.lock().unwrap().insert(torrent.hash().as_str().to_string());
+                Ok(())
+            }
+            n => Err(format_err!("{} of {} files failed to import", n, files.len())),
+        }
+    }
+
+    // Imports a single file under a new name, relative to the destination
+    pub async fn import_as(&self, source: &Path, target: &Path) -> io::Result<()> {
+        self.import_file(source, &self.destination.join(target)).await
+    }
+
+    async fn import_file(&self, source: &Path, target: &Path) -> io::Result<()> {
+        if tokio::fs::try_exists(target).await? {
+            return Ok(()); // Already imported
+        }
+        if let Some(parent) = target.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+
+        match self.mode {
+            ImportMode::Hardlink => match tokio::fs::hard_link(source, target).await {
+                Ok(_) => Ok(()),
+                Err(e) => {
+                    match is_cross_device(&e) {
+                        true => info!("{} is on another filesystem, copying instead of hardlinking", target.display()),
+                        false => warn!("Failed to hardlink {}, copying instead: {}", target.display(), e),
+                    }
+                    tokio::fs::copy(source, target).await.map(|_| ())
+                }
+            },
+            ImportMode::Copy => tokio::fs::copy(source, target).await.map(|_| ()),
+            ImportMode::Move => move_file(source, target).await,
+        }
+    }
+}
+
+// Renames the file, or copies and deletes it when the target is on another filesystem
+pub async fn move_file(source: &Path, target: &Path) -> io::Result<()> {
+    match tokio::fs::rename(source, target).await {
+        Ok(_) => Ok(()),
+        Err(e) if is_cross_device(&e) => {
+            tokio::fs::copy(source, target).await?;
+            tokio::fs::remove_file(source).await
+        }
+        Err(e) => Err(e),
+    }
+}
+
+fn is_cross_device(e: &io::Error) -> bool {
+    e.raw_os_error() == Some(CROSS_DEVICE_ERROR)
+}
\ No newline at end of file
@@ -0,0 +1,127 @@
+use std::ops::{Deref, Not};
+use std::sync::Arc;
+use std::time::Duration;
+
+use actix_web::web::Data;
+use chrono::Local;
+use log::{error, info, warn};
+
+use crate::AppConfig;
+use crate::api::media_server::{MediaLookup, MediaServer};
+use crate::api::notify::{DownloadEvent, Notifier};
+use crate::api::shutdown::Shutdown;
+use crate::api::watchlist::announce_available;
+use crate::db::DBConnection;
+use crate::db::attempts::{AttemptDatabase, AttemptOutcome};
+use crate::db::downloads::{DownloadDatabase, PendingImport};
+use crate::db::history::{HistoryDatabase, HistoryEvent};
+use crate::db::watchlist_states::{WatchlistState, WatchlistStateDatabase};
+
+static CHECK_INTERVAL: u64 = 60 * 5;
+
+// Completed downloads are checked against the media server until they show up, or the grace period runs out
+pub async fn monitor_imports(
+    db: Arc<DBConnection>,
+    media_server: Arc<dyn MediaServer>,
+    notifier: Arc<Notifier>,
+    shutdown: Arc<Shutdown>,
+    app_config: Data<AppConfig>,
+) {
+    if app_config.read_only || app_config.has_media_server().not() {
+        return;
+    }
+    info!("Starting Import Monitor");
+    let grace_period = chrono::Duration::minutes(app_config.import_grace_period_minutes);
+
+    loop {
+        if shutdown.sleep(Duration::from_secs(CHECK_INTERVAL)).await {
+            return;
+        }
+
+        let download_db = DownloadDatabase::new(db.deref());
+        let history_db = HistoryDatabase::new(db.deref());
+        let attempt_db = AttemptDatabase::new(db.deref());
+        let state_db = WatchlistStateDatabase::new(db.deref());
+        let pending = match download_db.fetch_pending_imports().await {
+            Ok(t) => t,
+            Err(e) => {
+                error!("Failed to fetch pending imports: {}", e);
+                continue;
+            }
+        };
+
+        for item in pending {
+            let imported = match is_imported(&item, &media_server, &app_config).await {
+                Ok(t) => t,
+                Err(e) => {
+                    warn!("Failed to check import for {}: {}", item.imdb_id, e);
+                    continue;
+                }
+            };
+
+            let result = match imported {
+                true => {
+                    info!("Imported: {}", item.imdb_id);
+                    let watched = match state_db.set_for_download(&item.magnet_hash, WatchlistState::Available, None).await {
+                        Ok(t) => t,
+                        Err(e) => {
+                            error!("Failed to update watchlist state: {}", e);
+                            false
+                        }
+                    };
+                    notifier.notify_download(db.deref(), &item.magnet_hash, DownloadEvent::Imported, None).await;
+                    if watched {
+                        announce_available(&item.magnet_hash, db.deref(), &notifier).await;
+                    }
+                    download_db.remove(item.id).await
+                }
+                false if item.completed_at + grace_period < Local::now() => {
+                    warn!("Import failed: {}", item.imdb_id);
+                    let detail = "Not found in the media server";
+                    if let Err(e) = history_db.insert_event(&item.magnet_hash, HistoryEvent::Failed, Some(detail)).await {
+                        error!("Failed to record download history: {}", e);
+                    }
+                    if let Err(e) = attempt_db.set_outcome(&item.magnet_hash, AttemptOutcome::Failed, Some(detail)).await {
+                        error!("Failed to record download attempt: {}", e);
+                    }
+                    notifier.notify_download(db.deref(), &item.magnet_hash, DownloadEvent::Failed, Some(detail)).await;
+                    download_db.mark_import_failed(item.id).await
+                }
+                false => Ok(()),
+            };
+
+            if let Err(e) = result {
+                error!("Failed to update import state for {}: {}", item.imdb_id, e);
+            }
+        }
+    }
+}
+
+async fn is_imported(
+    item: &PendingImport,
+    media_server: &Arc<dyn MediaServer>,
+    app_config: &AppConfig,
+) -> anyhow::Result<bool> {
+    let title = match (&item.title, item.year) {
+        (Some(title), Some(year)) => format!("{} ({})", title, year),
+        (Some(title), None) => title.to_owned(),
+        (None, _) => String::new(),
+    };
+
+    // TheMovieDB downloads are stored as tt + the TMDB ID
+    let lookup = match app_config.tmdb_api_key.is_empty() {
+        true => MediaLookup::new(&title, Some(item.imdb_id.to_owned()), None),
+        false => MediaLookup::new(&title, None, Some(item.imdb_id.trim_start_matches("tt").to_owned())),
+    };
+
+    match item.season {
+        Some(season) => {
+            let episodes = media_server.list_episodes(&lookup).await?;
+            let imported = episodes.iter().any(|e| {
+                e.season == season && item.episode.map_or(true, |episode| e.episode == episode) // Season packs have no episode
+            });
+            Ok(imported)
+        }
+        None => media_server.exists_in_library(&lookup, false).await,
+    }
+}
\ No newline at end of file
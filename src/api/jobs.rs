@@ -0,0 +1,163 @@
+use std::ops::Not;
+use std::sync::Arc;
+use std::time::Duration;
+
+use actix_web::web::Data;
+use anyhow::format_err;
+use chrono::{DateTime, Local};
+use log::{error, info, warn};
+use serde::{Deserialize, Serialize};
+use tokio::sync::Notify;
+
+use crate::AppConfig;
+use crate::api::list_imports::{import_watchlist, WatchlistImportJob};
+use crate::api::media_server::MediaServer;
+use crate::api::notify::Notifier;
+use crate::api::shutdown::Shutdown;
+use crate::api::torrent::Torrenter;
+use crate::api::watchlist::EpisodeSearch;
+use crate::db::DBConnection;
+use crate::db::downloads::FinishedDownload;
+use crate::db::jobs::{JobDatabase, JobRecord};
+use crate::server::imports::{import_files, ManualImportRequest};
+
+// Checked this often even without a wake up, for retries that have become due
+static POLL_INTERVAL: Duration = Duration::from_secs(30);
+static MAX_ATTEMPTS: i32 = 5;
+static MAX_BACKOFF_MINUTES: i64 = 6 * 60;
+
+// Work that's too slow to do inside a request or that's worth trying again later, stored in the jobs table
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Job {
+    // Files downloaded outside of roundup, see POST /import
+    Import(ManualImportRequest),
+    // Search for a replacement for a download that was thrown away
+    Requeue(FinishedDownload),
+    // Search for a watchlist episode shortly after it airs
+    EpisodeSearch(EpisodeSearch),
+    // Add the titles on an IMDb list to the watchlist
+    WatchlistImport(WatchlistImportJob),
+}
+
+impl Job {
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Job::Import(_) => "import",
+            Job::Requeue(_) => "requeue",
+            Job::EpisodeSearch(_) => "episode_search",
+            Job::WatchlistImport(_) => "watchlist_import",
+        }
+    }
+}
+
+pub struct JobQueue {
+    wake: Notify,
+}
+
+impl JobQueue {
+    pub fn new() -> Self {
+        JobQueue { wake: Notify::new() }
+    }
+
+    pub async fn enqueue(&self, db: &DBConnection, job: &Job) -> anyhow::Result<i64> {
+        self.enqueue_at(db, job, Local::now()).await
+    }
+
+    // Runs the job once run_at has passed
+    pub async fn enqueue_at(&self, db: &DBConnection, job: &Job, run_at: DateTime<Local>) -> anyhow::Result<i64> {
+        let payload = serde_json::to_string(job)?;
+        let id = JobDatabase::new(db).insert(job.kind(), &payload, run_at).await?;
+        self.wake.notify_one();
+        Ok(id)
+    }
+}
+
+// Runs queued jobs one at a time. Failed jobs are retried with a growing delay, up to MAX_ATTEMPTS times
+pub async fn run_jobs(
+    queue: Arc<JobQueue>,
+    db: Arc<DBConnection>,
+    torrenter: Arc<Torrenter>,
+    media_server: Arc<dyn MediaServer>,
+    notifier: Arc<Notifier>,
+    shutdown: Arc<Shutdown>,
+    app_config: Data<AppConfig>,
+) {
+    info!("Starting Job Worker");
+    let job_db = JobDatabase::new(&db);
+    match job_db.requeue_running().await {
+        Ok(0) => (),
+        Ok(t) => info!("Restarting {} interrupted jobs", t),
+        Err(e) => error!("Failed to restart interrupted jobs: {}", e),
+    }
+
+    while shutdown.is_triggered().not() {
+        let record = match job_db.claim_next().await {
+            Ok(Some(t)) => t,
+            Ok(None) => {
+                tokio::select! {
+                    _ = queue.wake.notified() => (),
+                    _ = tokio::time::sleep(POLL_INTERVAL) => (),
+                    _ = shutdown.wait() => return,
+                }
+                continue;
+            }
+            Err(e) => {
+                error!("Failed to fetch the next job: {}", e);
+                if shutdown.sleep(POLL_INTERVAL).await {
+                    return;
+                }
+                continue;
+            }
+        };
+
+        let result = match serde_json::from_str::<Job>(&record.payload) {
+            Ok(job) => run_job(job, &queue, &db, &torrenter, &media_server, &notifier, &app_config).await,
+            Err(e) => {
+                finish(&job_db, &record, Err(format_err!("Unreadable job, {}", e)), false).await;
+                continue;
+            }
+        };
+        finish(&job_db, &record, result, record.attempts < MAX_ATTEMPTS).await;
+    }
+}
+
+async fn run_job(
+    job: Job,
+    queue: &JobQueue,
+    db: &Arc<DBConnection>,
+    torrenter: &Arc<Torrenter>,
+    media_server: &Arc<dyn MediaServer>,
+    notifier: &Notifier,
+    app_config: &Data<AppConfig>,
+) -> anyhow::Result<()> {
+    match job {
+        Job::Import(request) => import_files(&request, media_server.as_ref(), notifier, db, app_config).await,
+        Job::Requeue(download) => crate::api::watchlist::requeue_download(&download, torrenter, db).await,
+        Job::EpisodeSearch(search) => {
+            crate::api::watchlist::search_episode(&search, queue, db, torrenter, media_server, notifier, app_config).await
+        }
+        Job::WatchlistImport(import) => import_watchlist(&import, db, notifier, app_config).await,
+    }
+}
+
+async fn finish(job_db: &JobDatabase<'_>, record: &JobRecord, result: anyhow::Result<()>, can_retry: bool) {
+    let update = match result {
+        Ok(_) => job_db.complete(record.id).await,
+        Err(e) if can_retry => {
+            let delay = 2i64.pow(record.attempts.clamp(0, 16) as u32).min(MAX_BACKOFF_MINUTES);
+            warn!("Job {} ({}) failed, retrying in {} minutes: {}", record.id, record.kind, delay, e);
+            job_db
+                .retry(record.id, &e.to_string(), Local::now() + chrono::Duration::minutes(delay))
+                .await
+        }
+        Err(e) => {
+            error!("Job {} ({}) failed: {}", record.id, record.kind, e);
+            job_db.fail(record.id, &e.to_string()).await
+        }
+    };
+
+    if let Err(e) = update {
+        error!("Failed to update job {}: {}", record.id, e);
+    }
+}
@@ -0,0 +1,74 @@
+use anyhow::format_err;
+use reqwest::{Client, ClientBuilder};
+use serde::Deserialize;
+use serde_json::json;
+
+// Kodi JSON-RPC client, only used to tell Kodi to rescan its library
+pub struct Kodi {
+    client: Client,
+    url: String,
+    username: String,
+    password: String,
+}
+
+#[derive(Deserialize, Debug)]
+struct KodiResponse {
+    result: Option<String>,
+    error: Option<KodiError>,
+}
+
+#[derive(Deserialize, Debug)]
+struct KodiError {
+    code: i64,
+    message: String,
+}
+
+impl Kodi {
+    pub fn new(url: &str, username: &str, password: &str) -> Self {
+        let client = ClientBuilder::new().user_agent("roundup/1.0").build().unwrap();
+
+        Kodi {
+            client,
+            url: format!("{}/jsonrpc", url.trim_end_matches('/')),
+            username: username.to_string(),
+            password: password.to_string(),
+        }
+    }
+
+    // Kodi sees the files through its own sources, so the whole video library is scanned
+    // rather than the download path which may not exist on the Kodi machine.
+    pub async fn scan_library(&self) -> anyhow::Result<()> {
+        let body = json!({
+            "jsonrpc": "2.0",
+            "method": "VideoLibrary.Scan",
+            "params": { "showdialogs": false },
+            "id": 1,
+        });
+
+        let mut req = self
+            .client
+            .post(&self.url)
+            .header("Content-Type", "application/json")
+            .body(body.to_string());
+        if !self.username.is_empty() {
+            req = req.basic_auth(&self.username, Some(&self.password));
+        }
+
+        let resp = req.send().await?;
+        if resp.status().is_client_error() || resp.status().is_server_error() {
+            let status = resp.status();
+            let text = resp.text().await?;
+            return Err(format_err!("Failed to send request, Status: {}, Body: {}", status, text));
+        }
+
+        let text = resp.text().await?;
+        let data: KodiResponse = serde_json::from_str(&text)?;
+        match data.error {
+            Some(e) => Err(format_err!("Kodi error {}: {}", e.code, e.message)),
+            None => match data.result.as_deref() {
+                Some("OK") => Ok(()),
+                r => Err(format_err!("Unexpected response from Kodi: {:?}", r)),
+            },
+        }
+    }
+}
\ No newline at end of file
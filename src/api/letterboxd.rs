@@ -0,0 +1,151 @@
+use std::collections::HashSet;
+use std::ops::Not;
+
+use anyhow::format_err;
+use log::warn;
+use regex::Regex;
+use reqwest::Url;
+
+use crate::api::list_imports::site_redirects;
+use crate::api::moviedb::MovieDB;
+
+// Letterboxd only has TheMovieDB ids (in RSS feeds) or titles and years (in CSV exports), so films are matched on
+// TheMovieDB and added by their IMDb id
+pub struct LetterboxdList {
+    pub imdb_ids: Vec<String>,
+    // Films that couldn't be matched, as "Title (Year): reason"
+    pub unmatched: Vec<String>,
+}
+
+pub fn validate_url(url: &str) -> Result<(), String> {
+    let url = Url::parse(url.trim()).map_err(|e| format!("Invalid url, {}", e))?;
+    let is_letterboxd = url.host_str().is_some_and(|h| h == "letterboxd.com" || h.ends_with(".letterboxd.com"));
+    if matches!(url.scheme(), "http" | "https").not() || is_letterboxd.not() {
+        return Err("Only letterboxd.com feeds can be imported".to_string());
+    }
+    Ok(())
+}
+
+pub async fn fetch_feed(url: &str) -> anyhow::Result<String> {
+    let client = reqwest::ClientBuilder::new()
+        .user_agent("roundup/1.0")
+        .redirect(site_redirects("letterboxd.com"))
+        .build()?;
+
+    let response = client.get(url).send().await?;
+    if response.status().is_success().not() {
+        return Err(format_err!("{} returned {}", url, response.status()));
+    }
+    Ok(response.text().await?)
+}
+
+pub async fn read_feed(body: &str, moviedb: &MovieDB) -> anyhow::Result<LetterboxdList> {
+    let pattern = Regex::new(r"<tmdb:movieId>(\d+)</tmdb:movieId>").unwrap();
+    let mut seen = HashSet::new();
+    let ids = pattern
+        .captures_iter(body)
+        .filter_map(|c| c[1].parse::<i32>().ok())
+        .filter(|id| seen.insert(*id))
+        .collect::<Vec<i32>>();
+    if ids.is_empty() {
+        return Err(format_err!("No films found, use a public RSS feed or a CSV export"));
+    }
+
+    let mut list = LetterboxdList {
+        imdb_ids: Vec::new(),
+        unmatched: Vec::new(),
+    };
+    for id in ids {
+        match moviedb.fetch_movie_imdb_id(id).await {
+            Ok(Some(t)) => list.imdb_ids.push(t),
+            Ok(None) => list.unmatched.push(format!("TheMovieDB {}: no IMDb id", id)),
+            Err(e) => {
+                warn!("Failed to fetch the IMDb id for TheMovieDB {}: {}", id, e);
+                list.unmatched.push(format!("TheMovieDB {}: {}", id, e));
+            }
+        }
+    }
+    Ok(list)
+}
+
+pub async fn read_csv(body: &str, moviedb: &MovieDB) -> anyhow::Result<LetterboxdList> {
+    let films = parse_csv(body);
+    if films.is_empty() {
+        return Err(format_err!("No films found, the CSV needs Name and Year columns"));
+    }
+
+    let mut list = LetterboxdList {
+        imdb_ids: Vec::new(),
+        unmatched: Vec::new(),
+    };
+    for (title, year) in films {
+        let label = match year {
+            Some(year) => format!("{} ({})", title, year),
+            None => title.clone(),
+        };
+        let result = match moviedb.find_movie_id(&title, year).await {
+            Ok(Some(id)) => moviedb.fetch_movie_imdb_id(id).await,
+            Ok(None) => Err(format_err!("not found on TheMovieDB")),
+            Err(e) => Err(e),
+        };
+        match result {
+            Ok(Some(t)) => list.imdb_ids.push(t),
+            Ok(None) => list.unmatched.push(format!("{}: no IMDb id", label)),
+            Err(e) => list.unmatched.push(format!("{}: {}", label, e)),
+        }
+    }
+    Ok(list)
+}
+
+// Watchlist exports are a single table, list exports start with a table describing the list. Films are the rows under
+// the last header with a Name and Year column
+fn parse_csv(body: &str) -> Vec<(String, Option<i32>)> {
+    let mut films = Vec::new();
+    let mut columns = None;
+
+    for line in body.lines() {
+        let fields = split_csv_line(line);
+        let name = fields.iter().position(|f| f == "Name");
+        let year = fields.iter().position(|f| f == "Year");
+        if let (Some(name), Some(year)) = (name, year) {
+            films.clear();
+            columns = Some((name, year));
+            continue;
+        }
+
+        let (name, year) = match columns {
+            Some(t) => t,
+            None => continue,
+        };
+        match fields.get(name).map(|n| n.trim()) {
+            Some(title) if title.is_empty().not() => {
+                let year = fields.get(year).and_then(|y| y.trim().parse::<i32>().ok());
+                films.push((title.to_string(), year));
+            }
+            _ => (),
+        }
+    }
+    films
+}
+
+// Quoted fields can hold commas, quotes inside them are doubled
+fn split_csv_line(line: &str) -> Vec<String> {
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = line.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match (c, quoted) {
+            ('"', true) if chars.peek() == Some(&'"') => {
+                field.push('"');
+                chars.next();
+            }
+            ('"', _) => quoted = quoted.not(),
+            (',', false) => fields.push(std::mem::take(&mut field)),
+            _ => field.push(c),
+        }
+    }
+    fields.push(field);
+    fields
+}
\ No newline at end of file
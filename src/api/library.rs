@@ -0,0 +1,365 @@
+use std::collections::HashMap;
+use std::fs;
+use std::ops::Not;
+use std::path::Path;
+use std::process::Command;
+use std::sync::Arc;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use chrono::Local;
+use log::{error, info};
+use regex::Regex;
+
+use crate::api::imdb::ItemType;
+use crate::api::media_server::{Episode, LibraryFile, LibraryStats, MediaLookup, MediaServer, normalise_title, RECENTLY_ADDED_LIMIT, RecentlyAdded};
+use crate::api::shutdown::Shutdown;
+use crate::api::torrent::MediaQuality;
+use crate::db::DBConnection;
+use crate::db::library::{LibraryDatabase, LibraryItem};
+
+static SCAN_INTERVAL: u64 = 3_600 * 6;
+static VIDEO_EXTENSIONS: [&str; 7] = ["mkv", "mp4", "avi", "mov", "webm", "m4v", "ts"];
+
+// Media server backed by scanning the media folders directly, for setups without Plex
+pub struct LocalLibrary {
+    db: DBConnection,
+    paths: Vec<String>,
+}
+
+impl LocalLibrary {
+    pub fn new(db: DBConnection, paths: Vec<String>) -> Self {
+        Self { db, paths }
+    }
+
+    pub async fn monitor(&self, shutdown: Arc<Shutdown>) {
+        info!("Starting Library Scanner");
+        loop {
+            for path in self.paths.iter() {
+                if let Err(e) = self.scan(path).await {
+                    error!("Failed to scan {}: {}", path, e);
+                }
+            }
+            if shutdown.sleep(Duration::from_secs(SCAN_INTERVAL)).await {
+                return;
+            }
+        }
+    }
+
+    async fn scan(&self, root: &str) -> anyhow::Result<()> {
+        let scan_started = Local::now();
+        let root_owned = root.to_string();
+        let items = tokio::task::spawn_blocking(move || {
+            let mut items = Vec::new();
+            walk_directory(Path::new(&root_owned), &mut items);
+            items
+        })
+        .await?;
+
+        info!("Found {} items in {}", items.len(), root);
+        let db = LibraryDatabase::new(&self.db);
+        let items = self.fill_missing_qualities(&db, root, items).await?;
+        db.insert_or_update_many(&items).await?;
+        db.remove_stale(root, scan_started).await?;
+
+        Ok(())
+    }
+
+    // File names without a resolution are probed with ffprobe, when it's installed
+    async fn fill_missing_qualities(
+        &self,
+        db: &LibraryDatabase<'_>,
+        root: &str,
+        mut items: Vec<LibraryItem>,
+    ) -> anyhow::Result<Vec<LibraryItem>> {
+        let known = db
+            .fetch_known_qualities(root)
+            .await?
+            .into_iter()
+            .map(|(path, size, quality)| (path, (size, quality)))
+            .collect::<HashMap<String, (i64, String)>>();
+
+        for item in items.iter_mut().filter(|i| i.quality.is_none()) {
+            if let Some((size, quality)) = known.get(&item.path) {
+                if *size == item.size {
+                    item.quality = Some(quality.clone());
+                }
+            }
+        }
+
+        if items.iter().all(|i| i.quality.is_some()) {
+            return Ok(items);
+        }
+
+        let items = tokio::task::spawn_blocking(move || {
+            if Command::new("ffprobe").arg("-version").output().is_err() {
+                return items;
+            }
+            for item in items.iter_mut().filter(|i| i.quality.is_none()) {
+                item.quality = probe_quality(&item.path).map(|q| q.to_string());
+            }
+            items
+        })
+        .await?;
+
+        Ok(items)
+    }
+
+    fn matches_year(item: &LibraryItem, year: Option<i32>) -> bool {
+        match (item.year, year) {
+            (Some(a), Some(b)) => (a - b).abs() <= 1, // Release years are often off by one between sources
+            _ => true,
+        }
+    }
+}
+
+#[async_trait]
+impl MediaServer for LocalLibrary {
+    async fn exists_in_library(&self, lookup: &MediaLookup, _: bool) -> anyhow::Result<bool> {
+        let (_, year) = lookup.title_and_year();
+        let library_db = LibraryDatabase::new(&self.db);
+        for title in lookup.titles() {
+            let items = library_db
+                .fetch_by_title(&normalise_title(title), ItemType::Movie)
+                .await?;
+            if items.iter().any(|i| LocalLibrary::matches_year(i, year)) {
+                return Ok(true);
+            }
+        }
+
+        Ok(false)
+    }
+
+    async fn list_episodes(&self, lookup: &MediaLookup) -> anyhow::Result<Vec<Episode>> {
+        let (_, year) = lookup.title_and_year();
+        let library_db = LibraryDatabase::new(&self.db);
+        let mut items = Vec::new();
+        for title in lookup.titles() {
+            items = library_db
+                .fetch_by_title(&normalise_title(title), ItemType::TvShow)
+                .await?;
+            if items.is_empty().not() {
+                break;
+            }
+        }
+
+        let episodes = items
+            .iter()
+            .filter(|i| LocalLibrary::matches_year(i, year))
+            .filter_map(|i| match (i.season, i.episode) {
+                (Some(season), Some(episode)) => Some(Episode { season, episode }),
+                _ => None,
+            })
+            .collect::<Vec<Episode>>();
+
+        Ok(episodes)
+    }
+
+    async fn library_file(&self, lookup: &MediaLookup) -> anyhow::Result<Option<LibraryFile>> {
+        let (_, year) = lookup.title_and_year();
+        let library_db = LibraryDatabase::new(&self.db);
+        for title in lookup.titles() {
+            let items = library_db
+                .fetch_by_title(&normalise_title(title), ItemType::Movie)
+                .await?;
+            let best = items
+                .into_iter()
+                .filter(|i| LocalLibrary::matches_year(i, year))
+                .filter_map(|i| {
+                    let quality = MediaQuality::from_label(i.quality.as_deref()?)?;
+                    Some(LibraryFile { path: i.path, quality })
+                })
+                .max_by_key(|f| f.quality);
+            if best.is_some() {
+                return Ok(best);
+            }
+        }
+
+        Ok(None)
+    }
+
+    async fn library_stats(&self) -> anyhow::Result<LibraryStats> {
+        let library_db = LibraryDatabase::new(&self.db);
+        let mut stats = LibraryStats::default();
+
+        for (_type, count) in library_db.count_by_type().await? {
+            match _type {
+                ItemType::Movie => stats.movies = count,
+                ItemType::TvShow => stats.episodes = count,
+            }
+        }
+        stats.shows = library_db.count_shows().await?;
+
+        for (quality, count) in library_db.count_movie_qualities().await? {
+            let quality = quality.unwrap_or(MediaQuality::Unknown.to_string());
+            *stats.movie_qualities.entry(quality).or_default() += count;
+        }
+
+        // Titles in the index are normalised, the file name reads better
+        stats.recently_added = library_db
+            .fetch_recent(RECENTLY_ADDED_LIMIT as i64)
+            .await?
+            .into_iter()
+            .map(|(path, year, _type, added_at)| {
+                let stem = Path::new(&path).file_stem().and_then(|s| s.to_str()).unwrap_or(&path).to_string();
+                RecentlyAdded {
+                    title: split_title_and_year(&stem).0,
+                    year,
+                    _type,
+                    added_at,
+                }
+            })
+            .collect();
+
+        Ok(stats)
+    }
+
+    async fn refresh_library(&self, path: Option<&str>) -> anyhow::Result<()> {
+        // Only rescan the library folder containing the path
+        let roots = self
+            .paths
+            .iter()
+            .filter(|r| path.is_none() || path.is_some_and(|p| p.starts_with(r.as_str())));
+        for root in roots {
+            self.scan(root).await?;
+        }
+
+        Ok(())
+    }
+}
+
+fn walk_directory(dir: &Path, items: &mut Vec<LibraryItem>) {
+    let entries = match fs::read_dir(dir) {
+        Ok(t) => t,
+        Err(e) => {
+            error!("Failed to read {}: {}", dir.display(), e);
+            return;
+        }
+    };
+
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if path.is_dir() {
+            walk_directory(&path, items);
+            continue;
+        }
+
+        let is_video = path
+            .extension()
+            .and_then(|e| e.to_str())
+            .is_some_and(|e| VIDEO_EXTENSIONS.contains(&e.to_ascii_lowercase().as_str()));
+        if is_video.not() {
+            continue;
+        }
+
+        let size = entry.metadata().map(|m| m.len() as i64).unwrap_or(0);
+        if let Some(item) = parse_file_name(&path, size) {
+            items.push(item);
+        }
+    }
+}
+
+// Handles both "Show (2020)/Season 01/Show - S01E02.mkv" style folders and scene names like "Show.2020.S01E02.1080p.mkv"
+fn parse_file_name(path: &Path, size: i64) -> Option<LibraryItem> {
+    let episode_regexp = Regex::new(r"(?i)s(\d{1,2})\s?e(\d{1,3})").unwrap();
+    let season_folder_regexp = Regex::new(r"(?i)^(season|series)\s*\d+$").unwrap();
+
+    let stem = path.file_stem()?.to_str()?;
+    let quality = quality_from_name(stem).map(|q| q.to_string());
+    let parent = path.parent();
+    let parent_name = parent.and_then(|p| p.file_name()).and_then(|p| p.to_str());
+
+    match episode_regexp.captures(stem) {
+        Some(captures) => {
+            let season = captures.get(1)?.as_str().parse::<i32>().ok()?;
+            let episode = captures.get(2)?.as_str().parse::<i32>().ok()?;
+
+            // Prefer the show folder name, file names are often abbreviated
+            let show_folder = match parent_name {
+                Some(p) if season_folder_regexp.is_match(p) => parent
+                    .and_then(|p| p.parent())
+                    .and_then(|p| p.file_name())
+                    .and_then(|p| p.to_str()),
+                _ => None,
+            };
+            let name = match show_folder {
+                Some(t) => t,
+                None => &stem[..captures.get(0)?.start()],
+            };
+            let (title, year) = split_title_and_year(name);
+            if title.is_empty() {
+                return None;
+            }
+
+            Some(LibraryItem {
+                path: path.to_str()?.to_string(),
+                title: normalise_title(&title),
+                year,
+                season: Some(season),
+                episode: Some(episode),
+                _type: ItemType::TvShow,
+                size,
+                quality,
+            })
+        }
+        None => {
+            let (mut title, mut year) = split_title_and_year(stem);
+            if year.is_none() {
+                if let Some(p) = parent_name {
+                    (title, year) = split_title_and_year(p);
+                }
+            }
+            if title.is_empty() {
+                return None;
+            }
+
+            Some(LibraryItem {
+                path: path.to_str()?.to_string(),
+                title: normalise_title(&title),
+                year,
+                season: None,
+                episode: None,
+                _type: ItemType::Movie,
+                size,
+                quality,
+            })
+        }
+    }
+}
+
+fn quality_from_name(name: &str) -> Option<MediaQuality> {
+    let quality_regexp = Regex::new(r"(?i)(?:^|[\s._\[\(\-])(\d{3,4}p|4k|8k)(?:[\s._\]\)\-]|$)").unwrap();
+    let captures = quality_regexp.captures(name)?;
+    MediaQuality::from_label(captures.get(1)?.as_str())
+}
+
+fn probe_quality(path: &str) -> Option<MediaQuality> {
+    let output = Command::new("ffprobe")
+        .args(["-v", "error", "-select_streams", "v:0", "-show_entries", "stream=width,height", "-of", "csv=p=0:s=x"])
+        .arg(path)
+        .output()
+        .ok()?;
+    if output.status.success().not() {
+        return None;
+    }
+
+    // "1920x1080"
+    let text = String::from_utf8_lossy(&output.stdout);
+    let (width, height) = text.trim().split_once('x')?;
+    Some(MediaQuality::from_resolution(width.parse().ok()?, height.parse().ok()?))
+}
+
+// "The.Matrix.1999.1080p" or "The Matrix (1999)" => ("The Matrix", Some(1999))
+fn split_title_and_year(name: &str) -> (String, Option<i32>) {
+    let year_regexp = Regex::new(r"[\s._\(\[\-]((?:19|20)\d{2})(?:[\s._\)\]\-]|$)").unwrap();
+    let cleaned = name.replace(['.', '_'], " ");
+
+    match year_regexp.captures(&cleaned) {
+        Some(captures) => {
+            let title = cleaned[..captures.get(0).unwrap().start()].trim().trim_end_matches('-').trim();
+            let year = captures.get(1).and_then(|y| y.as_str().parse::<i32>().ok());
+            (title.to_string(), year)
+        }
+        None => (cleaned.trim().trim_end_matches('-').trim().to_string(), None),
+    }
+}
@@ -0,0 +1,270 @@
+use std::collections::HashSet;
+use std::ops::Not;
+
+use anyhow::format_err;
+use log::{info, warn};
+use regex::Regex;
+use reqwest::header::{HeaderMap, HeaderValue};
+use reqwest::redirect::Policy;
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use utoipa::ToSchema;
+
+use crate::AppConfig;
+use crate::api::letterboxd;
+use crate::api::moviedb::MovieDB;
+use crate::api::notify::Notifier;
+use crate::db::DBConnection;
+use crate::db::imdb::IMDBDatabase;
+use crate::db::moviedb::MovieDBDatabase;
+use crate::db::watchlist_imports::WatchlistImportDatabase;
+use crate::server::api_v1::resolve_watchlist_id;
+use crate::server::download::set_watchlist_state;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum ListProvider {
+    #[default]
+    Imdb,
+    Letterboxd,
+}
+
+// A list or watchlist to add to the watchlist, either its URL or the CSV the site exports it as
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct WatchlistImportRequest {
+    #[serde(default)]
+    pub provider: ListProvider,
+    // eg https://www.imdb.com/list/ls000000000/ or https://letterboxd.com/username/rss/, the list has to be public
+    #[serde(default)]
+    pub url: Option<String>,
+    #[serde(default)]
+    pub csv: Option<String>,
+}
+
+impl WatchlistImportRequest {
+    pub fn validate(&self, app_config: &AppConfig) -> Result<(), String> {
+        // Letterboxd films are matched through TheMovieDB
+        if self.provider == ListProvider::Letterboxd && app_config.tmdb_api_key.is_empty() {
+            return Err("Letterboxd imports need a TheMovieDB API key".to_string());
+        }
+        let url = match (&self.url, &self.csv) {
+            (Some(url), None) => url,
+            (None, Some(_)) => return Ok(()),
+            _ => return Err("Send either a url or the csv".to_string()),
+        };
+
+        // Only the provider's site is fetched, so the server can't be pointed at anything else
+        match self.provider {
+            ListProvider::Imdb => {
+                let url = Url::parse(url.trim()).map_err(|e| format!("Invalid url, {}", e))?;
+                let is_imdb = url.host_str().is_some_and(|h| h == "imdb.com" || h.ends_with(".imdb.com"));
+                if matches!(url.scheme(), "http" | "https").not() || is_imdb.not() {
+                    return Err("Only imdb.com lists can be imported".to_string());
+                }
+                Ok(())
+            }
+            ListProvider::Letterboxd => letterboxd::validate_url(url),
+        }
+    }
+
+    pub fn source(&self) -> String {
+        match (&self.url, self.provider) {
+            (Some(url), _) => url.trim().to_string(),
+            (None, ListProvider::Imdb) => "imdb csv".to_string(),
+            (None, ListProvider::Letterboxd) => "letterboxd csv".to_string(),
+        }
+    }
+}
+
+// Job payload, import_id is the watchlist_imports row progress is written to
+#[derive(Debug, Serialize, Deserialize)]
+pub struct WatchlistImportJob {
+    pub import_id: i64,
+    pub request: WatchlistImportRequest,
+}
+
+// Run by the job worker. Titles are added one at a time so progress can be followed with GET /api/v1/watchlist/import/{id}
+pub async fn import_watchlist(
+    job: &WatchlistImportJob,
+    db: &DBConnection,
+    notifier: &Notifier,
+    app_config: &AppConfig,
+) -> anyhow::Result<()> {
+    let import_db = WatchlistImportDatabase::new(db);
+    let (ids, unmatched) = match read_list(&job.request, app_config).await {
+        Ok(t) => t,
+        Err(e) => {
+            import_db.finish(job.import_id, Some(&e.to_string())).await?;
+            return Err(e);
+        }
+    };
+    import_db.start(job.import_id, (ids.len() + unmatched.len()) as i32).await?;
+    info!("Importing {} titles from {}", ids.len(), job.request.source());
+    for detail in unmatched {
+        import_db.add_failure(job.import_id, &detail).await?;
+    }
+
+    let watchlist = match app_config.tmdb_api_key.is_empty() {
+        true => IMDBDatabase::new(db).fetch_watchlist().await?.into_iter().map(|i| i.id).collect::<HashSet<String>>(),
+        false => MovieDBDatabase::new(db).fetch_watchlist().await?.into_iter().map(|i| i.imdb_id).collect(),
+    };
+
+    // Shared between IMDb lookups so it's only fetched once
+    let mut query_key = None;
+    for id in ids {
+        if watchlist.contains(&id) {
+            import_db.count_existing(job.import_id).await?;
+            continue;
+        }
+
+        let outcome = match resolve_watchlist_id(&id, true, db, app_config, &mut query_key).await {
+            Ok(t) => set_watchlist_state(app_config, db, notifier, &t, true).await,
+            Err(e) => Err(e),
+        };
+        match outcome {
+            Ok(_) => import_db.count_added(job.import_id).await?,
+            Err(e) => {
+                warn!("Failed to import {} to the watchlist: {}", id, e);
+                import_db.add_failure(job.import_id, &format!("{}: {}", id, e)).await?
+            }
+        }
+    }
+
+    import_db.finish(job.import_id, None).await?;
+    Ok(())
+}
+
+// IMDb ids to add, and titles that couldn't be matched to one
+async fn read_list(request: &WatchlistImportRequest, app_config: &AppConfig) -> anyhow::Result<(Vec<String>, Vec<String>)> {
+    if request.provider == ListProvider::Letterboxd {
+        let moviedb = MovieDB::new(&app_config.tmdb_api_key);
+        let list = match (&request.url, &request.csv) {
+            (_, Some(csv)) => letterboxd::read_csv(csv, &moviedb).await?,
+            (Some(url), None) => letterboxd::read_feed(&letterboxd::fetch_feed(url.trim()).await?, &moviedb).await?,
+            (None, None) => return Err(format_err!("Nothing to import")),
+        };
+        let mut seen = HashSet::new();
+        let ids = list.imdb_ids.into_iter().filter(|id| seen.insert(id.clone())).collect();
+        return Ok((ids, list.unmatched));
+    }
+
+    let body = match (&request.url, &request.csv) {
+        (_, Some(csv)) => csv.to_owned(),
+        (Some(url), None) => fetch_imdb_list(url.trim()).await?,
+        (None, None) => return Err(format_err!("Nothing to import")),
+    };
+
+    let ids = parse_imdb_list(&body);
+    if ids.is_empty() {
+        return Err(format_err!("No titles found, make sure the list is public"));
+    }
+    Ok((ids, Vec::new()))
+}
+
+async fn fetch_imdb_list(url: &str) -> anyhow::Result<String> {
+    let mut headers = HeaderMap::new();
+    headers.insert("Accept-Language", HeaderValue::from_static("en-US,en;q=0.9"));
+    headers.insert("User-Agent", HeaderValue::from_static("Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/121.0.0.0 Safari/537.36"));
+    let client = reqwest::ClientBuilder::new()
+        .default_headers(headers)
+        .redirect(site_redirects("imdb.com"))
+        .build()?;
+
+    let response = client.get(url).send().await?;
+    if response.status().is_success().not() {
+        return Err(format_err!("{} returned {}", url, response.status()));
+    }
+    Ok(response.text().await?)
+}
+
+// Redirects are only followed within the site, so a list URL can't send the server anywhere else
+pub fn site_redirects(domain: &'static str) -> Policy {
+    Policy::custom(move |attempt| {
+        let url = attempt.url();
+        let on_site = matches!(url.scheme(), "http" | "https")
+            && url.host_str().is_some_and(|h| h == domain || h.ends_with(&format!(".{}", domain)));
+        match (on_site, attempt.previous().len()) {
+            (false, _) => attempt.stop(),
+            (true, 0..=4) => attempt.follow(),
+            (true, _) => attempt.error("Too many redirects"),
+        }
+    })
+}
+
+// Exports have the title's id in the Const column, the first id on each line. Pages are read from the list data they
+// embed, as the rest of the page links to recommended titles that aren't on the list. Only the first page of a list
+// is read so larger lists need exporting
+fn parse_imdb_list(body: &str) -> Vec<String> {
+    let is_csv = body.lines().next().is_some_and(|l| l.contains("Const"));
+    let mut seen = HashSet::new();
+
+    let ids = match is_csv {
+        true => {
+            let pattern = Regex::new(r"\btt\d{7,}\b").unwrap();
+            body.lines()
+                .skip(1)
+                .filter_map(|l| pattern.find(l).map(|m| m.as_str().to_string()))
+                .collect::<Vec<String>>()
+        }
+        false => {
+            let mut ids = Vec::new();
+            if let Some(data) = page_json(body, r#"<script id="__NEXT_DATA__" type="application/json">"#) {
+                collect_list_items(&data, &mut ids);
+            }
+            if ids.is_empty() {
+                ids = structured_list_items(body);
+            }
+            ids
+        }
+    };
+
+    ids.into_iter().filter(|id| seen.insert(id.clone())).collect()
+}
+
+fn page_json(body: &str, opening_tag: &str) -> Option<Value> {
+    let start = body.find(opening_tag)? + opening_tag.len();
+    let end = body[start..].find("</script>")? + start;
+    serde_json::from_str(&body[start..end]).ok()
+}
+
+// Lists and watchlists both keep their titles in titleListItemSearch.edges[].listItem.id
+fn collect_list_items(value: &Value, ids: &mut Vec<String>) {
+    match value {
+        Value::Object(fields) => {
+            if let Some(edges) = fields.get("titleListItemSearch").and_then(|t| t.get("edges")).and_then(|e| e.as_array()) {
+                ids.extend(
+                    edges
+                        .iter()
+                        .filter_map(|e| e.pointer("/listItem/id").and_then(|id| id.as_str()))
+                        .filter(|id| id.starts_with("tt"))
+                        .map(|id| id.to_string()),
+                );
+                return;
+            }
+            fields.values().for_each(|v| collect_list_items(v, ids));
+        }
+        Value::Array(items) => items.iter().for_each(|v| collect_list_items(v, ids)),
+        _ => (),
+    }
+}
+
+// Older list pages only have the schema.org ItemList, with a URL for each title
+fn structured_list_items(body: &str) -> Vec<String> {
+    let data = match page_json(body, r#"<script type="application/ld+json">"#) {
+        Some(t) => t,
+        None => return Vec::new(),
+    };
+    let pattern = Regex::new(r"/title/(tt\d{7,})").unwrap();
+
+    data.get("itemListElement")
+        .and_then(|e| e.as_array())
+        .map(|items| {
+            items
+                .iter()
+                .filter_map(|i| i.pointer("/item/url").and_then(|u| u.as_str()))
+                .filter_map(|u| pattern.captures(u).map(|c| c[1].to_string()))
+                .collect()
+        })
+        .unwrap_or_default()
+}
\ No newline at end of file
@@ -0,0 +1,239 @@
+use std::collections::BTreeMap;
+use std::ops::Not;
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use chrono::{DateTime, Local};
+use futures::future::join_all;
+use log::warn;
+use regex::Regex;
+use serde::Serialize;
+
+use crate::api::imdb::ItemType;
+use crate::api::torrent::MediaQuality;
+
+#[derive(Debug, PartialEq)]
+pub struct Episode {
+    pub season: i32,
+    pub episode: i32,
+}
+
+// The file currently in the library for an item, used to decide if a release would be an upgrade
+#[derive(Debug, Clone)]
+pub struct LibraryFile {
+    pub path: String,
+    pub quality: MediaQuality,
+}
+
+pub const RECENTLY_ADDED_LIMIT: usize = 10;
+
+#[derive(Debug, Default, Serialize)]
+pub struct LibraryStats {
+    pub movies: i64,
+    pub shows: i64,
+    pub episodes: i64,
+    pub movie_qualities: BTreeMap<String, i64>,
+    pub recently_added: Vec<RecentlyAdded>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RecentlyAdded {
+    pub title: String,
+    pub year: Option<i32>,
+    #[serde(rename = "type")]
+    pub _type: ItemType,
+    pub added_at: DateTime<Local>,
+}
+
+impl LibraryStats {
+    pub fn merge(&mut self, other: LibraryStats) {
+        self.movies += other.movies;
+        self.shows += other.shows;
+        self.episodes += other.episodes;
+        for (quality, count) in other.movie_qualities {
+            *self.movie_qualities.entry(quality).or_default() += count;
+        }
+        self.recently_added.extend(other.recently_added);
+        self.recently_added.sort_by(|a, b| b.added_at.cmp(&a.added_at));
+        self.recently_added.truncate(RECENTLY_ADDED_LIMIT);
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct MediaLookup {
+    pub search_term: String, // "Title (Year)", used when the IDs don't match anything
+    pub imdb_id: Option<String>,
+    pub tmdb_id: Option<String>,
+    pub aliases: Vec<String>, // Alternate titles to try when the main one doesn't match
+}
+
+impl MediaLookup {
+    pub fn new(search_term: &str, imdb_id: Option<String>, tmdb_id: Option<String>) -> Self {
+        Self {
+            search_term: search_term.to_string(),
+            imdb_id,
+            tmdb_id,
+            aliases: Vec::new(),
+        }
+    }
+
+    pub fn with_aliases(mut self, aliases: Vec<String>) -> Self {
+        self.aliases = aliases;
+        self
+    }
+
+    // The main title followed by any aliases
+    pub fn titles(&self) -> Vec<&str> {
+        let (title, _) = self.title_and_year();
+        let mut titles = vec![title];
+        titles.extend(self.aliases.iter().map(|a| a.as_str()));
+        titles
+    }
+
+    // Splits "Title (Year)" into its parts
+    pub fn title_and_year(&self) -> (&str, Option<i32>) {
+        let year_regexp = Regex::new(r"\((\d{4})\)").unwrap();
+        let title = match year_regexp.split(&self.search_term).next() {
+            Some(t) => t.trim(),
+            None => self.search_term.as_str(),
+        };
+        let year = year_regexp
+            .captures(&self.search_term)
+            .and_then(|c| c.get(1))
+            .and_then(|y| y.as_str().parse::<i32>().ok());
+
+        (title, year)
+    }
+}
+
+#[async_trait]
+pub trait MediaServer: Send + Sync {
+    async fn exists_in_library(&self, lookup: &MediaLookup, exact_match: bool) -> anyhow::Result<bool>;
+    async fn list_episodes(&self, lookup: &MediaLookup) -> anyhow::Result<Vec<Episode>>;
+    // Best quality copy of a movie in the library, None when missing or the quality is unknown
+    async fn library_file(&self, lookup: &MediaLookup) -> anyhow::Result<Option<LibraryFile>>;
+    // Scans just the given path when the server supports it, otherwise the whole library
+    async fn refresh_library(&self, path: Option<&str>) -> anyhow::Result<()>;
+    async fn library_stats(&self) -> anyhow::Result<LibraryStats>;
+}
+
+// Used when there is no media server to check against, everything is treated as missing
+pub struct NoMediaServer;
+
+#[async_trait]
+impl MediaServer for NoMediaServer {
+    async fn exists_in_library(&self, _: &MediaLookup, _: bool) -> anyhow::Result<bool> {
+        Ok(false)
+    }
+
+    async fn list_episodes(&self, _: &MediaLookup) -> anyhow::Result<Vec<Episode>> {
+        Ok(Vec::new())
+    }
+
+    async fn library_file(&self, _: &MediaLookup) -> anyhow::Result<Option<LibraryFile>> {
+        Ok(None)
+    }
+
+    async fn refresh_library(&self, _: Option<&str>) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    async fn library_stats(&self) -> anyhow::Result<LibraryStats> {
+        Ok(LibraryStats::default())
+    }
+}
+
+// Checks several media servers (or Plex servers) as one, an item exists if any of them has it
+pub struct MultiMediaServer {
+    servers: Vec<Arc<dyn MediaServer>>,
+}
+
+impl MultiMediaServer {
+    pub fn new(servers: Vec<Arc<dyn MediaServer>>) -> Self {
+        Self { servers }
+    }
+
+    // Errors are only returned when every server failed
+    fn collect_results<T>(results: Vec<anyhow::Result<T>>) -> anyhow::Result<Vec<T>> {
+        let total = results.len();
+        let mut output = Vec::new();
+        let mut error = None;
+        for result in results {
+            match result {
+                Ok(t) => output.push(t),
+                Err(e) => {
+                    warn!("Media server error: {}", e);
+                    error = Some(e);
+                }
+            }
+        }
+
+        match (output.is_empty(), error) {
+            (true, Some(e)) if total > 0 => Err(e),
+            _ => Ok(output),
+        }
+    }
+}
+
+#[async_trait]
+impl MediaServer for MultiMediaServer {
+    async fn exists_in_library(&self, lookup: &MediaLookup, exact_match: bool) -> anyhow::Result<bool> {
+        let results = join_all(self.servers.iter().map(|s| s.exists_in_library(lookup, exact_match))).await;
+        let results = MultiMediaServer::collect_results(results)?;
+        Ok(results.into_iter().any(|t| t))
+    }
+
+    async fn list_episodes(&self, lookup: &MediaLookup) -> anyhow::Result<Vec<Episode>> {
+        let results = join_all(self.servers.iter().map(|s| s.list_episodes(lookup))).await;
+        let mut episodes = Vec::new();
+        for episode in MultiMediaServer::collect_results(results)?.into_iter().flatten() {
+            if episodes.contains(&episode).not() {
+                episodes.push(episode);
+            }
+        }
+        Ok(episodes)
+    }
+
+    async fn library_file(&self, lookup: &MediaLookup) -> anyhow::Result<Option<LibraryFile>> {
+        let results = join_all(self.servers.iter().map(|s| s.library_file(lookup))).await;
+        let best = MultiMediaServer::collect_results(results)?
+            .into_iter()
+            .flatten()
+            .max_by_key(|f| f.quality);
+        Ok(best)
+    }
+
+    async fn refresh_library(&self, path: Option<&str>) -> anyhow::Result<()> {
+        let results = join_all(self.servers.iter().map(|s| s.refresh_library(path))).await;
+        MultiMediaServer::collect_results(results)?;
+        Ok(())
+    }
+
+    async fn library_stats(&self) -> anyhow::Result<LibraryStats> {
+        let results = join_all(self.servers.iter().map(|s| s.library_stats())).await;
+        let mut stats = LibraryStats::default();
+        for result in MultiMediaServer::collect_results(results)? {
+            stats.merge(result);
+        }
+        Ok(stats)
+    }
+}
+
+pub fn titles_match(library_title: &str, title: &str, exact_match: bool) -> bool {
+    let library_title = normalise_title(library_title);
+    let title = normalise_title(title);
+    match exact_match {
+        true => library_title == title,
+        false => library_title.starts_with(&title),
+    }
+}
+
+// Lowercase, drop leading articles and anything that isn't a letter or number
+pub fn normalise_title(title: &str) -> String {
+    let title = title.to_lowercase();
+    let title = ["the ", "a ", "an "]
+        .iter()
+        .find_map(|a| title.strip_prefix(a))
+        .unwrap_or(&title);
+    title.replace('&', "and").chars().filter(|c| c.is_alphanumeric()).collect()
+}
@@ -1,9 +1,33 @@
+pub mod bandwidth;
+pub mod coalesce;
+pub mod disk;
+pub mod fanart;
+pub mod file_filter;
 pub mod imdb;
+pub mod importer;
+pub mod imports;
+pub mod jobs;
+pub mod kodi;
+pub mod letterboxd;
+pub mod library;
+pub mod list_imports;
+pub mod media_server;
 pub mod plex;
+pub mod progress;
+pub mod recycle;
+pub mod retention;
+pub mod scrape;
+pub mod settings;
+pub mod shutdown;
 pub mod torrent;
+pub mod trackers;
+pub mod validation;
 pub mod watchlist;
 pub mod youtube;
 pub mod moviedb;
+pub mod notify;
+pub mod posters;
+pub mod profiles;
 mod yts;
 mod eztv;
 mod therarbg;
\ No newline at end of file
@@ -7,6 +7,7 @@ use reqwest::{Client, ClientBuilder, StatusCode};
 use reqwest::header::{HeaderMap, HeaderValue};
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
+use utoipa::ToSchema;
 use crate::api::imdb::{ItemType, SearchType};
 
 pub struct MovieDB {
@@ -14,7 +15,7 @@ pub struct MovieDB {
     api_key: String,
 }
 
-#[derive(Debug, sqlx::FromRow, Serialize)]
+#[derive(Debug, Clone, sqlx::FromRow, Serialize, ToSchema)]
 pub struct MovieDBItem {
     pub id: i32,
     pub imdb_id: String,
@@ -22,6 +23,8 @@ pub struct MovieDBItem {
     pub plot: String,
     pub release_date: chrono::NaiveDate,
     pub image_url: Option<String>,
+    pub poster_url: Option<String>,
+    pub backdrop_url: Option<String>,
     pub video_id: Option<String>,
     pub certification: Option<String>,
     pub runtime: Option<i64>,
@@ -34,10 +37,19 @@ pub struct MovieDBItem {
     pub updated_at: chrono::DateTime<Local>,
 }
 
+// Earliest release dates of a movie in any country
+#[derive(Debug, Default)]
+pub struct MovieReleases {
+    pub theatrical: Option<chrono::NaiveDate>,
+    // Digital, physical or TV, when it can be watched at home
+    pub home: Option<chrono::NaiveDate>,
+}
+
 #[derive(Debug)]
 pub struct MovieDBEpisode {
     pub season: i32,
     pub episode: i32,
+    pub air_date: Option<chrono::NaiveDate>,
 }
 
 impl MovieDB {
@@ -99,6 +111,7 @@ impl MovieDB {
         let mut episodes: Vec<MovieDBEpisode> = data.episodes.iter().map(|x| MovieDBEpisode {
             season: x.season_number,
             episode: x.episode_number,
+            air_date: x.air_date.as_ref().and_then(|t| chrono::NaiveDate::from_str(t).ok()),
         }).collect();
 
         if season.eq(&1) {
@@ -118,6 +131,51 @@ impl MovieDB {
         Ok(episodes)
     }
 
+    pub async fn fetch_tvdb_id(api_key: &str, id: i32) -> anyhow::Result<Option<i64>> {
+        let mut headers = HeaderMap::new();
+        headers.insert("Accept", HeaderValue::from_static("application/json"));
+
+        let client = ClientBuilder::new().default_headers(headers).user_agent("roundup/1.0").build().unwrap();
+
+        let query = vec![("api_key", api_key)];
+
+        let resp = client.get(format!("https://api.themoviedb.org/3/tv/{}/external_ids", id)).query(&query).send().await?;
+        if resp.status().is_client_error() || resp.status().is_server_error() {
+            let status = resp.status();
+            let text = resp.text().await?;
+            return Err(format_err!("Failed to send request, Status: {}, Text: {}", status, text))
+        }
+
+        let text = resp.text().await?;
+        let data: TVDetailsExternalIds = serde_json::from_str(&text)?;
+
+        Ok(data.tvdb_id)
+    }
+
+    pub async fn fetch_alternative_titles(&self, id: i32, _type: &ItemType) -> anyhow::Result<Vec<String>> {
+        let path = match _type {
+            ItemType::Movie => "movie",
+            ItemType::TvShow => "tv",
+        };
+        let query = vec![("api_key", &self.api_key)];
+
+        let resp = self.client.get(format!("https://api.themoviedb.org/3/{}/{}/alternative_titles", path, id)).query(&query).send().await?;
+        if resp.status().is_client_error() || resp.status().is_server_error() {
+            let status = resp.status();
+            let text = resp.text().await?;
+            return Err(format_err!("Failed to send request, Status: {}, Text: {}", status, text))
+        }
+
+        let text = resp.text().await?;
+        let data: AlternativeTitlesResponse = serde_json::from_str(&text)?;
+
+        let mut titles = data.titles.into_iter().map(|t| t.title).collect::<Vec<String>>();
+        titles.sort();
+        titles.dedup();
+
+        Ok(titles)
+    }
+
     async fn fetch_popular_movies(&self) -> anyhow::Result<Vec<MovieDBItem>> {
         let query = vec![("language","en-us"), ("page","1"), ("api_key", &self.api_key)];
         let resp = self.client.get("https://api.themoviedb.org/3/movie/popular").query(&query).send().await?;
@@ -344,6 +402,155 @@ impl MovieDB {
 
         Ok(results)
     }
+    pub async fn fetch_recommendations(&self, id: i32, _type: &ItemType) -> anyhow::Result<Vec<MovieDBItem>> {
+        let path = match _type {
+            ItemType::Movie => "movie",
+            ItemType::TvShow => "tv",
+        };
+        let query = vec![("language","en-us"), ("page","1"), ("api_key", &self.api_key)];
+
+        let resp = self.client.get(format!("https://api.themoviedb.org/3/{}/{}/recommendations", path, id)).query(&query).send().await?;
+        if resp.status().is_client_error() || resp.status().is_server_error() {
+            let status = resp.status();
+            let text = resp.text().await?;
+            return Err(format_err!("Failed to send request, Status: {}, Text: {}", status, text))
+        }
+
+        let text = resp.text().await?;
+        let data: SearchMultiResultResponse = serde_json::from_str(&text)?;
+
+        let mut movie_tasks = Vec::new();
+        let mut tv_tasks = Vec::new();
+        for result in data.results.into_iter().take(12) {
+            match result {
+                ResultType::Movie(m) => {
+                    if m.release_date.is_empty() {
+                        continue;
+                    }
+                    movie_tasks.push(MovieDB::fetch_movie_details(&self.api_key, m));
+                },
+                ResultType::TVShow(t) => {
+                    if t.first_air_date.is_empty() {
+                        continue;
+                    }
+                    tv_tasks.push(MovieDB::fetch_tv_details(&self.api_key, t));
+                },
+                ResultType::Person(_) => continue,
+            };
+        }
+
+        let mut results = Vec::new();
+        let mut output =  futures::prelude::future::join_all(movie_tasks).await;
+        let mut output_tv =  futures::prelude::future::join_all(tv_tasks).await;
+        output.append(&mut output_tv);
+        for task in output {
+            match task {
+                Ok(t) => results.push(t),
+                Err(e) => error!("{}", e),
+            };
+        }
+
+        Ok(results)
+    }
+
+    // For items that haven't come up in a search yet
+    pub async fn fetch_by_imdb_id(&self, imdb_id: &str) -> anyhow::Result<MovieDBItem> {
+        let query = vec![
+            ("external_source", "imdb_id"),
+            ("api_key", &self.api_key),
+        ];
+
+        let resp = self.client.get(format!("https://api.themoviedb.org/3/find/{}", imdb_id)).query(&query).send().await?;
+        if resp.status().is_client_error() || resp.status().is_server_error() {
+            let status = resp.status();
+            let text = resp.text().await?;
+            return Err(format_err!("Failed to send request, Status: {}, Text: {}", status, text))
+        }
+
+        let text = resp.text().await?;
+        let data: FindResponse = serde_json::from_str(&text)?;
+
+        if let Some(movie) = data.movie_results.into_iter().next() {
+            return MovieDB::fetch_movie_details(&self.api_key, movie).await;
+        }
+        match data.tv_results.into_iter().next() {
+            Some(show) => MovieDB::fetch_tv_details(&self.api_key, show).await,
+            None => Err(format_err!("Nothing found on TheMovieDB for {}", imdb_id)),
+        }
+    }
+
+    // The best match for a movie's title, the year narrows it down when it's known
+    pub async fn find_movie_id(&self, title: &str, year: Option<i32>) -> anyhow::Result<Option<i32>> {
+        let year = year.map(|y| y.to_string()).unwrap_or_default();
+        let mut query = vec![
+            ("query", title),
+            ("api_key", &self.api_key),
+        ];
+        if year.is_empty().not() {
+            query.push(("primary_release_year", &year));
+        }
+
+        let resp = self.client.get("https://api.themoviedb.org/3/search/movie").query(&query).send().await?;
+        if resp.status().is_client_error() || resp.status().is_server_error() {
+            let status = resp.status();
+            let text = resp.text().await?;
+            return Err(format_err!("Failed to send request, Status: {}, Text: {}", status, text))
+        }
+
+        let text = resp.text().await?;
+        let data: MovieSearchResponse = serde_json::from_str(&text)?;
+
+        Ok(data.results.first().map(|m| m.id))
+    }
+
+    pub async fn fetch_release_dates(&self, id: i32) -> anyhow::Result<MovieReleases> {
+        let query = vec![("api_key", &self.api_key)];
+
+        let resp = self.client.get(format!("https://api.themoviedb.org/3/movie/{}/release_dates", id)).query(&query).send().await?;
+        if resp.status().is_client_error() || resp.status().is_server_error() {
+            let status = resp.status();
+            let text = resp.text().await?;
+            return Err(format_err!("Failed to send request, Status: {}, Text: {}", status, text))
+        }
+
+        let text = resp.text().await?;
+        let data: MovieReleaseDates = serde_json::from_str(&text)?;
+
+        let mut releases = MovieReleases::default();
+        for release in data.results.iter().flat_map(|r| r.release_dates.iter()) {
+            let date = match release.release_date.get(..10).and_then(|d| chrono::NaiveDate::from_str(d).ok()) {
+                Some(t) => t,
+                None => continue,
+            };
+            let earliest = match release.release_type {
+                1..=3 => &mut releases.theatrical,
+                4..=6 => &mut releases.home,
+                _ => continue,
+            };
+            if earliest.is_none_or(|d| date < d) {
+                *earliest = Some(date);
+            }
+        }
+
+        Ok(releases)
+    }
+
+    // None when TheMovieDB doesn't know the movie's IMDb id
+    pub async fn fetch_movie_imdb_id(&self, id: i32) -> anyhow::Result<Option<String>> {
+        let query = vec![("api_key", &self.api_key)];
+
+        let resp = self.client.get(format!("https://api.themoviedb.org/3/movie/{}/external_ids", id)).query(&query).send().await?;
+        if resp.status().is_client_error() || resp.status().is_server_error() {
+            let status = resp.status();
+            let text = resp.text().await?;
+            return Err(format_err!("Failed to send request, Status: {}, Text: {}", status, text))
+        }
+
+        let text = resp.text().await?;
+        let data: MovieExternalIds = serde_json::from_str(&text)?;
+
+        Ok(data.imdb_id.filter(|t| t.is_empty().not()))
+    }
 
     async fn fetch_movie_details(api_key: &str, initial_search: SearchMultiResultMovie) -> anyhow::Result<MovieDBItem> {
         let mut headers = HeaderMap::new();
@@ -408,6 +615,8 @@ impl MovieDB {
             image_url: data.poster_path,
             plot: data.overview,
             release_date: chrono::NaiveDate::from_str(&data.release_date).unwrap(),
+            poster_url: None,
+            backdrop_url: None,
             video_id,
             certification,
             runtime: Some(data.runtime),
@@ -485,6 +694,8 @@ impl MovieDB {
             image_url: initial_search.poster_path.to_owned(),
             plot: data.overview,
             release_date: chrono::NaiveDate::from_str(&data.first_air_date).unwrap(),
+            poster_url: None,
+            backdrop_url: None,
             video_id,
             certification,
             runtime: None,
@@ -530,6 +741,23 @@ struct SearchMultiResultTVShow {
     first_air_date: String,
 }
 
+#[derive(Debug, Deserialize)]
+struct FindResponse {
+    movie_results: Vec<SearchMultiResultMovie>,
+    tv_results: Vec<SearchMultiResultTVShow>,
+}
+
+#[derive(Debug, Deserialize)]
+struct AlternativeTitlesResponse {
+    #[serde(alias = "results")] // TV Shows use results instead of titles
+    titles: Vec<AlternativeTitle>,
+}
+
+#[derive(Debug, Deserialize)]
+struct AlternativeTitle {
+    title: String,
+}
+
 #[derive(Debug, Deserialize)]
 struct ExternalIdResponse {
     id: i32,
@@ -561,6 +789,12 @@ struct MovieReleaseDatesItem {
 #[derive(Debug, Deserialize)]
 struct MovieReleaseDatesItemInner {
     certification: String,
+    // 1 premiere, 2 limited theatrical, 3 theatrical, 4 digital, 5 physical, 6 TV
+    #[serde(default, rename = "type")]
+    release_type: i32,
+    // eg "2023-07-19T00:00:00.000Z"
+    #[serde(default)]
+    release_date: String,
 }
 #[derive(Debug, Deserialize)]
 struct VideosResponse {
@@ -598,8 +832,22 @@ struct ContentRatingsResultItem {
     rating: String,
 }
 #[derive(Debug, Deserialize)]
+struct MovieExternalIds {
+    imdb_id: Option<String>,
+}
+#[derive(Debug, Deserialize)]
+struct MovieSearchResponse {
+    results: Vec<MovieSearchResult>,
+}
+#[derive(Debug, Deserialize)]
+struct MovieSearchResult {
+    id: i32,
+}
+#[derive(Debug, Deserialize)]
 struct TVDetailsExternalIds {
     imdb_id: Option<String>,
+    #[serde(default)]
+    tvdb_id: Option<i64>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -611,4 +859,6 @@ struct TVSeasonDetails {
 struct TVSeasonDetailsEpisode {
     episode_number: i32,
     season_number: i32,
+    #[serde(default)]
+    air_date: Option<String>,
 }
\ No newline at end of file
@@ -0,0 +1,336 @@
+use std::collections::HashMap;
+use std::ops::Not;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use anyhow::format_err;
+use async_trait::async_trait;
+use log::{error, info, warn};
+use reqwest::{Client, ClientBuilder};
+use ring::hmac;
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast;
+
+use crate::api::settings::SharedSettings;
+use crate::db::DBConnection;
+use crate::db::attempts::AttemptDatabase;
+use crate::db::downloads::DownloadDatabase;
+use crate::db::watchlist_states::WatchlistStateDatabase;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum DownloadEvent {
+    Grabbed,
+    Progress,
+    Completed,
+    Failed,
+    Imported,
+    // The rest aren't tied to a download
+    // detail lists the volumes running out of space
+    #[serde(rename = "low_disk_space")]
+    LowDiskSpace,
+    // A watchlist item had new releases available and they were started
+    #[serde(rename = "watchlist_match")]
+    WatchlistMatch,
+    // Something was added to the watchlist
+    #[serde(rename = "watchlist_added")]
+    WatchlistAdded,
+    // Sent alongside grabbed for releases of watchlist items
+    #[serde(rename = "watchlist_grabbed")]
+    WatchlistGrabbed,
+    // A watchlist download is ready to watch, after the media server has it if there is one
+    #[serde(rename = "watchlist_available")]
+    WatchlistAvailable,
+    // A background task failed, detail has the error
+    Error,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct Notification {
+    pub event: DownloadEvent,
+    pub magnet_hash: String,
+    pub imdb_id: Option<String>,
+    pub title: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub season: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub episode: Option<i32>,
+    pub quality: Option<String>,
+    // Indexer the release came from
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub source: Option<String>,
+    // Percent, only for progress events
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub progress: Option<u8>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub detail: Option<String>,
+}
+
+#[async_trait]
+pub trait NotificationBackend: Send + Sync {
+    async fn send(&self, notification: &Notification) -> anyhow::Result<()>;
+}
+
+// Plain webhooks from the settings page, looked up on every notification so changes apply straight away
+pub struct SettingsWebhookBackend {
+    settings: Arc<SharedSettings>,
+}
+
+impl SettingsWebhookBackend {
+    pub fn new(settings: Arc<SharedSettings>) -> Self {
+        SettingsWebhookBackend { settings }
+    }
+}
+
+#[async_trait]
+impl NotificationBackend for SettingsWebhookBackend {
+    async fn send(&self, notification: &Notification) -> anyhow::Result<()> {
+        let urls = self.settings.load().notification_webhooks.clone();
+
+        // One dead endpoint shouldn't stop the rest from being sent
+        let mut result = Ok(());
+        for url in urls {
+            if let Err(e) = WebhookBackend::new(&url, "", Vec::new()).send(notification).await {
+                result = Err(e);
+            }
+        }
+        result
+    }
+}
+
+// Delays between attempts when a webhook fails
+const WEBHOOK_RETRIES: [Duration; 3] = [Duration::from_secs(10), Duration::from_secs(60), Duration::from_secs(300)];
+
+// Posts each notification as JSON. With a secret the body is signed with HMAC-SHA256 in the
+// X-Roundup-Signature header, and an empty event list sends everything.
+#[derive(Clone)]
+pub struct WebhookBackend {
+    client: Client,
+    url: String,
+    secret: Option<hmac::Key>,
+    events: Vec<DownloadEvent>,
+}
+
+impl WebhookBackend {
+    pub fn new(url: &str, secret: &str, events: Vec<DownloadEvent>) -> Self {
+        let client = ClientBuilder::new().user_agent("roundup/1.0").build().unwrap();
+
+        WebhookBackend {
+            client,
+            url: url.to_string(),
+            secret: match secret.is_empty() {
+                true => None,
+                false => Some(hmac::Key::new(hmac::HMAC_SHA256, secret.as_bytes())),
+            },
+            events,
+        }
+    }
+
+    fn wants(&self, event: DownloadEvent) -> bool {
+        self.events.is_empty() || self.events.contains(&event)
+    }
+
+    async fn post(&self, body: &str) -> anyhow::Result<()> {
+        let mut request = self
+            .client
+            .post(&self.url)
+            .header("Content-Type", "application/json")
+            .body(body.to_string());
+        if let Some(key) = &self.secret {
+            let signature = hmac::sign(key, body.as_bytes())
+                .as_ref()
+                .iter()
+                .map(|b| format!("{:02x}", b))
+                .collect::<String>();
+            request = request.header("X-Roundup-Signature", format!("sha256={}", signature));
+        }
+
+        let resp = request.send().await?;
+        if resp.status().is_client_error() || resp.status().is_server_error() {
+            let status = resp.status();
+            let text = resp.text().await?;
+            return Err(format_err!("Failed to send request, Status: {}, Body: {}", status, text));
+        }
+
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl NotificationBackend for WebhookBackend {
+    // Only the first attempt is waited on, retries happen in the background so a dead endpoint
+    // doesn't hold up the torrent watcher
+    async fn send(&self, notification: &Notification) -> anyhow::Result<()> {
+        if self.wants(notification.event).not() {
+            return Ok(());
+        }
+
+        let body = serde_json::to_string(notification)?;
+        let error = match self.post(&body).await {
+            Ok(_) => return Ok(()),
+            Err(e) => e,
+        };
+
+        let webhook = self.clone();
+        tokio::task::spawn(async move {
+            for delay in WEBHOOK_RETRIES {
+                tokio::time::sleep(delay).await;
+                match webhook.post(&body).await {
+                    Ok(_) => {
+                        info!("Delivered webhook to {} after retrying", webhook.url);
+                        return;
+                    }
+                    Err(e) => warn!("Webhook to {} failed again: {}", webhook.url, e),
+                }
+            }
+            error!("Giving up on webhook to {}", webhook.url);
+        });
+
+        Err(format_err!("{}, retrying", error))
+    }
+}
+
+// Rebroadcasts notifications to the /events stream, progress is left to the /ws socket
+#[derive(Clone)]
+pub struct EventStream {
+    sender: broadcast::Sender<Notification>,
+}
+
+impl EventStream {
+    pub fn new() -> Self {
+        let (sender, _) = broadcast::channel(64);
+
+        EventStream { sender }
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<Notification> {
+        self.sender.subscribe()
+    }
+}
+
+#[async_trait]
+impl NotificationBackend for EventStream {
+    async fn send(&self, notification: &Notification) -> anyhow::Result<()> {
+        if notification.event == DownloadEvent::Progress {
+            return Ok(());
+        }
+
+        // Nobody listening isn't an error
+        let _ = self.sender.send(notification.clone());
+        Ok(())
+    }
+}
+
+// Hands download events to every configured backend
+pub struct Notifier {
+    backends: Vec<Box<dyn NotificationBackend>>,
+    // Last progress milestone reported for each torrent
+    milestones: Mutex<HashMap<String, u8>>,
+}
+
+impl Notifier {
+    pub fn new(backends: Vec<Box<dyn NotificationBackend>>) -> Self {
+        Notifier {
+            backends,
+            milestones: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        !self.backends.is_empty()
+    }
+
+    pub async fn dispatch(&self, notification: Notification) {
+        for backend in self.backends.iter() {
+            if let Err(e) = backend.send(&notification).await {
+                warn!("Failed to send notification: {}", e);
+            }
+        }
+    }
+
+    // For events that aren't about a download
+    pub async fn notify(&self, event: DownloadEvent, imdb_id: Option<&str>, title: Option<&str>, detail: Option<&str>) {
+        let notification = Notification {
+            event,
+            magnet_hash: String::new(),
+            imdb_id: imdb_id.map(|t| t.to_string()),
+            title: title.map(|t| t.to_string()),
+            season: None,
+            episode: None,
+            quality: None,
+            source: None,
+            progress: None,
+            detail: detail.map(|t| t.to_string()),
+        };
+
+        self.dispatch(notification).await;
+    }
+
+    // Fills in the item details from the download, hashes roundup doesn't know about are sent as is
+    pub async fn notify_download(&self, db: &DBConnection, hash: &str, event: DownloadEvent, detail: Option<&str>) {
+        if !self.is_enabled() {
+            return;
+        }
+
+        let download = match DownloadDatabase::new(db).fetch_by_hash(hash).await {
+            Ok(t) => t,
+            Err(e) => {
+                error!("Failed to fetch download for notification: {}", e);
+                None
+            }
+        };
+        let attempt = match AttemptDatabase::new(db).fetch_by_hash(hash).await {
+            Ok(t) => t,
+            Err(e) => {
+                error!("Failed to fetch download attempt for notification: {}", e);
+                None
+            }
+        };
+
+        let notification = Notification {
+            event,
+            magnet_hash: hash.to_string(),
+            imdb_id: download.as_ref().map(|d| d.imdb_id.clone()),
+            title: download.as_ref().and_then(|d| d.title.clone()),
+            season: download.as_ref().and_then(|d| d.season),
+            episode: download.as_ref().and_then(|d| d.episode),
+            quality: download.as_ref().map(|d| d.quality.clone()),
+            source: attempt.and_then(|a| a.source),
+            progress: match event {
+                DownloadEvent::Progress => self.milestones.lock().unwrap().get(hash).copied(),
+                _ => None,
+            },
+            detail: detail.map(|d| d.to_string()),
+        };
+
+        self.dispatch(notification).await;
+    }
+
+    // Only sent for releases of watchlist items, eg watchlist_grabbed
+    pub async fn notify_watchlist(&self, db: &DBConnection, hash: &str, event: DownloadEvent) {
+        if !self.is_enabled() {
+            return;
+        }
+
+        match WatchlistStateDatabase::new(db).is_watchlist_download(hash).await {
+            Ok(true) => self.notify_download(db, hash, event, None).await,
+            Ok(false) => (),
+            Err(e) => error!("Failed to check watchlist for notification: {}", e),
+        }
+    }
+
+    // Returns true when the download has passed another 25%. The first time a torrent is seen its
+    // current milestone is only recorded, so restarting doesn't repeat old milestones.
+    pub fn passed_milestone(&self, hash: &str, progress: f64) -> bool {
+        let milestone = ((progress * 4.0).floor() as u8).min(3) * 25;
+        let mut milestones = self.milestones.lock().unwrap();
+        match milestones.insert(hash.to_string(), milestone) {
+            Some(previous) => milestone > previous,
+            None => false,
+        }
+    }
+
+    pub fn forget(&self, hash: &str) {
+        self.milestones.lock().unwrap().remove(hash);
+    }
+}
\ No newline at end of file
@@ -1,38 +1,155 @@
 use std::ops::Not;
+use std::sync::{Arc, RwLock};
 
 use anyhow::format_err;
+use async_trait::async_trait;
+use chrono::{Local, TimeZone};
+use log::{info, warn};
 use rayon::prelude::*;
-use regex::Regex;
 use reqwest::{Client, ClientBuilder};
 use reqwest::header::{HeaderMap, HeaderValue};
 use serde::{Deserialize, Serialize};
 
-#[derive(Debug)]
-pub struct Episode {
-    pub season: i32,
-    pub episode: i32,
-}
+use crate::api::imdb::ItemType;
+use crate::api::media_server::{Episode, LibraryFile, LibraryStats, MediaLookup, MediaServer, RECENTLY_ADDED_LIMIT, RecentlyAdded, titles_match};
+use crate::api::torrent::MediaQuality;
 
 #[derive(Debug, Clone)]
 pub struct Plex {
     client: Client,
-    token: String,
+    token: Arc<RwLock<String>>,
+    base_url: String,
+    client_identifier: String,
+    sections: Vec<String>, // Library section IDs to check, empty checks every section
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PlexPin {
+    pub id: i64,
+    pub code: String,
+    pub auth_token: Option<String>,
 }
 
 impl Plex {
-    pub fn new() -> anyhow::Result<Self> {
-        let token = Plex::get_plex_auth_token()?;
+    // token comes from config/env or a previous PIN login, otherwise fall back to the local Plex install
+    pub fn new(
+        base_url: &str,
+        accept_invalid_certs: bool,
+        token: Option<String>,
+        client_identifier: &str,
+    ) -> anyhow::Result<Self> {
+        let token = match token {
+            Some(t) => t,
+            None => match Plex::get_plex_auth_token() {
+                Ok(t) => t,
+                Err(e) => {
+                    warn!("No Plex token found ({}), sign in at /plex/login", e);
+                    String::new()
+                }
+            },
+        };
 
         let mut headers = HeaderMap::new();
         headers.insert("User-Agent", HeaderValue::from_static("roundup/1.0"));
         headers.insert("Accept", HeaderValue::from_static("application/json"));
 
+        // Self-signed certs are common for servers on the local network
         let client = ClientBuilder::new()
             .default_headers(headers)
+            .danger_accept_invalid_certs(accept_invalid_certs)
             .build()
             .unwrap();
 
-        Ok(Self { client, token })
+        let base_url = base_url.trim_end_matches('/').to_string();
+
+        Ok(Self {
+            client,
+            token: Arc::new(RwLock::new(token)),
+            base_url,
+            client_identifier: client_identifier.to_string(),
+            sections: Vec::new(),
+        })
+    }
+
+    pub fn with_sections(mut self, sections: Vec<String>) -> Self {
+        self.sections = sections;
+        self
+    }
+
+    fn in_sections(&self, metadata: &PlexLibraryMetadata) -> bool {
+        self.sections.is_empty()
+            || metadata
+                .library_section_id
+                .is_some_and(|id| self.sections.contains(&id.to_string()))
+    }
+
+    pub fn generate_client_identifier() -> String {
+        let mut bytes = [0u8; 16];
+        ring::rand::SecureRandom::fill(&ring::rand::SystemRandom::new(), &mut bytes).unwrap();
+        bytes.iter().map(|b| format!("{:02x}", b)).collect()
+    }
+
+    fn token(&self) -> String {
+        self.token.read().unwrap().clone()
+    }
+
+    pub fn set_token(&self, token: &str) {
+        *self.token.write().unwrap() = token.to_string();
+        info!("Updated Plex token");
+    }
+
+    fn plex_tv_headers(&self) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert("X-Plex-Product", HeaderValue::from_static("roundup"));
+        if let Ok(t) = HeaderValue::from_str(&self.client_identifier) {
+            headers.insert("X-Plex-Client-Identifier", t);
+        }
+        headers
+    }
+
+    pub async fn create_pin(&self) -> anyhow::Result<PlexPin> {
+        let resp = self
+            .client
+            .post("https://plex.tv/api/v2/pins")
+            .headers(self.plex_tv_headers())
+            .query(&[("strong", "true")])
+            .send()
+            .await?;
+
+        let status = resp.status();
+        if status.is_client_error() || status.is_server_error() {
+            return Err(format_err!("Failed to create Plex PIN, Status: {}", status));
+        }
+
+        let pin: PlexPin = serde_json::from_str(&resp.text().await?)?;
+        Ok(pin)
+    }
+
+    // Returns the token once the user has approved the PIN
+    pub async fn check_pin(&self, id: i64) -> anyhow::Result<Option<String>> {
+        let resp = self
+            .client
+            .get(format!("https://plex.tv/api/v2/pins/{}", id))
+            .headers(self.plex_tv_headers())
+            .send()
+            .await?;
+
+        let status = resp.status();
+        if status.is_client_error() || status.is_server_error() {
+            return Err(format_err!("Failed to check Plex PIN, Status: {}", status));
+        }
+
+        let pin: PlexPin = serde_json::from_str(&resp.text().await?)?;
+        Ok(pin.auth_token.filter(|t| t.is_empty().not()))
+    }
+
+    pub fn auth_url(&self, code: &str) -> String {
+        format!(
+            "https://app.plex.tv/auth#?clientID={}&code={}&context%5Bdevice%5D%5Bproduct%5D=roundup",
+            urlencoding::encode(&self.client_identifier),
+            urlencoding::encode(code)
+        )
     }
 
     #[cfg(target_os = "windows")]
@@ -77,39 +194,18 @@ impl Plex {
         Ok(plist_file.plex_online_token)
     }
 
-    pub async fn exists_in_library(
-        &self,
-        search_term: &str,
-        exact_match: bool,
-    ) -> anyhow::Result<bool> {
-        let year_regexp = Regex::new(r"(\(\d{4}\))").unwrap();
-        let title = match year_regexp.split(search_term).next() {
-            Some(t) => t.trim(),
-            None => search_term,
-        };
-
-        let year = match year_regexp.find(search_term) {
-            Some(t) => t
-                .as_str()
-                .strip_prefix('(')
-                .unwrap()
-                .strip_suffix(')')
-                .unwrap()
-                .parse::<u32>()
-                .unwrap(),
-            None => 0,
-        };
-
+    async fn search_hub(&self, title: &str, hub_title: &str) -> anyhow::Result<Vec<PlexLibraryMetadata>> {
+        let token = self.token();
         let query = [
             ("query", title),
-            ("X-Plex-Token", self.token.as_str()),
+            ("X-Plex-Token", token.as_str()),
             ("limit", "100"),
             ("includeCollections", "1"),
             ("includeExternalMedia", "1"),
         ];
         let resp = self
             .client
-            .get("http://127.0.0.1:32400/hubs/search")
+            .get(format!("{}/hubs/search", self.base_url))
             .query(&query)
             .send()
             .await?;
@@ -124,138 +220,148 @@ impl Plex {
             Err(e) => return Err(e.into()),
         };
 
-        let movie_hub = match data
+        match data
             .media_container
             .hub
-            .par_iter()
-            .find_any(|h| h.title.eq("Movies"))
+            .into_par_iter()
+            .find_any(|h| h.title.as_str() == hub_title)
         {
-            Some(d) => d,
-            None => return Err(format_err!("Missing Movies hub")),
-        };
+            Some(t) => Ok(t
+                .metadata
+                .unwrap_or_default()
+                .into_iter()
+                .filter(|m| self.in_sections(m))
+                .collect()),
+            None => Err(format_err!("Missing {} hub in query", hub_title)),
+        }
+    }
 
-        let meta = match &movie_hub.metadata {
-            Some(t) => t,
-            None => return Ok(false), // No movies
-        };
+    async fn find_movie(
+        &self,
+        lookup: &MediaLookup,
+        exact_match: bool,
+    ) -> anyhow::Result<Option<PlexLibraryMetadata>> {
+        if let Some(metadata) = self.find_by_guid(lookup).await? {
+            return Ok(Some(metadata));
+        }
 
-        for metadata in meta {
-            if !exact_match {
-                if metadata.title.starts_with(title) && metadata.year.eq(&year) {
-                    match metadata.media.first() {
-                        Some(t) => match t.part.first() {
-                            Some(p) => {
-                                if p.file.is_empty().not() {
-                                    return Ok(true);
-                                }
-                            }
-                            None => continue,
-                        },
-                        None => continue,
-                    }
-                } else {
-                    continue;
-                }
-            } else if metadata.title == title && metadata.year.eq(&year) {
-                match metadata.media.first() {
-                    Some(t) => match t.part.first() {
-                        Some(p) => {
-                            if p.file.is_empty().not() {
-                                return Ok(true);
-                            }
-                        }
-                        None => continue,
-                    },
-                    None => continue,
-                }
-            } else {
-                continue;
+        let (_, year) = lookup.title_and_year();
+        let year = year.unwrap_or_default() as u32;
+
+        // Try the alternate titles when the main one isn't in the library
+        for title in lookup.titles() {
+            let movies = self.search_hub(title, "Movies").await?;
+            if let Some(movie) = movies
+                .into_iter()
+                .find(|m| titles_match(&m.title, title, exact_match) && m.year.eq(&year) && m.has_file())
+            {
+                return Ok(Some(movie));
             }
         }
 
-        Ok(false)
+        Ok(None)
     }
 
-    pub async fn tvshow_exists_in_library(
-        &self,
-        search_term: &str,
-    ) -> anyhow::Result<Vec<Episode>> {
-        let year_regexp = Regex::new(r"(\(\d{4}\))").unwrap();
-        let title = match year_regexp.split(&search_term).next() {
-            Some(t) => t.trim(),
-            None => &search_term,
-        };
+    // Agent GUIDs are far more reliable than titles, which differ on punctuation, articles and translations
+    async fn find_by_guid(&self, lookup: &MediaLookup) -> anyhow::Result<Option<PlexLibraryMetadata>> {
+        let guids = [
+            lookup.imdb_id.as_ref().map(|t| format!("imdb://{}", t)),
+            lookup.tmdb_id.as_ref().map(|t| format!("tmdb://{}", t)),
+        ];
 
-        let year = match year_regexp.find(&search_term) {
-            Some(t) => t
-                .as_str()
-                .strip_prefix("(")
-                .unwrap()
-                .strip_suffix(")")
-                .unwrap()
-                .parse::<u32>()
-                .unwrap(),
-            None => 0,
-        };
+        let token = self.token();
+        for guid in guids.into_iter().flatten() {
+            let query = [("guid", guid.as_str()), ("X-Plex-Token", token.as_str())];
+            let resp = self
+                .client
+                .get(format!("{}/library/all", self.base_url))
+                .query(&query)
+                .send()
+                .await?;
+
+            let status = resp.status();
+            if status.is_client_error() || status.is_server_error() {
+                return Err(format_err!("Failed to check library"));
+            }
 
-        let query = [
-            ("query", title),
-            ("X-Plex-Token", self.token.as_str()),
-            ("limit", "100"),
-            ("includeCollections", "1"),
-            ("includeExternalMedia", "1"),
-        ];
+            let data: PlexGuidSearch = match resp.text().await {
+                Ok(t) => serde_json::from_str(&t)?,
+                Err(e) => return Err(e.into()),
+            };
+
+            if let Some(t) = data.media_container.metadata.into_iter().find(|m| self.in_sections(m)) {
+                return Ok(Some(t));
+            }
+        }
+
+        Ok(None)
+    }
+
+    async fn find_section_for_path(&self, path: &str) -> anyhow::Result<Option<String>> {
+        let section = self
+            .fetch_sections()
+            .await?
+            .into_iter()
+            .find(|d| d.location.iter().any(|l| path.starts_with(&l.path)))
+            .map(|d| d.key);
+
+        Ok(section)
+    }
+
+    async fn fetch_sections(&self) -> anyhow::Result<Vec<PlexSection>> {
+        let token = self.token();
+        let query = [("X-Plex-Token", token.as_str())];
         let resp = self
             .client
-            .get("http://127.0.0.1:32400/hubs/search")
+            .get(format!("{}/library/sections", self.base_url))
             .query(&query)
             .send()
             .await?;
 
         let status = resp.status();
         if status.is_client_error() || status.is_server_error() {
-            return Err(format_err!("Failed to check library"));
+            return Err(format_err!("Failed to fetch library sections"));
         }
 
-        let data: PlexLibrarySearch = match resp.text().await {
+        let data: PlexSections = match resp.text().await {
             Ok(t) => serde_json::from_str(&t)?,
             Err(e) => return Err(e.into()),
         };
 
-        let shows_hub = match data
-            .media_container
-            .hub
-            .into_par_iter()
-            .find_any(|h| h.title.as_str() == "Shows")
-        {
-            Some(t) => match t.metadata {
-                Some(t) => t,
-                None => return Ok(vec![]),
-            },
-            None => return Err(format_err!("Missing TV hub in query")),
-        };
+        Ok(data.media_container.directory)
+    }
 
-        for metadata in shows_hub {
-            if metadata.title.starts_with(title) && metadata.year.eq(&year) {
-                let available = self
-                    .fetch_available_tvshow_children(&metadata.rating_key)
-                    .await?;
-                return Ok(available);
-            } else {
-                continue;
-            }
+    async fn fetch_stats_metadata(&self, path: &str, query: &[(&str, &str)]) -> anyhow::Result<Vec<PlexStatsMetadata>> {
+        let token = self.token();
+        let resp = self
+            .client
+            .get(format!("{}{}", self.base_url, path))
+            .query(&[("X-Plex-Token", token.as_str())])
+            .query(query)
+            .send()
+            .await?;
+
+        let status = resp.status();
+        if status.is_client_error() || status.is_server_error() {
+            return Err(format_err!("Failed to fetch library stats"));
         }
 
-        Ok(vec![])
+        let data: PlexStatsSearch = match resp.text().await {
+            Ok(t) => serde_json::from_str(&t)?,
+            Err(e) => return Err(e.into()),
+        };
+
+        Ok(data.media_container.metadata)
     }
 
     async fn fetch_available_tvshow_children(&self, show_id: &str) -> anyhow::Result<Vec<Episode>> {
-        let query = [("X-Plex-Token", self.token.as_str())];
+        let token = self.token();
+        let query = [("X-Plex-Token", token.as_str())];
         let resp = self
             .client
             .get(format!(
-                "http://127.0.0.1:32400/library/metadata/{}/allLeaves",
-                show_id
+                "{}/library/metadata/{}/allLeaves",
+                self.base_url, show_id
             ))
             .query(&query)
             .send()
@@ -289,6 +395,165 @@ impl Plex {
     }
 }
 
+#[async_trait]
+impl MediaServer for Plex {
+    async fn exists_in_library(
+        &self,
+        lookup: &MediaLookup,
+        exact_match: bool,
+    ) -> anyhow::Result<bool> {
+        Ok(self
+            .find_movie(lookup, exact_match)
+            .await?
+            .is_some_and(|m| m.has_file()))
+    }
+
+    async fn list_episodes(
+        &self,
+        lookup: &MediaLookup,
+    ) -> anyhow::Result<Vec<Episode>> {
+        if let Some(metadata) = self.find_by_guid(lookup).await? {
+            return self.fetch_available_tvshow_children(&metadata.rating_key).await;
+        }
+
+        let (_, year) = lookup.title_and_year();
+        let year = year.unwrap_or_default() as u32;
+
+        for title in lookup.titles() {
+            let shows = self.search_hub(title, "Shows").await?;
+            if let Some(metadata) = shows
+                .iter()
+                .find(|m| titles_match(&m.title, title, false) && m.year.eq(&year))
+            {
+                let available = self
+                    .fetch_available_tvshow_children(&metadata.rating_key)
+                    .await?;
+                return Ok(available);
+            }
+        }
+
+        Ok(vec![])
+    }
+
+    async fn library_file(&self, lookup: &MediaLookup) -> anyhow::Result<Option<LibraryFile>> {
+        Ok(self
+            .find_movie(lookup, false)
+            .await?
+            .and_then(|m| m.library_file()))
+    }
+
+    async fn library_stats(&self) -> anyhow::Result<LibraryStats> {
+        let mut stats = LibraryStats::default();
+
+        let sections = self
+            .fetch_sections()
+            .await?
+            .into_iter()
+            .filter(|s| self.sections.is_empty() || self.sections.contains(&s.key));
+        for section in sections {
+            let path = format!("/library/sections/{}/all", section.key);
+            match section._type.as_str() {
+                "movie" => {
+                    let movies = self.fetch_stats_metadata(&path, &[("type", "1")]).await?;
+                    stats.movies += movies.len() as i64;
+                    for movie in movies {
+                        let quality = movie
+                            .media
+                            .iter()
+                            .filter_map(|m| MediaQuality::from_label(m.video_resolution.as_deref()?))
+                            .max()
+                            .unwrap_or(MediaQuality::Unknown);
+                        *stats.movie_qualities.entry(quality.to_string()).or_default() += 1;
+                    }
+                }
+                "show" => {
+                    let shows = self.fetch_stats_metadata(&path, &[("type", "2")]).await?;
+                    stats.shows += shows.len() as i64;
+                    stats.episodes += shows.iter().map(|s| s.leaf_count.unwrap_or_default()).sum::<i64>();
+                }
+                _ => continue,
+            }
+        }
+
+        let limit = RECENTLY_ADDED_LIMIT.to_string();
+        let recent = self
+            .fetch_stats_metadata(
+                "/library/recentlyAdded",
+                &[("X-Plex-Container-Start", "0"), ("X-Plex-Container-Size", limit.as_str())],
+            )
+            .await?;
+        stats.recently_added = recent
+            .into_iter()
+            .filter(|m| self.sections.is_empty() || m.library_section_id.is_some_and(|id| self.sections.contains(&id.to_string())))
+            .map(|m| RecentlyAdded {
+                // Episodes and seasons are shown under the show's title
+                title: m.grandparent_title.or(m.parent_title).unwrap_or(m.title),
+                year: m.year,
+                _type: match m._type.as_str() {
+                    "movie" => ItemType::Movie,
+                    _ => ItemType::TvShow,
+                },
+                added_at: Local.timestamp_opt(m.added_at, 0).single().unwrap_or_else(Local::now),
+            })
+            .collect();
+
+        Ok(stats)
+    }
+
+    async fn refresh_library(&self, path: Option<&str>) -> anyhow::Result<()> {
+        let token = self.token();
+
+        // Partial scan of just the folder when it lives inside one of the library sections
+        let section = match path {
+            Some(p) => self.find_section_for_path(p).await?,
+            None => None,
+        };
+
+        let resp = match (section, path) {
+            (Some(section), Some(path)) => {
+                let query = [("path", path), ("X-Plex-Token", token.as_str())];
+                self.client
+                    .get(format!("{}/library/sections/{}/refresh", self.base_url, section))
+                    .query(&query)
+                    .send()
+                    .await?
+            }
+            _ if self.sections.is_empty().not() => {
+                // Only the sections we were told to look at
+                for section in self.sections.iter() {
+                    let query = [("X-Plex-Token", token.as_str())];
+                    let resp = self
+                        .client
+                        .get(format!("{}/library/sections/{}/refresh", self.base_url, section))
+                        .query(&query)
+                        .send()
+                        .await?;
+                    let status = resp.status();
+                    if status.is_client_error() || status.is_server_error() {
+                        return Err(format_err!("Failed to refresh library section {}", section));
+                    }
+                }
+                return Ok(());
+            }
+            _ => {
+                let query = [("X-Plex-Token", token.as_str())];
+                self.client
+                    .get(format!("{}/library/sections/all/refresh", self.base_url))
+                    .query(&query)
+                    .send()
+                    .await?
+            }
+        };
+
+        let status = resp.status();
+        if status.is_client_error() || status.is_server_error() {
+            return Err(format_err!("Failed to refresh library"));
+        }
+
+        Ok(())
+    }
+}
+
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 struct PlexLibrarySearch {
@@ -319,13 +584,112 @@ struct PlexLibraryMetadata {
     #[serde(default)]
     year: u32,
     rating_key: String,
+    #[serde(rename = "librarySectionID")]
+    library_section_id: Option<i64>,
     #[serde(rename = "Media", default)]
     media: Vec<MetadataMedia>,
 }
 
+impl PlexLibraryMetadata {
+    fn has_file(&self) -> bool {
+        self.media
+            .first()
+            .is_some_and(|x| x.part.first().is_some_and(|x| x.file.is_empty().not()))
+    }
+
+    // Plex can hold several versions of a movie, the best one is what an upgrade has to beat
+    fn library_file(&self) -> Option<LibraryFile> {
+        self.media
+            .iter()
+            .filter_map(|m| {
+                let quality = MediaQuality::from_label(m.video_resolution.as_deref()?)?;
+                let path = m.part.first().filter(|p| p.file.is_empty().not())?.file.clone();
+                Some(LibraryFile { path, quality })
+            })
+            .max_by_key(|f| f.quality)
+    }
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PlexSections {
+    #[serde(rename = "MediaContainer")]
+    media_container: PlexSectionsMediaContainer,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PlexSectionsMediaContainer {
+    #[serde(rename = "Directory", default)]
+    directory: Vec<PlexSection>,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PlexSection {
+    key: String,
+    #[serde(rename = "type", default)]
+    _type: String,
+    #[serde(rename = "Location", default)]
+    location: Vec<PlexSectionLocation>,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PlexSectionLocation {
+    path: String,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PlexStatsSearch {
+    #[serde(rename = "MediaContainer")]
+    media_container: PlexStatsMediaContainer,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PlexStatsMediaContainer {
+    #[serde(rename = "Metadata", default)]
+    metadata: Vec<PlexStatsMetadata>,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PlexStatsMetadata {
+    title: String,
+    #[serde(rename = "type", default)]
+    _type: String,
+    year: Option<i32>,
+    grandparent_title: Option<String>,
+    parent_title: Option<String>,
+    leaf_count: Option<i64>,
+    #[serde(default)]
+    added_at: i64,
+    #[serde(rename = "librarySectionID")]
+    library_section_id: Option<i64>,
+    #[serde(rename = "Media", default)]
+    media: Vec<MetadataMedia>,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PlexGuidSearch {
+    #[serde(rename = "MediaContainer")]
+    media_container: PlexGuidMediaContainer,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PlexGuidMediaContainer {
+    #[serde(rename = "Metadata", default)]
+    metadata: Vec<PlexLibraryMetadata>,
+}
+
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 struct MetadataMedia {
+    video_resolution: Option<String>,
     #[serde(rename = "Part")]
     part: Vec<Part>,
 }
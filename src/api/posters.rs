@@ -0,0 +1,133 @@
+use std::ops::Not;
+use std::path::PathBuf;
+
+use anyhow::format_err;
+use log::warn;
+use reqwest::{Client, ClientBuilder};
+
+use crate::api::coalesce::Coalescer;
+
+// Cards show posters at 200px wide, so the sources are asked for that size rather than the full image
+static POSTER_WIDTH: u32 = 200;
+// Posters over this are not what we asked for, don't fill the cache with them
+static MAX_POSTER_SIZE: usize = 5 * 1024 * 1024;
+
+pub type Poster = (Vec<u8>, &'static str);
+
+// Fetches posters from IMDb/TMDB for /image, caching them on disk when a cache path is set
+pub struct PosterCache {
+    client: Client,
+    path: Option<PathBuf>,
+    requests: Coalescer<String, Result<Poster, String>>,
+}
+
+impl PosterCache {
+    pub fn new(path: &str) -> Self {
+        PosterCache {
+            client: ClientBuilder::new().user_agent("roundup/1.0").build().unwrap(),
+            path: match path.is_empty() {
+                true => None,
+                false => Some(PathBuf::from(path)),
+            },
+            requests: Coalescer::new(),
+        }
+    }
+
+    pub async fn fetch(&self, id: &str, url: &str) -> anyhow::Result<Poster> {
+        let file = self.file_path(id);
+        if let Some(file) = &file {
+            if let Ok(bytes) = tokio::fs::read(file).await {
+                if let Some(content_type) = sniff_content_type(&bytes) {
+                    return Ok((bytes, content_type));
+                }
+            }
+        }
+
+        // A page of cards can ask for the same poster more than once
+        let poster = self
+            .requests
+            .run(id.to_string(), || async { self.download(url).await.map_err(|e| e.to_string()) })
+            .await
+            .map_err(|e| format_err!("Failed to fetch poster for {}, {}", id, e))?;
+
+        if let Some(file) = file {
+            if let Err(e) = self.write(&file, &poster.0).await {
+                warn!("Failed to cache poster {}, {}", file.display(), e);
+            }
+        }
+
+        Ok(poster)
+    }
+
+    async fn download(&self, url: &str) -> anyhow::Result<Poster> {
+        let mut resp = self.client.get(url).send().await?;
+        if resp.status().is_success().not() {
+            return Err(format_err!("{}", resp.status()));
+        }
+        if let Some(length) = resp.content_length().filter(|l| *l > MAX_POSTER_SIZE as u64) {
+            return Err(format_err!("Poster is too large, {} bytes", length));
+        }
+
+        // The length isn't always sent, so stop reading once it's over the limit either way
+        let mut bytes = Vec::new();
+        while let Some(chunk) = resp.chunk().await? {
+            bytes.extend_from_slice(&chunk);
+            if bytes.len() > MAX_POSTER_SIZE {
+                return Err(format_err!("Poster is too large, over {} bytes", MAX_POSTER_SIZE));
+            }
+        }
+        match sniff_content_type(&bytes) {
+            Some(t) => Ok((bytes, t)),
+            None => Err(format_err!("Not an image")),
+        }
+    }
+
+    async fn write(&self, file: &PathBuf, bytes: &[u8]) -> std::io::Result<()> {
+        if let Some(parent) = file.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+        tokio::fs::write(file, bytes).await
+    }
+
+    fn file_path(&self, id: &str) -> Option<PathBuf> {
+        let root = self.path.as_ref()?;
+        // ids come from the URL, anything that isn't a plain id doesn't get near the filesystem
+        match id.is_empty().not() && id.chars().all(|c| c.is_ascii_alphanumeric()) {
+            true => Some(root.join(format!("{}.img", id))),
+            false => None,
+        }
+    }
+}
+
+// IMDb image URLs carry their size, replace whatever was requested with the card size
+pub fn imdb_poster_url(url: &str) -> String {
+    match url.split_once("._V1_") {
+        Some((base, rest)) => {
+            let extension = rest.rsplit_once('.').map(|(_, e)| e).unwrap_or("jpg");
+            format!(
+                "{}._V1_UX{}_CR0,4,{},{}_.{}",
+                base,
+                POSTER_WIDTH,
+                POSTER_WIDTH,
+                POSTER_WIDTH * 3 / 2,
+                extension
+            )
+        }
+        None => url.to_string(),
+    }
+}
+
+pub fn tmdb_poster_url(path: &str) -> String {
+    format!("https://image.tmdb.org/t/p/w{}{}", POSTER_WIDTH, path)
+}
+
+// Checked against the bytes rather than the response header, so the same works for cached files
+fn sniff_content_type(bytes: &[u8]) -> Option<&'static str> {
+    match bytes {
+        [0xFF, 0xD8, 0xFF, ..] => Some("image/jpeg"),
+        [0x89, b'P', b'N', b'G', ..] => Some("image/png"),
+        [b'G', b'I', b'F', b'8', ..] => Some("image/gif"),
+        [b'R', b'I', b'F', b'F', _, _, _, _, b'W', b'E', b'B', b'P', ..] => Some("image/webp"),
+        _ => None,
+    }
+}
\ No newline at end of file
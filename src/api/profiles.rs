@@ -0,0 +1,113 @@
+use std::cmp::Reverse;
+use std::collections::HashSet;
+use std::ops::Not;
+
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::api::torrent::{MediaQuality, TorrentItem};
+
+// Which releases are wanted for an item and which of them is best. Watchlist items use their own
+// profile or the default one, without either the watchlist falls back to grabbing 1080p releases
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct QualityProfile {
+    #[serde(default)]
+    pub id: i32,
+    pub name: String,
+    // Allowed qualities, most wanted first
+    pub qualities: Vec<MediaQuality>,
+    // Once the library has this quality or better there's nothing left to upgrade
+    #[serde(default)]
+    pub cutoff: Option<MediaQuality>,
+    // Megabytes per release, releases without a listed size always pass
+    #[serde(default)]
+    pub min_size_mb: Option<i64>,
+    #[serde(default)]
+    pub max_size_mb: Option<i64>,
+    // Release name terms such as HDR, DV or x265, releases matching more of them rank higher
+    #[serde(default)]
+    pub preferred_terms: Vec<String>,
+    // Release name terms that rule a release out, eg HDR for a TV that can't play it
+    #[serde(default)]
+    pub rejected_terms: Vec<String>,
+    #[serde(default)]
+    pub is_default: bool,
+}
+
+impl QualityProfile {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.name.trim().is_empty() {
+            return Err("Profile needs a name".to_string());
+        }
+        if self.qualities.is_empty() {
+            return Err("Profile needs at least one quality".to_string());
+        }
+        if let Some(cutoff) = self.cutoff {
+            if self.qualities.contains(&cutoff).not() {
+                return Err(format!("Cutoff {} isn't one of the allowed qualities", cutoff));
+            }
+        }
+        if let (Some(min), Some(max)) = (self.min_size_mb, self.max_size_mb) {
+            if min > max {
+                return Err("Minimum size is larger than the maximum size".to_string());
+            }
+        }
+        Ok(())
+    }
+
+    pub fn allows(&self, item: &TorrentItem) -> bool {
+        let size_mb = item.size.map(|s| s / 1_048_576);
+        let too_small = matches!((size_mb, self.min_size_mb), (Some(s), Some(min)) if s < min);
+        let too_large = matches!((size_mb, self.max_size_mb), (Some(s), Some(max)) if s > max);
+
+        self.qualities.contains(&item.quality)
+            && too_small.not()
+            && too_large.not()
+            && self.rejected_terms.iter().any(|t| has_term(&item.name, t)).not()
+    }
+
+    // Nothing needs replacing once a file is at the cutoff, without a cutoff only the top quality is enough
+    pub fn meets_cutoff(&self, quality: MediaQuality) -> bool {
+        match self.cutoff.or_else(|| self.qualities.iter().max().copied()) {
+            Some(cutoff) => quality >= cutoff,
+            None => false,
+        }
+    }
+
+    // Quality order first, then preferred terms, then seeds
+    pub fn sort(&self, items: &mut [TorrentItem]) {
+        items.sort_by_key(|item| {
+            let rank = self.qualities.iter().position(|q| *q == item.quality).unwrap_or(usize::MAX);
+            let terms = self.preferred_terms.iter().filter(|t| has_term(&item.name, t)).count();
+            (rank, Reverse(terms), Reverse(item.seeds.unwrap_or(0)))
+        });
+    }
+
+    // The best allowed release for each episode, or for the movie, season packs count as their own episode
+    pub fn pick(&self, items: Vec<TorrentItem>) -> Vec<TorrentItem> {
+        let mut items = items.into_iter().filter(|i| self.allows(i)).collect::<Vec<TorrentItem>>();
+        self.sort(&mut items);
+
+        let mut seen = HashSet::new();
+        items.retain(|i| seen.insert((i.season, i.episode)));
+        items
+    }
+}
+
+// Whole words only, so "DV" doesn't match "DVD"
+pub fn has_term(name: &str, term: &str) -> bool {
+    let words = split_words(name);
+    let term = split_words(term);
+    if term.is_empty() {
+        return false;
+    }
+    words.windows(term.len()).any(|w| w == term.as_slice())
+}
+
+fn split_words(input: &str) -> Vec<String> {
+    input
+        .split(|c: char| c.is_ascii_alphanumeric().not() && c != '+')
+        .filter(|w| w.is_empty().not())
+        .map(|w| w.to_lowercase())
+        .collect()
+}
@@ -0,0 +1,55 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use serde::Serialize;
+use tokio::sync::broadcast;
+
+static CHANNEL_CAPACITY: usize = 256;
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct ProgressUpdate {
+    pub magnet_hash: String,
+    pub state: String,
+    pub progress: f64,
+    // Bytes per second
+    pub download_speed: i64,
+    // Seconds, None when qBittorrent can't estimate it
+    pub eta: Option<i64>,
+}
+
+// Passes download progress from the torrent watcher to anyone watching the downloads page
+pub struct ProgressFeed {
+    sender: broadcast::Sender<ProgressUpdate>,
+    last_sent: Mutex<HashMap<String, ProgressUpdate>>,
+}
+
+impl ProgressFeed {
+    pub fn new() -> Self {
+        let (sender, _) = broadcast::channel(CHANNEL_CAPACITY);
+
+        ProgressFeed {
+            sender,
+            last_sent: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<ProgressUpdate> {
+        self.sender.subscribe()
+    }
+
+    // Only sends downloads that have changed since they were last sent
+    pub fn publish(&self, update: ProgressUpdate) {
+        let mut last_sent = self.last_sent.lock().unwrap();
+        if last_sent.get(&update.magnet_hash) == Some(&update) {
+            return;
+        }
+
+        // Nobody listening isn't an error
+        let _ = self.sender.send(update.clone());
+        last_sent.insert(update.magnet_hash.clone(), update);
+    }
+
+    pub fn forget(&self, hash: &str) {
+        self.last_sent.lock().unwrap().remove(hash);
+    }
+}
\ No newline at end of file
@@ -0,0 +1,84 @@
+use std::io;
+use std::path::{Path, PathBuf};
+
+use chrono::{Local, NaiveDate};
+use log::{info, warn};
+
+use crate::api::importer::move_file;
+
+static FOLDER_DATE_FORMAT: &str = "%Y-%m-%d";
+
+// Files roundup removes are moved here instead of being deleted, grouped into a folder per day
+pub struct RecycleBin {
+    path: Option<PathBuf>,
+    retention_days: i64,
+}
+
+impl RecycleBin {
+    pub fn new(path: &str, retention_days: i64) -> Self {
+        RecycleBin {
+            path: match path.is_empty() {
+                true => None,
+                false => Some(PathBuf::from(path)),
+            },
+            retention_days,
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.path.is_some()
+    }
+
+    // Deletes the file outright when no recycle bin is configured
+    pub async fn remove_file(&self, file: &Path) -> io::Result<()> {
+        let root = match &self.path {
+            Some(t) => t,
+            None => return tokio::fs::remove_file(file).await,
+        };
+
+        let folder = root.join(Local::now().format(FOLDER_DATE_FORMAT).to_string());
+        tokio::fs::create_dir_all(&folder).await?;
+
+        let name = file.file_name().unwrap_or(file.as_os_str()).to_string_lossy().to_string();
+        let mut target = folder.join(&name);
+        let mut count = 1;
+        while tokio::fs::try_exists(&target).await? {
+            target = folder.join(format!("{}.{}", name, count));
+            count += 1;
+        }
+
+        move_file(file, &target).await?;
+        info!("Recycled {} to {}", file.display(), target.display());
+        Ok(())
+    }
+
+    // Removes the day folders older than the retention period
+    pub async fn cleanup(&self) {
+        let root = match &self.path {
+            Some(t) => t,
+            None => return,
+        };
+        let cutoff = Local::now().date_naive() - chrono::Duration::days(self.retention_days);
+
+        let mut entries = match tokio::fs::read_dir(root).await {
+            Ok(t) => t,
+            Err(e) => {
+                warn!("Failed to read recycle bin {}: {}", root.display(), e);
+                return;
+            }
+        };
+        while let Ok(Some(entry)) = entries.next_entry().await {
+            let name = entry.file_name().to_string_lossy().to_string();
+            let date = match NaiveDate::parse_from_str(&name, FOLDER_DATE_FORMAT) {
+                Ok(t) => t,
+                Err(_) => continue, // Not ours
+            };
+            if date < cutoff {
+                match tokio::fs::remove_dir_all(entry.path()).await {
+                    Ok(_) => info!("Emptied recycle bin folder {}", name),
+                    Err(e) => warn!("Failed to empty recycle bin folder {}: {}", name, e),
+                }
+            }
+        }
+    }
+}
\ No newline at end of file
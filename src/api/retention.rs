@@ -0,0 +1,57 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use log::{info, warn};
+
+use crate::api::shutdown::Shutdown;
+use crate::db::DBConnection;
+use crate::db::retention::RetentionDatabase;
+
+static PRUNE_INTERVAL: Duration = Duration::from_secs(60 * 60 * 24);
+// The statistics page shows the last 30 days of watchlist checks
+static MINIMUM_HISTORY_DAYS: i64 = 30;
+// Finished jobs are only kept for GET /api/v1/jobs, and episode searches add one for every hour they retry
+static JOB_RETENTION_DAYS: i64 = 7;
+
+// Once a day removes download history older than history_days, browser preferences that haven't been changed
+// in preferences_days and finished jobs. A negative number of days keeps everything but the jobs
+pub async fn monitor_retention(db: Arc<DBConnection>, history_days: i64, preferences_days: i64, shutdown: Arc<Shutdown>) {
+    info!("Starting Database Pruning");
+
+    loop {
+        prune(&db, history_days, preferences_days).await;
+
+        if shutdown.sleep(PRUNE_INTERVAL).await {
+            return;
+        }
+    }
+}
+
+async fn prune(db: &DBConnection, history_days: i64, preferences_days: i64) {
+    let retention_db = RetentionDatabase::new(db);
+
+    let mut removed = Vec::new();
+    if history_days >= 0 {
+        match retention_db.prune_history(history_days.max(MINIMUM_HISTORY_DAYS)).await {
+            Ok(t) => removed.extend(t),
+            Err(e) => warn!("Failed to prune download history, {}", e),
+        }
+    }
+    if preferences_days >= 0 {
+        match retention_db.prune_preferences(preferences_days).await {
+            Ok(t) => removed.extend(t),
+            Err(e) => warn!("Failed to prune preferences, {}", e),
+        }
+    }
+    match retention_db.prune_jobs(JOB_RETENTION_DAYS).await {
+        Ok(t) => removed.extend(t),
+        Err(e) => warn!("Failed to prune jobs, {}", e),
+    }
+
+    for (table, count) in removed.into_iter().filter(|(_, count)| *count > 0) {
+        info!("Removed {} old rows from {}", count, table);
+        if let Err(e) = retention_db.vacuum(table).await {
+            warn!("Failed to vacuum {}, {}", table, e);
+        }
+    }
+}
\ No newline at end of file
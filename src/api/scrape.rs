@@ -0,0 +1,103 @@
+use std::ops::Not;
+use std::time::Duration;
+
+use anyhow::format_err;
+use tokio::net::UdpSocket;
+
+// BEP 15 UDP tracker protocol
+const PROTOCOL_ID: u64 = 0x41727101980;
+const ACTION_CONNECT: u32 = 0;
+const ACTION_SCRAPE: u32 = 2;
+const TRACKER_TIMEOUT: Duration = Duration::from_secs(3);
+
+// Asks every UDP tracker in the magnet (plus any configured trackers) for the current seed count.
+// Returns the highest count seen, or None if no tracker answered.
+pub async fn scrape_seeds(magnet_uri: &str, extra_trackers: &[String]) -> Option<u32> {
+    let info_hash = info_hash_from_magnet(magnet_uri)?;
+
+    let mut trackers = magnet_uri
+        .split('&')
+        .filter_map(|p| p.strip_prefix("tr="))
+        .filter_map(|t| urlencoding::decode(t).ok().map(|t| t.to_string()))
+        .collect::<Vec<String>>();
+    trackers.extend(extra_trackers.iter().cloned());
+
+    // The same tracker is often listed with and without its announce path, so duplicates are removed by address
+    let mut addresses = trackers
+        .iter()
+        .filter_map(|t| t.strip_prefix("udp://"))
+        .map(|t| t.split('/').next().unwrap_or(t))
+        .collect::<Vec<&str>>();
+    addresses.sort_unstable();
+    addresses.dedup();
+
+    let requests = addresses
+        .into_iter()
+        .map(|address| tokio::time::timeout(TRACKER_TIMEOUT, scrape_udp_tracker(address, &info_hash)));
+
+    futures::future::join_all(requests)
+        .await
+        .into_iter()
+        .filter_map(|r| r.ok().and_then(|r| r.ok()))
+        .max()
+}
+
+async fn scrape_udp_tracker(address: &str, info_hash: &[u8; 20]) -> anyhow::Result<u32> {
+    let socket = UdpSocket::bind("0.0.0.0:0").await?;
+    socket.connect(address).await?;
+
+    let transaction_id = transaction_id();
+    let mut request = Vec::with_capacity(16);
+    request.extend_from_slice(&PROTOCOL_ID.to_be_bytes());
+    request.extend_from_slice(&ACTION_CONNECT.to_be_bytes());
+    request.extend_from_slice(&transaction_id.to_be_bytes());
+    socket.send(&request).await?;
+
+    let mut buf = [0u8; 64];
+    let len = socket.recv(&mut buf).await?;
+    if len < 16 || read_u32(&buf, 0) != ACTION_CONNECT || read_u32(&buf, 4) != transaction_id {
+        return Err(format_err!("Invalid connect response from {}", address));
+    }
+    let connection_id = &buf[8..16];
+
+    let transaction_id = transaction_id.wrapping_add(1);
+    let mut request = Vec::with_capacity(36);
+    request.extend_from_slice(connection_id);
+    request.extend_from_slice(&ACTION_SCRAPE.to_be_bytes());
+    request.extend_from_slice(&transaction_id.to_be_bytes());
+    request.extend_from_slice(info_hash);
+    socket.send(&request).await?;
+
+    let len = socket.recv(&mut buf).await?;
+    if len < 20 || read_u32(&buf, 0) != ACTION_SCRAPE || read_u32(&buf, 4) != transaction_id {
+        return Err(format_err!("Invalid scrape response from {}", address));
+    }
+
+    // seeders, completed, leechers
+    Ok(read_u32(&buf, 8))
+}
+
+fn info_hash_from_magnet(magnet_uri: &str) -> Option<[u8; 20]> {
+    let hash = magnet_uri
+        .split(['?', '&'])
+        .find_map(|p| p.strip_prefix("xt=urn:btih:"))?;
+
+    // Base32 hashes are rare enough to not bother with
+    if hash.len() != 40 || hash.is_ascii().not() {
+        return None;
+    }
+
+    let mut output = [0u8; 20];
+    for (i, byte) in output.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&hash[i * 2..i * 2 + 2], 16).ok()?;
+    }
+    Some(output)
+}
+
+fn read_u32(buf: &[u8], offset: usize) -> u32 {
+    u32::from_be_bytes([buf[offset], buf[offset + 1], buf[offset + 2], buf[offset + 3]])
+}
+
+fn transaction_id() -> u32 {
+    chrono::Local::now().timestamp_subsec_nanos()
+}
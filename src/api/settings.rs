@@ -0,0 +1,188 @@
+use std::ops::Not;
+use std::path::Path;
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, SystemTime};
+
+use anyhow::format_err;
+use log::{error, info};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::AppConfig;
+use crate::api::shutdown::Shutdown;
+use crate::api::torrent::MediaQuality;
+use crate::db::DBConnection;
+use crate::db::settings::SettingsDatabase;
+
+pub const INDEXERS: [&str; 3] = ["YTS", "EZTV", "TheRARBG"];
+// Runtime settings the config file can set, indexers are only turned off from the settings page
+const FILE_SETTINGS: [&str; 4] = ["minimum_quality", "trackers", "watchlist_recheck_interval_hours", "notification_webhooks"];
+
+// Settings that can be changed at runtime from the settings page, config.json provides the starting values
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct RuntimeSettings {
+    pub minimum_quality: MediaQuality,
+    pub trackers: Vec<String>,
+    pub watchlist_recheck_interval_hours: i64,
+    // Names from INDEXERS that shouldn't be searched
+    pub disabled_indexers: Vec<String>,
+    pub notification_webhooks: Vec<String>,
+}
+
+impl RuntimeSettings {
+    pub fn indexer_enabled(&self, name: &str) -> bool {
+        self.disabled_indexers.iter().any(|i| i.eq_ignore_ascii_case(name)).not()
+    }
+
+    // Stored rows override the defaults key by key, rows that no longer parse are ignored
+    pub async fn load_from_db(defaults: RuntimeSettings, db: &DBConnection) -> anyhow::Result<RuntimeSettings> {
+        let mut settings = serde_json::to_value(&defaults)?;
+        let fields = match settings.as_object_mut() {
+            Some(t) => t,
+            None => return Err(format_err!("Settings aren't an object")),
+        };
+
+        for (key, value) in SettingsDatabase::new(db).fetch_all().await? {
+            if let (Some(field), Ok(value)) = (fields.get_mut(&key), serde_json::from_str(&value)) {
+                *field = value;
+            }
+        }
+
+        Ok(serde_json::from_value(settings).unwrap_or(defaults))
+    }
+
+    pub async fn save_to_db(&self, db: &DBConnection) -> anyhow::Result<()> {
+        let rows = match serde_json::to_value(self)? {
+            serde_json::Value::Object(t) => t
+                .into_iter()
+                .map(|(key, value)| (key, value.to_string()))
+                .collect::<Vec<(String, String)>>(),
+            _ => return Err(format_err!("Settings aren't an object")),
+        };
+
+        SettingsDatabase::new(db).insert_or_update_many(&rows).await?;
+        Ok(())
+    }
+}
+
+// Readers get a snapshot, saving swaps in a whole new set so background tasks never see half an update
+pub struct SharedSettings {
+    current: RwLock<Arc<RuntimeSettings>>,
+}
+
+impl SharedSettings {
+    pub fn new(settings: RuntimeSettings) -> Self {
+        SharedSettings {
+            current: RwLock::new(Arc::new(settings)),
+        }
+    }
+
+    pub fn load(&self) -> Arc<RuntimeSettings> {
+        Arc::clone(&self.current.read().unwrap())
+    }
+
+    pub fn store(&self, settings: RuntimeSettings) {
+        *self.current.write().unwrap() = Arc::new(settings);
+    }
+
+    // Re-reads the config file, only the runtime settings change, everything else still needs a restart.
+    // Runtime settings written in the file replace the saved ones and are saved in their place, the rest keep their
+    // saved values. Returns the settings that were taken from the file
+    pub async fn reload(&self, db: &DBConnection) -> anyhow::Result<Vec<String>> {
+        let config = AppConfig::load()?;
+        let file_keys = match crate::config_path() {
+            Some(t) => crate::config_file_keys(&t)?,
+            None => Default::default(),
+        };
+
+        let saved = RuntimeSettings::load_from_db(config.runtime_settings(), db).await?;
+        let mut settings = serde_json::to_value(&saved)?;
+        let from_file = serde_json::to_value(config.runtime_settings())?;
+        let mut changed = Vec::new();
+        for key in FILE_SETTINGS.iter().filter(|k| file_keys.contains(**k)) {
+            if let (Some(field), Some(value)) = (settings.get_mut(*key), from_file.get(*key)) {
+                if field != value {
+                    *field = value.clone();
+                    changed.push(key.to_string());
+                }
+            }
+        }
+        if changed.is_empty() {
+            return Ok(changed);
+        }
+
+        let settings: RuntimeSettings = serde_json::from_value(settings)?;
+        if config.read_only.not() {
+            settings.save_to_db(db).await?;
+        }
+        self.store(settings);
+        Ok(changed)
+    }
+}
+
+// Settings in config.json from before they were kept in the database are copied in the first time the settings table
+// is empty. The original file is renamed to config.json.bak and config.json is written again without them, so it's
+// clear they're now changed from the settings page. Returns whether anything was imported
+pub async fn import_config_file(config: &AppConfig, db: &DBConnection) -> anyhow::Result<bool> {
+    let path = match crate::config_path() {
+        Some(t) if t.extension().is_some_and(|e| e.eq_ignore_ascii_case("json")) => t,
+        _ => return Ok(false),
+    };
+    if config.read_only || SettingsDatabase::new(db).fetch_all().await?.is_empty().not() {
+        return Ok(false);
+    }
+
+    let mut file = match serde_json::from_str::<serde_json::Value>(&std::fs::read_to_string(&path)?)? {
+        serde_json::Value::Object(t) => t,
+        _ => return Err(format_err!("{} isn't a JSON object", path.display())),
+    };
+    let settings = config.runtime_settings();
+    if let serde_json::Value::Object(keys) = serde_json::to_value(&settings)? {
+        file.retain(|key, _| keys.contains_key(key).not());
+    }
+    settings.save_to_db(db).await?;
+
+    let backup = path.with_extension("json.bak");
+    std::fs::rename(&path, &backup)?;
+    if let Err(e) = std::fs::write(&path, serde_json::to_string_pretty(&file)?) {
+        // Put the original back rather than start without a config file
+        std::fs::rename(&backup, &path)?;
+        return Err(e.into());
+    }
+
+    Ok(true)
+}
+
+static CONFIG_CHECK_INTERVAL: Duration = Duration::from_secs(10);
+
+// Reloads the settings whenever the config file is saved
+pub async fn monitor_config(settings: Arc<SharedSettings>, db: Arc<DBConnection>, shutdown: Arc<Shutdown>) {
+    let path = match crate::config_path() {
+        Some(t) => t,
+        None => return, // Running from environment variables only
+    };
+    let mut last_modified = modified_at(&path);
+
+    loop {
+        if shutdown.sleep(CONFIG_CHECK_INTERVAL).await {
+            return;
+        }
+
+        let modified = modified_at(&path);
+        if modified == last_modified {
+            continue;
+        }
+        last_modified = modified;
+
+        info!("{} changed, reloading settings", path.display());
+        match settings.reload(&db).await {
+            Ok(t) if t.is_empty() => info!("No settings changed, other changes apply after a restart"),
+            Ok(t) => info!("Reloaded {}, other changes apply after a restart", t.join(", ")),
+            Err(e) => error!("Failed to reload settings, keeping the current ones: {}", e),
+        }
+    }
+}
+
+fn modified_at(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|m| m.modified()).ok()
+}
\ No newline at end of file
@@ -0,0 +1,75 @@
+use std::time::Duration;
+
+use actix_web::dev::ServerHandle;
+use log::{error, info};
+use tokio::sync::watch;
+
+// Seconds the HTTP server waits for open connections to finish before closing them
+pub const SHUTDOWN_TIMEOUT: u64 = 30;
+
+// Tells the background tasks to stop once they've finished what they're doing, so a restart
+// doesn't cut off a database write or a half finished import
+pub struct Shutdown {
+    sender: watch::Sender<bool>,
+}
+
+impl Shutdown {
+    pub fn new() -> Self {
+        let (sender, _) = watch::channel(false);
+
+        Self { sender }
+    }
+
+    pub fn trigger(&self) {
+        self.sender.send_replace(true);
+    }
+
+    pub fn is_triggered(&self) -> bool {
+        *self.sender.borrow()
+    }
+
+    pub async fn wait(&self) {
+        let mut receiver = self.sender.subscribe();
+        let _ = receiver.wait_for(|t| *t).await;
+    }
+
+    // Returns true if woken up early by a shutdown
+    pub async fn sleep(&self, delay: Duration) -> bool {
+        tokio::select! {
+            _ = tokio::time::sleep(delay) => false,
+            _ = self.wait() => true,
+        }
+    }
+}
+
+// Waits for ctrl+c or SIGTERM (docker stop, systemd), then stops the background tasks and lets the
+// HTTP server drain its connections
+pub async fn listen(shutdown: &Shutdown, server: ServerHandle) {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+
+        let mut terminate = match signal(SignalKind::terminate()) {
+            Ok(t) => t,
+            Err(e) => {
+                error!("Failed to listen for SIGTERM, {}", e);
+                return;
+            }
+        };
+        tokio::select! {
+            _ = tokio::signal::ctrl_c() => (),
+            _ = terminate.recv() => (),
+        }
+    }
+    #[cfg(not(unix))]
+    {
+        if let Err(e) = tokio::signal::ctrl_c().await {
+            error!("Failed to listen for ctrl+c, {}", e);
+            return;
+        }
+    }
+
+    info!("Shutting down, waiting for running tasks to finish");
+    shutdown.trigger();
+    server.stop(true).await;
+}
\ No newline at end of file
@@ -294,6 +294,9 @@ impl TheRARBG {
                             season: item.season,
                             episode: item.episode,
                             seeds: Some(item.seeds),
+                            verified: false,
+                            source: String::new(),
+                            size: None,
                         })
                     }
                     _ => break,
@@ -336,7 +339,7 @@ impl TorrentSearch for TheRARBG {
             return Err(format_err!("No torrents available"));
         }
 
-        let imdb_id = imdb_id.unwrap();
+        let imdb_id = imdb_id.unwrap_or_default();
         // TODO: See if we can add Rayon into_par_iter() here.
         let tasks = outputs
             .into_iter()
@@ -415,6 +418,10 @@ impl TorrentSearch for TheRARBG {
         }
         Ok(torrents)
     }
+
+    fn source(&self) -> &'static str {
+        "TheRARBG"
+    }
 }
 
 struct TheRARBGItem {
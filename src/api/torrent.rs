@@ -1,16 +1,23 @@
 use std::fmt;
 use std::fmt::Formatter;
 use std::ops::Not;
+use std::sync::Arc;
 
 use anyhow::format_err;
 use async_trait::async_trait;
-use log::warn;
+use log::{info, warn};
 use qbittorrent::queries::TorrentDownload;
+use qbittorrent::traits::TorrentData;
 use serde::{Deserialize, Serialize};
 use tokio::sync::mpsc::UnboundedSender;
+use utoipa::ToSchema;
 use rayon::prelude::*;
 
+use crate::api::coalesce::Coalescer;
+use crate::api::settings::SharedSettings;
+use crate::api::trackers::TrackerList;
 use crate::api::imdb::{IMDBEpisode, ItemType};
+use crate::api::media_server::normalise_title;
 
 #[async_trait]
 pub trait TorrentSearch: Send {
@@ -20,9 +27,12 @@ pub trait TorrentSearch: Send {
         imdb_id: Option<String>,
         tv_episodes: Option<Vec<IMDBEpisode>>,
     ) -> anyhow::Result<Vec<TorrentItem>>;
+
+    // Indexer name shown in the download history
+    fn source(&self) -> &'static str;
 }
 
-#[derive(Debug, Eq, PartialEq, Serialize, Deserialize, Ord, PartialOrd, Copy, Clone)]
+#[derive(Debug, Eq, PartialEq, Serialize, Deserialize, Ord, PartialOrd, Copy, Clone, ToSchema)]
 pub enum MediaQuality {
     #[serde(alias = "unknown")]
     Unknown,
@@ -44,7 +54,7 @@ pub enum MediaQuality {
     _4320p, // 8K
 }
 
-#[derive(Serialize, Debug)]
+#[derive(Serialize, Debug, Clone, ToSchema)]
 pub struct TorrentItem {
     pub imdb_id: String,
     pub name: String,
@@ -57,6 +67,14 @@ pub struct TorrentItem {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub episode: Option<i32>,
     pub seeds: Option<u32>,
+    // seeds has been replaced with a live count from the trackers
+    #[serde(skip_serializing)]
+    pub verified: bool,
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub source: String,
+    // Bytes, not every indexer lists it
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub size: Option<i64>,
 }
 
 impl TorrentItem {
@@ -79,50 +97,113 @@ impl TorrentItem {
             season,
             episode,
             seeds,
+            verified: false,
+            source: String::new(),
+            size: None,
         }
     }
+
+    pub fn magnet_hash(&self) -> String {
+        self.magnet_uri
+            .split_at(20)
+            .1
+            .split_once('&')
+            .unwrap()
+            .0
+            .to_lowercase()
+    }
 }
 
 pub struct Torrenter {
-    client: qbittorrent::Api,
+    client: Option<qbittorrent::Api>, // None when running as a mock client
     mpsc: UnboundedSender<String>,
-    min_quality: MediaQuality,
-    trackers: Vec<String>
+    // Minimum quality, trackers and enabled indexers can change while running
+    settings: Arc<SharedSettings>,
+    // Public trackers added to the configured ones
+    tracker_list: Arc<TrackerList>,
+    // Identical searches running at the same time share one set of indexer requests
+    searches: Coalescer<String, Result<Vec<TorrentItem>, String>>,
 }
 impl Torrenter {
     pub async fn new(
         username: &str,
         password: &str,
         address: &str,
-        min_quality: MediaQuality,
         mpsc_sender: UnboundedSender<String>,
-        trackers: Vec<String>
+        settings: Arc<SharedSettings>,
+        tracker_list: Arc<TrackerList>,
     ) -> Self {
         let client = qbittorrent::Api::new(username, password, address)
             .await
             .unwrap();
 
         Self {
-            client,
-            min_quality,
+            client: Some(client),
             mpsc: mpsc_sender,
-            trackers
+            settings,
+            tracker_list,
+            searches: Coalescer::new(),
         }
     }
 
+    // Searches as normal but never connects to qBittorrent, used for read-only mode
+    pub fn mock(
+        mpsc_sender: UnboundedSender<String>,
+        settings: Arc<SharedSettings>,
+        tracker_list: Arc<TrackerList>,
+    ) -> Self {
+        Self {
+            client: None,
+            mpsc: mpsc_sender,
+            settings,
+            tracker_list,
+            searches: Coalescer::new(),
+        }
+    }
+
+    // Configured trackers plus the public tracker list
+    pub fn trackers(&self) -> Vec<String> {
+        self.tracker_list.merge(&self.settings.load().trackers)
+    }
+
     pub async fn find_torrent(
         &self,
         search_term: String,
         imdb_id: Option<String>,
         tv_episodes: Option<Vec<IMDBEpisode>>,
     ) -> anyhow::Result<Vec<TorrentItem>> {
+        let episodes = tv_episodes
+            .iter()
+            .flatten()
+            .map(|e| format!("{}x{}", e.season, e.episode))
+            .collect::<Vec<String>>()
+            .join(",");
+        let key = format!("{}|{}|{}", search_term, imdb_id.as_deref().unwrap_or_default(), episodes);
+
+        self.searches
+            .run(key, || async {
+                self.search_sites(search_term, imdb_id, tv_episodes)
+                    .await
+                    .map_err(|e| e.to_string())
+            })
+            .await
+            .map_err(|e| format_err!(e))
+    }
+
+    async fn search_sites(
+        &self,
+        search_term: String,
+        imdb_id: Option<String>,
+        tv_episodes: Option<Vec<IMDBEpisode>>,
+    ) -> anyhow::Result<Vec<TorrentItem>> {
+        let settings = self.settings.load();
         let ordering: Vec<Box<dyn TorrentSearch>> = vec![
-            crate::api::yts::YTS::new(&self.trackers),  // Movie
-            crate::api::eztv::EZTV::new(),              // TV
-            crate::api::therarbg::TheRARBG::new(),      // Any
+            crate::api::yts::YTS::new(&self.trackers()),    // Movie
+            crate::api::eztv::EZTV::new(),                  // TV
+            crate::api::therarbg::TheRARBG::new(),          // Any
         ];
 
-        for site in ordering {
+        for site in ordering.into_iter().filter(|s| settings.indexer_enabled(s.source())) {
             match site
                 .search(search_term.clone(), imdb_id.clone(), tv_episodes.clone())
                 .await
@@ -131,9 +212,14 @@ impl Torrenter {
                     if r.is_empty() {
                         continue;
                     } else {
+                        let source = site.source();
                         let filtered = r
                             .into_par_iter()
-                            .filter(|item| (item.quality as u8) >= (self.min_quality as u8))
+                            .filter(|item| (item.quality as u8) >= (settings.minimum_quality as u8))
+                            .map(|mut item| {
+                                item.source = source.to_string();
+                                item
+                            })
                             .collect::<Vec<TorrentItem>>();
                         if filtered.is_empty().not() {
                             return Ok(filtered);
@@ -152,6 +238,62 @@ impl Torrenter {
         Err(format_err!("No torrents found matching criteria"))
     }
 
+    // Searches by name for each alternate title, for releases that aren't tagged with the IMDb ID
+    pub async fn find_torrent_by_aliases(
+        &self,
+        aliases: &[String],
+        imdb_id: String,
+        tv_episodes: Option<Vec<IMDBEpisode>>,
+    ) -> anyhow::Result<Vec<TorrentItem>> {
+        let settings = self.settings.load();
+        let site = crate::api::therarbg::TheRARBG::new();
+        if settings.indexer_enabled(site.source()).not() {
+            return Err(format_err!("No torrents found matching criteria"));
+        }
+
+        for alias in aliases {
+            let mut items = match site.search(alias.clone(), None, tv_episodes.clone()).await {
+                Ok(t) => t,
+                Err(e) => {
+                    warn!("{}", e);
+                    continue;
+                }
+            };
+
+            items.retain(|item| {
+                (item.quality as u8) >= (settings.minimum_quality as u8)
+                    && release_name_matches(&item.name, alias)
+            });
+            if items.is_empty() {
+                continue;
+            }
+
+            items.iter_mut().for_each(|item| {
+                item.imdb_id = imdb_id.clone();
+                item.source = site.source().to_string();
+            });
+            return Ok(items);
+        }
+
+        Err(format_err!("No torrents found matching criteria"))
+    }
+
+    // Indexer seed counts are often stale, so check with the trackers directly
+    pub async fn verify_health(&self, items: &mut [TorrentItem]) {
+        let trackers = self.trackers();
+        let scrapes = items
+            .iter()
+            .map(|i| crate::api::scrape::scrape_seeds(&i.magnet_uri, &trackers));
+        let results = futures::future::join_all(scrapes).await;
+
+        for (item, seeds) in items.iter_mut().zip(results) {
+            if let Some(seeds) = seeds {
+                item.seeds = Some(seeds);
+                item.verified = true;
+            }
+        }
+    }
+
     pub async fn start_download(&self, item: TorrentItem) -> anyhow::Result<()> {
         let hash = item
             .magnet_uri
@@ -161,14 +303,84 @@ impl Torrenter {
             .unwrap()
             .0
             .to_lowercase();
+        let client = match &self.client {
+            Some(t) => t,
+            None => {
+                info!("Mock client, skipping download: {}", hash);
+                return Ok(());
+            }
+        };
         self.mpsc.send(hash)?;
 
-        let torrent = TorrentDownload::new(Some(item.magnet_uri), None);
-        self.client.add_new_torrent(&torrent).await?;
+        // Some indexers return magnets without trackers, which can take a long time to find peers
+        let magnet_uri = add_trackers(&item.magnet_uri, &self.trackers());
+        let torrent = TorrentDownload::new(Some(magnet_uri), None);
+        client.add_new_torrent(&torrent).await?;
+        Ok(())
+    }
+
+    // Removes a torrent from qBittorrent, a torrent that's already gone isn't an error
+    pub async fn remove_download(&self, hash: &str, delete_files: bool) -> anyhow::Result<()> {
+        let client = match &self.client {
+            Some(t) => t,
+            None => {
+                info!("Mock client, skipping removal: {}", hash);
+                return Ok(());
+            }
+        };
+
+        let torrents = client.get_torrent_list().await?;
+        if let Some(torrent) = torrents.iter().find(|t| t.hash().as_str().eq_ignore_ascii_case(hash)) {
+            client.delete_torrents(vec![torrent.hash()], delete_files).await?;
+        }
         Ok(())
     }
 }
 
+impl MediaQuality {
+    // Classified by width as well so widescreen films (1920x800) aren't mistaken for 720p
+    pub fn from_resolution(width: u32, height: u32) -> MediaQuality {
+        match (width, height) {
+            (w, h) if w >= 7680 || h >= 4320 => MediaQuality::_4320p,
+            (w, h) if w >= 3840 || h >= 2160 => MediaQuality::_2160p,
+            (w, h) if w >= 1920 || h >= 1080 => MediaQuality::_1080p,
+            (w, h) if w >= 1280 || h >= 720 => MediaQuality::_720p,
+            (w, h) if w >= 640 || h >= 480 => MediaQuality::_480p,
+            _ => MediaQuality::Unknown,
+        }
+    }
+
+    // Labels found in file names, eg "1080p", or Plex's videoResolution, eg "1080" or "4k"
+    pub fn from_label(label: &str) -> Option<MediaQuality> {
+        match label.to_lowercase().trim_end_matches('p') {
+            "sd" | "480" | "576" => Some(MediaQuality::_480p),
+            "720" => Some(MediaQuality::_720p),
+            "1080" => Some(MediaQuality::_1080p),
+            "2160" | "4k" => Some(MediaQuality::_2160p),
+            "4320" | "8k" => Some(MediaQuality::_4320p),
+            _ => None,
+        }
+    }
+
+    // Displayed names as well as labels, eg "Telesync" or "2160p"
+    pub fn from_name(name: &str) -> Option<MediaQuality> {
+        match name.trim().to_lowercase().as_str() {
+            "unknown" => Some(MediaQuality::Unknown),
+            "cam" => Some(MediaQuality::Cam),
+            "telesync" | "ts" => Some(MediaQuality::Telesync),
+            "better than 1080p" => Some(MediaQuality::BetterThan1080p),
+            t => MediaQuality::from_label(t),
+        }
+    }
+
+    // Best guess from a release name, eg "The.Matrix.1999.1080p.BluRay"
+    pub fn from_release_name(name: &str) -> MediaQuality {
+        name.split(|c: char| c.is_ascii_alphanumeric().not())
+            .find_map(MediaQuality::from_label)
+            .unwrap_or(MediaQuality::Unknown)
+    }
+}
+
 impl fmt::Display for MediaQuality {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
         match self {
@@ -184,3 +396,98 @@ impl fmt::Display for MediaQuality {
         }
     }
 }
+
+// Release names look like "Title.Year.Quality...", so the normalised name should start with the title
+pub fn release_name_matches(release_name: &str, title: &str) -> bool {
+    let title = normalise_title(title);
+    let release_name = normalise_title(&release_name.replace(['.', '_'], " "));
+    title.is_empty().not() && release_name.starts_with(&title)
+}
+
+// Puts the info hash first as lowercase hex, which is what magnet_hash() and qBittorrent use.
+// Returns the magnet and its display name, or the hash when it doesn't have one.
+pub fn normalise_magnet(magnet_uri: &str) -> anyhow::Result<(String, String)> {
+    let params = match magnet_uri.trim().strip_prefix("magnet:?") {
+        Some(t) => t,
+        None => return Err(format_err!("Not a magnet URI")),
+    };
+
+    let mut hash = None;
+    let mut name = None;
+    let mut rest = Vec::new();
+    for param in params.split('&').filter(|p| p.is_empty().not()) {
+        match param.strip_prefix("xt=urn:btih:") {
+            Some(t) => hash = Some(t),
+            None => {
+                if let Some(t) = param.strip_prefix("dn=") {
+                    name = urlencoding::decode(&t.replace('+', " ")).ok().map(|t| t.to_string());
+                }
+                rest.push(param.to_string());
+            }
+        }
+    }
+
+    let hash = match hash {
+        Some(t) if t.len() == 40 && t.chars().all(|c| c.is_ascii_hexdigit()) => t.to_lowercase(),
+        Some(t) if t.len() == 32 => base32_to_hex(t)?,
+        Some(t) => return Err(format_err!("Invalid info hash: {}", t)),
+        None => return Err(format_err!("Missing BitTorrent info hash")),
+    };
+    if rest.is_empty() {
+        rest.push(format!("dn={}", hash));
+    }
+
+    let name = name.filter(|t| t.trim().is_empty().not()).unwrap_or(hash.clone());
+    Ok((format!("magnet:?xt=urn:btih:{}&{}", hash, rest.join("&")), name))
+}
+
+// Keeps the magnet's own trackers, minus repeats and empty ones, then appends the trackers it's missing
+pub fn add_trackers(magnet_uri: &str, trackers: &[String]) -> String {
+    let (prefix, params) = match magnet_uri.split_once('?') {
+        Some(t) => t,
+        None => return magnet_uri.to_string(),
+    };
+
+    let mut rest = Vec::new();
+    let mut existing: Vec<String> = Vec::new();
+    for param in params.split('&').filter(|p| p.is_empty().not()) {
+        match param.strip_prefix("tr=") {
+            Some(t) => {
+                let tracker = urlencoding::decode(t).map(|t| t.to_string()).unwrap_or(t.to_string());
+                if tracker.is_empty().not() && existing.contains(&tracker).not() {
+                    existing.push(tracker);
+                }
+            }
+            None => rest.push(param.to_string()),
+        }
+    }
+    for tracker in trackers {
+        if existing.contains(tracker).not() {
+            existing.push(tracker.clone());
+        }
+    }
+
+    rest.extend(existing.iter().map(|t| format!("tr={}", urlencoding::encode(t))));
+    format!("{}?{}", prefix, rest.join("&"))
+}
+
+fn base32_to_hex(input: &str) -> anyhow::Result<String> {
+    let mut output = String::with_capacity(40);
+    let mut bits: u32 = 0;
+    let mut bit_count = 0;
+    for c in input.to_ascii_uppercase().chars() {
+        let value = match c {
+            'A'..='Z' => c as u32 - 'A' as u32,
+            '2'..='7' => c as u32 - '2' as u32 + 26,
+            _ => return Err(format_err!("Invalid info hash: {}", input)),
+        };
+        bits = ((bits << 5) | value) & 0xffff;
+        bit_count += 5;
+        if bit_count >= 8 {
+            bit_count -= 8;
+            output.push_str(&format!("{:02x}", (bits >> bit_count) & 0xff));
+        }
+    }
+
+    Ok(output)
+}
\ No newline at end of file
@@ -0,0 +1,104 @@
+use std::ops::Not;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+use anyhow::format_err;
+use log::{info, warn};
+use reqwest::{Client, ClientBuilder};
+
+use crate::TRACKER_SCHEMES;
+use crate::api::shutdown::Shutdown;
+
+// Last fetched list, so a restart has trackers before the first fetch finishes or when the list can't be reached
+static CACHE_FILE: &str = "tracker_list.txt";
+
+// A public tracker list, eg ngosang/trackerslist, fetched on a schedule and added to the configured trackers
+pub struct TrackerList {
+    client: Client,
+    url: String,
+    trackers: RwLock<Arc<Vec<String>>>,
+}
+
+impl TrackerList {
+    pub fn new(url: &str) -> Self {
+        let cached = match url.is_empty() {
+            true => Vec::new(),
+            false => std::fs::read_to_string(CACHE_FILE)
+                .map(|t| parse_tracker_list(&t))
+                .unwrap_or_default(),
+        };
+
+        TrackerList {
+            client: ClientBuilder::new().user_agent("roundup/1.0").build().unwrap(),
+            url: url.to_string(),
+            trackers: RwLock::new(Arc::new(cached)),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.url.is_empty().not()
+    }
+
+    // The configured trackers first, then the public ones that aren't already in there
+    pub fn merge(&self, configured: &[String]) -> Vec<String> {
+        let public = Arc::clone(&self.trackers.read().unwrap());
+        let mut trackers = configured.to_vec();
+        for tracker in public.iter() {
+            if trackers.contains(tracker).not() {
+                trackers.push(tracker.clone());
+            }
+        }
+        trackers
+    }
+
+    async fn refresh(&self) -> anyhow::Result<usize> {
+        let response = self.client.get(&self.url).send().await?;
+        if response.status().is_success().not() {
+            return Err(format_err!("{} returned {}", self.url, response.status()));
+        }
+
+        let body = response.text().await?;
+        let trackers = parse_tracker_list(&body);
+        if trackers.is_empty() {
+            return Err(format_err!("{} didn't list any trackers", self.url));
+        }
+
+        if let Err(e) = tokio::fs::write(CACHE_FILE, trackers.join("\n")).await {
+            warn!("Failed to cache tracker list, {}", e);
+        }
+        let count = trackers.len();
+        *self.trackers.write().unwrap() = Arc::new(trackers);
+        Ok(count)
+    }
+}
+
+pub async fn monitor_tracker_list(list: Arc<TrackerList>, interval_hours: i64, shutdown: Arc<Shutdown>) {
+    if list.is_enabled().not() {
+        return;
+    }
+    info!("Starting Tracker List Updates");
+
+    let interval = Duration::from_secs(60 * 60 * interval_hours.max(1) as u64);
+    loop {
+        match list.refresh().await {
+            Ok(t) => info!("Updated tracker list, {} trackers", t),
+            Err(e) => warn!("Failed to update tracker list, {}", e),
+        }
+
+        if shutdown.sleep(interval).await {
+            return;
+        }
+    }
+}
+
+// One announce URL per line, blank lines separate them in the ngosang lists
+fn parse_tracker_list(body: &str) -> Vec<String> {
+    let mut trackers = Vec::new();
+    for line in body.lines().map(str::trim) {
+        let valid = TRACKER_SCHEMES.iter().any(|s| line.starts_with(s));
+        if valid && trackers.iter().any(|t| t == line).not() {
+            trackers.push(line.to_string());
+        }
+    }
+    trackers
+}
@@ -0,0 +1,108 @@
+use std::ops::Not;
+use std::path::PathBuf;
+use std::process::Command;
+
+use anyhow::format_err;
+
+use crate::api::torrent::MediaQuality;
+
+static SUBTITLE_TYPES: [&str; 6] = ["srt", "sub", "idx", "ass", "ssa", "vtt"];
+// Anything shorter is a sample or a fake
+static MINIMUM_DURATION: f64 = 60.0;
+
+// What a finished download should look like
+pub struct Expectation {
+    pub quality: Option<MediaQuality>,
+    // Seconds, only known for movies
+    pub runtime: Option<i64>,
+}
+
+pub fn ffprobe_available() -> bool {
+    Command::new("ffprobe").arg("-version").output().is_ok()
+}
+
+pub fn is_video_file(name: &str, valid_file_types: &[String]) -> bool {
+    let name = name.to_ascii_lowercase();
+    valid_file_types.iter().any(|t| name.ends_with(t.as_str())) && is_subtitle_file(&name).not()
+}
+
+// Samples are always under a minute and often a lower resolution, so they're left out of the checks whatever the file
+// filter skips. Whole words only, eg "Sample/movie.sample.mkv" but not "samples.of.life.mkv"
+pub fn is_sample_file(name: &str) -> bool {
+    name.split(|c: char| c.is_ascii_alphanumeric().not()).any(|w| w.eq_ignore_ascii_case("sample"))
+}
+
+pub fn is_subtitle_file(name: &str) -> bool {
+    let name = name.to_ascii_lowercase();
+    SUBTITLE_TYPES.iter().any(|t| name.ends_with(t))
+}
+
+// Blocking, run with spawn_blocking
+pub fn validate_files(files: &[PathBuf], expected: &Expectation) -> anyhow::Result<()> {
+    if files.is_empty() {
+        return Err(format_err!("No video files found"));
+    }
+
+    for file in files {
+        let (quality, duration) = probe_video(file)?;
+
+        if let Some(expected_quality) = expected.quality {
+            // Only checked for resolutions, cam and telesync releases can be anything
+            if expected_quality >= MediaQuality::_480p && quality < expected_quality {
+                return Err(format_err!(
+                    "{} is {}, expected {}",
+                    file.display(),
+                    quality,
+                    expected_quality
+                ));
+            }
+        }
+
+        if duration < MINIMUM_DURATION {
+            return Err(format_err!("{} is only {} seconds long", file.display(), duration as i64));
+        }
+        if let Some(runtime) = expected.runtime {
+            if runtime > 0 && duration < (runtime as f64) / 2.0 {
+                return Err(format_err!(
+                    "{} is {} minutes long, expected around {}",
+                    file.display(),
+                    duration as i64 / 60,
+                    runtime / 60
+                ));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn probe_video(file: &PathBuf) -> anyhow::Result<(MediaQuality, f64)> {
+    let output = Command::new("ffprobe")
+        .args([
+            "-v", "error",
+            "-select_streams", "v:0",
+            "-show_entries", "stream=width,height:format=duration",
+            "-of", "default=noprint_wrappers=1",
+        ])
+        .arg(file)
+        .output()?;
+    if output.status.success().not() {
+        return Err(format_err!("{} is not a valid video file", file.display()));
+    }
+
+    // "width=1920\nheight=1080\nduration=5400.000000"
+    let text = String::from_utf8_lossy(&output.stdout);
+    let value = |key: &str| {
+        text.lines()
+            .find_map(|l| l.strip_prefix(key).and_then(|v| v.strip_prefix('=')))
+            .and_then(|v| v.trim().parse::<f64>().ok())
+    };
+
+    let (width, height) = match (value("width"), value("height")) {
+        (Some(w), Some(h)) => (w as u32, h as u32),
+        _ => return Err(format_err!("{} has no video stream", file.display())),
+    };
+    let duration = value("duration").unwrap_or_default();
+
+    Ok((MediaQuality::from_resolution(width, height), duration))
+}
\ No newline at end of file
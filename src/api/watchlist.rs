@@ -1,60 +1,218 @@
-use std::ops::Deref;
+use std::cmp::Reverse;
+use std::collections::{HashMap, HashSet};
+use std::ops::{Deref, Not};
 use std::sync::Arc;
 use std::time::Duration;
 
 use actix_web::web::Data;
 use anyhow::format_err;
-use chrono::Datelike;
+use chrono::{Datelike, Local};
 use log::{info, warn};
 use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use tokio::sync::{mpsc, Mutex};
 use tokio::time::Instant;
 
+use crate::api::disk::DiskMonitor;
 use crate::api::imdb::{IMDBEpisode, IMDBItem, ItemType};
-use crate::api::moviedb::MovieDBItem;
-use crate::api::plex::Plex;
+use crate::api::jobs::{Job, JobQueue};
+use crate::api::moviedb::{MovieDB, MovieDBItem};
+use crate::api::media_server::{MediaLookup, MediaServer};
+use crate::api::notify::{DownloadEvent, Notifier};
+use crate::api::profiles::has_term;
+use crate::api::settings::SharedSettings;
+use crate::api::shutdown::Shutdown;
 use crate::api::torrent::{MediaQuality, Torrenter, TorrentItem};
 use crate::AppConfig;
 use crate::db::DBConnection;
-use crate::db::downloads::DownloadDatabase;
+use crate::db::attempts::{AttemptDatabase, AttemptOutcome};
+use crate::db::blocklist::BlocklistDatabase;
+use crate::db::downloads::{DownloadDatabase, FinishedDownload};
+use crate::db::episodes::EpisodeDatabase;
+use crate::db::history::{HistoryDatabase, HistoryEvent};
 use crate::db::imdb::IMDBDatabase;
 use crate::db::moviedb::MovieDBDatabase;
+use crate::db::profiles::QualityProfileDatabase;
+use crate::db::stats::StatsDatabase;
+use crate::db::upgrades::UpgradeDatabase;
+use crate::db::watchlist_options::{WatchlistOptions, WatchlistOptionsDatabase};
+use crate::db::watchlist_states::{watchlist_key, WatchlistState, WatchlistStateDatabase};
 use crate::server::download;
 use crate::server::download::TorrentQuery;
 static ONE_HOUR: u64 = 3_600;
+// Shortest sleep between watchlist checks, so a check that isn't recorded can't turn into a busy loop
+static MIN_RECHECK_WAKE: Duration = Duration::from_secs(60);
+// Failed releases for the same item or episode before requeue_download stops looking for another
+pub static MAX_FAILED_ATTEMPTS: i64 = 5;
+// Episodes are first searched for this long after midnight on their air date, then hourly for a day
+static FIRST_EPISODE_SEARCH_HOURS: i64 = 2;
+static EPISODE_SEARCH_WINDOW_HOURS: i64 = 24;
+// Movies without a known home release are searched for this long after their theatrical release
+static THEATRICAL_WINDOW_DAYS: i64 = 90;
+
+// Lets the API start a watchlist check without waiting for the next interval, either for the whole
+// watchlist or a single item
+pub struct WatchlistRecheck {
+    sender: mpsc::UnboundedSender<Option<String>>,
+    receiver: Mutex<mpsc::UnboundedReceiver<Option<String>>>,
+}
+
+impl WatchlistRecheck {
+    pub fn new() -> Self {
+        let (sender, receiver) = mpsc::unbounded_channel();
+
+        Self {
+            sender,
+            receiver: Mutex::new(receiver),
+        }
+    }
+
+    // id is an IMDb ID or TheMovieDB ID, if a check is already running the next one starts as soon as it finishes
+    pub fn trigger(&self, id: Option<String>) {
+        let _ = self.sender.send(id);
+    }
+
+    async fn wait(&self, delay: Duration, shutdown: &Shutdown) -> Wake {
+        let mut receiver = self.receiver.lock().await;
+        tokio::select! {
+            _ = tokio::time::sleep_until(Instant::now() + delay) => Wake::Scheduled,
+            _ = shutdown.wait() => Wake::Scheduled,
+            t = receiver.recv() => {
+                info!("Watchlist recheck requested");
+                Wake::Requested(t.flatten())
+            }
+        }
+    }
+}
+
+// Why the watchlist monitor woke up
+enum Wake {
+    // The interval passed, only items that are due are checked
+    Scheduled,
+    // A recheck through the API, of one item or the whole watchlist
+    Requested(Option<String>),
+}
+
+// Items are due once their own interval, or the global one, has passed since they were last checked
+#[derive(Default)]
+struct RecheckSchedule {
+    intervals: HashMap<String, i32>,
+    last_checks: HashMap<String, chrono::DateTime<Local>>,
+    // Nothing is due until the default delay has passed, rather than checking everything again
+    failed: bool,
+}
+
+impl RecheckSchedule {
+    async fn load(db: &DBConnection) -> Self {
+        let intervals = WatchlistOptionsDatabase::new(db).fetch_recheck_intervals().await;
+        let last_checks = StatsDatabase::new(db).fetch_last_watchlist_checks().await;
+        match (intervals, last_checks) {
+            (Ok(intervals), Ok(last_checks)) => RecheckSchedule { intervals, last_checks, failed: false },
+            (Err(e), _) | (_, Err(e)) => {
+                warn!("Failed to fetch watchlist recheck intervals, {}", e);
+                RecheckSchedule { failed: true, ..RecheckSchedule::default() }
+            }
+        }
+    }
+
+    // Options and checks are both stored under the item's watchlist key
+    fn is_due(&self, key: &str, default_delay: Duration) -> bool {
+        self.time_until_due(key, default_delay).is_zero()
+    }
+
+    // Until the first of the items is due, items that have never been checked are due now
+    fn next_wake(&self, keys: &[String], default_delay: Duration) -> Duration {
+        keys.iter()
+            .map(|key| self.time_until_due(key, default_delay))
+            .min()
+            .unwrap_or(default_delay)
+            .max(MIN_RECHECK_WAKE)
+    }
+
+    fn time_until_due(&self, key: &str, default_delay: Duration) -> Duration {
+        if self.failed {
+            return default_delay;
+        }
+        let delay = match self.intervals.get(key) {
+            Some(t) => chrono::Duration::hours(*t as i64),
+            None => chrono::Duration::seconds(default_delay.as_secs() as i64),
+        };
+        match self.last_checks.get(key) {
+            Some(t) => (*t + delay - Local::now()).to_std().unwrap_or(Duration::ZERO),
+            None => Duration::ZERO,
+        }
+    }
+}
 
 pub async fn monitor_watchlist(
     db: Arc<DBConnection>,
-    plex: Arc<Plex>,
+    media_server: Arc<dyn MediaServer>,
     torrenter: Arc<Torrenter>,
+    disk: Arc<DiskMonitor>,
+    notifier: Arc<Notifier>,
+    recheck: Arc<WatchlistRecheck>,
+    settings: Arc<SharedSettings>,
+    shutdown: Arc<Shutdown>,
     app_config: Data<AppConfig>,
 ) {
+    if app_config.read_only {
+        info!("Read-only mode, not monitoring watchlist");
+        return;
+    }
     info!("Starting Watchlist Monitor");
     let imdb_db = IMDBDatabase::new(db.deref());
     let movie_db = MovieDBDatabase::new(db.deref());
-    let mut recheck_interval = 6;
-    if app_config.watchlist_recheck_interval_hours.gt(&6) { // Minimum of 6 hours delay, to prevent pointless spam.
-        recheck_interval = app_config.watchlist_recheck_interval_hours as u64;
-    }
-    let recheck_delay = Duration::from_secs(ONE_HOUR * recheck_interval);
+    let mut wake = Wake::Scheduled;
 
     loop {
+        if shutdown.is_triggered() {
+            info!("Stopping Watchlist Monitor");
+            return;
+        }
+        // Read every time round, the interval can be changed from the settings page
+        let mut recheck_interval = 6;
+        let interval_hours = settings.load().watchlist_recheck_interval_hours;
+        if interval_hours.gt(&6) { // Minimum of 6 hours delay, to prevent pointless spam.
+            recheck_interval = interval_hours as u64;
+        }
+        let recheck_delay = Duration::from_secs(ONE_HOUR * recheck_interval);
+        let schedule = RecheckSchedule::load(&db).await;
+        let keys;
+
+        if disk.is_low() {
+            warn!("Low disk space, skipping watchlist check");
+            shutdown.sleep(Duration::from_secs(ONE_HOUR)).await;
+            continue;
+        }
         info!("Fetching Watchlist");
         match app_config.tmdb_api_key.is_empty() {
             true => {
-                let watchlist = imdb_db.fetch_watchlist().await.unwrap();
+                let mut watchlist = imdb_db.fetch_watchlist().await.unwrap();
+                keys = watchlist.iter().map(|i| i.id.clone()).collect::<Vec<String>>();
+                match std::mem::replace(&mut wake, Wake::Scheduled) {
+                    Wake::Scheduled => watchlist.retain(|i| schedule.is_due(&i.id, recheck_delay)),
+                    Wake::Requested(Some(id)) => watchlist.retain(|i| i.id == id),
+                    Wake::Requested(None) => (),
+                }
                 if watchlist.is_empty() {
-                    let _ = tokio::time::sleep_until(Instant::now() + recheck_delay).await;
+                    wake = recheck.wait(schedule.next_wake(&keys, recheck_delay), &shutdown).await;
                     continue;
                 }
 
                 info!("Checking downloads for Items");
 
                 for item in watchlist {
+                    // Finish the current item, the rest are picked up on the next start
+                    if shutdown.is_triggered() {
+                        break;
+                    }
                     info!("Checking: {} - {}", item.title, item.id);
+                    set_state(&db, &item.id, WatchlistState::Searching, None).await;
                     let result = match item._type {
                         ItemType::Movie => {
                             check_movie_downloads_imdb(
                                 &item,
+                                media_server.clone(),
                                 torrenter.clone(),
                                 Arc::clone(&db),
                                 Data::clone(&app_config),
@@ -64,7 +222,7 @@ pub async fn monitor_watchlist(
                         ItemType::TvShow => {
                             check_tv_downloads_imdb(
                                 &item,
-                                plex.clone(),
+                                media_server.clone(),
                                 torrenter.clone(),
                                 Data::clone(&app_config),
                                 Arc::clone(&db),
@@ -73,8 +231,12 @@ pub async fn monitor_watchlist(
                         }
                     };
 
+                    record_check(&db, &item.id, result.is_ok()).await;
+                    finish_search(&db, &item.id, &result).await;
                     match result {
-                        Ok(_) => (),
+                        Ok(_) => {
+                            notifier.notify(DownloadEvent::WatchlistMatch, Some(&item.id), Some(&item.title), None).await
+                        }
                         Err(e) => {
                             warn!("{}", e);
                             continue;
@@ -83,34 +245,57 @@ pub async fn monitor_watchlist(
                 }
             }
             false => {
-                let watchlist = movie_db.fetch_watchlist().await.unwrap();
+                let mut watchlist = movie_db.fetch_watchlist().await.unwrap();
+                keys = watchlist.iter().map(|i| watchlist_key(&i.id.to_string())).collect::<Vec<String>>();
+                match std::mem::replace(&mut wake, Wake::Scheduled) {
+                    Wake::Scheduled => watchlist.retain(|i| schedule.is_due(&watchlist_key(&i.id.to_string()), recheck_delay)),
+                    Wake::Requested(Some(id)) => watchlist.retain(|i| i.id.to_string() == id || i.imdb_id == id),
+                    Wake::Requested(None) => (),
+                }
                 if watchlist.is_empty() {
-                    let _ = tokio::time::sleep_until(Instant::now() + recheck_delay).await;
+                    wake = recheck.wait(schedule.next_wake(&keys, recheck_delay), &shutdown).await;
                     continue;
                 }
 
                 info!("Checking downloads for Items");
 
                 for item in watchlist {
+                    // Finish the current item, the rest are picked up on the next start
+                    if shutdown.is_triggered() {
+                        break;
+                    }
                     info!("Checking: {} - {}", item.title, item.id);
+                    set_state(&db, &format!("tt{}", item.id), WatchlistState::Searching, None).await;
                     let result = match item._type {
                         ItemType::Movie => {
-                            check_movie_downloads_moviedb(&item, torrenter.clone(), Arc::clone(&db))
-                                .await
+                            check_movie_downloads_moviedb(
+                                &item,
+                                media_server.clone(),
+                                torrenter.clone(),
+                                Arc::clone(&db),
+                                Data::clone(&app_config),
+                            )
+                            .await
                         }
                         ItemType::TvShow => {
                             check_tv_downloads_moviedb(
                                 &item,
-                                plex.clone(),
+                                media_server.clone(),
                                 torrenter.clone(),
                                 Data::clone(&app_config),
+                                Arc::clone(&db),
                             )
                             .await
                         }
                     };
 
+                    record_check(&db, &format!("tt{}", item.id), result.is_ok()).await;
+                    finish_search(&db, &format!("tt{}", item.id), &result).await;
                     match result {
-                        Ok(_) => (),
+                        Ok(_) => {
+                            let id = format!("tt{}", item.id);
+                            notifier.notify(DownloadEvent::WatchlistMatch, Some(&id), Some(&item.title), None).await
+                        }
                         Err(e) => {
                             warn!("{}", e);
                             continue;
@@ -120,20 +305,259 @@ pub async fn monitor_watchlist(
             }
         }
 
+        // The items that were just checked aren't due again until their interval has passed
+        let wake_delay = RecheckSchedule::load(&db).await.next_wake(&keys, recheck_delay);
         info!(
-            "Sleeping for {} hours...",
-            recheck_delay.as_secs() / 60 / 60
+            "Sleeping for {} minutes...",
+            wake_delay.as_secs() / 60
         );
-        let _ = tokio::time::sleep_until(Instant::now() + recheck_delay).await;
+        wake = recheck.wait(wake_delay, &shutdown).await;
+    }
+}
+
+// A queued search for one episode of a watchlist show. It's searched for hourly until the until time, after that the
+// episode is left to the regular watchlist check
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EpisodeSearch {
+    // IMDb ID or TheMovieDB ID, as the episode is stored in tv_episodes
+    pub show_id: String,
+    pub season: i32,
+    pub episode: i32,
+    pub until: chrono::DateTime<Local>,
+}
+
+// Queues a search for each watchlist episode shortly after its air date, so new episodes are grabbed without waiting
+// for the next full watchlist check. Air dates don't have a time, so an episode counts as airing at midnight
+pub async fn schedule_episode_searches(
+    db: Arc<DBConnection>,
+    queue: Arc<JobQueue>,
+    disk: Arc<DiskMonitor>,
+    shutdown: Arc<Shutdown>,
+    app_config: Data<AppConfig>,
+) {
+    if app_config.read_only {
+        return;
+    }
+    info!("Starting Episode Search Scheduler");
+
+    loop {
+        match disk.is_low() {
+            true => warn!("Low disk space, not scheduling episode searches"),
+            false => {
+                if let Err(e) = queue_episode_searches(&db, &queue, &app_config).await {
+                    warn!("Failed to schedule episode searches, {}", e);
+                }
+            }
+        }
+
+        if shutdown.sleep(Duration::from_secs(ONE_HOUR)).await {
+            return;
+        }
+    }
+}
+
+async fn queue_episode_searches(db: &DBConnection, queue: &JobQueue, app_config: &AppConfig) -> anyhow::Result<()> {
+    let show_ids = match app_config.tmdb_api_key.is_empty() {
+        true => IMDBDatabase::new(db)
+            .fetch_watchlist()
+            .await?
+            .into_iter()
+            .filter(|i| matches!(i._type, ItemType::TvShow))
+            .map(|i| i.id)
+            .collect::<Vec<String>>(),
+        false => MovieDBDatabase::new(db)
+            .fetch_watchlist()
+            .await?
+            .into_iter()
+            .filter(|i| matches!(i._type, ItemType::TvShow))
+            .map(|i| i.id.to_string())
+            .collect::<Vec<String>>(),
+    };
+    if show_ids.is_empty() {
+        return Ok(());
+    }
+
+    // Yesterday's episodes can still be inside their search window
+    let now = Local::now();
+    let today = now.date_naive();
+    let episode_db = EpisodeDatabase::new(db);
+    let episodes = episode_db
+        .fetch_unscheduled(&show_ids, today - chrono::Duration::days(1), today + chrono::Duration::days(1))
+        .await?;
+
+    for (show_id, season, episode, air_date) in episodes {
+        let aired = match air_date.and_hms_opt(0, 0, 0).and_then(|t| t.and_local_timezone(Local).earliest()) {
+            Some(t) => t,
+            None => continue,
+        };
+        let first_search = aired + chrono::Duration::hours(FIRST_EPISODE_SEARCH_HOURS);
+        let search = EpisodeSearch {
+            show_id: show_id.clone(),
+            season,
+            episode,
+            until: first_search + chrono::Duration::hours(EPISODE_SEARCH_WINDOW_HOURS),
+        };
+
+        if search.until > now {
+            queue.enqueue_at(db, &Job::EpisodeSearch(search), first_search.max(now)).await?;
+            info!("Searching for {} S{:02}E{:02} from {}", show_id, season, episode, first_search.format("%Y-%m-%d %H:%M"));
+        }
+        episode_db.mark_scheduled(&show_id, season, episode).await?;
+    }
+
+    Ok(())
+}
+
+pub async fn search_episode(
+    search: &EpisodeSearch,
+    queue: &JobQueue,
+    db: &Arc<DBConnection>,
+    torrenter: &Arc<Torrenter>,
+    media_server: &Arc<dyn MediaServer>,
+    notifier: &Notifier,
+    app_config: &Data<AppConfig>,
+) -> anyhow::Result<()> {
+    let label = format!("{} S{:02}E{:02}", search.show_id, search.season, search.episode);
+    let episode = IMDBEpisode {
+        id: String::new(),
+        season: search.season,
+        episode: search.episode,
+        air_date: None,
+    };
+
+    // Shows removed from the watchlist since the search was queued are left alone
+    let (state_id, title, result) = match app_config.tmdb_api_key.is_empty() {
+        true => {
+            let item = match IMDBDatabase::new(db).fetch_watchlist().await?.into_iter().find(|i| i.id == search.show_id) {
+                Some(t) => t,
+                None => return Ok(()),
+            };
+            let lookup_title = format!("{} ({})", item.title, item.year);
+            if has_episode(search, &item.id, &lookup_title, db, media_server, app_config).await? {
+                return Ok(());
+            }
+
+            set_state(db, &item.id, WatchlistState::Searching, None).await;
+            let result = find_downloads_and_start_imdb(&item, Some(vec![episode]), Arc::clone(torrenter), Arc::clone(db)).await;
+            (item.id.clone(), item.title, result)
+        }
+        false => {
+            let id = search.show_id.parse::<i32>()?;
+            let item = match MovieDBDatabase::new(db).fetch_watchlist().await?.into_iter().find(|i| i.id == id) {
+                Some(t) => t,
+                None => return Ok(()),
+            };
+            let state_id = format!("tt{}", item.id);
+            let lookup_title = format!("{} ({})", item.title, item.release_date.year());
+            if has_episode(search, &state_id, &lookup_title, db, media_server, app_config).await? {
+                return Ok(());
+            }
+
+            set_state(db, &state_id, WatchlistState::Searching, None).await;
+            let result = find_downloads_and_start_moviedb(&item, Some(vec![episode]), Arc::clone(torrenter), Arc::clone(db)).await;
+            (state_id, item.title, result)
+        }
+    };
+
+    finish_search(db, &state_id, &result).await;
+    match result {
+        Ok(_) => notifier.notify(DownloadEvent::WatchlistMatch, Some(&state_id), Some(&title), None).await,
+        Err(e) => {
+            let next = Local::now() + chrono::Duration::hours(1);
+            if next > search.until {
+                info!("Nothing found for {} in the day after it aired, leaving it to the watchlist check: {}", label, e);
+                return Ok(());
+            }
+            info!("Nothing found for {} yet, searching again at {}: {}", label, next.format("%H:%M"), e);
+            queue.enqueue_at(db, &Job::EpisodeSearch(search.clone()), next).await?;
+        }
+    }
+
+    Ok(())
+}
+
+// Already in the library or downloading
+async fn has_episode(
+    search: &EpisodeSearch,
+    imdb_id: &str,
+    title: &str,
+    db: &DBConnection,
+    media_server: &Arc<dyn MediaServer>,
+    app_config: &AppConfig,
+) -> anyhow::Result<bool> {
+    let episode = IMDBEpisode {
+        id: String::new(),
+        season: search.season,
+        episode: search.episode,
+        air_date: None,
+    };
+    let (is_downloading, remaining) = DownloadDatabase::new(db).is_downloading(imdb_id, Some(vec![episode])).await?;
+    if is_downloading && remaining.is_none() {
+        return Ok(true);
+    }
+
+    let lookup = download::stored_media_lookup(app_config, db, &search.show_id, title).await?;
+    let existing = media_server.list_episodes(&lookup).await?;
+    Ok(existing.iter().any(|e| e.season == search.season && e.episode == search.episode))
+}
+
+async fn set_state(db: &DBConnection, imdb_id: &str, state: WatchlistState, detail: Option<&str>) {
+    if let Err(e) = WatchlistStateDatabase::new(db).set(imdb_id, state, detail).await {
+        warn!("Failed to update watchlist state, {}", e);
+    }
+}
+
+// Leaves the state alone when the check already moved it on, eg to available when nothing is missing
+async fn finish_search(db: &DBConnection, imdb_id: &str, result: &anyhow::Result<()>) {
+    let (state, detail) = match result {
+        Ok(_) => (WatchlistState::Grabbed, None),
+        Err(e) => (WatchlistState::Wanted, Some(e.to_string())),
+    };
+    let from = [WatchlistState::Searching];
+    if let Err(e) = WatchlistStateDatabase::new(db).advance(imdb_id, &from, state, detail.as_deref()).await {
+        warn!("Failed to update watchlist state, {}", e);
+    }
+}
+
+// For the watchlist hit rate on /stats
+async fn record_check(db: &DBConnection, imdb_id: &str, found: bool) {
+    if let Err(e) = StatsDatabase::new(db).insert_watchlist_check(imdb_id, found).await {
+        warn!("Failed to record watchlist check, {}", e);
     }
 }
 
 async fn check_movie_downloads_imdb(
     item: &IMDBItem,
+    media_server: Arc<dyn MediaServer>,
     torrenter: Arc<Torrenter>,
     db: Arc<DBConnection>,
-    _: Data<AppConfig>,
+    app_config: Data<AppConfig>,
 ) -> anyhow::Result<()> {
+    let options = WatchlistOptionsDatabase::new(db.deref()).fetch(&item.id).await?;
+    if options.upgrade_until_cutoff {
+        let movie = UpgradeMovie {
+            item_id: &item.id,
+            imdb_id: &item.id,
+            title: &item.title,
+            year: item.year,
+            search_id: &item.id,
+        };
+        match check_movie_upgrade(&movie, &options, media_server, &torrenter, &db, &app_config).await? {
+            UpgradeCheck::Missing => (),
+            UpgradeCheck::Grabbed => return Ok(()),
+            UpgradeCheck::Finished => {
+                IMDBDatabase::new(db.deref()).update_watchlist_item(&item.id, false).await?;
+                return Err(format_err!("{} meets its quality profile, removed from the watchlist", item.id));
+            }
+            UpgradeCheck::Unknown => {
+                IMDBDatabase::new(db.deref()).update_watchlist_item(&item.id, false).await?;
+                return Err(format_err!("{} has no library file to upgrade, removed from the watchlist", item.id));
+            }
+        }
+        // Stays on the watchlist until it's in the library at the cutoff
+        return find_downloads_and_start_imdb(item, None, torrenter, db).await;
+    }
+
     find_downloads_and_start_imdb(item, None, torrenter, db.clone()).await?;
 
     // Remove from watchlist as no further movies will release under this ID
@@ -144,7 +568,7 @@ async fn check_movie_downloads_imdb(
 }
 async fn check_tv_downloads_imdb(
     item: &IMDBItem,
-    plex: Arc<Plex>,
+    media_server: Arc<dyn MediaServer>,
     torrenter: Arc<Torrenter>,
     app_config: Data<AppConfig>,
     db: Arc<DBConnection>,
@@ -152,8 +576,9 @@ async fn check_tv_downloads_imdb(
     let title = format!("{} ({})", &item.title, item.year);
 
     let missing_episodes =
-        download::find_missing_tv_shows(plex, app_config, &item.id, &title).await?;
+        download::find_missing_tv_shows(media_server, app_config, db.deref(), &item.id, &title).await?;
     if missing_episodes.is_none() {
+        set_state(&db, &item.id, WatchlistState::Available, None).await;
         return Err(format_err!("No missing episodes"));
     }
     find_downloads_and_start_imdb(item, missing_episodes, torrenter, db).await?;
@@ -165,10 +590,44 @@ async fn check_tv_downloads_imdb(
 
 async fn check_movie_downloads_moviedb(
     item: &MovieDBItem,
+    media_server: Arc<dyn MediaServer>,
     torrenter: Arc<Torrenter>,
     db: Arc<DBConnection>,
+    app_config: Data<AppConfig>,
 ) -> anyhow::Result<()> {
-    find_downloads_and_start_moviedb(item, None, torrenter).await?;
+    if let Some(detail) = waiting_for_release(item, &app_config).await {
+        set_state(&db, &format!("tt{}", item.id), WatchlistState::Waiting, Some(&detail)).await;
+        return Err(format_err!("{}: {}", item.id, detail));
+    }
+
+    let options = WatchlistOptionsDatabase::new(db.deref()).fetch(&item.id.to_string()).await?;
+    if options.upgrade_until_cutoff {
+        let item_id = item.id.to_string();
+        let imdb_id = format!("tt{}", item.id);
+        let movie = UpgradeMovie {
+            item_id: &item_id,
+            imdb_id: &imdb_id,
+            title: &item.title,
+            year: item.release_date.year() as i64,
+            search_id: &item.imdb_id,
+        };
+        match check_movie_upgrade(&movie, &options, media_server, &torrenter, &db, &app_config).await? {
+            UpgradeCheck::Missing => (),
+            UpgradeCheck::Grabbed => return Ok(()),
+            UpgradeCheck::Finished => {
+                MovieDBDatabase::new(db.deref()).update_watchlist_item(item.id, false).await?;
+                return Err(format_err!("{} meets its quality profile, removed from the watchlist", item.id));
+            }
+            UpgradeCheck::Unknown => {
+                MovieDBDatabase::new(db.deref()).update_watchlist_item(item.id, false).await?;
+                return Err(format_err!("{} has no library file to upgrade, removed from the watchlist", item.id));
+            }
+        }
+        // Stays on the watchlist until it's in the library at the cutoff
+        return find_downloads_and_start_moviedb(item, None, torrenter, db).await;
+    }
+
+    find_downloads_and_start_moviedb(item, None, torrenter, db.clone()).await?;
 
     // Remove from watchlist as no further movies will release under this ID
     let movie_db = MovieDBDatabase::new(db.deref());
@@ -177,21 +636,44 @@ async fn check_movie_downloads_moviedb(
     Ok(())
 }
 
+// Movies that are only in theaters aren't searched for, the only releases around would be CAMs. Without a digital,
+// physical or TV release date a movie counts as released THEATRICAL_WINDOW_DAYS after it reached theaters
+async fn waiting_for_release(item: &MovieDBItem, app_config: &AppConfig) -> Option<String> {
+    let releases = match MovieDB::new(&app_config.tmdb_api_key).fetch_release_dates(item.id).await {
+        Ok(t) => t,
+        Err(e) => {
+            warn!("Failed to fetch release dates for {}, searching anyway: {}", item.id, e);
+            return None;
+        }
+    };
+
+    let today = Local::now().date_naive();
+    let theatrical = releases.theatrical.unwrap_or(item.release_date);
+    match releases.home {
+        Some(home) if home <= today => None,
+        Some(home) => Some(format!("Waiting for digital release on {}", home)),
+        None if theatrical + chrono::Duration::days(THEATRICAL_WINDOW_DAYS) <= today => None,
+        None => Some("Waiting for digital release".to_string()),
+    }
+}
+
 async fn check_tv_downloads_moviedb(
     item: &MovieDBItem,
-    plex: Arc<Plex>,
+    media_server: Arc<dyn MediaServer>,
     torrenter: Arc<Torrenter>,
     app_config: Data<AppConfig>,
+    db: Arc<DBConnection>,
 ) -> anyhow::Result<()> {
     let title = format!("{} ({})", &item.title, item.release_date.year());
 
     let id = item.id.to_string();
     info!("Checking TV Downloads: {}", id);
-    let missing_episodes = download::find_missing_tv_shows(plex, app_config, &id, &title).await?;
+    let missing_episodes = download::find_missing_tv_shows(media_server, app_config, db.deref(), &id, &title).await?;
     if missing_episodes.is_none() {
+        set_state(&db, &format!("tt{}", id), WatchlistState::Available, None).await;
         return Err(format_err!("No missing episodes"));
     }
-    find_downloads_and_start_moviedb(item, missing_episodes, torrenter).await?;
+    find_downloads_and_start_moviedb(item, missing_episodes, torrenter, db).await?;
 
     // Don't remove from watchlist as TV show may have future seasons/episodes
 
@@ -204,11 +686,15 @@ async fn find_downloads_and_start_imdb(
     torrenter: Arc<Torrenter>,
     db: Arc<DBConnection>,
 ) -> anyhow::Result<()> {
+    let options = WatchlistOptionsDatabase::new(db.deref()).fetch(&item.id).await?;
+    let episodes = apply_season_options(episodes, &options)?;
+    let episodes = apply_new_episode_option(episodes, &options, &item.id, db.deref()).await?;
     let download_db = DownloadDatabase::new(db.deref());
     let (is_downloading, remaining_episodes) =
         download_db.is_downloading(&item.id, episodes).await?;
 
     if is_downloading && remaining_episodes.is_none() {
+        set_state(&db, &item.id, WatchlistState::Downloading, None).await;
         return Err(format_err!("Already downloading."));
     }
 
@@ -224,21 +710,27 @@ async fn find_downloads_and_start_imdb(
         Err(e) => return Err(e),
     };
 
+    let blocked = BlocklistDatabase::new(db.deref()).fetch_hashes().await?;
+    let active = download_db.fetch_active_hashes().await?;
     let torrents = torrents
         .into_par_iter()
         .filter(|x| {
-            x.quality == MediaQuality::_1080p
+            blocked.contains(&x.magnet_hash()).not()
+                && active.contains(&x.magnet_hash()).not()
                 && match x.episode {
                     Some(e) => e >= 0,
                     None => true,
                 }
         })
         .collect::<Vec<TorrentItem>>();
+    let torrents = select_releases(&item.id, &options, torrents, db.deref()).await?;
     if torrents.is_empty() {
         return Err(format_err!("No torrents available"));
     }
     info!("Downloading Item: {}", item.id);
     let download_db = DownloadDatabase::new(db.deref());
+    let history_db = HistoryDatabase::new(db.deref());
+    let attempt_db = AttemptDatabase::new(db.deref());
     for torrent in torrents {
         let query = TorrentQuery {
             imdb_id: torrent.imdb_id.clone(),
@@ -246,12 +738,22 @@ async fn find_downloads_and_start_imdb(
             episode: torrent.episode,
             quality: torrent.quality,
             magnet_uri: torrent.magnet_uri.clone(),
+            replaces: None,
+            name: torrent.name.clone(),
+            source: torrent.source.clone(),
+            size: torrent.size,
         };
 
         match download_db.insert(&query).await {
             Ok(_) => (),
             Err(e) => return Err(format_err!("Failed to insert torrent, {}", e)),
         }
+        if let Err(e) = history_db.insert_grab(&query).await {
+            warn!("Failed to record download history, {}", e);
+        }
+        if let Err(e) = attempt_db.insert(&query).await {
+            warn!("Failed to record download attempt, {}", e);
+        }
         match torrenter.start_download(torrent).await {
             Ok(_) => (),
             Err(e) => return Err(format_err!("Failed to start download, {}", e)),
@@ -264,12 +766,26 @@ async fn find_downloads_and_start_moviedb(
     item: &MovieDBItem,
     episodes: Option<Vec<IMDBEpisode>>,
     torrenter: Arc<Torrenter>,
+    db: Arc<DBConnection>,
 ) -> anyhow::Result<()> {
+    let options = WatchlistOptionsDatabase::new(db.deref()).fetch(&item.id.to_string()).await?;
+    let episodes = apply_season_options(episodes, &options)?;
+    let episodes = apply_new_episode_option(episodes, &options, &item.id.to_string(), db.deref()).await?;
+    // Downloads of TheMovieDB items are stored under the watchlist key
+    let download_id = watchlist_key(&item.id.to_string());
+    let download_db = DownloadDatabase::new(db.deref());
+    let (is_downloading, remaining_episodes) = download_db.is_downloading(&download_id, episodes).await?;
+
+    if is_downloading && remaining_episodes.is_none() {
+        set_state(&db, &download_id, WatchlistState::Downloading, None).await;
+        return Err(format_err!("Already downloading."));
+    }
+
     let torrents = match torrenter
         .find_torrent(
             item.title.to_owned(),
             Some(item.imdb_id.to_owned()),
-            episodes,
+            remaining_episodes,
         )
         .await
     {
@@ -277,16 +793,43 @@ async fn find_downloads_and_start_moviedb(
         Err(e) => return Err(e),
     };
 
+    let blocked = BlocklistDatabase::new(db.deref()).fetch_hashes().await?;
+    let active = download_db.fetch_active_hashes().await?;
     let torrents = torrents
         .into_par_iter()
-        .filter(|x| x.quality == MediaQuality::_1080p)
+        .filter(|x| blocked.contains(&x.magnet_hash()).not() && active.contains(&x.magnet_hash()).not())
         .collect::<Vec<TorrentItem>>();
+    let torrents = select_releases(&item.id.to_string(), &options, torrents, db.deref()).await?;
     if torrents.is_empty() {
         return Err(format_err!("No torrents available"));
     }
 
     info!("Downloading Item: {}", item.id);
+    let history_db = HistoryDatabase::new(db.deref());
+    let attempt_db = AttemptDatabase::new(db.deref());
     for torrent in torrents {
+        let query = TorrentQuery {
+            imdb_id: download_id.clone(),
+            season: torrent.season,
+            episode: torrent.episode,
+            quality: torrent.quality,
+            magnet_uri: torrent.magnet_uri.clone(),
+            replaces: None,
+            name: torrent.name.clone(),
+            source: torrent.source.clone(),
+            size: torrent.size,
+        };
+
+        match download_db.insert(&query).await {
+            Ok(_) => (),
+            Err(e) => return Err(format_err!("Failed to insert torrent, {}", e)),
+        }
+        if let Err(e) = history_db.insert_grab(&query).await {
+            warn!("Failed to record download history, {}", e);
+        }
+        if let Err(e) = attempt_db.insert(&query).await {
+            warn!("Failed to record download attempt, {}", e);
+        }
         match torrenter.start_download(torrent).await {
             Ok(_) => (),
             Err(e) => return Err(format_err!("Failed to start download, {}", e)),
@@ -295,3 +838,296 @@ async fn find_downloads_and_start_moviedb(
 
     Ok(())
 }
+
+// A watchlist movie with "upgrade until cutoff" turned on. item_id is the IMDb ID or TMDB ID the watchlist, options and
+// profile are stored under, search_id is what the indexers are searched with
+struct UpgradeMovie<'a> {
+    item_id: &'a str,
+    imdb_id: &'a str,
+    title: &'a str,
+    year: i64,
+    search_id: &'a str,
+}
+
+enum UpgradeCheck {
+    // Not in the library yet, it's grabbed like any other movie
+    Missing,
+    // A better release has been started, the library copy is replaced once it's imported
+    Grabbed,
+    // The library copy meets the profile's cutoff, or there's no profile to upgrade towards
+    Finished,
+    // It's been downloaded but the library can't say which file it is or what quality, eg without a media server, so
+    // there's nothing to compare a better release against
+    Unknown,
+}
+
+// In the library without a usable file, or a download of it has finished before
+async fn already_downloaded(
+    movie: &UpgradeMovie<'_>,
+    lookup: &MediaLookup,
+    media_server: &dyn MediaServer,
+    db: &DBConnection,
+) -> anyhow::Result<bool> {
+    if media_server.exists_in_library(lookup, false).await? {
+        return Ok(true);
+    }
+    let attempts = AttemptDatabase::new(db).fetch_for_item(movie.imdb_id).await?;
+    Ok(attempts.iter().any(|a| a.outcome == AttemptOutcome::Completed.as_str()))
+}
+
+async fn check_movie_upgrade(
+    movie: &UpgradeMovie<'_>,
+    options: &WatchlistOptions,
+    media_server: Arc<dyn MediaServer>,
+    torrenter: &Torrenter,
+    db: &DBConnection,
+    app_config: &AppConfig,
+) -> anyhow::Result<UpgradeCheck> {
+    // Covers the first grab and an upgrade that hasn't finished yet
+    let (is_downloading, _) = DownloadDatabase::new(db).is_downloading(movie.imdb_id, None).await?;
+    if is_downloading {
+        set_state(db, movie.imdb_id, WatchlistState::Downloading, None).await;
+        return Err(format_err!("Already downloading."));
+    }
+
+    let title = format!("{} ({})", movie.title, movie.year);
+    let lookup = download::stored_media_lookup(app_config, db, movie.item_id, &title).await?;
+    let existing = match media_server.library_file(&lookup).await? {
+        Some(t) => t,
+        None if already_downloaded(movie, &lookup, media_server.as_ref(), db).await? => {
+            set_state(db, movie.imdb_id, WatchlistState::Available, None).await;
+            return Ok(UpgradeCheck::Unknown);
+        }
+        None => return Ok(UpgradeCheck::Missing),
+    };
+    let finished = match QualityProfileDatabase::new(db).fetch_for_item(movie.item_id).await? {
+        Some(profile) => profile.meets_cutoff(existing.quality),
+        None => true,
+    };
+    if finished {
+        set_state(db, movie.imdb_id, WatchlistState::Available, None).await;
+        return Ok(UpgradeCheck::Finished);
+    }
+
+    let blocked = BlocklistDatabase::new(db).fetch_hashes().await?;
+    let mut torrents = torrenter
+        .find_torrent(movie.title.to_owned(), Some(movie.search_id.to_owned()), None)
+        .await?;
+    torrents.retain(|t| t.quality > existing.quality && blocked.contains(&t.magnet_hash()).not());
+    let torrent = match select_releases(movie.item_id, options, torrents, db).await?.into_iter().next() {
+        Some(t) => t,
+        None => return Err(format_err!("No releases better than {} for {}", existing.quality, movie.imdb_id)),
+    };
+
+    info!("Upgrading {} from {} with {}", movie.imdb_id, existing.quality, torrent.name);
+    let query = TorrentQuery {
+        imdb_id: movie.imdb_id.to_owned(),
+        season: None,
+        episode: None,
+        quality: torrent.quality,
+        magnet_uri: torrent.magnet_uri.clone(),
+        replaces: Some(existing.path.clone()),
+        name: torrent.name.clone(),
+        source: torrent.source.clone(),
+        size: torrent.size,
+    };
+    DownloadDatabase::new(db).insert(&query).await?;
+    if let Err(e) = HistoryDatabase::new(db).insert_grab(&query).await {
+        warn!("Failed to record download history, {}", e);
+    }
+    if let Err(e) = AttemptDatabase::new(db).insert(&query).await {
+        warn!("Failed to record download attempt, {}", e);
+    }
+    UpgradeDatabase::new(db).insert(&query.magnet_hash(), &existing.path).await?;
+    torrenter.start_download(torrent).await?;
+
+    Ok(UpgradeCheck::Grabbed)
+}
+
+// Only seasons that hadn't started airing when "only future seasons" was turned on, judged by the earliest missing
+// episode with an air date. Episodes without one haven't been announced yet so they count as future
+fn apply_season_options(
+    episodes: Option<Vec<IMDBEpisode>>,
+    options: &WatchlistOptions,
+) -> anyhow::Result<Option<Vec<IMDBEpisode>>> {
+    let (episodes, from) = match (episodes, options.future_seasons_from) {
+        (Some(episodes), Some(from)) => (episodes, from),
+        (episodes, _) => return Ok(episodes),
+    };
+
+    let mut started = HashSet::new();
+    for episode in episodes.iter() {
+        if episode.air_date.is_some_and(|d| d < from) {
+            started.insert(episode.season);
+        }
+    }
+    let episodes = episodes
+        .into_iter()
+        .filter(|e| started.contains(&e.season).not())
+        .collect::<Vec<IMDBEpisode>>();
+    if episodes.is_empty() {
+        return Err(format_err!("No missing episodes in future seasons"));
+    }
+
+    Ok(Some(episodes))
+}
+
+// Drops episodes that aired before the show was added, going by the air dates stored for it. Episodes without one
+// haven't aired yet, so they're kept
+async fn apply_new_episode_option(
+    episodes: Option<Vec<IMDBEpisode>>,
+    options: &WatchlistOptions,
+    show_id: &str,
+    db: &DBConnection,
+) -> anyhow::Result<Option<Vec<IMDBEpisode>>> {
+    let (episodes, from) = match (episodes, options.new_episodes_from) {
+        (Some(episodes), Some(from)) => (episodes, from),
+        (episodes, _) => return Ok(episodes),
+    };
+
+    let aired = EpisodeDatabase::new(db).fetch_aired_before(show_id, from).await?.into_iter().collect::<HashSet<(i32, i32)>>();
+    let episodes = episodes
+        .into_iter()
+        .filter(|e| aired.contains(&(e.season, e.episode)).not())
+        .collect::<Vec<IMDBEpisode>>();
+    if episodes.is_empty() {
+        return Err(format_err!("No missing episodes aired after {}", from));
+    }
+
+    Ok(Some(episodes))
+}
+
+// The item's quality profile picks the best release for each episode, without one every 1080p release is grabbed.
+// With a language set only releases tagged with it, or MULTi, are considered
+async fn select_releases(
+    item_id: &str,
+    options: &WatchlistOptions,
+    torrents: Vec<TorrentItem>,
+    db: &DBConnection,
+) -> anyhow::Result<Vec<TorrentItem>> {
+    let torrents = match &options.language {
+        Some(language) => torrents
+            .into_iter()
+            .filter(|t| has_term(&t.name, language) || has_term(&t.name, "multi"))
+            .collect(),
+        None => torrents,
+    };
+    let releases = match QualityProfileDatabase::new(db).fetch_for_item(item_id).await? {
+        Some(profile) => profile.pick(torrents),
+        None => torrents
+            .into_iter()
+            .filter(|x| x.quality == MediaQuality::_1080p)
+            .collect(),
+    };
+
+    Ok(releases)
+}
+
+// Records that a watchlist download can be watched and lets the notifiers know, with the release details
+pub async fn announce_available(hash: &str, db: &DBConnection, notifier: &Notifier) {
+    if let Err(e) = HistoryDatabase::new(db).insert_event(hash, HistoryEvent::Available, None).await {
+        warn!("Failed to record download history, {}", e);
+    }
+    notifier.notify_download(db, hash, DownloadEvent::WatchlistAvailable, None).await;
+}
+
+// Searches again for a download that was thrown away, picking the next best release that isn't blocked
+pub async fn requeue_download(
+    download: &FinishedDownload,
+    torrenter: &Torrenter,
+    db: &DBConnection,
+) -> anyhow::Result<()> {
+    let failed = AttemptDatabase::new(db).count_failed(&download.imdb_id, download.season, download.episode).await?;
+    if failed >= MAX_FAILED_ATTEMPTS {
+        warn!("Not retrying {}, {} releases have already failed", download.imdb_id, failed);
+        return Ok(());
+    }
+    let (title, search_id) = match (&download.title, &download.search_id) {
+        (Some(title), Some(id)) => (title.to_owned(), id.to_owned()),
+        _ => return Err(format_err!("No details stored for {}", download.imdb_id)),
+    };
+    let episodes = match (download.season, download.episode) {
+        (Some(season), Some(episode)) => Some(vec![IMDBEpisode {
+            id: String::new(),
+            season,
+            episode,
+            air_date: None,
+        }]),
+        _ => None,
+    };
+
+    let blocked = BlocklistDatabase::new(db).fetch_hashes().await?;
+    let mut torrents = torrenter.find_torrent(title, Some(search_id), episodes).await?;
+    torrents.retain(|t| {
+        t.season == download.season
+            && t.episode == download.episode
+            && blocked.contains(&t.magnet_hash()).not()
+    });
+    // Same quality as the original grab first, then the best seeded
+    torrents.sort_by_key(|t| (t.quality.to_string() != download.quality, Reverse(t.seeds.unwrap_or(0))));
+
+    let torrent = match torrents.into_iter().next() {
+        Some(t) => t,
+        None => return Err(format_err!("No other releases available for {}", download.imdb_id)),
+    };
+
+    info!("Retrying {} with {}", download.imdb_id, torrent.name);
+    let query = TorrentQuery {
+        imdb_id: download.imdb_id.clone(),
+        season: torrent.season,
+        episode: torrent.episode,
+        quality: torrent.quality,
+        magnet_uri: torrent.magnet_uri.clone(),
+        replaces: None,
+        name: torrent.name.clone(),
+        source: torrent.source.clone(),
+        size: torrent.size,
+    };
+    DownloadDatabase::new(db).insert(&query).await?;
+    HistoryDatabase::new(db).insert_grab(&query).await?;
+    AttemptDatabase::new(db).insert(&query).await?;
+    torrenter.start_download(torrent).await?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DEFAULT_DELAY: Duration = Duration::from_secs(6 * 3_600);
+
+    #[test]
+    fn failed_schedule_waits_the_default_delay() {
+        let schedule = RecheckSchedule { failed: true, ..RecheckSchedule::default() };
+        let keys = vec!["tt0133093".to_string()];
+
+        assert!(schedule.is_due("tt0133093", DEFAULT_DELAY).not());
+        assert_eq!(schedule.next_wake(&keys, DEFAULT_DELAY), DEFAULT_DELAY);
+    }
+
+    #[test]
+    fn unchecked_item_is_due_without_a_busy_loop() {
+        let schedule = RecheckSchedule::default();
+        let keys = vec!["tt0133093".to_string()];
+
+        assert!(schedule.is_due("tt0133093", DEFAULT_DELAY));
+        assert_eq!(schedule.next_wake(&keys, DEFAULT_DELAY), MIN_RECHECK_WAKE);
+    }
+
+    #[test]
+    fn empty_watchlist_waits_the_default_delay() {
+        assert_eq!(RecheckSchedule::default().next_wake(&[], DEFAULT_DELAY), DEFAULT_DELAY);
+    }
+
+    #[test]
+    fn waits_until_the_next_item_is_due() {
+        let mut schedule = RecheckSchedule::default();
+        schedule.last_checks.insert("tt0133093".to_string(), Local::now() - chrono::Duration::hours(5));
+        schedule.last_checks.insert("tt0234215".to_string(), Local::now());
+        let keys = vec!["tt0133093".to_string(), "tt0234215".to_string()];
+
+        let wake = schedule.next_wake(&keys, DEFAULT_DELAY);
+        assert!(wake <= Duration::from_secs(3_600) && wake > Duration::from_secs(3_500));
+    }
+}
\ No newline at end of file
@@ -94,7 +94,7 @@ impl TorrentSearch for YTS {
                     _ => MediaQuality::Unknown,
                 };
 
-                let item = TorrentItem::new(
+                let mut item = TorrentItem::new(
                     movie.imdb_code.to_string(),
                     movie.title.to_owned(),
                     magnet,
@@ -104,12 +104,17 @@ impl TorrentSearch for YTS {
                     None,
                     None,
                 );
+                item.size = Some(torrent.size_bytes);
                 results.push(item);
             }
         }
 
         Ok(results)
     }
+
+    fn source(&self) -> &'static str {
+        "YTS"
+    }
 }
 
 #[derive(Debug, Clone, Deserialize)]
@@ -140,4 +145,5 @@ struct Torrent {
     seeds: i64,
     peers: i64,
     size: String,
+    size_bytes: i64,
 }
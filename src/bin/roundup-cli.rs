@@ -0,0 +1,179 @@
+use std::ops::Not;
+
+use anyhow::format_err;
+use reqwest::{Method, StatusCode};
+use serde_json::Value;
+
+// Small client for the JSON API, for headless servers and scripts.
+// The server and key are read from ROUNDUP_URL and ROUNDUP_API_KEY or the --url and --api-key options.
+
+const USAGE: &str = "Usage: roundup-cli [--url URL] [--api-key KEY] [--json] <command>
+
+Commands:
+  search <text> [--type movie|tv]   Search for movies or TV shows
+  watchlist                         List the watchlist
+  watch <id>                        Add an IMDb/TheMovieDB id to the watchlist
+  unwatch <id>                      Remove an id from the watchlist
+  downloads [--state <state>]       List downloads (all, downloading, stalled, completed or failed)
+  recheck [id]                      Check the watchlist (or one item on it) for new releases now";
+
+struct Client {
+    http: reqwest::Client,
+    url: String,
+    api_key: Option<String>,
+}
+
+impl Client {
+    async fn request(&self, method: Method, path: &str) -> anyhow::Result<Option<Value>> {
+        let mut request = self.http.request(method, format!("{}{}", self.url, path));
+        if let Some(key) = &self.api_key {
+            request = request.header("X-Api-Key", key);
+        }
+
+        let response = request.send().await?;
+        let status = response.status();
+        let body = response.text().await?;
+        if status.is_success().not() {
+            return Err(format_err!("{}: {}", status, body));
+        }
+
+        match status == StatusCode::NO_CONTENT || body.is_empty() {
+            true => Ok(None),
+            false => Ok(Some(serde_json::from_str(&body)?)),
+        }
+    }
+}
+
+#[tokio::main]
+async fn main() {
+    if let Err(e) = run(std::env::args().skip(1).collect()).await {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    }
+}
+
+async fn run(args: Vec<String>) -> anyhow::Result<()> {
+    let mut url = std::env::var("ROUNDUP_URL").unwrap_or(String::from("http://127.0.0.1:80"));
+    let mut api_key = std::env::var("ROUNDUP_API_KEY").ok().filter(|k| k.is_empty().not());
+    let mut json = false;
+    let mut _type = String::from("movie");
+    let mut state = String::from("all");
+    let mut positional = Vec::new();
+
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--url" => url = args.next().ok_or(format_err!("--url needs a value"))?,
+            "--api-key" => api_key = Some(args.next().ok_or(format_err!("--api-key needs a value"))?),
+            "--type" => _type = args.next().ok_or(format_err!("--type needs a value"))?,
+            "--state" => state = args.next().ok_or(format_err!("--state needs a value"))?,
+            "--json" => json = true,
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                return Ok(());
+            }
+            _ => positional.push(arg),
+        }
+    }
+
+    let client = Client {
+        http: reqwest::Client::new(),
+        url: url.trim_end_matches('/').to_string(),
+        api_key,
+    };
+
+    let command = positional.first().map(|c| c.as_str()).unwrap_or_default();
+    let argument = positional[1.min(positional.len())..].join(" ");
+    let response = match (command, argument.is_empty()) {
+        ("search", false) => {
+            let path = format!(
+                "/api/v1/search?type={}&mode=query&query={}",
+                urlencoding::encode(&_type),
+                urlencoding::encode(&argument)
+            );
+            client.request(Method::GET, &path).await?
+        }
+        ("watchlist", _) => client.request(Method::GET, "/api/v1/watchlist").await?,
+        ("watch", false) => {
+            let path = format!("/api/v1/watchlist/{}", urlencoding::encode(&argument));
+            client.request(Method::PUT, &path).await?;
+            println!("Added {} to the watchlist", argument);
+            return Ok(());
+        }
+        ("unwatch", false) => {
+            let path = format!("/api/v1/watchlist/{}", urlencoding::encode(&argument));
+            client.request(Method::DELETE, &path).await?;
+            println!("Removed {} from the watchlist", argument);
+            return Ok(());
+        }
+        ("downloads", _) => {
+            let path = format!("/api/v1/downloads?state={}", urlencoding::encode(&state));
+            client.request(Method::GET, &path).await?
+        }
+        ("recheck", _) => {
+            let path = match argument.is_empty() {
+                true => "/api/v1/watchlist/recheck".to_string(),
+                false => format!("/api/v1/watchlist/recheck?imdb_id={}", urlencoding::encode(&argument)),
+            };
+            client.request(Method::POST, &path).await?;
+            println!("Watchlist recheck started");
+            return Ok(());
+        }
+        _ => return Err(format_err!("{}", USAGE)),
+    };
+
+    let items = match response {
+        Some(Value::Array(t)) => t,
+        _ => return Err(format_err!("Unexpected response")),
+    };
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&items)?);
+        return Ok(());
+    }
+
+    for item in items {
+        match command {
+            "downloads" => println!("{}", format_download(&item)),
+            _ => println!("{}", format_item(&item)),
+        }
+    }
+
+    Ok(())
+}
+
+// IMDb items have a year, TheMovieDB items have a release date
+fn format_item(item: &Value) -> String {
+    let id = match &item["id"] {
+        Value::String(t) => t.to_string(),
+        t => t.to_string(),
+    };
+    let year = match &item["year"] {
+        Value::Number(t) => t.to_string(),
+        _ => item["release_date"].as_str().unwrap_or_default().chars().take(4).collect(),
+    };
+    let watchlist = match item["watchlist"].as_bool().unwrap_or(false) {
+        true => " [watchlist]",
+        false => "",
+    };
+
+    format!("{:<12} {} ({}){}", id, item["title"].as_str().unwrap_or_default(), year, watchlist)
+}
+
+fn format_download(item: &Value) -> String {
+    let mut title = item["title"].as_str().unwrap_or_default().to_string();
+    match (item["season"].as_i64(), item["episode"].as_i64()) {
+        (Some(s), Some(e)) => title.push_str(&format!(" S{:02}E{:02}", s, e)),
+        (Some(s), None) => title.push_str(&format!(" S{:02}", s)),
+        _ => (),
+    };
+    let progress = item["progress"].as_f64().unwrap_or(0.0) * 100.0;
+
+    format!(
+        "{:<40} {:>5.1}% {:<12} {}",
+        title,
+        progress,
+        item["state"].as_str().unwrap_or_default(),
+        item["magnet_hash"].as_str().unwrap_or_default()
+    )
+}
\ No newline at end of file
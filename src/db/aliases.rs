@@ -0,0 +1,52 @@
+use super::DBConnection;
+
+pub struct AliasDatabase<'a> {
+    db: &'a DBConnection
+}
+
+impl<'a> AliasDatabase<'a> {
+    pub fn new(db: &'a DBConnection) -> AliasDatabase {
+        AliasDatabase {
+            db
+        }
+    }
+
+    // item_id is an IMDb ID or a TMDB ID depending on which metadata source is in use
+    pub async fn insert_many(&self, item_id: &str, titles: &[String], source: &str) -> Result<(), sqlx::Error> {
+        let query = "INSERT INTO aliases(item_id, title, source) VALUES ($1, $2, $3) ON CONFLICT (item_id, title) DO NOTHING";
+
+        for title in titles {
+            let _ = sqlx::query(query)
+                .bind(item_id)
+                .bind(title.trim())
+                .bind(source)
+                .execute(&self.db.db)
+                .await?;
+        }
+
+        Ok(())
+    }
+
+    pub async fn fetch(&self, item_id: &str) -> Result<Vec<String>, sqlx::Error> {
+        let query = "SELECT title FROM aliases WHERE item_id = $1 ORDER BY source DESC, created_at ASC";
+
+        let titles = sqlx::query_scalar::<_, String>(query)
+            .bind(item_id)
+            .fetch_all(&self.db.db)
+            .await?;
+
+        Ok(titles)
+    }
+
+    pub async fn remove(&self, item_id: &str, title: &str) -> Result<(), sqlx::Error> {
+        let query = "DELETE FROM aliases WHERE item_id = $1 AND title = $2";
+
+        let _ = sqlx::query(query)
+            .bind(item_id)
+            .bind(title)
+            .execute(&self.db.db)
+            .await?;
+
+        Ok(())
+    }
+}
\ No newline at end of file
@@ -0,0 +1,108 @@
+use chrono::Local;
+use serde::Serialize;
+use sqlx::FromRow;
+
+use super::DBConnection;
+
+// Keys for calling the API from scripts, only the SHA256 of each key is stored
+pub struct ApiKeyDatabase<'a> {
+    db: &'a DBConnection
+}
+
+// Each scope includes the ones before it
+#[derive(Debug, Eq, PartialEq, Ord, PartialOrd, Copy, Clone)]
+pub enum ApiKeyScope {
+    // Searching and listing
+    Read,
+    // Starting downloads and changing the watchlist
+    Download,
+    // Changing settings
+    Admin,
+}
+
+impl ApiKeyScope {
+    pub fn from_param(param: &str) -> Option<ApiKeyScope> {
+        match param.to_ascii_lowercase().as_str() {
+            "read" => Some(ApiKeyScope::Read),
+            "download" => Some(ApiKeyScope::Download),
+            "admin" => Some(ApiKeyScope::Admin),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ApiKeyScope::Read => "read",
+            ApiKeyScope::Download => "download",
+            ApiKeyScope::Admin => "admin",
+        }
+    }
+}
+
+#[derive(Debug, Serialize, FromRow)]
+pub struct ApiKey {
+    pub id: i32,
+    pub name: String,
+    pub scope: String,
+    pub created_at: chrono::DateTime<Local>,
+    pub last_used_at: Option<chrono::DateTime<Local>>,
+}
+
+impl<'a> ApiKeyDatabase<'a> {
+    pub fn new(db: &'a DBConnection) -> ApiKeyDatabase {
+        ApiKeyDatabase {
+            db
+        }
+    }
+
+    pub async fn insert(&self, name: &str, key_hash: &str, scope: ApiKeyScope) -> Result<i32, sqlx::Error> {
+        let query = "INSERT INTO api_keys(name, key_hash, scope) VALUES ($1, $2, $3) RETURNING id";
+
+        sqlx::query_scalar::<_, i32>(query)
+            .bind(name)
+            .bind(key_hash)
+            .bind(scope.as_str())
+            .fetch_one(&self.db.db)
+            .await
+    }
+
+    pub async fn fetch_all(&self) -> Result<Vec<ApiKey>, sqlx::Error> {
+        let query = "SELECT id, name, scope, created_at, last_used_at FROM api_keys ORDER BY id";
+
+        sqlx::query_as::<_, ApiKey>(query)
+            .fetch_all(&self.db.db)
+            .await
+    }
+
+    pub async fn count(&self) -> Result<i64, sqlx::Error> {
+        let query = "SELECT COUNT(*) FROM api_keys";
+
+        sqlx::query_scalar::<_, i64>(query)
+            .fetch_one(&self.db.db)
+            .await
+    }
+
+    pub async fn remove(&self, id: i32) -> Result<bool, sqlx::Error> {
+        let query = "DELETE FROM api_keys WHERE id = $1";
+
+        let result = sqlx::query(query)
+            .bind(id)
+            .execute(&self.db.db)
+            .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    // Returns the key's scope if it exists, and marks it as used
+    pub async fn find_by_hash(&self, key_hash: &str) -> Result<Option<ApiKeyScope>, sqlx::Error> {
+        let query = "UPDATE api_keys SET last_used_at = now() WHERE key_hash = $1 RETURNING scope";
+
+        let scope = sqlx::query_scalar::<_, String>(query)
+            .bind(key_hash)
+            .fetch_optional(&self.db.db)
+            .await?;
+
+        // Unknown scopes get the least access rather than locking the key out
+        Ok(scope.map(|s| ApiKeyScope::from_param(&s).unwrap_or(ApiKeyScope::Read)))
+    }
+}
\ No newline at end of file
@@ -0,0 +1,117 @@
+use chrono::Local;
+use serde::Serialize;
+use utoipa::ToSchema;
+
+use crate::server::download::TorrentQuery;
+
+use super::DBConnection;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AttemptOutcome {
+    Downloading,
+    Completed,
+    Failed,
+    Cancelled,
+}
+
+impl AttemptOutcome {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AttemptOutcome::Downloading => "downloading",
+            AttemptOutcome::Completed => "completed",
+            AttemptOutcome::Failed => "failed",
+            AttemptOutcome::Cancelled => "cancelled",
+        }
+    }
+}
+
+#[derive(Debug, sqlx::FromRow, Serialize, ToSchema)]
+pub struct DownloadAttempt {
+    pub id: i32,
+    pub imdb_id: String,
+    pub season: Option<i32>,
+    pub episode: Option<i32>,
+    pub magnet_hash: String,
+    pub release_name: Option<String>,
+    pub source: Option<String>,
+    pub outcome: String,
+    pub detail: Option<String>,
+    pub created_at: chrono::DateTime<Local>,
+    pub updated_at: chrono::DateTime<Local>,
+}
+
+// Every release roundup has started for an item or episode, with how it went. Unlike the history this is one row
+// per release, so it can answer "how many releases have failed for this episode"
+pub struct AttemptDatabase<'a> {
+    db: &'a DBConnection
+}
+
+impl<'a> AttemptDatabase<'a> {
+    pub fn new(db: &'a DBConnection) -> AttemptDatabase {
+        AttemptDatabase {
+            db
+        }
+    }
+
+    pub async fn insert(&self, item: &TorrentQuery) -> Result<(), sqlx::Error> {
+        let query = "INSERT INTO download_attempts(imdb_id, season, episode, magnet_hash, release_name, source, outcome) VALUES ($1, $2, $3, $4, $5, $6, $7)";
+
+        let _ = sqlx::query(query)
+            .bind(&item.imdb_id)
+            .bind(item.season)
+            .bind(item.episode)
+            .bind(item.magnet_hash())
+            .bind(Some(&item.name).filter(|n| !n.is_empty()))
+            .bind(Some(&item.source).filter(|s| !s.is_empty()))
+            .bind(AttemptOutcome::Downloading.as_str())
+            .execute(&self.db.db)
+            .await?;
+
+        Ok(())
+    }
+
+    // Only the latest attempt with the hash, the same release can be tried again after being cancelled
+    pub async fn set_outcome(&self, magnet_hash: &str, outcome: AttemptOutcome, detail: Option<&str>) -> Result<(), sqlx::Error> {
+        let query = "UPDATE download_attempts SET outcome = $2, detail = $3, updated_at = now() WHERE id = (SELECT id FROM download_attempts WHERE magnet_hash = $1 ORDER BY created_at DESC, id DESC LIMIT 1)";
+
+        let _ = sqlx::query(query)
+            .bind(magnet_hash)
+            .bind(outcome.as_str())
+            .bind(detail)
+            .execute(&self.db.db)
+            .await?;
+
+        Ok(())
+    }
+
+    pub async fn fetch_by_hash(&self, magnet_hash: &str) -> Result<Option<DownloadAttempt>, sqlx::Error> {
+        let query = "SELECT * FROM download_attempts WHERE magnet_hash = $1 ORDER BY id DESC LIMIT 1";
+
+        sqlx::query_as::<_, DownloadAttempt>(query)
+            .bind(magnet_hash)
+            .fetch_optional(&self.db.db)
+            .await
+    }
+
+    // Newest first, for every season and episode of a show
+    pub async fn fetch_for_item(&self, imdb_id: &str) -> Result<Vec<DownloadAttempt>, sqlx::Error> {
+        let query = "SELECT * FROM download_attempts WHERE imdb_id = $1 ORDER BY created_at DESC, id DESC";
+
+        sqlx::query_as::<_, DownloadAttempt>(query)
+            .bind(imdb_id)
+            .fetch_all(&self.db.db)
+            .await
+    }
+
+    pub async fn count_failed(&self, imdb_id: &str, season: Option<i32>, episode: Option<i32>) -> Result<i64, sqlx::Error> {
+        let query = "SELECT COUNT(*) FROM download_attempts WHERE imdb_id = $1 AND season IS NOT DISTINCT FROM $2 AND episode IS NOT DISTINCT FROM $3 AND outcome = $4";
+
+        sqlx::query_scalar::<_, i64>(query)
+            .bind(imdb_id)
+            .bind(season)
+            .bind(episode)
+            .bind(AttemptOutcome::Failed.as_str())
+            .fetch_one(&self.db.db)
+            .await
+    }
+}
\ No newline at end of file
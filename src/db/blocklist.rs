@@ -0,0 +1,39 @@
+use std::collections::HashSet;
+
+use super::DBConnection;
+
+// Releases that shouldn't be downloaded again
+pub struct BlocklistDatabase<'a> {
+    db: &'a DBConnection
+}
+
+impl<'a> BlocklistDatabase<'a> {
+    pub fn new(db: &'a DBConnection) -> BlocklistDatabase {
+        BlocklistDatabase {
+            db
+        }
+    }
+
+    pub async fn insert(&self, magnet_hash: &str, imdb_id: &str, reason: &str) -> Result<(), sqlx::Error> {
+        let query = "INSERT INTO blocked_releases(magnet_hash, imdb_id, reason) VALUES ($1, $2, $3) ON CONFLICT (magnet_hash) DO UPDATE SET reason = $3";
+
+        let _ = sqlx::query(query)
+            .bind(magnet_hash)
+            .bind(imdb_id)
+            .bind(reason)
+            .execute(&self.db.db)
+            .await?;
+
+        Ok(())
+    }
+
+    pub async fn fetch_hashes(&self) -> Result<HashSet<String>, sqlx::Error> {
+        let query = "SELECT magnet_hash FROM blocked_releases";
+
+        let hashes = sqlx::query_scalar::<_, String>(query)
+            .fetch_all(&self.db.db)
+            .await?;
+
+        Ok(hashes.into_iter().collect())
+    }
+}
\ No newline at end of file
@@ -1,26 +1,17 @@
+use std::collections::HashSet;
 use std::ops::Not;
 
 use chrono::Local;
 use qbittorrent::data::Hash;
-use rayon::prelude::*;
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 use sqlx::{Postgres, QueryBuilder, Row};
+use utoipa::ToSchema;
 
 use crate::api::imdb::{IMDBEpisode, ItemType};
 use crate::api::torrent::MediaQuality;
 use crate::server::download::TorrentQuery;
 
-use super::DBConnection;
-
-// Voodoo shit from https://github.com/rust-lang/rust/issues/89976#issuecomment-1073115246
-// Fixes Tokio error with thinking a generic lifetime is for a specific lifetime when it needs to be generic.
-// thanks https://github.com/petrosagg
-fn type_hint<F>(f: F) -> F
-where
-    F: for<'a> FnMut(&'a IMDBEpisode) -> i32,
-{
-    f
-}
+use super::{BATCH_SIZE, DBConnection};
 
 #[derive(sqlx::FromRow, Serialize)]
 pub struct ActiveDownloadItem {
@@ -39,8 +30,9 @@ pub struct ActiveDownloadItem {
     pub updated_at: chrono::DateTime<Local>,
 }
 
-#[derive(sqlx::FromRow, Serialize)]
+#[derive(sqlx::FromRow, Serialize, ToSchema)]
 pub struct ActiveDownloadIMDBItem {
+    pub id: i32,
     pub imdb_id: String,
     pub season: Option<i32>,
     pub episode: Option<i32>,
@@ -53,6 +45,149 @@ pub struct ActiveDownloadIMDBItem {
     pub image_url: String,
     pub rating: String,
     pub runtime: Option<i64>,
+    pub import_state: Option<String>,
+    pub magnet_hash: String,
+    // Bytes per second
+    pub download_speed: i64,
+    // Seconds, None when qBittorrent can't estimate it
+    pub eta: Option<i64>,
+    pub release_name: Option<String>,
+    pub source: Option<String>,
+    // Bytes
+    pub size: Option<i64>,
+}
+
+// A finished download waiting to show up in the media server
+#[derive(sqlx::FromRow)]
+pub struct PendingImport {
+    pub id: i32,
+    pub imdb_id: String,
+    pub magnet_hash: String,
+    pub season: Option<i32>,
+    pub episode: Option<i32>,
+    pub completed_at: chrono::DateTime<Local>,
+    pub title: Option<String>,
+    pub year: Option<i64>,
+}
+
+// A finished download, with what's needed to check its files and search for it again
+#[derive(sqlx::FromRow, Debug, Clone, Serialize, Deserialize)]
+pub struct FinishedDownload {
+    pub id: i32,
+    pub imdb_id: String,
+    pub season: Option<i32>,
+    pub episode: Option<i32>,
+    pub quality: String,
+    pub _type: ItemType,
+    pub title: Option<String>,
+    // Real IMDb id, as TheMovieDB downloads are stored under their TheMovieDB id
+    pub search_id: Option<String>,
+    // Seconds
+    pub runtime: Option<i64>,
+}
+
+pub const IMPORT_PENDING: &str = "pending";
+pub const IMPORT_FAILED: &str = "failed";
+
+pub const DOWNLOADS_PAGE_SIZE: i64 = 24;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DownloadStateFilter {
+    All,
+    Downloading,
+    Stalled,
+    Completed,
+    ImportFailed,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DownloadSort {
+    Added,
+    // Oldest first, the reverse of Added
+    Age,
+    Progress,
+    Title,
+}
+
+// Everything the downloads view can be narrowed by, None matches any value
+#[derive(Debug, Clone)]
+pub struct DownloadFilter {
+    pub state: DownloadStateFilter,
+    pub item_type: Option<ItemType>,
+    pub quality: Option<MediaQuality>,
+}
+
+impl DownloadStateFilter {
+    pub fn from_param(param: &str) -> Self {
+        match param.to_ascii_lowercase().as_str() {
+            "downloading" | "active" => DownloadStateFilter::Downloading,
+            "stalled" => DownloadStateFilter::Stalled,
+            "completed" | "complete" | "finished" => DownloadStateFilter::Completed,
+            "failed" | "import_failed" => DownloadStateFilter::ImportFailed,
+            _ => DownloadStateFilter::All,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DownloadStateFilter::All => "all",
+            DownloadStateFilter::Downloading => "downloading",
+            DownloadStateFilter::Stalled => "stalled",
+            DownloadStateFilter::Completed => "completed",
+            DownloadStateFilter::ImportFailed => "failed",
+        }
+    }
+}
+
+impl DownloadFilter {
+    pub fn from_params(state: Option<&str>, item_type: Option<&str>, quality: Option<&str>) -> Self {
+        let item_type = match item_type.unwrap_or_default().to_ascii_lowercase().as_str() {
+            "movie" | "film" => Some(ItemType::Movie),
+            "tv" | "show" | "series" => Some(ItemType::TvShow),
+            _ => None,
+        };
+
+        DownloadFilter {
+            state: DownloadStateFilter::from_param(state.unwrap_or_default()),
+            item_type,
+            quality: quality.and_then(MediaQuality::from_label),
+        }
+    }
+
+    // Query string for links that keep the current filters
+    pub fn as_query(&self) -> String {
+        let item_type = match self.item_type {
+            Some(ItemType::Movie) => "movie",
+            Some(ItemType::TvShow) => "tv",
+            None => "all",
+        };
+        let quality = match self.quality {
+            Some(t) => t.to_string(),
+            None => "all".to_string(),
+        };
+
+        format!("type={}&state={}&quality={}", item_type, self.state.as_str(), quality)
+    }
+}
+
+impl DownloadSort {
+    pub fn from_param(param: &str) -> Self {
+        match param.to_ascii_lowercase().as_str() {
+            "age" | "oldest" => DownloadSort::Age,
+            "progress" => DownloadSort::Progress,
+            "title" | "name" => DownloadSort::Title,
+            _ => DownloadSort::Added,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DownloadSort::Added => "added",
+            DownloadSort::Age => "age",
+            DownloadSort::Progress => "progress",
+            DownloadSort::Title => "title",
+        }
+    }
 }
 
 pub struct DownloadDatabase<'a> {
@@ -65,29 +200,18 @@ impl<'a> DownloadDatabase<'a> {
     }
 
     pub async fn insert(&self, item: &TorrentQuery) -> Result<(), sqlx::Error> {
-        let query = "INSERT INTO active_downloads(imdb_id, season, episode, magnet_hash, quality, _type) VALUES ($1, $2, $3, $4, $5, $6);";
-
-        let _type = match item.episode {
-            Some(_) => ItemType::TvShow,
-            None => ItemType::Movie,
-        };
-
-        let hash = item
-            .magnet_uri
-            .split_at(20)
-            .1
-            .split_once('&')
-            .unwrap()
-            .0
-            .to_lowercase();
+        let query = "INSERT INTO active_downloads(imdb_id, season, episode, magnet_hash, quality, _type, release_name, source, size) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9);";
 
         sqlx::query(query)
             .bind(&item.imdb_id)
             .bind(item.season)
             .bind(item.episode)
-            .bind(hash)
+            .bind(query_hash(item))
             .bind(item.quality.to_string())
-            .bind(_type)
+            .bind(query_type(item))
+            .bind(Some(&item.name).filter(|n| !n.is_empty()))
+            .bind(Some(&item.source).filter(|s| !s.is_empty()))
+            .bind(item.size)
             .execute(&self.db.db)
             .await?;
 
@@ -103,7 +227,8 @@ impl<'a> DownloadDatabase<'a> {
 
         match episodes {
             None => {
-                query_builder.push("SELECT id FROM active_downloads WHERE imdb_id = ");
+                // Failed imports can be downloaded again
+                query_builder.push("SELECT id FROM active_downloads WHERE import_state IS DISTINCT FROM 'failed' AND imdb_id = ");
                 query_builder.push_bind(imdb_id);
                 let resp = query_builder.build().fetch_optional(&self.db.db).await?;
                 match resp {
@@ -121,44 +246,25 @@ impl<'a> DownloadDatabase<'a> {
                     }
                 });
 
-                let seasons = episodes.chunk_by(|a, b| a.season == b.season);
-
-                let mut downloading_episodes: Vec<(i32, i32)> = Vec::new();
-                for season in seasons {
-                    query_builder.reset();
-                    let season_number = season.first().unwrap().season;
-                    query_builder
-                        .push("SELECT season, episode FROM active_downloads WHERE imdb_id = ");
-                    query_builder.push_bind(imdb_id);
-                    query_builder.push(" AND season = ");
-                    query_builder.push_bind(season_number);
-
-                    query_builder.push(" AND episode in (");
-                    let mut episodes = season.iter().map(type_hint(|e| e.episode)).peekable();
-                    while let Some(episode) = episodes.next() {
-                        query_builder.push_bind(episode);
-                        if episodes.peek().is_some() {
-                            query_builder.push(", ");
-                        }
-                    }
-
-                    query_builder.push(" )");
-                    let mut resp: Vec<(i32, i32)> = query_builder
-                        .build_query_as()
-                        .fetch_all(&self.db.db)
-                        .await?;
-                    downloading_episodes.append(&mut resp);
-                }
+                // One query for every episode, matched as (season, episode) pairs
+                let seasons = episodes.iter().map(|e| e.season).collect::<Vec<i32>>();
+                let episode_numbers = episodes.iter().map(|e| e.episode).collect::<Vec<i32>>();
+                let query = "SELECT d.season, d.episode FROM active_downloads d \
+                    JOIN UNNEST($2::int[], $3::int[]) AS wanted(season, episode) ON d.season = wanted.season AND d.episode = wanted.episode \
+                    WHERE d.import_state IS DISTINCT FROM 'failed' AND d.imdb_id = $1";
+                let downloading_episodes: HashSet<(i32, i32)> = sqlx::query_as::<_, (i32, i32)>(query)
+                    .bind(imdb_id)
+                    .bind(seasons)
+                    .bind(episode_numbers)
+                    .fetch_all(&self.db.db)
+                    .await?
+                    .into_iter()
+                    .collect();
                 let sorted_len = episodes.len();
 
                 let filtered = episodes
-                    .into_par_iter()
-                    .filter(|e| {
-                        downloading_episodes
-                            .par_iter()
-                            .any(|a| a == &(e.season, e.episode))
-                            .not()
-                    })
+                    .into_iter()
+                    .filter(|e| downloading_episodes.contains(&(e.season, e.episode)).not())
                     .collect::<Vec<IMDBEpisode>>();
                 if filtered.is_empty() {
                     Ok((true, None)) // We are downloading everything
@@ -173,11 +279,56 @@ impl<'a> DownloadDatabase<'a> {
 
     pub async fn fetch_downloads_with_imdb_data(
         &self,
+        filter: &DownloadFilter,
+        sort: DownloadSort,
+        page: i64,
     ) -> anyhow::Result<Vec<ActiveDownloadIMDBItem>> {
         let mut query_builder: QueryBuilder<Postgres> = QueryBuilder::new(String::new());
         query_builder.push(
-            "SELECT imdb_id, season, episode, quality, active_downloads._type, state, progress, title, year, image_url, rating, runtime FROM active_downloads LEFT JOIN imdb ON active_downloads.imdb_id = imdb.id"
+            "SELECT active_downloads.id, imdb_id, season, episode, quality, active_downloads._type, state, progress, title, year, image_url, rating, runtime, import_state, magnet_hash, download_speed, eta, release_name, source, size FROM active_downloads LEFT JOIN imdb ON active_downloads.imdb_id = imdb.id"
         );
+
+        query_builder.push(" WHERE true");
+        match filter.state {
+            DownloadStateFilter::All => (),
+            DownloadStateFilter::Downloading => {
+                query_builder.push(" AND progress < 1 AND state IN ('downloading', 'forcedDL', 'metaDL', 'queuedDL', 'checkingDL', 'allocating')");
+            }
+            DownloadStateFilter::Stalled => {
+                query_builder.push(" AND progress < 1 AND state = 'stalledDL'");
+            }
+            DownloadStateFilter::Completed => {
+                query_builder.push(" AND progress >= 1");
+            }
+            DownloadStateFilter::ImportFailed => {
+                query_builder.push(" AND import_state = ");
+                query_builder.push_bind(IMPORT_FAILED);
+            }
+        }
+
+        if let Some(item_type) = &filter.item_type {
+            query_builder.push(" AND active_downloads._type = ");
+            query_builder.push_bind(item_type.clone());
+        }
+
+        if let Some(quality) = filter.quality {
+            query_builder.push(" AND quality = ");
+            query_builder.push_bind(quality.to_string());
+        }
+
+        match sort {
+            DownloadSort::Added => query_builder.push(" ORDER BY active_downloads.created_at DESC"),
+            DownloadSort::Age => query_builder.push(" ORDER BY active_downloads.created_at ASC"),
+            DownloadSort::Progress => query_builder.push(" ORDER BY progress DESC"),
+            DownloadSort::Title => query_builder.push(" ORDER BY title ASC, season ASC, episode ASC"),
+        };
+
+        // Secondary ordering keeps pages stable when the primary values are equal
+        query_builder.push(", active_downloads.id ASC LIMIT ");
+        query_builder.push_bind(DOWNLOADS_PAGE_SIZE);
+        query_builder.push(" OFFSET ");
+        query_builder.push_bind(page.max(0) * DOWNLOADS_PAGE_SIZE);
+
         let resp = query_builder
             .build_query_as::<ActiveDownloadIMDBItem>()
             .fetch_all(&self.db.db)
@@ -186,15 +337,29 @@ impl<'a> DownloadDatabase<'a> {
         Ok(resp)
     }
 
+    pub async fn count(&self) -> Result<i64, sqlx::Error> {
+        let query = "SELECT COUNT(*) FROM active_downloads";
+
+        sqlx::query_scalar::<_, i64>(query)
+            .fetch_one(&self.db.db)
+            .await
+    }
+
     pub async fn update(
         &self,
         hash: &str,
         state: &str,
         progress: f64,
+        download_speed: i64,
+        eta: Option<i64>,
     ) -> anyhow::Result<(), sqlx::Error> {
         let mut query_builder: QueryBuilder<Postgres> = QueryBuilder::new(String::new());
         query_builder.push("UPDATE active_downloads SET progress = ");
         query_builder.push_bind(progress);
+        query_builder.push(", download_speed = ");
+        query_builder.push_bind(download_speed);
+        query_builder.push(", eta = ");
+        query_builder.push_bind(eta);
         query_builder.push(", state = ");
         query_builder.push_bind(state);
         query_builder.push(", updated_at = ");
@@ -205,18 +370,34 @@ impl<'a> DownloadDatabase<'a> {
 
         Ok(())
     }
+    // Same as insert, one statement per BATCH_SIZE items
     pub async fn insert_many(&self, items: &[TorrentQuery]) -> Result<(), sqlx::Error> {
-        // TODO: FIX WITH ACTUAL PSQL STATEMENT OR SQLX FUNCTION THAT IS BETTER THAN THIS TRASH
-        for item in items.iter() {
-            self.insert(item).await?;
+        let mut tx = self.db.db.begin().await?;
+        for chunk in items.chunks(BATCH_SIZE) {
+            let mut query_builder: QueryBuilder<Postgres> = QueryBuilder::new(
+                "INSERT INTO active_downloads(imdb_id, season, episode, magnet_hash, quality, _type, release_name, source, size) ",
+            );
+            query_builder.push_values(chunk, |mut row, item| {
+                row.push_bind(&item.imdb_id)
+                    .push_bind(item.season)
+                    .push_bind(item.episode)
+                    .push_bind(query_hash(item))
+                    .push_bind(item.quality.to_string())
+                    .push_bind(query_type(item))
+                    .push_bind(Some(&item.name).filter(|n| !n.is_empty()))
+                    .push_bind(Some(&item.source).filter(|s| !s.is_empty()))
+                    .push_bind(item.size);
+            });
+            query_builder.build().execute(&mut *tx).await?;
         }
+        tx.commit().await?;
 
         Ok(())
     }
 
     pub async fn remove_all(&self) -> Result<(), sqlx::Error> {
         let mut query_builder: QueryBuilder<Postgres> =
-            QueryBuilder::new(String::from("DELETE FROM active_downloads"));
+            QueryBuilder::new(String::from("DELETE FROM active_downloads WHERE import_state IS NULL"));
         query_builder.build().execute(&self.db.db).await?;
 
         Ok(())
@@ -224,7 +405,7 @@ impl<'a> DownloadDatabase<'a> {
 
     pub async fn remove_all_finished(&self) -> Result<(), sqlx::Error> {
         let mut query_builder: QueryBuilder<Postgres> = QueryBuilder::new(String::from(
-            "DELETE FROM active_downloads WHERE state IN (",
+            "DELETE FROM active_downloads WHERE import_state IS NULL AND state IN (",
         ));
 
         query_builder.push_bind("pausedUP");
@@ -239,9 +420,9 @@ impl<'a> DownloadDatabase<'a> {
     pub async fn remove_manually_removed(
         &self,
         active_hashes: &[&Hash],
-    ) -> Result<(), sqlx::Error> {
+    ) -> Result<Vec<String>, sqlx::Error> {
         let mut query_builder: QueryBuilder<Postgres> = QueryBuilder::new(String::from(
-            "DELETE FROM active_downloads WHERE magnet_hash NOT IN (",
+            "DELETE FROM active_downloads WHERE import_state IS NULL AND magnet_hash NOT IN (",
         ));
 
         let len = active_hashes.len();
@@ -251,8 +432,110 @@ impl<'a> DownloadDatabase<'a> {
                 query_builder.push(",");
             }
         });
-        query_builder.push(")");
-        query_builder.build().execute(&self.db.db).await?;
+        query_builder.push(") RETURNING magnet_hash");
+        let removed = query_builder
+            .build_query_scalar::<String>()
+            .fetch_all(&self.db.db)
+            .await?;
+        Ok(removed)
+    }
+
+    // Completed downloads are kept until the media server has picked them up
+    pub async fn mark_completed(&self, hash: &str) -> Result<(), sqlx::Error> {
+        let query = "UPDATE active_downloads SET import_state = $1, completed_at = $2 WHERE magnet_hash = $3 AND import_state IS NULL";
+
+        let _ = sqlx::query(query)
+            .bind(IMPORT_PENDING)
+            .bind(Local::now())
+            .bind(hash)
+            .execute(&self.db.db)
+            .await?;
+
+        Ok(())
+    }
+
+    pub async fn fetch_pending_imports(&self) -> Result<Vec<PendingImport>, sqlx::Error> {
+        let query = "SELECT active_downloads.id, active_downloads.imdb_id, magnet_hash, season, episode, completed_at, COALESCE(imdb.title, moviedb.title) AS title, COALESCE(imdb.year, EXTRACT(YEAR FROM moviedb.release_date)::BIGINT) AS year FROM active_downloads LEFT JOIN imdb ON active_downloads.imdb_id = imdb.id LEFT JOIN moviedb ON active_downloads.imdb_id = 'tt' || moviedb.id::TEXT WHERE import_state = $1";
+
+        let items = sqlx::query_as::<_, PendingImport>(query)
+            .bind(IMPORT_PENDING)
+            .fetch_all(&self.db.db)
+            .await?;
+
+        Ok(items)
+    }
+
+    pub async fn fetch_by_hash(&self, hash: &str) -> Result<Option<FinishedDownload>, sqlx::Error> {
+        // TheMovieDB stores runtime in minutes, IMDb in seconds
+        let query = "SELECT active_downloads.id, active_downloads.imdb_id, season, episode, quality, active_downloads._type, COALESCE(imdb.title, moviedb.title) AS title, COALESCE(imdb.id, moviedb.imdb_id) AS search_id, COALESCE(imdb.runtime, moviedb.runtime * 60) AS runtime FROM active_downloads LEFT JOIN imdb ON active_downloads.imdb_id = imdb.id LEFT JOIN moviedb ON active_downloads.imdb_id = 'tt' || moviedb.id::TEXT WHERE magnet_hash = $1 LIMIT 1";
+
+        let item = sqlx::query_as::<_, FinishedDownload>(query)
+            .bind(hash)
+            .fetch_optional(&self.db.db)
+            .await?;
+
+        Ok(item)
+    }
+
+    pub async fn mark_import_failed(&self, id: i32) -> Result<(), sqlx::Error> {
+        let query = "UPDATE active_downloads SET import_state = $1 WHERE id = $2";
+
+        let _ = sqlx::query(query)
+            .bind(IMPORT_FAILED)
+            .bind(id)
+            .execute(&self.db.db)
+            .await?;
+
+        Ok(())
+    }
+
+    pub async fn fetch_active_hashes(&self) -> Result<HashSet<String>, sqlx::Error> {
+        let query = "SELECT magnet_hash FROM active_downloads WHERE import_state IS NULL";
+
+        let hashes = sqlx::query_scalar::<_, String>(query)
+            .fetch_all(&self.db.db)
+            .await?;
+
+        Ok(hashes.into_iter().collect())
+    }
+
+    pub async fn remove_by_hash(&self, hash: &str) -> Result<(), sqlx::Error> {
+        let query = "DELETE FROM active_downloads WHERE magnet_hash = $1";
+
+        let _ = sqlx::query(query)
+            .bind(hash)
+            .execute(&self.db.db)
+            .await?;
+
+        Ok(())
+    }
+
+    pub async fn remove(&self, id: i32) -> Result<(), sqlx::Error> {
+        let query = "DELETE FROM active_downloads WHERE id = $1";
+
+        let _ = sqlx::query(query)
+            .bind(id)
+            .execute(&self.db.db)
+            .await?;
+
         Ok(())
     }
 }
+
+fn query_hash(item: &TorrentQuery) -> String {
+    item.magnet_uri
+        .split_at(20)
+        .1
+        .split_once('&')
+        .unwrap()
+        .0
+        .to_lowercase()
+}
+
+// Anything without an episode, including season packs, is stored as a movie
+fn query_type(item: &TorrentQuery) -> ItemType {
+    match item.episode {
+        Some(_) => ItemType::TvShow,
+        None => ItemType::Movie,
+    }
+}
\ No newline at end of file
@@ -0,0 +1,100 @@
+use chrono::{Local, NaiveDate};
+use crate::api::imdb::IMDBEpisode;
+use super::DBConnection;
+
+pub struct EpisodeDatabase<'a> {
+    db: &'a DBConnection
+}
+
+impl<'a> EpisodeDatabase<'a> {
+    pub fn new(db: &'a DBConnection) -> EpisodeDatabase {
+        EpisodeDatabase {
+            db
+        }
+    }
+
+    pub async fn insert_or_update_many(&self, show_id: &str, episodes: &[IMDBEpisode]) -> Result<(), sqlx::Error> {
+        let query = "INSERT INTO tv_episodes(show_id, season, episode, air_date) VALUES ($1, $2, $3, $4) ON CONFLICT (show_id, season, episode) DO UPDATE SET air_date = COALESCE($4, tv_episodes.air_date), updated_at = $5";
+
+        for episode in episodes {
+            let _ = sqlx::query(query)
+                .bind(show_id)
+                .bind(episode.season)
+                .bind(episode.episode)
+                .bind(episode.air_date)
+                .bind(Local::now())
+                .execute(&self.db.db)
+                .await?;
+        }
+
+        Ok(())
+    }
+
+    pub async fn fetch_aired(&self, show_id: &str) -> Result<Vec<(i32, i32)>, sqlx::Error> {
+        let query = "SELECT season, episode FROM tv_episodes WHERE show_id = $1 AND air_date <= CURRENT_DATE";
+
+        let episodes = sqlx::query_as::<_, (i32, i32)>(query)
+            .bind(show_id)
+            .fetch_all(&self.db.db)
+            .await?;
+
+        Ok(episodes)
+    }
+
+    // Episodes known to have aired before the date, episodes without an air date aren't included
+    pub async fn fetch_aired_before(&self, show_id: &str, date: NaiveDate) -> Result<Vec<(i32, i32)>, sqlx::Error> {
+        let query = "SELECT season, episode FROM tv_episodes WHERE show_id = $1 AND air_date < $2";
+
+        let episodes = sqlx::query_as::<_, (i32, i32)>(query)
+            .bind(show_id)
+            .bind(date)
+            .fetch_all(&self.db.db)
+            .await?;
+
+        Ok(episodes)
+    }
+
+    // Episodes of the shows airing between the dates that don't have a search queued yet
+    pub async fn fetch_unscheduled(
+        &self,
+        show_ids: &[String],
+        from: NaiveDate,
+        to: NaiveDate,
+    ) -> Result<Vec<(String, i32, i32, NaiveDate)>, sqlx::Error> {
+        let query = "SELECT show_id, season, episode, air_date FROM tv_episodes WHERE show_id = ANY($1) AND air_date BETWEEN $2 AND $3 AND NOT search_scheduled";
+
+        let episodes = sqlx::query_as::<_, (String, i32, i32, NaiveDate)>(query)
+            .bind(show_ids)
+            .bind(from)
+            .bind(to)
+            .fetch_all(&self.db.db)
+            .await?;
+
+        Ok(episodes)
+    }
+
+    pub async fn mark_scheduled(&self, show_id: &str, season: i32, episode: i32) -> Result<(), sqlx::Error> {
+        let query = "UPDATE tv_episodes SET search_scheduled = true WHERE show_id = $1 AND season = $2 AND episode = $3";
+
+        let _ = sqlx::query(query)
+            .bind(show_id)
+            .bind(season)
+            .bind(episode)
+            .execute(&self.db.db)
+            .await?;
+
+        Ok(())
+    }
+
+    // Episodes airing from a week ago onwards, for the calendar feed
+    pub async fn fetch_upcoming(&self, show_ids: &[String]) -> Result<Vec<(String, i32, i32, NaiveDate)>, sqlx::Error> {
+        let query = "SELECT show_id, season, episode, air_date FROM tv_episodes WHERE show_id = ANY($1) AND air_date >= CURRENT_DATE - 7 ORDER BY air_date, show_id, season, episode";
+
+        let episodes = sqlx::query_as::<_, (String, i32, i32, NaiveDate)>(query)
+            .bind(show_ids)
+            .fetch_all(&self.db.db)
+            .await?;
+
+        Ok(episodes)
+    }
+}
\ No newline at end of file
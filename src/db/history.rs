@@ -0,0 +1,142 @@
+use chrono::Local;
+use serde::Serialize;
+
+use crate::server::download::TorrentQuery;
+
+use super::DBConnection;
+
+pub const HISTORY_PAGE_SIZE: i64 = 50;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum HistoryEvent {
+    Grabbed,
+    Completed,
+    Failed,
+    Deleted,
+    Imported,
+    // A watchlist download is ready to watch, in the media server if there is one
+    Available,
+}
+
+impl HistoryEvent {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            HistoryEvent::Grabbed => "grabbed",
+            HistoryEvent::Completed => "completed",
+            HistoryEvent::Failed => "failed",
+            HistoryEvent::Deleted => "deleted",
+            HistoryEvent::Imported => "imported",
+            HistoryEvent::Available => "available",
+        }
+    }
+}
+
+#[derive(sqlx::FromRow, Serialize)]
+pub struct HistoryItem {
+    pub id: i32,
+    pub imdb_id: String,
+    pub season: Option<i32>,
+    pub episode: Option<i32>,
+    pub magnet_hash: String,
+    pub release_name: Option<String>,
+    pub source: Option<String>,
+    pub size: Option<i64>,
+    pub event: String,
+    pub detail: Option<String>,
+    pub title: Option<String>,
+    pub created_at: chrono::DateTime<Local>,
+}
+
+pub struct HistoryDatabase<'a> {
+    db: &'a DBConnection
+}
+
+impl<'a> HistoryDatabase<'a> {
+    pub fn new(db: &'a DBConnection) -> HistoryDatabase {
+        HistoryDatabase {
+            db
+        }
+    }
+
+    pub async fn insert_grab(&self, item: &TorrentQuery) -> Result<(), sqlx::Error> {
+        let query = "INSERT INTO download_history(imdb_id, season, episode, magnet_hash, release_name, source, size, event) VALUES ($1, $2, $3, $4, $5, $6, $7, $8)";
+
+        let _ = sqlx::query(query)
+            .bind(&item.imdb_id)
+            .bind(item.season)
+            .bind(item.episode)
+            .bind(item.magnet_hash())
+            .bind(Some(&item.name).filter(|n| !n.is_empty()))
+            .bind(Some(&item.source).filter(|s| !s.is_empty()))
+            .bind(item.size)
+            .bind(HistoryEvent::Grabbed.as_str())
+            .execute(&self.db.db)
+            .await?;
+
+        Ok(())
+    }
+
+    // Files imported by hand have no torrent behind them
+    pub async fn insert_manual_import(
+        &self,
+        imdb_id: &str,
+        season: Option<i32>,
+        episode: Option<i32>,
+        file_name: &str,
+        target: &str,
+    ) -> Result<(), sqlx::Error> {
+        let query = "INSERT INTO download_history(imdb_id, season, episode, magnet_hash, release_name, source, event, detail) VALUES ($1, $2, $3, '', $4, 'manual', $5, $6)";
+
+        let _ = sqlx::query(query)
+            .bind(imdb_id)
+            .bind(season)
+            .bind(episode)
+            .bind(file_name)
+            .bind(HistoryEvent::Imported.as_str())
+            .bind(target)
+            .execute(&self.db.db)
+            .await?;
+
+        Ok(())
+    }
+
+    // Later events copy the details from the grab, torrents roundup didn't start aren't recorded
+    pub async fn insert_event(&self, magnet_hash: &str, event: HistoryEvent, detail: Option<&str>) -> Result<(), sqlx::Error> {
+        let query = "INSERT INTO download_history(imdb_id, season, episode, magnet_hash, release_name, source, size, event, detail) SELECT imdb_id, season, episode, magnet_hash, release_name, source, size, $2, $3 FROM download_history WHERE magnet_hash = $1 AND event = $4 ORDER BY created_at DESC LIMIT 1";
+
+        let _ = sqlx::query(query)
+            .bind(magnet_hash)
+            .bind(event.as_str())
+            .bind(detail)
+            .bind(HistoryEvent::Grabbed.as_str())
+            .execute(&self.db.db)
+            .await?;
+
+        Ok(())
+    }
+
+    pub async fn fetch(&self, page: i64) -> Result<Vec<HistoryItem>, sqlx::Error> {
+        let query = "SELECT download_history.id, download_history.imdb_id, season, episode, magnet_hash, release_name, source, size, event, detail, COALESCE(imdb.title, moviedb.title) AS title, download_history.created_at FROM download_history LEFT JOIN imdb ON download_history.imdb_id = imdb.id LEFT JOIN moviedb ON download_history.imdb_id = 'tt' || moviedb.id::TEXT ORDER BY download_history.created_at DESC, download_history.id DESC LIMIT $1 OFFSET $2";
+
+        let items = sqlx::query_as::<_, HistoryItem>(query)
+            .bind(HISTORY_PAGE_SIZE)
+            .bind(page * HISTORY_PAGE_SIZE)
+            .fetch_all(&self.db.db)
+            .await?;
+
+        Ok(items)
+    }
+
+    // Newest grabs, completions and watchlist items becoming available, for the activity feed
+    pub async fn fetch_activity(&self, limit: i64) -> Result<Vec<HistoryItem>, sqlx::Error> {
+        let query = "SELECT download_history.id, download_history.imdb_id, season, episode, magnet_hash, release_name, source, size, event, detail, COALESCE(imdb.title, moviedb.title) AS title, download_history.created_at FROM download_history LEFT JOIN imdb ON download_history.imdb_id = imdb.id LEFT JOIN moviedb ON download_history.imdb_id = 'tt' || moviedb.id::TEXT WHERE event = ANY($1) ORDER BY download_history.created_at DESC, download_history.id DESC LIMIT $2";
+
+        let items = sqlx::query_as::<_, HistoryItem>(query)
+            .bind([HistoryEvent::Grabbed.as_str(), HistoryEvent::Completed.as_str(), HistoryEvent::Available.as_str()])
+            .bind(limit)
+            .fetch_all(&self.db.db)
+            .await?;
+
+        Ok(items)
+    }
+}
\ No newline at end of file
@@ -1,8 +1,9 @@
+use std::collections::HashSet;
 use std::ops::Not;
 use anyhow::format_err;
 use sqlx::{Postgres, QueryBuilder};
 use crate::api::imdb::{IMDBItem, SearchType};
-use super::DBConnection;
+use super::{BATCH_SIZE, DBConnection, Pagination};
 
 pub struct IMDBDatabase<'a> {
     db: &'a DBConnection
@@ -35,14 +36,14 @@ impl<'a> IMDBDatabase<'a> {
         Ok(())
     }
 
-    pub async fn fetch(&self, search_type: SearchType) -> anyhow::Result<Vec<IMDBItem>, sqlx::Error> {
+    pub async fn fetch(&self, search_type: SearchType, pagination: Pagination) -> anyhow::Result<Vec<IMDBItem>, sqlx::Error> {
         let mut query_builder: QueryBuilder<Postgres> = QueryBuilder::new(String::from("SELECT * FROM imdb WHERE "));
 
         match search_type {
             SearchType::Query(s) => {
                 query_builder.push("title SIMILAR TO '");
                 query_builder.push_bind(s.to_ascii_lowercase());
-                query_builder.push("*'");
+                query_builder.push("*' ORDER BY title ASC");
             }
             SearchType::MoviePopular => {
                 query_builder.push("_type = 'movie' AND popularity_rank IS NOT NULL ORDER BY popularity_rank ASC");
@@ -57,11 +58,13 @@ impl<'a> IMDBDatabase<'a> {
                 query_builder.push("_type = 'tvshow' AND release_order IS NOT NULL ORDER BY release_order ASC");
             }
             SearchType::Watchlist => {
-                query_builder.push("watchlist = true");
+                query_builder.push("watchlist = true ORDER BY title ASC");
             },
             SearchType::Downloads => unreachable!()
         };
 
+        pagination.push_limit(&mut query_builder);
+
         let resp = query_builder
             .build_query_as::<IMDBItem>()
             .fetch_all(&self.db.db)
@@ -174,11 +177,74 @@ impl<'a> IMDBDatabase<'a> {
         Ok(())
     }
 
+    // Plot, rating and runtime are fetched again once they're older than max_age_days, negative never refetches
+    pub async fn is_metadata_stale(&self, id: &str, max_age_days: i64) -> Result<bool, sqlx::Error> {
+        if max_age_days < 0 {
+            return Ok(false);
+        }
+        let query = "SELECT metadata_updated_at IS NULL OR metadata_updated_at < now() - make_interval(days => $2) FROM imdb WHERE id = $1";
+
+        let stale = sqlx::query_scalar::<_, bool>(query)
+            .bind(id)
+            .bind(max_age_days.clamp(0, i32::MAX as i64) as i32)
+            .fetch_optional(&self.db.db)
+            .await?;
+
+        Ok(stale.unwrap_or(true))
+    }
+
+    pub async fn mark_metadata_updated(&self, id: &str) -> Result<(), sqlx::Error> {
+        let query = "UPDATE imdb SET metadata_updated_at = now() WHERE id = $1";
+
+        let _ = sqlx::query(query)
+            .bind(id)
+            .execute(&self.db.db)
+            .await?;
+
+        Ok(())
+    }
+
+    pub async fn update_artwork(&self, id: &str, poster_url: Option<&str>, backdrop_url: Option<&str>) -> anyhow::Result<(), sqlx::Error> {
+        let query = "UPDATE imdb SET poster_url = COALESCE($2, poster_url), backdrop_url = COALESCE($3, backdrop_url) WHERE id = $1";
+
+        let _ = sqlx::query(query)
+            .bind(id)
+            .bind(poster_url)
+            .bind(backdrop_url)
+            .execute(&self.db.db)
+            .await?;
+
+        Ok(())
+    }
+
+    // Same as insert_or_update, one statement per BATCH_SIZE items
     pub async fn insert_or_update_many(&self, items: &[IMDBItem]) -> Result<(), sqlx::Error> {
-        // TODO: FIX WITH ACTUAL PSQL STATEMENT OR SQLX FUNCTION THAT IS BETTER THAN THIS TRASH
-        for item in items {
-            self.insert_or_update(item).await?;
+        // A statement can't update the same row twice, so only the last copy of an item is kept
+        let mut seen = HashSet::new();
+        let mut items = items.iter().rev().filter(|i| seen.insert(&i.id)).collect::<Vec<&IMDBItem>>();
+        items.reverse();
+
+        let mut tx = self.db.db.begin().await?;
+        for chunk in items.chunks(BATCH_SIZE) {
+            let mut query_builder: QueryBuilder<Postgres> = QueryBuilder::new(
+                "INSERT INTO imdb as i_db(id, title, year, image_url, rating, popularity_rank, release_order, _type, created_at, updated_at) ",
+            );
+            query_builder.push_values(chunk, |mut row, item| {
+                row.push_bind(&item.id)
+                    .push_bind(&item.title)
+                    .push_bind(item.year)
+                    .push_bind(&item.image_url)
+                    .push_bind(&item.rating)
+                    .push_bind(item.popularity_rank)
+                    .push_bind(item.release_order)
+                    .push_bind(&item._type)
+                    .push_bind(item.created_at)
+                    .push_bind(item.updated_at);
+            });
+            query_builder.push(" ON CONFLICT (id) DO UPDATE SET image_url = EXCLUDED.image_url, popularity_rank = COALESCE(EXCLUDED.popularity_rank, i_db.popularity_rank), release_order = COALESCE(EXCLUDED.release_order, i_db.release_order), updated_at = EXCLUDED.updated_at");
+            query_builder.build().execute(&mut *tx).await?;
         }
+        tx.commit().await?;
 
         Ok(())
     }
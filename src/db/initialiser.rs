@@ -1,8 +1,9 @@
 use log::info;
-use sqlx::Executor;
 
 use super::DBConnection;
 
+// Schema changes live in ./migrations and are embedded in the binary. Each one runs once, in order, and is recorded
+// in _sqlx_migrations. Add a new file with the next number for any change, never edit one that's been released
 pub struct DatabaseInitialiser<'a> {
     db: &'a DBConnection,
 }
@@ -13,20 +14,7 @@ impl<'a> DatabaseInitialiser<'a> {
     }
     pub async fn initialise(&self) -> anyhow::Result<()> {
         info!("Initialising Database");
-        let mut tx = self.db.db.begin().await?;
-
-        let item_type_sql = include_str!("sql/item_type.sql");
-        let imdb_sql = include_str!("sql/imdb.sql");
-        let moviedb_sql = include_str!("sql/moviedb.sql");
-        let active_downloads_sql = include_str!("sql/downloads.sql");
-
-        // Doesn't return anything useful on success or error so can ignore, if it fails the app just won't work
-        tx.execute(item_type_sql).await?;
-        tx.execute(imdb_sql).await?;
-        tx.execute(moviedb_sql).await?;
-        tx.execute(active_downloads_sql).await?;
-
-        tx.commit().await?;
+        sqlx::migrate!("./migrations").run(&self.db.db).await?;
         Ok(())
     }
-}
+}
\ No newline at end of file
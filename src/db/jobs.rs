@@ -0,0 +1,121 @@
+use chrono::Local;
+use serde::Serialize;
+use sqlx::FromRow;
+use utoipa::ToSchema;
+
+use super::DBConnection;
+
+pub const JOB_QUEUED: &str = "queued";
+pub const JOB_RUNNING: &str = "running";
+pub const JOB_DONE: &str = "done";
+pub const JOB_FAILED: &str = "failed";
+
+// Queued background work, the payload is the job as JSON
+pub struct JobDatabase<'a> {
+    db: &'a DBConnection
+}
+
+#[derive(Debug, Serialize, FromRow, ToSchema)]
+pub struct JobRecord {
+    pub id: i64,
+    pub kind: String,
+    #[serde(skip)]
+    pub payload: String,
+    pub state: String,
+    pub attempts: i32,
+    pub last_error: Option<String>,
+    pub run_at: chrono::DateTime<Local>,
+    pub created_at: chrono::DateTime<Local>,
+    pub updated_at: chrono::DateTime<Local>,
+}
+
+impl<'a> JobDatabase<'a> {
+    pub fn new(db: &'a DBConnection) -> JobDatabase {
+        JobDatabase {
+            db
+        }
+    }
+
+    pub async fn insert(&self, kind: &str, payload: &str, run_at: chrono::DateTime<Local>) -> Result<i64, sqlx::Error> {
+        let query = "INSERT INTO jobs(kind, payload, run_at) VALUES ($1, $2, $3) RETURNING id";
+
+        sqlx::query_scalar::<_, i64>(query)
+            .bind(kind)
+            .bind(payload)
+            .bind(run_at)
+            .fetch_one(&self.db.db)
+            .await
+    }
+
+    // Marks the next due job as running and returns it. SKIP LOCKED keeps two workers from taking the same job
+    pub async fn claim_next(&self) -> Result<Option<JobRecord>, sqlx::Error> {
+        let query = "UPDATE jobs SET state = $1, attempts = attempts + 1, updated_at = $2 WHERE id = (SELECT id FROM jobs WHERE state = $3 AND run_at <= $2 ORDER BY run_at LIMIT 1 FOR UPDATE SKIP LOCKED) RETURNING *";
+
+        sqlx::query_as::<_, JobRecord>(query)
+            .bind(JOB_RUNNING)
+            .bind(Local::now())
+            .bind(JOB_QUEUED)
+            .fetch_optional(&self.db.db)
+            .await
+    }
+
+    pub async fn complete(&self, id: i64) -> Result<(), sqlx::Error> {
+        self.set_state(id, JOB_DONE, None).await
+    }
+
+    pub async fn fail(&self, id: i64, error: &str) -> Result<(), sqlx::Error> {
+        self.set_state(id, JOB_FAILED, Some(error)).await
+    }
+
+    pub async fn retry(&self, id: i64, error: &str, run_at: chrono::DateTime<Local>) -> Result<(), sqlx::Error> {
+        let query = "UPDATE jobs SET state = $2, last_error = $3, run_at = $4, updated_at = $5 WHERE id = $1";
+
+        let _ = sqlx::query(query)
+            .bind(id)
+            .bind(JOB_QUEUED)
+            .bind(error)
+            .bind(run_at)
+            .bind(Local::now())
+            .execute(&self.db.db)
+            .await?;
+
+        Ok(())
+    }
+
+    // Jobs that were running when roundup stopped are started again
+    pub async fn requeue_running(&self) -> Result<u64, sqlx::Error> {
+        let query = "UPDATE jobs SET state = $1, updated_at = $2 WHERE state = $3";
+
+        let result = sqlx::query(query)
+            .bind(JOB_QUEUED)
+            .bind(Local::now())
+            .bind(JOB_RUNNING)
+            .execute(&self.db.db)
+            .await?;
+
+        Ok(result.rows_affected())
+    }
+
+    pub async fn fetch_recent(&self, limit: i64) -> Result<Vec<JobRecord>, sqlx::Error> {
+        let query = "SELECT * FROM jobs ORDER BY created_at DESC LIMIT $1";
+
+        sqlx::query_as::<_, JobRecord>(query)
+            .bind(limit)
+            .fetch_all(&self.db.db)
+            .await
+    }
+
+    async fn set_state(&self, id: i64, state: &str, error: Option<&str>) -> Result<(), sqlx::Error> {
+        let query = "UPDATE jobs SET state = $2, last_error = COALESCE($3, last_error), updated_at = $4 WHERE id = $1";
+
+        let _ = sqlx::query(query)
+            .bind(id)
+            .bind(state)
+            .bind(error)
+            .bind(Local::now())
+            .execute(&self.db.db)
+            .await?;
+
+        Ok(())
+    }
+}
\ No newline at end of file
@@ -0,0 +1,127 @@
+use chrono::Local;
+use crate::api::imdb::ItemType;
+use super::DBConnection;
+
+#[derive(Debug, sqlx::FromRow)]
+pub struct LibraryItem {
+    pub path: String,
+    pub title: String, // Normalised, see media_server::normalise_title
+    pub year: Option<i32>,
+    pub season: Option<i32>,
+    pub episode: Option<i32>,
+    pub _type: ItemType,
+    pub size: i64,
+    pub quality: Option<String>, // eg "1080p", from the file name or ffprobe
+}
+
+pub struct LibraryDatabase<'a> {
+    db: &'a DBConnection
+}
+
+impl<'a> LibraryDatabase<'a> {
+    pub fn new(db: &'a DBConnection) -> LibraryDatabase {
+        LibraryDatabase {
+            db
+        }
+    }
+
+    pub async fn insert_or_update_many(&self, items: &[LibraryItem]) -> Result<(), sqlx::Error> {
+        let query = "INSERT INTO library_items(path, title, year, season, episode, _type, size, quality) VALUES ($1, $2, $3, $4, $5, $6, $7, $9) ON CONFLICT (path) DO UPDATE SET title = $2, year = $3, season = $4, episode = $5, _type = $6, size = $7, updated_at = $8, quality = $9";
+
+        let now = Local::now();
+        for item in items {
+            let _ = sqlx::query(query)
+                .bind(&item.path)
+                .bind(&item.title)
+                .bind(item.year)
+                .bind(item.season)
+                .bind(item.episode)
+                .bind(&item._type)
+                .bind(item.size)
+                .bind(now)
+                .bind(&item.quality)
+                .execute(&self.db.db)
+                .await?;
+        }
+
+        Ok(())
+    }
+
+    // Anything under root that wasn't seen since scan_started has been deleted from disk
+    pub async fn remove_stale(&self, root: &str, scan_started: chrono::DateTime<Local>) -> Result<(), sqlx::Error> {
+        let query = "DELETE FROM library_items WHERE starts_with(path, $1) AND updated_at < $2";
+
+        let _ = sqlx::query(query)
+            .bind(root)
+            .bind(scan_started)
+            .execute(&self.db.db)
+            .await?;
+
+        Ok(())
+    }
+
+    pub async fn fetch_by_title(&self, title: &str, _type: ItemType) -> Result<Vec<LibraryItem>, sqlx::Error> {
+        let query = "SELECT path, title, year, season, episode, _type, size, quality FROM library_items WHERE title = $1 AND _type = $2";
+
+        let items = sqlx::query_as::<_, LibraryItem>(query)
+            .bind(title)
+            .bind(_type)
+            .fetch_all(&self.db.db)
+            .await?;
+
+        Ok(items)
+    }
+
+    // Previously probed files, so unchanged files don't need probing again
+    pub async fn fetch_known_qualities(&self, root: &str) -> Result<Vec<(String, i64, String)>, sqlx::Error> {
+        let query = "SELECT path, size, quality FROM library_items WHERE starts_with(path, $1) AND quality IS NOT NULL";
+
+        let items = sqlx::query_as::<_, (String, i64, String)>(query)
+            .bind(root)
+            .fetch_all(&self.db.db)
+            .await?;
+
+        Ok(items)
+    }
+
+    pub async fn count_by_type(&self) -> Result<Vec<(ItemType, i64)>, sqlx::Error> {
+        let query = "SELECT _type, COUNT(*) FROM library_items GROUP BY _type";
+
+        let counts = sqlx::query_as::<_, (ItemType, i64)>(query)
+            .fetch_all(&self.db.db)
+            .await?;
+
+        Ok(counts)
+    }
+
+    pub async fn count_shows(&self) -> Result<i64, sqlx::Error> {
+        let query = "SELECT COUNT(DISTINCT (title, year)) FROM library_items WHERE _type = 'tvshow'";
+
+        let count = sqlx::query_scalar::<_, i64>(query)
+            .fetch_one(&self.db.db)
+            .await?;
+
+        Ok(count)
+    }
+
+    pub async fn count_movie_qualities(&self) -> Result<Vec<(Option<String>, i64)>, sqlx::Error> {
+        let query = "SELECT quality, COUNT(*) FROM library_items WHERE _type = 'movie' GROUP BY quality";
+
+        let counts = sqlx::query_as::<_, (Option<String>, i64)>(query)
+            .fetch_all(&self.db.db)
+            .await?;
+
+        Ok(counts)
+    }
+
+    pub async fn fetch_recent(&self, limit: i64) -> Result<Vec<(String, Option<i32>, ItemType, chrono::DateTime<Local>)>, sqlx::Error> {
+        let query = "SELECT path, year, _type, created_at FROM library_items ORDER BY created_at DESC LIMIT $1";
+
+        let items = sqlx::query_as::<_, (String, Option<i32>, ItemType, chrono::DateTime<Local>)>(query)
+            .bind(limit)
+            .fetch_all(&self.db.db)
+            .await?;
+
+        Ok(items)
+    }
+}
\ No newline at end of file
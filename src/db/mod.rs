@@ -1,24 +1,127 @@
+use std::time::Duration;
+
+use log::warn;
+use sqlx::{Postgres, QueryBuilder};
 use sqlx::postgres::{PgPool, PgPoolOptions};
 
+pub mod aliases;
+pub mod api_keys;
+pub mod attempts;
+pub mod blocklist;
 pub mod downloads;
+pub mod episodes;
+pub mod history;
 pub mod imdb;
 pub mod initialiser;
+pub mod jobs;
+pub mod library;
 pub mod moviedb;
+pub mod plex;
+pub mod preferences;
+pub mod profiles;
+pub mod retention;
+pub mod settings;
+pub mod similar;
+pub mod stats;
+pub mod upgrades;
+pub mod watchlist_imports;
+pub mod watchlist_options;
+pub mod watchlist_states;
 
 #[derive(Clone)]
 pub struct DBConnection {
     db: PgPool,
 }
 
+// Connection pool settings from the config
+#[derive(Debug, Clone, Copy)]
+pub struct PoolSettings {
+    pub max_connections: u32,
+    pub acquire_timeout: Duration,
+    // Attempts after the first, for when Postgres is still starting, eg in docker-compose
+    pub connect_retries: u32,
+}
+
+static MAX_RETRY_DELAY: Duration = Duration::from_secs(30);
+
 impl DBConnection {
-    pub async fn new(connection_uri: &str) -> Result<DBConnection, sqlx::Error> {
-        let pool = PgPoolOptions::new().connect(connection_uri).await?;
-        Ok(DBConnection { db: pool })
+    pub async fn new(connection_uri: &str, settings: &PoolSettings) -> Result<DBConnection, sqlx::Error> {
+        let options = PgPoolOptions::new()
+            .max_connections(settings.max_connections)
+            .acquire_timeout(settings.acquire_timeout);
+
+        let mut delay = Duration::from_secs(1);
+        let mut attempt = 0;
+        loop {
+            match options.clone().connect(connection_uri).await {
+                Ok(pool) => return Ok(DBConnection { db: pool }),
+                Err(e) if attempt < settings.connect_retries && is_retryable(&e) => {
+                    attempt += 1;
+                    warn!(
+                        "Database isn't reachable yet, retrying in {}s ({}/{}): {}",
+                        delay.as_secs(), attempt, settings.connect_retries, e
+                    );
+                    tokio::time::sleep(delay).await;
+                    delay = (delay * 2).min(MAX_RETRY_DELAY);
+                }
+                Err(e) => return Err(e),
+            }
+        }
     }
 
-    pub async fn from_env(key: &str) -> anyhow::Result<DBConnection> {
+    pub async fn from_env(key: &str, settings: &PoolSettings) -> anyhow::Result<DBConnection> {
         let connection_uri = std::env::var(key)?;
-        let pool = PgPoolOptions::new().connect(&connection_uri).await?;
-        Ok(DBConnection { db: pool })
+        Ok(DBConnection::new(&connection_uri, settings).await?)
     }
 }
+
+// The server being down or still starting up, rather than bad credentials or a missing database
+fn is_retryable(error: &sqlx::Error) -> bool {
+    match error {
+        sqlx::Error::Io(_) | sqlx::Error::PoolTimedOut => true,
+        // cannot_connect_now, "the database system is starting up"
+        sqlx::Error::Database(e) => e.code().is_some_and(|c| c == "57P03"),
+        _ => false,
+    }
+}
+
+pub const SEARCH_PAGE_SIZE: i64 = 48;
+// Rows per multi-row INSERT, Postgres allows at most 65535 bind parameters in a statement
+pub const BATCH_SIZE: usize = 1000;
+pub const MAX_PAGE_SIZE: i64 = 200;
+
+// A page of search results, pages start at 0
+#[derive(Debug, Clone, Copy)]
+pub struct Pagination {
+    pub page: i64,
+    pub limit: i64,
+}
+
+impl Pagination {
+    pub fn new(page: Option<i64>, limit: Option<i64>) -> Self {
+        Pagination {
+            page: page.unwrap_or(0).max(0),
+            limit: limit.unwrap_or(SEARCH_PAGE_SIZE).clamp(1, MAX_PAGE_SIZE),
+        }
+    }
+
+    pub fn offset(&self) -> i64 {
+        self.page * self.limit
+    }
+
+    pub fn push_limit(&self, query_builder: &mut QueryBuilder<Postgres>) {
+        query_builder.push(" LIMIT ");
+        query_builder.push_bind(self.limit);
+        query_builder.push(" OFFSET ");
+        query_builder.push_bind(self.offset());
+    }
+
+    // For results that came straight from IMDb/TheMovieDB rather than the database
+    pub fn apply<T>(&self, items: Vec<T>) -> Vec<T> {
+        items
+            .into_iter()
+            .skip(self.offset() as usize)
+            .take(self.limit as usize)
+            .collect()
+    }
+}
\ No newline at end of file
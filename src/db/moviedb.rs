@@ -1,7 +1,9 @@
+use std::collections::HashSet;
+
 use sqlx::{Postgres, QueryBuilder};
 use crate::api::imdb::{SearchType};
 use crate::api::moviedb::MovieDBItem;
-use super::DBConnection;
+use super::{BATCH_SIZE, DBConnection, Pagination};
 
 pub struct MovieDBDatabase<'a> {
     db: &'a DBConnection
@@ -38,33 +40,35 @@ impl<'a> MovieDBDatabase<'a> {
         Ok(())
     }
 
-    pub async fn fetch(&self, search_type: SearchType) -> anyhow::Result<Vec<MovieDBItem>, sqlx::Error> {
+    pub async fn fetch(&self, search_type: SearchType, pagination: Pagination) -> anyhow::Result<Vec<MovieDBItem>, sqlx::Error> {
         let mut query_builder: QueryBuilder<Postgres> = QueryBuilder::new(String::from("SELECT * FROM moviedb WHERE "));
 
         match search_type {
             SearchType::Query(s) => {
                 query_builder.push("title SIMILAR TO '");
                 query_builder.push_bind(s.to_ascii_lowercase());
-                query_builder.push("*'");
+                query_builder.push("*' ORDER BY title ASC");
             }
             SearchType::MoviePopular => {
-                query_builder.push("_type = 'movie' AND popularity_rank IS NOT NULL ORDER BY popularity_rank ASC LIMIT 100");
+                query_builder.push("_type = 'movie' AND popularity_rank IS NOT NULL ORDER BY popularity_rank ASC");
             }
             SearchType::MovieLatestRelease => {
-                query_builder.push("_type = 'movie' AND release_date IS NOT NULL ORDER BY release_date DESC LIMIT 100");
+                query_builder.push("_type = 'movie' AND release_date IS NOT NULL ORDER BY release_date DESC");
             }
             SearchType::TVPopular => {
-                query_builder.push("_type = 'tvshow' AND popularity_rank IS NOT NULL ORDER BY popularity_rank ASC LIMIT 100");
+                query_builder.push("_type = 'tvshow' AND popularity_rank IS NOT NULL ORDER BY popularity_rank ASC");
             }
             SearchType::TVLatestRelease => {
-                query_builder.push("_type = 'tvshow' AND release_date IS NOT NULL ORDER BY release_date DESC LIMIT 100");
+                query_builder.push("_type = 'tvshow' AND release_date IS NOT NULL ORDER BY release_date DESC");
             }
             SearchType::Watchlist => {
-                query_builder.push("watchlist = true");
+                query_builder.push("watchlist = true ORDER BY title ASC");
             },
             SearchType::Downloads => unreachable!(),
         };
 
+        pagination.push_limit(&mut query_builder);
+
         let resp = query_builder
             .build_query_as::<MovieDBItem>()
             .fetch_all(&self.db.db)
@@ -84,6 +88,17 @@ impl<'a> MovieDBDatabase<'a> {
         Ok(items)
     }
 
+    pub async fn fetch_item_by_imdb_id(&self, imdb_id: &str) -> anyhow::Result<Vec<MovieDBItem>, sqlx::Error> {
+        let query = "SELECT * FROM moviedb WHERE imdb_id = $1";
+
+        let items = sqlx::query_as::<_, MovieDBItem>(&query)
+            .bind(imdb_id)
+            .fetch_all(&self.db.db)
+            .await?;
+
+        Ok(items)
+    }
+
     pub async fn fetch_watchlist(&self) -> anyhow::Result<Vec<MovieDBItem>, sqlx::Error> {
         let query = "SELECT * FROM moviedb WHERE watchlist = true";
 
@@ -110,6 +125,19 @@ impl<'a> MovieDBDatabase<'a> {
         Ok(())
     }
 
+    pub async fn update_artwork(&self, id: i32, poster_url: Option<&str>, backdrop_url: Option<&str>) -> anyhow::Result<(), sqlx::Error> {
+        let query = "UPDATE moviedb SET poster_url = COALESCE($2, poster_url), backdrop_url = COALESCE($3, backdrop_url) WHERE id = $1";
+
+        let _ = sqlx::query(query)
+            .bind(id)
+            .bind(poster_url)
+            .bind(backdrop_url)
+            .execute(&self.db.db)
+            .await?;
+
+        Ok(())
+    }
+
     // pub async fn update_metadata(&self, item: &IMDBItem) -> anyhow::Result<()> {
     //     let mut query_builder: QueryBuilder<Postgres> = QueryBuilder::new(String::from("UPDATE moviedb SET "));
     //     let mut is_empty_query = true;
@@ -180,12 +208,38 @@ impl<'a> MovieDBDatabase<'a> {
     //     Ok(())
     // }
 
+    // Same as insert_or_update, one statement per BATCH_SIZE items
     pub async fn insert_or_update_many(&self, items: &[MovieDBItem]) -> Result<(), sqlx::Error> {
-        // TODO: FIX WITH ACTUAL PSQL STATEMENT OR SQLX FUNCTION THAT IS BETTER THAN THIS TRASH
-
-        for item in items {
-            self.insert_or_update(item).await?;
+        // A statement can't update the same row twice, so only the last copy of an item is kept
+        let mut seen = HashSet::new();
+        let mut items = items.iter().rev().filter(|i| seen.insert(i.id)).collect::<Vec<&MovieDBItem>>();
+        items.reverse();
+
+        let mut tx = self.db.db.begin().await?;
+        for chunk in items.chunks(BATCH_SIZE) {
+            let mut query_builder: QueryBuilder<Postgres> = QueryBuilder::new(
+                "INSERT INTO moviedb as m_db(id, imdb_id, title, plot, release_date, image_url, video_id, certification, runtime, popularity_rank, _type, watchlist, created_at, updated_at) ",
+            );
+            query_builder.push_values(chunk, |mut row, item| {
+                row.push_bind(item.id)
+                    .push_bind(&item.imdb_id)
+                    .push_bind(&item.title)
+                    .push_bind(&item.plot)
+                    .push_bind(item.release_date)
+                    .push_bind(&item.image_url)
+                    .push_bind(&item.video_id)
+                    .push_bind(&item.certification)
+                    .push_bind(item.runtime)
+                    .push_bind(item.popularity_rank)
+                    .push_bind(&item._type)
+                    .push_bind(item.watchlist)
+                    .push_bind(item.created_at)
+                    .push_bind(item.updated_at);
+            });
+            query_builder.push(" ON CONFLICT (id) DO UPDATE SET image_url = COALESCE(EXCLUDED.image_url, m_db.image_url), video_id = COALESCE(EXCLUDED.video_id, m_db.video_id), certification = COALESCE(EXCLUDED.certification, m_db.certification), popularity_rank = COALESCE(EXCLUDED.popularity_rank, m_db.popularity_rank), updated_at = EXCLUDED.updated_at");
+            query_builder.build().execute(&mut *tx).await?;
         }
+        tx.commit().await?;
 
         Ok(())
     }
@@ -0,0 +1,48 @@
+use chrono::Local;
+use super::DBConnection;
+
+#[derive(sqlx::FromRow)]
+pub struct PlexAuth {
+    pub client_identifier: String,
+    pub token: Option<String>,
+}
+
+pub struct PlexAuthDatabase<'a> {
+    db: &'a DBConnection
+}
+
+impl<'a> PlexAuthDatabase<'a> {
+    pub fn new(db: &'a DBConnection) -> PlexAuthDatabase {
+        PlexAuthDatabase {
+            db
+        }
+    }
+
+    // Only ever one row, the client identifier has to stay the same for plex.tv to recognise us
+    pub async fn fetch_or_insert(&self, client_identifier: &str) -> Result<PlexAuth, sqlx::Error> {
+        let query = "INSERT INTO plex_auth(id, client_identifier) VALUES (1, $1) ON CONFLICT (id) DO NOTHING";
+
+        let _ = sqlx::query(query)
+            .bind(client_identifier)
+            .execute(&self.db.db)
+            .await?;
+
+        let auth = sqlx::query_as::<_, PlexAuth>("SELECT client_identifier, token FROM plex_auth WHERE id = 1")
+            .fetch_one(&self.db.db)
+            .await?;
+
+        Ok(auth)
+    }
+
+    pub async fn update_token(&self, token: &str) -> Result<(), sqlx::Error> {
+        let query = "UPDATE plex_auth SET token = $1, updated_at = $2 WHERE id = 1";
+
+        let _ = sqlx::query(query)
+            .bind(token)
+            .bind(Local::now())
+            .execute(&self.db.db)
+            .await?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,63 @@
+use chrono::Local;
+use serde::Serialize;
+use sqlx::FromRow;
+
+use super::{DBConnection, SEARCH_PAGE_SIZE};
+
+// UI preferences for each browser, keyed by the id in its roundup_client cookie
+pub struct PreferencesDatabase<'a> {
+    db: &'a DBConnection
+}
+
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct UserPreferences {
+    pub search_mode: String,
+    pub page_size: i64,
+    // Quality label, eg "1080p", empty for no preference
+    pub preferred_quality: String,
+    pub theme: String,
+}
+
+impl Default for UserPreferences {
+    fn default() -> Self {
+        UserPreferences {
+            search_mode: "popular_movies".to_string(),
+            page_size: SEARCH_PAGE_SIZE,
+            preferred_quality: String::new(),
+            theme: "auto".to_string(),
+        }
+    }
+}
+
+impl<'a> PreferencesDatabase<'a> {
+    pub fn new(db: &'a DBConnection) -> PreferencesDatabase {
+        PreferencesDatabase {
+            db
+        }
+    }
+
+    pub async fn fetch(&self, client_id: &str) -> Result<Option<UserPreferences>, sqlx::Error> {
+        let query = "SELECT search_mode, page_size, preferred_quality, theme FROM user_preferences WHERE client_id = $1";
+
+        sqlx::query_as::<_, UserPreferences>(query)
+            .bind(client_id)
+            .fetch_optional(&self.db.db)
+            .await
+    }
+
+    pub async fn insert_or_update(&self, client_id: &str, preferences: &UserPreferences) -> Result<(), sqlx::Error> {
+        let query = "INSERT INTO user_preferences(client_id, search_mode, page_size, preferred_quality, theme, updated_at) VALUES ($1, $2, $3, $4, $5, $6) ON CONFLICT (client_id) DO UPDATE SET search_mode = $2, page_size = $3, preferred_quality = $4, theme = $5, updated_at = $6";
+
+        let _ = sqlx::query(query)
+            .bind(client_id)
+            .bind(&preferences.search_mode)
+            .bind(preferences.page_size)
+            .bind(&preferences.preferred_quality)
+            .bind(&preferences.theme)
+            .bind(Local::now())
+            .execute(&self.db.db)
+            .await?;
+
+        Ok(())
+    }
+}
\ No newline at end of file
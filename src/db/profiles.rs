@@ -0,0 +1,183 @@
+use sqlx::FromRow;
+
+use crate::api::profiles::QualityProfile;
+use crate::api::torrent::MediaQuality;
+
+use super::DBConnection;
+
+// Quality profiles, and which profile each watchlist item uses
+pub struct QualityProfileDatabase<'a> {
+    db: &'a DBConnection
+}
+
+// Qualities are stored by their displayed name, eg "1080p"
+#[derive(FromRow)]
+struct QualityProfileRow {
+    id: i32,
+    name: String,
+    qualities: Vec<String>,
+    cutoff: Option<String>,
+    min_size_mb: Option<i64>,
+    max_size_mb: Option<i64>,
+    preferred_terms: Vec<String>,
+    rejected_terms: Vec<String>,
+    is_default: bool,
+}
+
+impl From<QualityProfileRow> for QualityProfile {
+    fn from(row: QualityProfileRow) -> Self {
+        QualityProfile {
+            id: row.id,
+            name: row.name,
+            qualities: row.qualities.iter().filter_map(|q| MediaQuality::from_name(q)).collect(),
+            cutoff: row.cutoff.as_deref().and_then(MediaQuality::from_name),
+            min_size_mb: row.min_size_mb,
+            max_size_mb: row.max_size_mb,
+            preferred_terms: row.preferred_terms,
+            rejected_terms: row.rejected_terms,
+            is_default: row.is_default,
+        }
+    }
+}
+
+const COLUMNS: &str = "id, name, qualities, cutoff, min_size_mb, max_size_mb, preferred_terms, rejected_terms, is_default";
+
+impl<'a> QualityProfileDatabase<'a> {
+    pub fn new(db: &'a DBConnection) -> QualityProfileDatabase {
+        QualityProfileDatabase {
+            db
+        }
+    }
+
+    pub async fn fetch_all(&self) -> Result<Vec<QualityProfile>, sqlx::Error> {
+        let query = format!("SELECT {} FROM quality_profiles ORDER BY name", COLUMNS);
+
+        let rows = sqlx::query_as::<_, QualityProfileRow>(&query)
+            .fetch_all(&self.db.db)
+            .await?;
+
+        Ok(rows.into_iter().map(QualityProfile::from).collect())
+    }
+
+    pub async fn fetch(&self, id: i32) -> Result<Option<QualityProfile>, sqlx::Error> {
+        let query = format!("SELECT {} FROM quality_profiles WHERE id = $1", COLUMNS);
+
+        let row = sqlx::query_as::<_, QualityProfileRow>(&query)
+            .bind(id)
+            .fetch_optional(&self.db.db)
+            .await?;
+
+        Ok(row.map(QualityProfile::from))
+    }
+
+    // The item's own profile, otherwise the default profile if there is one
+    pub async fn fetch_for_item(&self, item_id: &str) -> Result<Option<QualityProfile>, sqlx::Error> {
+        let query = "SELECT p.id, p.name, p.qualities, p.cutoff, p.min_size_mb, p.max_size_mb, p.preferred_terms, p.rejected_terms, p.is_default \
+            FROM quality_profiles p LEFT JOIN watchlist_profiles w ON w.profile_id = p.id AND w.item_id = $1 \
+            WHERE w.item_id IS NOT NULL OR p.is_default ORDER BY w.item_id IS NULL LIMIT 1";
+
+        let row = sqlx::query_as::<_, QualityProfileRow>(query)
+            .bind(item_id)
+            .fetch_optional(&self.db.db)
+            .await?;
+
+        Ok(row.map(QualityProfile::from))
+    }
+
+    pub async fn insert(&self, profile: &QualityProfile) -> Result<i32, sqlx::Error> {
+        let query = "INSERT INTO quality_profiles(name, qualities, cutoff, min_size_mb, max_size_mb, preferred_terms, rejected_terms, is_default) VALUES ($1, $2, $3, $4, $5, $6, $7, $8) RETURNING id";
+
+        let mut tx = self.db.db.begin().await?;
+        if profile.is_default {
+            clear_default(&mut tx, None).await?;
+        }
+        let id = sqlx::query_scalar::<_, i32>(query)
+            .bind(&profile.name)
+            .bind(quality_names(&profile.qualities))
+            .bind(profile.cutoff.map(|q| q.to_string()))
+            .bind(profile.min_size_mb)
+            .bind(profile.max_size_mb)
+            .bind(&profile.preferred_terms)
+            .bind(&profile.rejected_terms)
+            .bind(profile.is_default)
+            .fetch_one(&mut *tx)
+            .await?;
+        tx.commit().await?;
+
+        Ok(id)
+    }
+
+    pub async fn update(&self, id: i32, profile: &QualityProfile) -> Result<bool, sqlx::Error> {
+        let query = "UPDATE quality_profiles SET name = $2, qualities = $3, cutoff = $4, min_size_mb = $5, max_size_mb = $6, preferred_terms = $7, rejected_terms = $8, is_default = $9 WHERE id = $1";
+
+        let mut tx = self.db.db.begin().await?;
+        if profile.is_default {
+            clear_default(&mut tx, Some(id)).await?;
+        }
+        let result = sqlx::query(query)
+            .bind(id)
+            .bind(&profile.name)
+            .bind(quality_names(&profile.qualities))
+            .bind(profile.cutoff.map(|q| q.to_string()))
+            .bind(profile.min_size_mb)
+            .bind(profile.max_size_mb)
+            .bind(&profile.preferred_terms)
+            .bind(&profile.rejected_terms)
+            .bind(profile.is_default)
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    // Watchlist items using it go back to the default profile
+    pub async fn remove(&self, id: i32) -> Result<bool, sqlx::Error> {
+        let query = "DELETE FROM quality_profiles WHERE id = $1";
+
+        let result = sqlx::query(query)
+            .bind(id)
+            .execute(&self.db.db)
+            .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    // None goes back to the default profile
+    pub async fn set_for_item(&self, item_id: &str, profile_id: Option<i32>) -> Result<(), sqlx::Error> {
+        let _ = match profile_id {
+            Some(profile_id) => {
+                let query = "INSERT INTO watchlist_profiles(item_id, profile_id) VALUES ($1, $2) ON CONFLICT (item_id) DO UPDATE SET profile_id = $2";
+                sqlx::query(query)
+                    .bind(item_id)
+                    .bind(profile_id)
+                    .execute(&self.db.db)
+                    .await?
+            }
+            None => {
+                let query = "DELETE FROM watchlist_profiles WHERE item_id = $1";
+                sqlx::query(query)
+                    .bind(item_id)
+                    .execute(&self.db.db)
+                    .await?
+            }
+        };
+
+        Ok(())
+    }
+}
+
+async fn clear_default(tx: &mut sqlx::Transaction<'_, sqlx::Postgres>, keep: Option<i32>) -> Result<(), sqlx::Error> {
+    let query = "UPDATE quality_profiles SET is_default = false WHERE is_default AND id <> $1";
+
+    let _ = sqlx::query(query)
+        .bind(keep.unwrap_or(0))
+        .execute(&mut **tx)
+        .await?;
+
+    Ok(())
+}
+
+fn quality_names(qualities: &[MediaQuality]) -> Vec<String> {
+    qualities.iter().map(|q| q.to_string()).collect()
+}
@@ -0,0 +1,91 @@
+use super::DBConnection;
+use super::attempts::AttemptOutcome;
+use super::jobs::{JOB_DONE, JOB_FAILED};
+
+// Removes old rows from the tables that only ever grow
+pub struct RetentionDatabase<'a> {
+    db: &'a DBConnection
+}
+
+// (table, timestamp column)
+static HISTORY_TABLES: [(&str, &str); 2] = [
+    ("download_history", "created_at"),
+    ("watchlist_checks", "created_at"),
+];
+static PREFERENCES_TABLES: [(&str, &str); 1] = [("user_preferences", "updated_at")];
+
+impl<'a> RetentionDatabase<'a> {
+    pub fn new(db: &'a DBConnection) -> RetentionDatabase {
+        RetentionDatabase {
+            db
+        }
+    }
+
+    // Download history, download attempts and watchlist checks older than days
+    pub async fn prune_history(&self, days: i64) -> Result<Vec<(&'static str, u64)>, sqlx::Error> {
+        let mut removed = self.prune(&HISTORY_TABLES, days).await?;
+        removed.extend(self.prune_attempts(days).await?);
+        Ok(removed)
+    }
+
+    // Attempts decide watchlist states and when to stop retrying, so they're only removed once the download has gone
+    // and the item is off the watchlist
+    async fn prune_attempts(&self, days: i64) -> Result<Vec<(&'static str, u64)>, sqlx::Error> {
+        let query = "DELETE FROM download_attempts WHERE updated_at < now() - make_interval(days => $1) AND outcome <> $2 \
+            AND NOT EXISTS (SELECT 1 FROM active_downloads WHERE active_downloads.magnet_hash = download_attempts.magnet_hash) \
+            AND NOT EXISTS (SELECT 1 FROM imdb WHERE imdb.id = download_attempts.imdb_id AND imdb.watchlist) \
+            AND NOT EXISTS (SELECT 1 FROM moviedb WHERE 'tt' || moviedb.id::TEXT = download_attempts.imdb_id AND moviedb.watchlist)";
+
+        let result = sqlx::query(query)
+            .bind(days.clamp(0, i32::MAX as i64) as i32)
+            .bind(AttemptOutcome::Downloading.as_str())
+            .execute(&self.db.db)
+            .await?;
+
+        Ok(vec![("download_attempts", result.rows_affected())])
+    }
+
+    // Jobs that finished or gave up more than days ago, queued and running jobs are kept whatever their age
+    pub async fn prune_jobs(&self, days: i64) -> Result<Vec<(&'static str, u64)>, sqlx::Error> {
+        let query = "DELETE FROM jobs WHERE state = ANY($1) AND updated_at < now() - make_interval(days => $2)";
+
+        let result = sqlx::query(query)
+            .bind([JOB_DONE, JOB_FAILED])
+            .bind(days.clamp(0, i32::MAX as i64) as i32)
+            .execute(&self.db.db)
+            .await?;
+
+        Ok(vec![("jobs", result.rows_affected())])
+    }
+
+    // Preferences of browsers that haven't saved them in days
+    pub async fn prune_preferences(&self, days: i64) -> Result<Vec<(&'static str, u64)>, sqlx::Error> {
+        self.prune(&PREFERENCES_TABLES, days).await
+    }
+
+    async fn prune(&self, tables: &[(&'static str, &'static str)], days: i64) -> Result<Vec<(&'static str, u64)>, sqlx::Error> {
+        let mut removed = Vec::new();
+        for (table, column) in tables {
+            let query = format!("DELETE FROM {} WHERE {} < now() - make_interval(days => $1)", table, column);
+
+            let result = sqlx::query(&query)
+                .bind(days.clamp(0, i32::MAX as i64) as i32)
+                .execute(&self.db.db)
+                .await?;
+            removed.push((*table, result.rows_affected()));
+        }
+
+        Ok(removed)
+    }
+
+    // Lets Postgres reuse the space from deleted rows and refreshes the planner's statistics. Can't run in a transaction
+    pub async fn vacuum(&self, table: &'static str) -> Result<(), sqlx::Error> {
+        let query = format!("VACUUM (ANALYZE) {}", table);
+
+        let _ = sqlx::query(&query)
+            .execute(&self.db.db)
+            .await?;
+
+        Ok(())
+    }
+}
\ No newline at end of file
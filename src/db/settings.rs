@@ -0,0 +1,41 @@
+use chrono::Local;
+
+use super::DBConnection;
+
+// Settings changed from the settings page, each value is stored as JSON and overrides config.json
+pub struct SettingsDatabase<'a> {
+    db: &'a DBConnection
+}
+
+impl<'a> SettingsDatabase<'a> {
+    pub fn new(db: &'a DBConnection) -> SettingsDatabase {
+        SettingsDatabase {
+            db
+        }
+    }
+
+    pub async fn fetch_all(&self) -> Result<Vec<(String, String)>, sqlx::Error> {
+        let query = "SELECT key, value FROM settings";
+
+        sqlx::query_as::<_, (String, String)>(query)
+            .fetch_all(&self.db.db)
+            .await
+    }
+
+    pub async fn insert_or_update_many(&self, settings: &[(String, String)]) -> Result<(), sqlx::Error> {
+        let query = "INSERT INTO settings(key, value) VALUES ($1, $2) ON CONFLICT (key) DO UPDATE SET value = $2, updated_at = $3";
+
+        let mut tx = self.db.db.begin().await?;
+        for (key, value) in settings {
+            let _ = sqlx::query(query)
+                .bind(key)
+                .bind(value)
+                .bind(Local::now())
+                .execute(&mut *tx)
+                .await?;
+        }
+        tx.commit().await?;
+
+        Ok(())
+    }
+}
\ No newline at end of file
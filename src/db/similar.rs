@@ -0,0 +1,52 @@
+use crate::api::imdb::IMDBItem;
+use crate::api::moviedb::MovieDBItem;
+use super::DBConnection;
+
+pub struct SimilarDatabase<'a> {
+    db: &'a DBConnection
+}
+
+impl<'a> SimilarDatabase<'a> {
+    pub fn new(db: &'a DBConnection) -> SimilarDatabase {
+        SimilarDatabase {
+            db
+        }
+    }
+
+    pub async fn insert_many(&self, source_id: &str, similar_ids: &[String]) -> Result<(), sqlx::Error> {
+        let query = "INSERT INTO similar_titles(source_id, similar_id, position) VALUES ($1, $2, $3) ON CONFLICT (source_id, similar_id) DO UPDATE SET position = $3";
+
+        for (i, similar_id) in similar_ids.iter().enumerate() {
+            let _ = sqlx::query(query)
+                .bind(source_id)
+                .bind(similar_id)
+                .bind(i as i32)
+                .execute(&self.db.db)
+                .await?;
+        }
+
+        Ok(())
+    }
+
+    pub async fn fetch_imdb(&self, source_id: &str) -> anyhow::Result<Vec<IMDBItem>, sqlx::Error> {
+        let query = "SELECT imdb.* FROM similar_titles INNER JOIN imdb ON imdb.id = similar_titles.similar_id WHERE similar_titles.source_id = $1 ORDER BY similar_titles.position ASC";
+
+        let items = sqlx::query_as::<_, IMDBItem>(query)
+            .bind(source_id)
+            .fetch_all(&self.db.db)
+            .await?;
+
+        Ok(items)
+    }
+
+    pub async fn fetch_moviedb(&self, source_id: &str) -> anyhow::Result<Vec<MovieDBItem>, sqlx::Error> {
+        let query = "SELECT moviedb.* FROM similar_titles INNER JOIN moviedb ON moviedb.id::TEXT = similar_titles.similar_id WHERE similar_titles.source_id = $1 ORDER BY similar_titles.position ASC";
+
+        let items = sqlx::query_as::<_, MovieDBItem>(query)
+            .bind(source_id)
+            .fetch_all(&self.db.db)
+            .await?;
+
+        Ok(items)
+    }
+}
\ No newline at end of file
@@ -1,5 +0,0 @@
-DO $$ BEGIN
-    CREATE TYPE item_type as ENUM ('movie', 'tvshow');
-EXCEPTION
-    WHEN duplicate_object THEN null;
-END $$;
\ No newline at end of file
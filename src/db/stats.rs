@@ -0,0 +1,99 @@
+use std::collections::HashMap;
+
+use chrono::Local;
+use serde::Serialize;
+
+use super::DBConnection;
+
+// Totals for the /stats page, built from the download history
+#[derive(Debug, Serialize)]
+pub struct DownloadStats {
+    pub completed_this_week: i64,
+    pub grabbed_this_week: i64,
+    pub failed_this_week: i64,
+    // Seconds from grab to completion, None until something has completed
+    pub average_completion_seconds: Option<i64>,
+    pub indexers: Vec<IndexerStats>,
+    // Share of watchlist checks in the last 30 days that started a download
+    pub watchlist_checks: i64,
+    pub watchlist_hit_rate: Option<f64>,
+}
+
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub struct IndexerStats {
+    pub source: String,
+    pub grabs: i64,
+    // Bytes, releases without a known size aren't counted
+    pub size: i64,
+}
+
+pub struct StatsDatabase<'a> {
+    db: &'a DBConnection
+}
+
+impl<'a> StatsDatabase<'a> {
+    pub fn new(db: &'a DBConnection) -> StatsDatabase {
+        StatsDatabase {
+            db
+        }
+    }
+
+    pub async fn insert_watchlist_check(&self, imdb_id: &str, found: bool) -> Result<(), sqlx::Error> {
+        let query = "INSERT INTO watchlist_checks(imdb_id, found) VALUES ($1, $2)";
+
+        let _ = sqlx::query(query)
+            .bind(imdb_id)
+            .bind(found)
+            .execute(&self.db.db)
+            .await?;
+
+        Ok(())
+    }
+
+    // When each item was last checked, keyed like insert_watchlist_check
+    pub async fn fetch_last_watchlist_checks(&self) -> Result<HashMap<String, chrono::DateTime<Local>>, sqlx::Error> {
+        let query = "SELECT imdb_id, MAX(created_at) FROM watchlist_checks GROUP BY imdb_id";
+
+        let checks = sqlx::query_as::<_, (String, chrono::DateTime<Local>)>(query)
+            .fetch_all(&self.db.db)
+            .await?;
+
+        Ok(checks.into_iter().collect())
+    }
+
+    pub async fn fetch(&self) -> Result<DownloadStats, sqlx::Error> {
+        let query = "SELECT COUNT(*) FILTER (WHERE event = 'completed'), COUNT(*) FILTER (WHERE event = 'grabbed'), COUNT(*) FILTER (WHERE event = 'failed') FROM download_history WHERE created_at >= now() - INTERVAL '7 days'";
+        let (completed_this_week, grabbed_this_week, failed_this_week) = sqlx::query_as::<_, (i64, i64, i64)>(query)
+            .fetch_one(&self.db.db)
+            .await?;
+
+        // Measured from the latest grab before each completion, as a release can be grabbed more than once
+        let query = "SELECT EXTRACT(EPOCH FROM AVG(c.created_at - g.created_at))::BIGINT FROM download_history c JOIN LATERAL (SELECT created_at FROM download_history WHERE magnet_hash = c.magnet_hash AND event = 'grabbed' AND created_at <= c.created_at ORDER BY created_at DESC LIMIT 1) g ON true WHERE c.event = 'completed'";
+        let average_completion_seconds = sqlx::query_scalar::<_, Option<i64>>(query)
+            .fetch_one(&self.db.db)
+            .await?;
+
+        let query = "SELECT COALESCE(source, 'unknown') AS source, COUNT(*) AS grabs, COALESCE(SUM(size), 0)::BIGINT AS size FROM download_history WHERE event = 'grabbed' GROUP BY 1 ORDER BY size DESC, grabs DESC";
+        let indexers = sqlx::query_as::<_, IndexerStats>(query)
+            .fetch_all(&self.db.db)
+            .await?;
+
+        let query = "SELECT COUNT(*), COUNT(*) FILTER (WHERE found) FROM watchlist_checks WHERE created_at >= now() - INTERVAL '30 days'";
+        let (watchlist_checks, watchlist_hits) = sqlx::query_as::<_, (i64, i64)>(query)
+            .fetch_one(&self.db.db)
+            .await?;
+
+        Ok(DownloadStats {
+            completed_this_week,
+            grabbed_this_week,
+            failed_this_week,
+            average_completion_seconds,
+            indexers,
+            watchlist_checks,
+            watchlist_hit_rate: match watchlist_checks {
+                0 => None,
+                t => Some(watchlist_hits as f64 / t as f64),
+            },
+        })
+    }
+}
\ No newline at end of file
@@ -0,0 +1,49 @@
+use super::DBConnection;
+
+// Downloads that replace a lower quality file already in the library
+pub struct UpgradeDatabase<'a> {
+    db: &'a DBConnection
+}
+
+impl<'a> UpgradeDatabase<'a> {
+    pub fn new(db: &'a DBConnection) -> UpgradeDatabase {
+        UpgradeDatabase {
+            db
+        }
+    }
+
+    pub async fn insert(&self, magnet_hash: &str, replaces: &str) -> Result<(), sqlx::Error> {
+        let query = "INSERT INTO pending_upgrades(magnet_hash, replaces) VALUES ($1, $2) ON CONFLICT (magnet_hash) DO UPDATE SET replaces = $2";
+
+        let _ = sqlx::query(query)
+            .bind(magnet_hash)
+            .bind(replaces)
+            .execute(&self.db.db)
+            .await?;
+
+        Ok(())
+    }
+
+    // Path of the file to remove once the download has been imported
+    pub async fn fetch(&self, magnet_hash: &str) -> Result<Option<String>, sqlx::Error> {
+        let query = "SELECT replaces FROM pending_upgrades WHERE magnet_hash = $1";
+
+        let replaces = sqlx::query_scalar::<_, String>(query)
+            .bind(magnet_hash)
+            .fetch_optional(&self.db.db)
+            .await?;
+
+        Ok(replaces)
+    }
+
+    pub async fn remove(&self, magnet_hash: &str) -> Result<(), sqlx::Error> {
+        let query = "DELETE FROM pending_upgrades WHERE magnet_hash = $1";
+
+        let _ = sqlx::query(query)
+            .bind(magnet_hash)
+            .execute(&self.db.db)
+            .await?;
+
+        Ok(())
+    }
+}
\ No newline at end of file
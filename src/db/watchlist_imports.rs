@@ -0,0 +1,127 @@
+use chrono::Local;
+use serde::Serialize;
+use sqlx::FromRow;
+use utoipa::ToSchema;
+
+use super::DBConnection;
+
+pub const IMPORT_QUEUED: &str = "queued";
+pub const IMPORT_RUNNING: &str = "running";
+pub const IMPORT_DONE: &str = "done";
+pub const IMPORT_FAILED: &str = "failed";
+
+// Progress of IMDb list imports, the work itself runs as a job
+pub struct WatchlistImportDatabase<'a> {
+    db: &'a DBConnection
+}
+
+#[derive(Debug, Serialize, FromRow, ToSchema)]
+pub struct WatchlistImport {
+    pub id: i64,
+    pub source: String,
+    pub state: String,
+    // Titles found in the list
+    pub total: i32,
+    pub added: i32,
+    // Already on the watchlist
+    pub existing: i32,
+    pub failed: Vec<String>,
+    // Why the list itself couldn't be read
+    pub last_error: Option<String>,
+    pub created_at: chrono::DateTime<Local>,
+    pub updated_at: chrono::DateTime<Local>,
+}
+
+impl<'a> WatchlistImportDatabase<'a> {
+    pub fn new(db: &'a DBConnection) -> WatchlistImportDatabase {
+        WatchlistImportDatabase {
+            db
+        }
+    }
+
+    pub async fn insert(&self, source: &str) -> Result<i64, sqlx::Error> {
+        let query = "INSERT INTO watchlist_imports(source, state) VALUES ($1, $2) RETURNING id";
+
+        sqlx::query_scalar::<_, i64>(query)
+            .bind(source)
+            .bind(IMPORT_QUEUED)
+            .fetch_one(&self.db.db)
+            .await
+    }
+
+    pub async fn fetch(&self, id: i64) -> Result<Option<WatchlistImport>, sqlx::Error> {
+        let query = "SELECT * FROM watchlist_imports WHERE id = $1";
+
+        sqlx::query_as::<_, WatchlistImport>(query)
+            .bind(id)
+            .fetch_optional(&self.db.db)
+            .await
+    }
+
+    // Counts start again when a job is retried, titles added the first time then count as existing
+    pub async fn start(&self, id: i64, total: i32) -> Result<(), sqlx::Error> {
+        let query = "UPDATE watchlist_imports SET state = $2, total = $3, added = 0, existing = 0, failed = '{}', last_error = NULL, updated_at = $4 WHERE id = $1";
+
+        let _ = sqlx::query(query)
+            .bind(id)
+            .bind(IMPORT_RUNNING)
+            .bind(total)
+            .bind(Local::now())
+            .execute(&self.db.db)
+            .await?;
+
+        Ok(())
+    }
+
+    pub async fn count_added(&self, id: i64) -> Result<(), sqlx::Error> {
+        self.increment(id, "added").await
+    }
+
+    pub async fn count_existing(&self, id: i64) -> Result<(), sqlx::Error> {
+        self.increment(id, "existing").await
+    }
+
+    pub async fn add_failure(&self, id: i64, detail: &str) -> Result<(), sqlx::Error> {
+        let query = "UPDATE watchlist_imports SET failed = array_append(failed, $2), updated_at = $3 WHERE id = $1";
+
+        let _ = sqlx::query(query)
+            .bind(id)
+            .bind(detail)
+            .bind(Local::now())
+            .execute(&self.db.db)
+            .await?;
+
+        Ok(())
+    }
+
+    pub async fn finish(&self, id: i64, error: Option<&str>) -> Result<(), sqlx::Error> {
+        let query = "UPDATE watchlist_imports SET state = $2, last_error = $3, updated_at = $4 WHERE id = $1";
+
+        let state = match error {
+            Some(_) => IMPORT_FAILED,
+            None => IMPORT_DONE,
+        };
+        let _ = sqlx::query(query)
+            .bind(id)
+            .bind(state)
+            .bind(error)
+            .bind(Local::now())
+            .execute(&self.db.db)
+            .await?;
+
+        Ok(())
+    }
+
+    // column is one of the counters, never user input
+    async fn increment(&self, id: i64, column: &str) -> Result<(), sqlx::Error> {
+        let query = format!("UPDATE watchlist_imports SET {0} = {0} + 1, updated_at = $2 WHERE id = $1", column);
+
+        let _ = sqlx::query(&query)
+            .bind(id)
+            .bind(Local::now())
+            .execute(&self.db.db)
+            .await?;
+
+        Ok(())
+    }
+}
\ No newline at end of file
@@ -0,0 +1,91 @@
+use std::collections::HashMap;
+use std::ops::Not;
+
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use super::DBConnection;
+use super::watchlist_states::watchlist_key;
+
+// Per item overrides for the watchlist, the quality profile is set separately
+#[derive(Debug, Clone, Default, sqlx::FromRow, Serialize, Deserialize, ToSchema)]
+pub struct WatchlistOptions {
+    // Releases have to be tagged with this language (or MULTi), eg "french". Leave empty for untagged English releases
+    #[serde(default)]
+    pub language: Option<String>,
+    // TV shows only, skips every season that had started airing when this was turned on
+    #[serde(default)]
+    pub only_future_seasons: bool,
+    #[serde(default, skip_deserializing)]
+    pub future_seasons_from: Option<NaiveDate>,
+    // TV shows only, skips every episode that aired before the show was added to the watchlist, or before this was
+    // turned on for shows added before that was recorded
+    #[serde(default)]
+    pub only_new_episodes: bool,
+    #[serde(default, skip_deserializing)]
+    pub new_episodes_from: Option<NaiveDate>,
+    // Hours between checks of this item, instead of watchlist_recheck_interval_hours. At least 1
+    #[serde(default)]
+    pub recheck_interval_hours: Option<i32>,
+    // Movies only, keeps replacing the library copy with better releases until the quality profile's cutoff is met
+    #[serde(default)]
+    pub upgrade_until_cutoff: bool,
+}
+
+pub struct WatchlistOptionsDatabase<'a> {
+    db: &'a DBConnection
+}
+
+impl<'a> WatchlistOptionsDatabase<'a> {
+    pub fn new(db: &'a DBConnection) -> WatchlistOptionsDatabase {
+        WatchlistOptionsDatabase {
+            db
+        }
+    }
+
+    // Items without overrides get the defaults
+    pub async fn fetch(&self, item_id: &str) -> Result<WatchlistOptions, sqlx::Error> {
+        let query = "SELECT language, future_seasons_from IS NOT NULL AS only_future_seasons, future_seasons_from, \
+            new_episodes_from IS NOT NULL AS only_new_episodes, new_episodes_from, recheck_interval_hours, upgrade_until_cutoff FROM watchlist_options WHERE item_id = $1";
+
+        let options = sqlx::query_as::<_, WatchlistOptions>(query)
+            .bind(watchlist_key(item_id))
+            .fetch_optional(&self.db.db)
+            .await?;
+
+        Ok(options.unwrap_or_default())
+    }
+
+    // Items with their own recheck interval, by watchlist key
+    pub async fn fetch_recheck_intervals(&self) -> Result<HashMap<String, i32>, sqlx::Error> {
+        let query = "SELECT item_id, recheck_interval_hours FROM watchlist_options WHERE recheck_interval_hours IS NOT NULL";
+
+        let intervals = sqlx::query_as::<_, (String, i32)>(query)
+            .fetch_all(&self.db.db)
+            .await?;
+
+        Ok(intervals.into_iter().collect())
+    }
+
+    // Turning only_future_seasons or only_new_episodes on again keeps the original date
+    pub async fn set(&self, item_id: &str, options: &WatchlistOptions) -> Result<(), sqlx::Error> {
+        let query = "INSERT INTO watchlist_options(item_id, language, future_seasons_from, upgrade_until_cutoff, new_episodes_from, recheck_interval_hours) \
+            VALUES ($1, $2, CASE WHEN $3 THEN CURRENT_DATE END, $4, CASE WHEN $5 THEN COALESCE((SELECT added_at::date FROM watchlist_states WHERE imdb_id = $1), CURRENT_DATE) END, $6) \
+            ON CONFLICT (item_id) DO UPDATE SET language = $2, future_seasons_from = CASE WHEN $3 THEN COALESCE(watchlist_options.future_seasons_from, CURRENT_DATE) END, upgrade_until_cutoff = $4, \
+            new_episodes_from = CASE WHEN $5 THEN COALESCE(watchlist_options.new_episodes_from, (SELECT added_at::date FROM watchlist_states WHERE imdb_id = $1), CURRENT_DATE) END, recheck_interval_hours = $6";
+
+        let language = options.language.as_deref().map(str::trim).filter(|l| l.is_empty().not());
+        let _ = sqlx::query(query)
+            .bind(watchlist_key(item_id))
+            .bind(language)
+            .bind(options.only_future_seasons)
+            .bind(options.upgrade_until_cutoff)
+            .bind(options.only_new_episodes)
+            .bind(options.recheck_interval_hours)
+            .execute(&self.db.db)
+            .await?;
+
+        Ok(())
+    }
+}
\ No newline at end of file
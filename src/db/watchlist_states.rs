@@ -0,0 +1,171 @@
+use std::collections::HashMap;
+
+use chrono::Local;
+use serde::Serialize;
+
+use super::DBConnection;
+
+// wanted -> searching -> grabbed -> downloading -> available. A check that finds nothing goes back to wanted, movies
+// that are only in theaters are waiting
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum WatchlistState {
+    Wanted,
+    Waiting,
+    Searching,
+    Grabbed,
+    Downloading,
+    Available,
+}
+
+impl WatchlistState {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            WatchlistState::Wanted => "wanted",
+            WatchlistState::Waiting => "waiting",
+            WatchlistState::Searching => "searching",
+            WatchlistState::Grabbed => "grabbed",
+            WatchlistState::Downloading => "downloading",
+            WatchlistState::Available => "available",
+        }
+    }
+}
+
+#[derive(Debug, sqlx::FromRow, Serialize)]
+pub struct WatchlistStatus {
+    pub imdb_id: String,
+    pub state: String,
+    // Why a check didn't grab anything, eg "No torrents available"
+    pub detail: Option<String>,
+    pub updated_at: chrono::DateTime<Local>,
+}
+
+// Watchlist tables are keyed by IMDb id, TheMovieDB ids get the same "tt" prefix
+pub fn watchlist_key(id: &str) -> String {
+    match id.starts_with("tt") {
+        true => id.to_string(),
+        false => format!("tt{}", id),
+    }
+}
+
+pub struct WatchlistStateDatabase<'a> {
+    db: &'a DBConnection
+}
+
+impl<'a> WatchlistStateDatabase<'a> {
+    pub fn new(db: &'a DBConnection) -> WatchlistStateDatabase {
+        WatchlistStateDatabase {
+            db
+        }
+    }
+
+    pub async fn set(&self, imdb_id: &str, state: WatchlistState, detail: Option<&str>) -> Result<(), sqlx::Error> {
+        let query = "INSERT INTO watchlist_states(imdb_id, state, detail, updated_at) VALUES ($1, $2, $3, now()) \
+            ON CONFLICT (imdb_id) DO UPDATE SET state = $2, detail = $3, updated_at = now()";
+
+        let _ = sqlx::query(query)
+            .bind(imdb_id)
+            .bind(state.as_str())
+            .bind(detail)
+            .execute(&self.db.db)
+            .await?;
+
+        Ok(())
+    }
+
+    // Re-adding an item counts as adding it again, for "only new episodes"
+    pub async fn added(&self, imdb_id: &str) -> Result<(), sqlx::Error> {
+        let query = "INSERT INTO watchlist_states(imdb_id, state, updated_at, added_at) VALUES ($1, $2, now(), now()) \
+            ON CONFLICT (imdb_id) DO UPDATE SET state = $2, detail = NULL, updated_at = now(), added_at = now()";
+
+        let _ = sqlx::query(query)
+            .bind(imdb_id)
+            .bind(WatchlistState::Wanted.as_str())
+            .execute(&self.db.db)
+            .await?;
+
+        Ok(())
+    }
+
+    // Moves items that are in one of the from states, so a check that ended early doesn't undo what it found
+    pub async fn advance(
+        &self,
+        imdb_id: &str,
+        from: &[WatchlistState],
+        state: WatchlistState,
+        detail: Option<&str>,
+    ) -> Result<(), sqlx::Error> {
+        let query = "UPDATE watchlist_states SET state = $3, detail = $4, updated_at = now() WHERE imdb_id = $1 AND state = ANY($2)";
+
+        let _ = sqlx::query(query)
+            .bind(imdb_id)
+            .bind(from.iter().map(|s| s.as_str()).collect::<Vec<&str>>())
+            .bind(state.as_str())
+            .bind(detail)
+            .execute(&self.db.db)
+            .await?;
+
+        Ok(())
+    }
+
+    // For the torrent monitor, which knows the release rather than the item. Returns false when the release isn't for
+    // a watchlist item
+    pub async fn set_for_download(&self, magnet_hash: &str, state: WatchlistState, detail: Option<&str>) -> Result<bool, sqlx::Error> {
+        let query = "UPDATE watchlist_states SET state = $2, detail = $3, updated_at = now() \
+            WHERE imdb_id = (SELECT imdb_id FROM download_attempts WHERE magnet_hash = $1 ORDER BY id DESC LIMIT 1)";
+
+        let result = sqlx::query(query)
+            .bind(magnet_hash)
+            .bind(state.as_str())
+            .bind(detail)
+            .execute(&self.db.db)
+            .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    pub async fn is_watchlist_download(&self, magnet_hash: &str) -> Result<bool, sqlx::Error> {
+        let query = "SELECT EXISTS(SELECT 1 FROM watchlist_states \
+            WHERE imdb_id = (SELECT imdb_id FROM download_attempts WHERE magnet_hash = $1 ORDER BY id DESC LIMIT 1))";
+
+        sqlx::query_scalar::<_, bool>(query)
+            .bind(magnet_hash)
+            .fetch_one(&self.db.db)
+            .await
+    }
+
+    // Grabbed items whose torrents have started receiving data
+    pub async fn mark_downloading(&self) -> Result<(), sqlx::Error> {
+        let query = "UPDATE watchlist_states SET state = $1, detail = NULL, updated_at = now() \
+            WHERE state = $2 AND imdb_id IN (SELECT imdb_id FROM active_downloads WHERE progress > 0 AND progress < 1)";
+
+        let _ = sqlx::query(query)
+            .bind(WatchlistState::Downloading.as_str())
+            .bind(WatchlistState::Grabbed.as_str())
+            .execute(&self.db.db)
+            .await?;
+
+        Ok(())
+    }
+
+    pub async fn fetch_many(&self, imdb_ids: &[String]) -> Result<HashMap<String, WatchlistStatus>, sqlx::Error> {
+        let query = "SELECT imdb_id, state, detail, updated_at FROM watchlist_states WHERE imdb_id = ANY($1)";
+
+        let rows = sqlx::query_as::<_, WatchlistStatus>(query)
+            .bind(imdb_ids)
+            .fetch_all(&self.db.db)
+            .await?;
+
+        Ok(rows.into_iter().map(|r| (r.imdb_id.clone(), r)).collect())
+    }
+
+    pub async fn remove(&self, imdb_id: &str) -> Result<(), sqlx::Error> {
+        let query = "DELETE FROM watchlist_states WHERE imdb_id = $1";
+
+        let _ = sqlx::query(query)
+            .bind(imdb_id)
+            .execute(&self.db.db)
+            .await?;
+
+        Ok(())
+    }
+}
\ No newline at end of file
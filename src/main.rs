@@ -2,12 +2,14 @@ use std::collections::{HashMap, HashSet};
 use std::fs::{self, File};
 use std::io::BufReader;
 use std::ops::Not;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 use std::time::Duration;
 
 use actix_web::{App, HttpServer};
 use actix_web::middleware::Logger;
-use actix_web::web::Data;
+use actix_web::web::{self, Data};
+use anyhow::format_err;
 use chrono::{DateTime, Local};
 use log::{error, info, warn};
 use qbittorrent::Api;
@@ -16,13 +18,34 @@ use qbittorrent::traits::TorrentData;
 use rayon::prelude::*;
 use serde::Deserialize;
 use tokio::sync::Mutex;
-use tokio::time::Instant;
 
-use crate::api::imdb::SearchType;
+use crate::api::imdb::{ItemType, SearchType};
+use crate::api::importer::{ImportMode, Importer};
+use crate::api::jobs::{Job, JobQueue};
+use crate::api::library::LocalLibrary;
+use crate::api::kodi::Kodi;
+use crate::api::media_server::{MediaServer, MultiMediaServer, NoMediaServer};
+use crate::api::bandwidth::{BandwidthScheduler, SpeedSchedule};
+use crate::api::disk::DiskMonitor;
+use crate::api::file_filter::FileFilter;
+use crate::api::notify::{DownloadEvent, EventStream, NotificationBackend, Notifier, SettingsWebhookBackend, WebhookBackend};
+use crate::api::progress::{ProgressFeed, ProgressUpdate};
+use crate::api::recycle::RecycleBin;
+use crate::api::settings::{RuntimeSettings, SharedSettings};
+use crate::api::shutdown::{Shutdown, SHUTDOWN_TIMEOUT};
 use crate::api::torrent::MediaQuality;
-use crate::db::DBConnection;
-use crate::db::downloads::DownloadDatabase;
+use crate::api::trackers::TrackerList;
+use crate::api::validation::{self, Expectation};
+use crate::api::watchlist::{announce_available, WatchlistRecheck};
+use crate::db::{DBConnection, PoolSettings};
+use crate::db::attempts::{AttemptDatabase, AttemptOutcome};
+use crate::db::blocklist::BlocklistDatabase;
+use crate::db::downloads::{DownloadDatabase, FinishedDownload};
+use crate::db::history::{HistoryDatabase, HistoryEvent};
 use crate::db::initialiser::DatabaseInitialiser;
+use crate::db::plex::PlexAuthDatabase;
+use crate::db::upgrades::UpgradeDatabase;
+use crate::db::watchlist_states::{WatchlistState, WatchlistStateDatabase};
 
 mod api;
 mod db;
@@ -36,12 +59,19 @@ async fn main() -> anyhow::Result<()> {
     if cfg!(debug_assertions) {
         console_subscriber::init();
     }
-    let config = AppConfig::load();
+    let config = AppConfig::load()?;
+    // Checks the config and connections, then exits without starting anything
+    if std::env::args().skip(1).any(|a| a == "--check-config") {
+        return check_config(&config).await;
+    }
 
     match config.tmdb_api_key.is_empty() {
         true => info!("Using IMDB"),
         false => info!("Using The MovieDB"),
     };
+    if config.read_only {
+        info!("Running in read-only mode, downloads and watchlist changes are disabled");
+    }
 
     // This is to trigger a fresh check on launch for first time of request type
     let mut twelve_hour_ago: DateTime<Local> = Local::now();
@@ -49,22 +79,9 @@ async fn main() -> anyhow::Result<()> {
         .checked_sub_signed(chrono::Duration::hours(12))
         .unwrap();
 
-    let plex_session = api::plex::Plex::new()?;
-
-    let (torrent_tx, mut torrent_rx) = tokio::sync::mpsc::unbounded_channel();
-    let torrent_client = api::torrent::Torrenter::new(
-        &config.qbittorrent_username,
-        &config.qbittorrent_password,
-        &config.qbittorrent_url,
-        config.minimum_quality,
-        torrent_tx.clone(),
-        config.trackers.clone(),
-    )
-    .await;
-
     let db_conn = match config.db_url.is_empty() {
-        true => DBConnection::from_env("DB_URI").await?,
-        false => DBConnection::new(&config.db_url).await?,
+        true => DBConnection::from_env("DB_URI", &config.db_pool).await?,
+        false => DBConnection::new(&config.db_url, &config.db_pool).await?,
     };
 
     match DatabaseInitialiser::new(&db_conn).initialise().await {
@@ -74,6 +91,105 @@ async fn main() -> anyhow::Result<()> {
         }
     };
 
+    match api::settings::import_config_file(&config, &db_conn).await {
+        Ok(true) => info!("Moved the settings in config.json to the database, the original is in config.json.bak"),
+        Ok(false) => (),
+        Err(e) => error!("Failed to import settings from config.json: {}", e),
+    }
+    // Values saved from the settings page take priority over config.json
+    let settings = RuntimeSettings::load_from_db(config.runtime_settings(), &db_conn).await?;
+    let settings = Arc::new(SharedSettings::new(settings));
+    let shutdown = Arc::new(Shutdown::new());
+
+    let tracker_list = Arc::new(TrackerList::new(&config.tracker_list_url));
+    let tracker_task = tokio::task::spawn(api::trackers::monitor_tracker_list(
+        Arc::clone(&tracker_list),
+        config.tracker_list_interval_hours,
+        Arc::clone(&shutdown),
+    ));
+
+    let (torrent_tx, mut torrent_rx) = tokio::sync::mpsc::unbounded_channel();
+    let torrent_client = match config.read_only {
+        true => api::torrent::Torrenter::mock(torrent_tx.clone(), Arc::clone(&settings), Arc::clone(&tracker_list)),
+        false => {
+            api::torrent::Torrenter::new(
+                &config.qbittorrent_username,
+                &config.qbittorrent_password,
+                &config.qbittorrent_url,
+                torrent_tx.clone(),
+                Arc::clone(&settings),
+                Arc::clone(&tracker_list),
+            )
+            .await
+        }
+    };
+
+    let mut plex_session: Option<Arc<api::plex::Plex>> = None;
+    let mut library_task = None;
+    let media_server: Arc<dyn MediaServer> = match config.media_server.to_ascii_lowercase().as_str() {
+        "none" | "disabled" => {
+            info!("No media server configured, library checks are disabled");
+            Arc::new(NoMediaServer)
+        }
+        "local" | "filesystem" => {
+            info!("Using local library: {:?}", config.library_paths);
+            let library = Arc::new(LocalLibrary::new(db_conn.clone(), config.library_paths.clone()));
+            let scanner = Arc::clone(&library);
+            let scanner_shutdown = Arc::clone(&shutdown);
+            library_task = Some(tokio::task::spawn(async move { scanner.monitor(scanner_shutdown).await }));
+            library
+        }
+        _ => {
+            let plex_url = match config.plex_url.is_empty() {
+                true => std::env::var("PLEX_URL").unwrap_or(String::from("http://127.0.0.1:32400")),
+                false => config.plex_url.clone(),
+            };
+            info!("Using Plex at {}", plex_url);
+
+            let plex_auth = PlexAuthDatabase::new(&db_conn)
+                .fetch_or_insert(&api::plex::Plex::generate_client_identifier())
+                .await?;
+            let token = match config.plex_token.is_empty() {
+                true => std::env::var("PLEX_TOKEN").ok().or(plex_auth.token),
+                false => Some(config.plex_token.clone()),
+            };
+
+            let plex = Arc::new(
+                api::plex::Plex::new(
+                    &plex_url,
+                    config.plex_accept_invalid_certs,
+                    token,
+                    &plex_auth.client_identifier,
+                )?
+                .with_sections(config.plex_sections.clone()),
+            );
+            plex_session = Some(Arc::clone(&plex));
+
+            match config.plex_servers.is_empty() {
+                true => plex,
+                false => {
+                    let mut servers: Vec<Arc<dyn MediaServer>> = vec![plex];
+                    for server in config.plex_servers.iter() {
+                        info!("Using additional Plex at {}", server.url);
+                        let token = match server.token.is_empty() {
+                            true => None,
+                            false => Some(server.token.clone()),
+                        };
+                        let plex = api::plex::Plex::new(
+                            &server.url,
+                            server.accept_invalid_certs,
+                            token,
+                            &plex_auth.client_identifier,
+                        )?
+                        .with_sections(server.sections.clone());
+                        servers.push(Arc::new(plex));
+                    }
+                    Arc::new(MultiMediaServer::new(servers))
+                }
+            }
+        }
+    };
+
     let cache_update: QueryCache = vec![
         (SearchType::MoviePopular, twelve_hour_ago.to_owned()),
         (SearchType::MovieLatestRelease, twelve_hour_ago.to_owned()),
@@ -87,8 +203,52 @@ async fn main() -> anyhow::Result<()> {
 
     let app_config_clone = Data::clone(&app_config);
     let db = Data::clone(&db_conn);
+    let media_server_watcher = Arc::clone(&media_server);
+    let torrent_client = Arc::new(torrent_client);
+    let event_stream = EventStream::new();
+    let mut notification_backends: Vec<Box<dyn NotificationBackend>> =
+        vec![Box::new(SettingsWebhookBackend::new(Arc::clone(&settings)))];
+    notification_backends.extend(app_config.webhooks.iter().map(|w| {
+        Box::new(WebhookBackend::new(&w.url, &w.secret, w.events.clone())) as Box<dyn NotificationBackend>
+    }));
+    notification_backends.push(Box::new(event_stream.clone()));
+    let notifier = Arc::new(Notifier::new(notification_backends));
+    let recycle_bin = Arc::new(RecycleBin::new(&app_config.recycle_bin_path, app_config.recycle_bin_days));
+    let mut disk_paths = vec![app_config.download_path.clone(), app_config.import_path.clone()];
+    disk_paths.extend(app_config.library_paths.iter().cloned());
+    let disk = Arc::new(DiskMonitor::new(disk_paths, app_config.minimum_free_space_gb));
+    let progress_feed = Arc::new(ProgressFeed::new());
+    let watchlist_recheck = Arc::new(WatchlistRecheck::new());
+    let job_queue = Arc::new(JobQueue::new());
+    let hooks = CompletionHooks {
+        jobs: Arc::clone(&job_queue),
+        notifier: Arc::clone(&notifier),
+        recycle_bin: Arc::clone(&recycle_bin),
+        disk: Arc::clone(&disk),
+        progress: Arc::clone(&progress_feed),
+        validate: app_config.validate_downloads && validation::ffprobe_available(),
+        importer: match app_config.import_path.is_empty() {
+            true => None,
+            false => Some(Importer::new(
+                ImportMode::from_config(&app_config.import_mode),
+                &app_config.import_path,
+            )),
+        },
+        kodi: match app_config.kodi_url.is_empty() {
+            true => None,
+            false => Some(Kodi::new(
+                &app_config.kodi_url,
+                &app_config.kodi_username,
+                &app_config.kodi_password,
+            )),
+        },
+    };
+    let watcher_shutdown = Arc::clone(&shutdown);
     let torrent_watcher = tokio::task::spawn(async move {
         let config = Data::clone(&app_config_clone);
+        if config.read_only {
+            return; // Nothing to monitor without a qBittorrent connection
+        }
         let delay_dur = Duration::from_millis(15000);
         let client = Api::new(
             &config.qbittorrent_username,
@@ -97,9 +257,16 @@ async fn main() -> anyhow::Result<()> {
         )
         .await
         .unwrap();
+        let bandwidth = BandwidthScheduler::new(
+            &config.qbittorrent_url,
+            &config.qbittorrent_username,
+            &config.qbittorrent_password,
+            config.speed_schedules.clone(),
+        );
         let mut torrents_filtered = HashSet::new();
         let mut stalled_torrents = HashMap::new();
         let mut auto_torrents = HashSet::new();
+        let mut connected = true;
         loop {
             let mut interval = tokio::time::interval(Duration::from_millis(100));
             while let Some(val) = tokio::select! {
@@ -108,53 +275,237 @@ async fn main() -> anyhow::Result<()> {
                 }
                 _ = interval.tick() => None
             } {
+                hooks.notifier.notify_download(&db, &val, DownloadEvent::Grabbed, None).await;
+                hooks.notifier.notify_watchlist(&db, &val, DownloadEvent::WatchlistGrabbed).await;
                 auto_torrents.insert(val);
             }
-            let _ = monitor_torrents(
+            if let Err(e) = bandwidth.apply().await {
+                warn!("Failed to set speed limits: {}", e);
+            }
+            let result = monitor_torrents(
                 &client,
                 &config,
                 &db,
+                &media_server_watcher,
+                &hooks,
                 &mut torrents_filtered,
                 &mut stalled_torrents,
                 &mut auto_torrents,
             )
             .await;
-            tokio::time::sleep_until(Instant::now() + delay_dur).await;
+            // Only report losing the connection once rather than every pass
+            match (result, connected) {
+                (Ok(_), _) => connected = true,
+                (Err(e), true) => {
+                    error!("Failed to fetch torrents from qBittorrent: {}", e);
+                    let detail = format!("Failed to fetch torrents from qBittorrent: {}", e);
+                    hooks.notifier.notify(DownloadEvent::Error, None, None, Some(&detail)).await;
+                    connected = false;
+                }
+                (Err(_), false) => (),
+            }
+            if watcher_shutdown.sleep(delay_dur).await {
+                info!("Stopping Torrent Watcher");
+                return;
+            }
         }
     });
 
     let db_conn = Data::clone(&db_conn);
     let db_conn_watchlist = Data::clone(&db_conn);
-    let torrent_client = Arc::new(torrent_client);
+    let recycle_shutdown = Arc::clone(&shutdown);
+    let recycle_task = tokio::task::spawn(async move {
+        if recycle_bin.is_enabled().not() {
+            return;
+        }
+        loop {
+            recycle_bin.cleanup().await;
+            if recycle_shutdown.sleep(Duration::from_secs(60 * 60 * 6)).await {
+                return;
+            }
+        }
+    });
+    let disk_task = tokio::task::spawn(api::disk::monitor_disk_space(
+        Arc::clone(&disk),
+        Arc::clone(&notifier),
+        Arc::clone(&shutdown),
+    ));
     let watchlist_task = tokio::task::spawn(api::watchlist::monitor_watchlist(
         db_conn_watchlist.into_inner(),
-        Arc::new(plex_session.clone()),
+        Arc::clone(&media_server),
+        Arc::clone(&torrent_client),
+        Arc::clone(&disk),
+        Arc::clone(&notifier),
+        Arc::clone(&watchlist_recheck),
+        Arc::clone(&settings),
+        Arc::clone(&shutdown),
+        Data::clone(&app_config),
+    ));
+    let config_task = tokio::task::spawn(api::settings::monitor_config(
+        Arc::clone(&settings),
+        Data::clone(&db_conn).into_inner(),
+        Arc::clone(&shutdown),
+    ));
+    let retention_task = tokio::task::spawn(api::retention::monitor_retention(
+        Data::clone(&db_conn).into_inner(),
+        app_config.history_retention_days,
+        app_config.preferences_retention_days,
+        Arc::clone(&shutdown),
+    ));
+    let import_task = tokio::task::spawn(api::imports::monitor_imports(
+        Data::clone(&db_conn).into_inner(),
+        Arc::clone(&media_server),
+        Arc::clone(&notifier),
+        Arc::clone(&shutdown),
+        Data::clone(&app_config),
+    ));
+    let episode_search_task = tokio::task::spawn(api::watchlist::schedule_episode_searches(
+        Data::clone(&db_conn).into_inner(),
+        Arc::clone(&job_queue),
+        Arc::clone(&disk),
+        Arc::clone(&shutdown),
+        Data::clone(&app_config),
+    ));
+    let jobs_task = tokio::task::spawn(api::jobs::run_jobs(
+        Arc::clone(&job_queue),
+        Data::clone(&db_conn).into_inner(),
         Arc::clone(&torrent_client),
+        Arc::clone(&media_server),
+        Arc::clone(&notifier),
+        Arc::clone(&shutdown),
         Data::clone(&app_config),
     ));
 
     let youtube = Data::new(youtube);
     let cache_update = Data::new(Mutex::new(cache_update));
-    let plex_session = Data::new(plex_session);
+    let searches = Data::new(server::query::SearchRequests::default());
+    let posters = Data::new(api::posters::PosterCache::new(&app_config.image_cache_path));
+    let api_keys = Data::new(server::auth::ApiKeyCache::new());
+    let media_server: Data<dyn MediaServer> = Data::from(media_server);
+    let plex_session = plex_session.map(Data::from);
     let torrent_client = Data::from(torrent_client);
+    let notifier = Data::from(notifier);
+    let disk = Data::from(disk);
+    let progress_feed = Data::from(progress_feed);
+    let watchlist_recheck = Data::from(watchlist_recheck);
+    let job_queue = Data::from(job_queue);
+    let event_stream = Data::new(event_stream);
+    let settings = Data::from(settings);
 
+    let base_path = app_config.base_path.clone();
+    let compression = server::compress::Compression::new(app_config.gzip_level, app_config.brotli_level);
+    // Built once so every worker shares the same buckets
+    let rate_limit = server::rate_limit::RateLimit::new(
+        app_config.rate_limit_per_minute,
+        app_config.rate_limit_burst,
+        &app_config.trusted_proxies,
+        &app_config.base_path,
+    );
     let server = HttpServer::new(move || {
-        App::new()
+        let app = App::new()
             .wrap(Logger::default())
             .app_data(Data::clone(&cache_update))
+            .app_data(Data::clone(&searches))
+            .app_data(Data::clone(&posters))
+            .app_data(Data::clone(&api_keys))
             .app_data(Data::clone(&db_conn))
-            .app_data(Data::clone(&plex_session))
+            .app_data(Data::clone(&media_server))
             .app_data(Data::clone(&torrent_client))
             .app_data(Data::clone(&youtube))
+            .app_data(Data::clone(&notifier))
+            .app_data(Data::clone(&disk))
+            .app_data(Data::clone(&progress_feed))
+            .app_data(Data::clone(&watchlist_recheck))
+            .app_data(Data::clone(&job_queue))
+            .app_data(Data::clone(&event_stream))
+            .app_data(Data::clone(&settings))
             .app_data(Data::clone(&app_config))
-            .service(actix_files::Files::new("/static", "./static").show_files_listing())
-            .service(server::index)
-            .service(server::query::search)
-            .service(server::query::modal_metadata)
-            .service(server::download::update_watchlist)
-            .service(server::download::start_download)
-            .service(server::download::find_download)
-            .service(server::download::start_download_post)
+            .wrap(server::auth::ApiKeyAuth)
+            .wrap(server::error::ErrorPages::new(&base_path))
+            .wrap(rate_limit.clone())
+            .wrap(compression);
+
+        // Only available when Plex is the media server
+        let app = match &plex_session {
+            Some(t) => app.app_data(Data::clone(t)),
+            None => app,
+        };
+
+        app.service(
+            web::scope(&base_path)
+                .service(server::assets::static_file)
+                .service(server::image::poster)
+                .service(server::index)
+                .service(server::query::search)
+                .service(server::query::modal_metadata)
+                .service(server::query::refresh_item)
+                .service(server::query::more_like_this)
+                .service(server::download::update_watchlist)
+                .service(server::download::recheck_watchlist)
+                .service(server::download::start_download)
+                .service(server::download::find_download)
+                .service(server::download::find_episode_download)
+                .service(server::download::start_download_post)
+                .service(server::download::submit_magnet)
+                .service(server::download::dismiss_download)
+                .service(server::plex::plex_login)
+                .service(server::plex::plex_login_check)
+                .service(server::aliases::list_aliases)
+                .service(server::aliases::add_alias)
+                .service(server::aliases::remove_alias)
+                .service(server::stats::library_stats)
+                .service(server::stats::download_stats)
+                .service(server::calendar::calendar)
+                .service(server::history::download_history)
+                .service(server::history::history_feed)
+                .service(server::download::remove_download)
+                .service(server::imports::manual_import)
+                .service(server::live::progress_socket)
+                .service(server::live::event_stream)
+                .service(server::api_v1::openapi_spec)
+                .service(server::api_v1::api_docs)
+                .service(server::api_v1::api_search)
+                .service(server::api_v1::api_item)
+                .service(server::api_v1::api_item_torrents)
+                .service(server::api_v1::api_item_attempts)
+                .service(server::api_v1::api_watchlist)
+                .service(server::api_v1::api_add_to_watchlist)
+                .service(server::api_v1::api_remove_from_watchlist)
+                .service(server::api_v1::api_recheck_watchlist)
+                .service(server::api_v1::api_bulk_add_to_watchlist)
+                .service(server::api_v1::api_bulk_remove_from_watchlist)
+                .service(server::api_v1::api_import_watchlist)
+                .service(server::api_v1::api_watchlist_import)
+                .service(server::api_v1::api_downloads)
+                .service(server::api_v1::api_start_downloads)
+                .service(server::api_v1::api_cancel_download)
+                .service(server::arr::arr_system_status)
+                .service(server::arr::arr_queue)
+                .service(server::arr::arr_movie_lookup)
+                .service(server::arr::arr_series_lookup)
+                .service(server::arr::arr_movies)
+                .service(server::arr::arr_series)
+                .service(server::arr::arr_add_movie)
+                .service(server::arr::arr_add_series)
+                .service(server::auth::list_api_keys)
+                .service(server::auth::create_api_key)
+                .service(server::auth::revoke_api_key)
+                .service(server::settings::get_settings)
+                .service(server::settings::update_settings)
+                .service(server::settings::reload_settings)
+                .service(server::preferences::get_preferences)
+                .service(server::preferences::update_preferences)
+                .service(server::api_v1::api_settings)
+                .service(server::api_v1::api_update_settings)
+                .service(server::api_v1::api_profiles)
+                .service(server::api_v1::api_create_profile)
+                .service(server::api_v1::api_update_profile)
+                .service(server::api_v1::api_remove_profile)
+                .service(server::api_v1::api_set_watchlist_profile)
+                .service(server::api_v1::api_watchlist_options)
+                .service(server::api_v1::api_set_watchlist_options)
+                .service(server::api_v1::api_jobs)
+        )
     })
     .bind(("0.0.0.0", 80))?;
 
@@ -182,15 +533,38 @@ async fn main() -> anyhow::Result<()> {
         server
     };
 
-    server.run().await?;
+    // Signals are handled here rather than by actix so the background tasks are stopped as well
+    let server = server
+        .disable_signals()
+        .shutdown_timeout(SHUTDOWN_TIMEOUT)
+        .run();
+    let server_handle = server.handle();
+    let signal_shutdown = Arc::clone(&shutdown);
+    tokio::task::spawn(async move { api::shutdown::listen(&signal_shutdown, server_handle).await });
+    server.await?;
+    // In case the server stopped for any other reason
+    shutdown.trigger();
 
     watchlist_task.await?;
+    import_task.await?;
+    episode_search_task.await?;
+    jobs_task.await?;
+    config_task.await?;
+    retention_task.await?;
+    recycle_task.await?;
+    disk_task.await?;
+    tracker_task.await?;
     torrent_watcher.await?;
+    if let Some(t) = library_task {
+        t.await?;
+    }
 
     Ok(())
 }
 
-#[derive(Debug, Deserialize, Clone)]
+// Every value is optional so roundup can run from environment variables alone, see apply_defaults
+#[derive(Debug, Deserialize, Clone, Default)]
+#[serde(default)]
 struct AppConfigImport {
     qbittorrent_url: String,
     qbittorrent_username: String,
@@ -200,9 +574,350 @@ struct AppConfigImport {
     minimum_quality: String,
     youtube_api_key: String,
     tmdb_api_key: String,
+    #[serde(default)]
+    fanart_api_key: String,
     watchlist_recheck_interval_hours: i64,
     #[serde(default)]
     trackers: Vec<String>,
+    #[serde(default)]
+    read_only: bool,
+    #[serde(default)]
+    require_api_key: bool,
+    #[serde(default)]
+    media_server: String,
+    #[serde(default)]
+    verify_torrent_health: bool,
+    #[serde(default)]
+    plex_url: String,
+    #[serde(default)]
+    plex_accept_invalid_certs: bool,
+    #[serde(default)]
+    plex_token: String,
+    #[serde(default)]
+    library_paths: Vec<String>,
+    #[serde(default)]
+    upgrade_quality: bool,
+    #[serde(default)]
+    plex_sections: Vec<String>,
+    #[serde(default)]
+    plex_servers: Vec<PlexServerConfig>,
+    #[serde(default)]
+    import_grace_period_minutes: i64,
+    #[serde(default)]
+    stalled_timeout_hours: i64,
+    #[serde(default)]
+    stalled_action: String,
+    #[serde(default)]
+    validate_downloads: bool,
+    #[serde(default)]
+    import_path: String,
+    #[serde(default)]
+    import_mode: String,
+    #[serde(default)]
+    notification_webhooks: Vec<String>,
+    #[serde(default)]
+    webhooks: Vec<WebhookConfigImport>,
+    #[serde(default)]
+    recycle_bin_path: String,
+    #[serde(default)]
+    recycle_bin_days: i64,
+    #[serde(default)]
+    kodi_url: String,
+    #[serde(default)]
+    kodi_username: String,
+    #[serde(default)]
+    kodi_password: String,
+    #[serde(default)]
+    download_path: String,
+    #[serde(default)]
+    minimum_free_space_gb: u64,
+    #[serde(default)]
+    speed_schedules: Vec<SpeedScheduleConfig>,
+    #[serde(default)]
+    image_cache_path: String,
+    #[serde(default)]
+    rate_limit_per_minute: u32,
+    #[serde(default)]
+    rate_limit_burst: u32,
+    #[serde(default)]
+    trusted_proxies: Vec<String>,
+    #[serde(default)]
+    gzip_level: u32,
+    #[serde(default)]
+    brotli_level: u32,
+    #[serde(default)]
+    tracker_list_url: String,
+    #[serde(default)]
+    tracker_list_interval_hours: i64,
+    #[serde(default)]
+    history_retention_days: i64,
+    #[serde(default)]
+    preferences_retention_days: i64,
+    file_filter: FileFilterConfig,
+    #[serde(default)]
+    db_max_connections: u32,
+    #[serde(default)]
+    db_acquire_timeout_secs: u64,
+    #[serde(default)]
+    db_connect_retries: i64,
+    #[serde(default)]
+    metadata_refresh_days: i64,
+}
+
+const MINIMUM_QUALITIES: [&str; 15] = [
+    "unknown", "cam", "telesync", "ts", "tele-sync", "720p", "720", "1080p", "1080", "2160p", "2160", "4k", "4320p", "4320",
+    "8K",
+];
+const DEFAULT_FILE_TYPES: [&str; 7] = ["mkv", "mp4", "avi", "srt", "mov", "webm", "m4v"];
+const TRACKER_SCHEMES: [&str; 5] = ["udp://", "http://", "https://", "ws://", "wss://"];
+
+impl AppConfigImport {
+    fn read_file(path: &Path) -> anyhow::Result<AppConfigImport> {
+        let buffer = match fs::read_to_string(path) {
+            Ok(t) => t,
+            Err(e) => return Err(format_err!("Failed to read {}, {}", path.display(), e)),
+        };
+
+        // Same structure whichever format it's written in, the parsers' messages include the line and column
+        let extension = path.extension().and_then(|e| e.to_str()).unwrap_or_default().to_ascii_lowercase();
+        let imported: Result<AppConfigImport, String> = match extension.as_str() {
+            "toml" => toml::from_str(&buffer).map_err(|e| e.to_string()),
+            "yaml" | "yml" => serde_yaml::from_str(&buffer).map_err(|e| e.to_string()),
+            _ => serde_json::from_str(&buffer).map_err(|e| e.to_string()),
+        };
+
+        match imported {
+            Ok(t) => Ok(t),
+            Err(e) => Err(format_err!("Failed to parse {}, {}", path.display(), e)),
+        }
+    }
+
+    // Environment variables override the config file, eg QBITTORRENT_URL replaces qbittorrent_url.
+    // Secrets can be read from a file Docker secrets style, eg QBITTORRENT_PASSWORD_FILE=/run/secrets/qbittorrent_password
+    fn read_env(&mut self) -> anyhow::Result<()> {
+        let values = [
+            ("QBITTORRENT_URL", &mut self.qbittorrent_url),
+            ("QBITTORRENT_USERNAME", &mut self.qbittorrent_username),
+            ("QBITTORRENT_PASSWORD", &mut self.qbittorrent_password),
+            ("DB_URL", &mut self.db_url),
+            ("MINIMUM_QUALITY", &mut self.minimum_quality),
+            ("YOUTUBE_API_KEY", &mut self.youtube_api_key),
+            ("TMDB_API_KEY", &mut self.tmdb_api_key),
+            ("FANART_API_KEY", &mut self.fanart_api_key),
+            ("MEDIA_SERVER", &mut self.media_server),
+            ("KODI_PASSWORD", &mut self.kodi_password),
+        ];
+        for (name, value) in values {
+            if let Ok(t) = std::env::var(name) {
+                *value = t;
+            }
+        }
+
+        let secrets = [
+            ("QBITTORRENT_USERNAME_FILE", &mut self.qbittorrent_username),
+            ("QBITTORRENT_PASSWORD_FILE", &mut self.qbittorrent_password),
+            ("DB_URL_FILE", &mut self.db_url),
+            ("YOUTUBE_API_KEY_FILE", &mut self.youtube_api_key),
+            ("TMDB_API_KEY_FILE", &mut self.tmdb_api_key),
+            ("FANART_API_KEY_FILE", &mut self.fanart_api_key),
+            ("PLEX_TOKEN_FILE", &mut self.plex_token),
+            ("KODI_PASSWORD_FILE", &mut self.kodi_password),
+        ];
+        for (name, value) in secrets {
+            let path = match std::env::var(name) {
+                Ok(t) if t.is_empty().not() => t,
+                _ => continue,
+            };
+            match fs::read_to_string(&path) {
+                // Files written with echo or an editor end in a newline
+                Ok(t) => *value = t.trim_end_matches(['\r', '\n']).to_string(),
+                Err(e) => return Err(format_err!("Failed to read {} from {}, {}", name, path, e)),
+            }
+        }
+
+        Ok(())
+    }
+
+    // Fills in what config.json used to require, matching the values it ships with
+    fn apply_defaults(&mut self) {
+        if self.qbittorrent_url.is_empty() {
+            self.qbittorrent_url = String::from("http://127.0.0.1:8080");
+        }
+        if self.qbittorrent_username.is_empty() {
+            self.qbittorrent_username = String::from("admin");
+        }
+        if self.valid_file_types.is_empty() {
+            self.valid_file_types = DEFAULT_FILE_TYPES.iter().map(|t| t.to_string()).collect();
+        }
+        if self.minimum_quality.is_empty() {
+            self.minimum_quality = String::from("720p");
+        }
+        if self.watchlist_recheck_interval_hours == 0 {
+            self.watchlist_recheck_interval_hours = 6;
+        }
+    }
+
+    // Everything wrong with the config at once, rather than failing on the first problem
+    fn validate(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+
+        check_url(&mut problems, "qbittorrent_url", &self.qbittorrent_url);
+        for (name, url) in [("plex_url", &self.plex_url), ("kodi_url", &self.kodi_url)] {
+            if url.is_empty().not() {
+                check_url(&mut problems, name, url);
+            }
+        }
+        for server in self.plex_servers.iter() {
+            check_url(&mut problems, "plex_servers url", &server.url);
+        }
+        for url in self.notification_webhooks.iter() {
+            check_url(&mut problems, "notification_webhooks", url);
+        }
+        for webhook in self.webhooks.iter() {
+            check_url(&mut problems, "webhooks url", &webhook.url);
+        }
+        if self.tracker_list_url.is_empty().not() {
+            check_url(&mut problems, "tracker_list_url", &self.tracker_list_url);
+        }
+
+        if MINIMUM_QUALITIES.contains(&self.minimum_quality.as_str()).not() {
+            problems.push(format!(
+                "minimum_quality \"{}\" isn't known, use one of cam, telesync, 720p, 1080p, 2160p, 4320p or unknown",
+                self.minimum_quality
+            ));
+        }
+
+        for tracker in self.trackers.iter() {
+            let valid = TRACKER_SCHEMES.iter().any(|s| tracker.starts_with(s))
+                && reqwest::Url::parse(tracker).is_ok_and(|u| u.host_str().is_some());
+            if valid.not() {
+                problems.push(format!(
+                    "tracker \"{}\" should be a udp://, http(s):// or ws(s):// announce URL",
+                    tracker
+                ));
+            }
+        }
+
+        if self.file_filter.minimum_size_mb < 0 {
+            problems.push("file_filter minimum_size_mb can't be negative".to_string());
+        }
+
+        if self.watchlist_recheck_interval_hours < 1 {
+            problems.push("watchlist_recheck_interval_hours must be at least 1".to_string());
+        }
+
+        // The only value without a sensible default
+        if self.db_url.is_empty() && std::env::var("DB_URI").is_err() {
+            problems.push("db_url isn't set, set it in the config file or with DB_URL or DB_URI".to_string());
+        }
+
+        problems
+    }
+}
+
+fn check_url(problems: &mut Vec<String>, name: &str, url: &str) {
+    match reqwest::Url::parse(url) {
+        Ok(t) if matches!(t.scheme(), "http" | "https") => (),
+        Ok(_) => problems.push(format!("{} \"{}\" must start with http:// or https://", name, url)),
+        Err(e) => problems.push(format!("{} \"{}\" isn't a valid URL, {}", name, url, e)),
+    }
+}
+
+// --check-config, the config has already been validated so this checks what it points at
+async fn check_config(config: &AppConfig) -> anyhow::Result<()> {
+    let mut failed = false;
+
+    let db = match config.db_url.is_empty() {
+        true => DBConnection::from_env("DB_URI", &config.db_pool).await,
+        false => DBConnection::new(&config.db_url, &config.db_pool).await.map_err(anyhow::Error::from),
+    };
+    match db {
+        Ok(_) => info!("Connected to the database"),
+        Err(e) => {
+            error!("Failed to connect to the database, check db_url or DB_URI: {}", e);
+            failed = true;
+        }
+    }
+
+    if config.read_only.not() {
+        match Api::new(&config.qbittorrent_username, &config.qbittorrent_password, &config.qbittorrent_url).await {
+            Ok(_) => info!("Logged in to qBittorrent at {}", config.qbittorrent_url),
+            Err(e) => {
+                error!(
+                    "Failed to log in to qBittorrent at {}, check qbittorrent_url, qbittorrent_username and qbittorrent_password: {:?}",
+                    config.qbittorrent_url, e
+                );
+                failed = true;
+            }
+        }
+    }
+
+    match failed {
+        true => Err(format_err!("Config check failed")),
+        false => {
+            info!("Config is valid");
+            Ok(())
+        }
+    }
+}
+
+// Which files in a torrent are downloaded, extensions default to valid_file_types
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+struct FileFilterConfig {
+    extensions: Vec<String>,
+    minimum_size_mb: i64,
+    exclude_patterns: Vec<String>,
+}
+
+impl Default for FileFilterConfig {
+    fn default() -> Self {
+        FileFilterConfig {
+            extensions: Vec::new(),
+            minimum_size_mb: 0,
+            exclude_patterns: vec![String::from("sample"), String::from("extras")],
+        }
+    }
+}
+
+// Additional Plex servers, checked alongside the main one
+#[derive(Deserialize, Debug, Clone)]
+struct PlexServerConfig {
+    url: String,
+    #[serde(default)]
+    token: String,
+    #[serde(default)]
+    accept_invalid_certs: bool,
+    #[serde(default)]
+    sections: Vec<String>,
+}
+
+// {"url": "...", "secret": "...", "events": ["grabbed", "completed"]}, no events means all of them
+#[derive(Deserialize, Debug, Clone)]
+struct WebhookConfigImport {
+    url: String,
+    #[serde(default)]
+    secret: String,
+    #[serde(default)]
+    events: Vec<String>,
+}
+
+#[derive(Debug, Clone)]
+struct WebhookConfig {
+    url: String,
+    secret: String,
+    events: Vec<DownloadEvent>,
+}
+
+// Global speed limits for part of the day, eg. {"start": "18:00", "end": "23:00", "download_limit_kb": 2048}
+#[derive(Deserialize, Debug, Clone)]
+struct SpeedScheduleConfig {
+    start: String,
+    end: String,
+    #[serde(default)]
+    download_limit_kb: i64,
+    #[serde(default)]
+    upload_limit_kb: i64,
 }
 
 #[derive(Debug, Clone)]
@@ -211,25 +926,109 @@ struct AppConfig {
     qbittorrent_username: String,
     qbittorrent_password: String,
     db_url: String,
+    db_pool: PoolSettings,
     valid_file_types: Vec<String>,
+    file_filter: FileFilter,
     minimum_quality: MediaQuality,
     youtube_api_key: String,
     tmdb_api_key: String,
+    fanart_api_key: String,
     watchlist_recheck_interval_hours: i64,
     trackers: Vec<String>,
+    read_only: bool,
+    require_api_key: bool,
+    media_server: String,
+    verify_torrent_health: bool,
+    plex_url: String,
+    plex_accept_invalid_certs: bool,
+    plex_token: String,
+    library_paths: Vec<String>,
+    upgrade_quality: bool,
+    plex_sections: Vec<String>,
+    plex_servers: Vec<PlexServerConfig>,
+    import_grace_period_minutes: i64,
+    stalled_timeout_hours: i64,
+    replace_stalled: bool,
+    validate_downloads: bool,
+    import_path: String,
+    import_mode: String,
+    notification_webhooks: Vec<String>,
+    webhooks: Vec<WebhookConfig>,
+    recycle_bin_path: String,
+    recycle_bin_days: i64,
+    kodi_url: String,
+    kodi_username: String,
+    kodi_password: String,
+    download_path: String,
+    minimum_free_space_gb: u64,
+    speed_schedules: Vec<SpeedSchedule>,
+    image_cache_path: String,
+    rate_limit_per_minute: u32,
+    rate_limit_burst: u32,
+    // Reverse proxies whose X-Forwarded-For is believed when rate limiting
+    trusted_proxies: Vec<String>,
+    gzip_level: u32,
+    brotli_level: u32,
+    tracker_list_url: String,
+    tracker_list_interval_hours: i64,
+    // Negative keeps everything
+    history_retention_days: i64,
+    preferences_retention_days: i64,
+    // Negative only fetches metadata that's missing
+    metadata_refresh_days: i64,
+    // Serve ./static from this folder instead of the copy built into the binary, for development
+    static_dir: String,
+    // URL prefix when served behind a reverse proxy, eg "/roundup", empty when served from the root
+    base_path: String,
 }
 
 impl AppConfig {
-    pub fn load() -> AppConfig {
-        let buffer = fs::read_to_string("./config.json").unwrap();
+    pub fn load() -> anyhow::Result<AppConfig> {
+        let path = config_path();
+        let mut imported = match &path {
+            Some(t) => AppConfigImport::read_file(t)?,
+            None => AppConfigImport::default(),
+        };
+        imported.read_env()?;
+        imported.apply_defaults();
 
-        let imported: AppConfigImport = serde_json::from_str(&buffer).unwrap();
+        let problems = imported.validate();
+        if problems.is_empty().not() {
+            let source = match &path {
+                Some(t) => t.display().to_string(),
+                None => "config (no config file found, set the values with environment variables)".to_string(),
+            };
+            return Err(format_err!("Invalid {}:\n  - {}", source, problems.join("\n  - ")));
+        }
 
         let config = AppConfig {
             qbittorrent_url: imported.qbittorrent_url,
             qbittorrent_username: imported.qbittorrent_username,
             qbittorrent_password: imported.qbittorrent_password,
             db_url: imported.db_url,
+            db_pool: PoolSettings {
+                max_connections: match imported.db_max_connections {
+                    0 => 10,
+                    t => t,
+                },
+                acquire_timeout: Duration::from_secs(match imported.db_acquire_timeout_secs {
+                    0 => 30,
+                    t => t,
+                }),
+                // Negative connects once
+                connect_retries: match imported.db_connect_retries {
+                    0 => 10,
+                    t => t.clamp(0, u32::MAX as i64) as u32,
+                },
+            },
+            file_filter: FileFilter::new(
+                match imported.file_filter.extensions.is_empty() {
+                    true => &imported.valid_file_types,
+                    false => &imported.file_filter.extensions,
+                },
+                imported.file_filter.minimum_size_mb,
+                &imported.file_filter.exclude_patterns,
+            ),
             valid_file_types: imported.valid_file_types,
             minimum_quality: match imported.minimum_quality.as_str() {
                 "cam" => MediaQuality::Cam,
@@ -242,11 +1041,326 @@ impl AppConfig {
             },
             youtube_api_key: imported.youtube_api_key,
             tmdb_api_key: imported.tmdb_api_key,
+            fanart_api_key: imported.fanart_api_key,
             watchlist_recheck_interval_hours: imported.watchlist_recheck_interval_hours,
             trackers: imported.trackers,
+            read_only: imported.read_only,
+            require_api_key: imported.require_api_key,
+            media_server: imported.media_server,
+            verify_torrent_health: imported.verify_torrent_health,
+            plex_url: imported.plex_url,
+            plex_accept_invalid_certs: imported.plex_accept_invalid_certs,
+            plex_token: imported.plex_token,
+            library_paths: imported.library_paths,
+            upgrade_quality: imported.upgrade_quality,
+            plex_sections: imported.plex_sections,
+            plex_servers: imported.plex_servers,
+            import_grace_period_minutes: match imported.import_grace_period_minutes {
+                0 => 60,
+                t => t,
+            },
+            stalled_timeout_hours: match imported.stalled_timeout_hours {
+                0 => 12,
+                t => t,
+            },
+            replace_stalled: imported.stalled_action.to_ascii_lowercase() != "reannounce",
+            validate_downloads: imported.validate_downloads,
+            import_path: imported.import_path,
+            import_mode: imported.import_mode,
+            notification_webhooks: imported.notification_webhooks,
+            webhooks: imported
+                .webhooks
+                .into_iter()
+                .map(|w| WebhookConfig {
+                    events: w
+                        .events
+                        .iter()
+                        .filter_map(|e| match serde_json::from_value(serde_json::Value::String(e.to_string())) {
+                            Ok(t) => Some(t),
+                            Err(_) => {
+                                warn!("Ignoring unknown webhook event: {}", e);
+                                None
+                            }
+                        })
+                        .collect(),
+                    url: w.url,
+                    secret: w.secret,
+                })
+                .collect(),
+            recycle_bin_path: imported.recycle_bin_path,
+            recycle_bin_days: match imported.recycle_bin_days {
+                0 => 7,
+                t => t,
+            },
+            kodi_url: imported.kodi_url,
+            kodi_username: imported.kodi_username,
+            kodi_password: imported.kodi_password,
+            download_path: imported.download_path,
+            minimum_free_space_gb: imported.minimum_free_space_gb,
+            speed_schedules: imported
+                .speed_schedules
+                .iter()
+                .filter_map(|s| {
+                    match SpeedSchedule::new(&s.start, &s.end, s.download_limit_kb, s.upload_limit_kb) {
+                        Ok(t) => Some(t),
+                        Err(e) => {
+                            warn!("Ignoring speed schedule: {}", e);
+                            None
+                        }
+                    }
+                })
+                .collect(),
+            image_cache_path: imported.image_cache_path,
+            rate_limit_per_minute: imported.rate_limit_per_minute,
+            rate_limit_burst: imported.rate_limit_burst,
+            trusted_proxies: imported.trusted_proxies,
+            gzip_level: imported.gzip_level,
+            brotli_level: imported.brotli_level,
+            tracker_list_url: imported.tracker_list_url,
+            tracker_list_interval_hours: match imported.tracker_list_interval_hours {
+                0 => 24,
+                t => t,
+            },
+            history_retention_days: match imported.history_retention_days {
+                0 => 365,
+                t => t,
+            },
+            preferences_retention_days: match imported.preferences_retention_days {
+                0 => 180,
+                t => t,
+            },
+            metadata_refresh_days: match imported.metadata_refresh_days {
+                0 => 30,
+                t => t,
+            },
+            static_dir: std::env::var("ROUNDUP_STATIC_DIR").unwrap_or_default(),
+            base_path: normalise_base_path(&std::env::var("ROUNDUP_BASE_PATH").unwrap_or_default()),
         };
 
-        config
+        Ok(config)
+    }
+
+    pub fn runtime_settings(&self) -> RuntimeSettings {
+        RuntimeSettings {
+            minimum_quality: self.minimum_quality,
+            trackers: self.trackers.clone(),
+            watchlist_recheck_interval_hours: self.watchlist_recheck_interval_hours,
+            disabled_indexers: Vec::new(),
+            notification_webhooks: self.notification_webhooks.clone(),
+        }
+    }
+
+    pub fn has_media_server(&self) -> bool {
+        matches!(self.media_server.to_ascii_lowercase().as_str(), "none" | "disabled").not()
+    }
+}
+
+// ROUNDUP_CONFIG if set, otherwise the first of config.json, config.toml, config.yaml and config.yml in the working
+// directory. None when there isn't one, everything is then set from the environment
+fn config_path() -> Option<PathBuf> {
+    if let Ok(t) = std::env::var("ROUNDUP_CONFIG") {
+        return Some(PathBuf::from(t));
+    }
+
+    ["./config.json", "./config.toml", "./config.yaml", "./config.yml"]
+        .iter()
+        .map(PathBuf::from)
+        .find(|p| p.exists())
+}
+
+// The top level keys written in the config file, whatever format it's in
+fn config_file_keys(path: &Path) -> anyhow::Result<HashSet<String>> {
+    let buffer = fs::read_to_string(path)?;
+    let extension = path.extension().and_then(|e| e.to_str()).unwrap_or_default().to_ascii_lowercase();
+    let keys: HashMap<String, serde::de::IgnoredAny> = match extension.as_str() {
+        "toml" => toml::from_str(&buffer)?,
+        "yaml" | "yml" => serde_yaml::from_str(&buffer)?,
+        _ => serde_json::from_str(&buffer)?,
+    };
+
+    Ok(keys.into_keys().collect())
+}
+
+// "roundup", "/roundup/" etc. all become "/roundup"
+fn normalise_base_path(path: &str) -> String {
+    match path.trim().trim_matches('/') {
+        "" => String::new(),
+        t => format!("/{}", t),
+    }
+}
+
+// Optional steps run once a download has finished
+struct CompletionHooks {
+    jobs: Arc<JobQueue>,
+    notifier: Arc<Notifier>,
+    recycle_bin: Arc<RecycleBin>,
+    disk: Arc<DiskMonitor>,
+    progress: Arc<ProgressFeed>,
+    // Check files with ffprobe, only set when it's installed
+    validate: bool,
+    importer: Option<Importer>,
+    kodi: Option<Kodi>,
+}
+
+// Checks a finished download's files with ffprobe. Bad releases are blocklisted, deleted and searched for again.
+// Returns false when the download was rejected.
+async fn validate_download(
+    client: &Api,
+    config: &AppConfig,
+    db: &DBConnection,
+    hooks: &CompletionHooks,
+    torrent: &Torrent,
+) -> bool {
+    let hash = torrent.hash().as_str().to_lowercase();
+    let download_db = DownloadDatabase::new(db);
+    let download = match download_db.fetch_by_hash(&hash).await {
+        Ok(Some(d)) => d,
+        Ok(None) => return true, // Not started by roundup, nothing to compare against
+        Err(e) => {
+            error!("Failed to fetch download: {}", e);
+            return true;
+        }
+    };
+    let contents = match client.contents(torrent).await {
+        Ok(c) => c,
+        Err(_) => return true,
+    };
+
+    // Moves are imported after this check
+    let root = match &hooks.importer {
+        Some(importer) if importer.has_imported(torrent.hash().as_str()) => importer.destination(),
+        _ => torrent.save_path(),
+    };
+    // Samples and extras the file filter skipped would fail the checks, unless the filter left everything in
+    let videos = contents
+        .iter()
+        .filter(|c| validation::is_video_file(c.name(), &config.valid_file_types))
+        .filter(|c| validation::is_sample_file(c.name()).not())
+        .collect::<Vec<_>>();
+    let wanted = videos
+        .iter()
+        .filter(|c| config.file_filter.wanted(c.name(), *c.size()))
+        .copied()
+        .collect::<Vec<_>>();
+    let files = match wanted.is_empty() {
+        true => videos,
+        false => wanted,
+    };
+    let files = files
+        .iter()
+        .map(|c| Path::new(root).join(c.name()))
+        .collect::<Vec<PathBuf>>();
+    let expected = Expectation {
+        quality: MediaQuality::from_label(&download.quality),
+        runtime: match download._type {
+            ItemType::Movie => download.runtime,
+            ItemType::TvShow => None,
+        },
+    };
+
+    let paths = files.clone();
+    let reason = match tokio::task::spawn_blocking(move || validation::validate_files(&paths, &expected)).await {
+        Ok(Ok(_)) | Err(_) => return true,
+        Ok(Err(e)) => e.to_string(),
+    };
+
+    // Imported copies are outside of qBittorrent's reach
+    for file in files.iter() {
+        if let Err(e) = hooks.recycle_bin.remove_file(file).await {
+            warn!("Failed to remove {}: {}", file.display(), e);
+        }
+    }
+    reject_download(client, db, hooks, torrent, &download, &reason).await;
+
+    false
+}
+
+// Blocklists the release, deletes the torrent and its files, then searches for the next best release
+async fn reject_download(
+    client: &Api,
+    db: &DBConnection,
+    hooks: &CompletionHooks,
+    torrent: &Torrent,
+    download: &FinishedDownload,
+    reason: &str,
+) {
+    warn!("Rejected download {}: {}", torrent.hash(), reason);
+    let hash = torrent.hash().as_str().to_lowercase();
+
+    if let Err(e) = BlocklistDatabase::new(db).insert(&hash, &download.imdb_id, reason).await {
+        error!("Failed to blocklist release: {}", e);
+    }
+    if let Err(e) = HistoryDatabase::new(db).insert_event(&hash, HistoryEvent::Failed, Some(reason)).await {
+        error!("Failed to record download history: {}", e);
+    }
+    if let Err(e) = AttemptDatabase::new(db).set_outcome(&hash, AttemptOutcome::Failed, Some(reason)).await {
+        error!("Failed to record download attempt: {}", e);
+    }
+    if let Err(e) = WatchlistStateDatabase::new(db).set_for_download(&hash, WatchlistState::Wanted, Some(reason)).await {
+        error!("Failed to update watchlist state: {}", e);
+    }
+    hooks.notifier.notify_download(db, &hash, DownloadEvent::Failed, Some(reason)).await;
+    hooks.notifier.forget(&hash);
+    hooks.progress.forget(&hash);
+    if let Err(e) = client.delete_torrents(vec![torrent.hash()], true).await {
+        error!("Error Deleting torrents: {}", e);
+    }
+    if let Err(e) = DownloadDatabase::new(db).remove(download.id).await {
+        error!("DB Error removing download: {}", e);
+    }
+    if let Some(importer) = &hooks.importer {
+        importer.forget(torrent.hash().as_str());
+    }
+
+    if hooks.disk.is_low() {
+        warn!("Low disk space, not looking for a replacement for {}", download.imdb_id);
+        return;
+    }
+    // Queued so a search that fails, eg the indexers being down, is tried again later
+    if let Err(e) = hooks.jobs.enqueue(db, &Job::Requeue(download.clone())).await {
+        error!("Failed to queue a search for a replacement for {}: {}", download.imdb_id, e);
+    }
+}
+
+// Removes the lower quality file an upgrade was downloaded to replace
+async fn replace_upgraded_file(
+    client: &Api,
+    db: &DBConnection,
+    media_server: &Arc<dyn MediaServer>,
+    recycle_bin: &RecycleBin,
+    torrent: &Torrent,
+) {
+    let upgrade_db = UpgradeDatabase::new(db);
+    let hash = torrent.hash().as_str().to_lowercase();
+    let replaces = match upgrade_db.fetch(&hash).await {
+        Ok(Some(t)) => t,
+        Ok(None) => return,
+        Err(e) => {
+            error!("Failed to fetch upgrade: {}", e);
+            return;
+        }
+    };
+
+    // Don't delete the new download if it was saved over the old file's name
+    let old_file_name = Path::new(&replaces).file_name();
+    let contents = client.contents(torrent).await.unwrap_or_default();
+    let overwritten = contents
+        .iter()
+        .any(|c| Path::new(c.name()).file_name() == old_file_name);
+
+    if overwritten.not() {
+        match recycle_bin.remove_file(Path::new(&replaces)).await {
+            Ok(_) => info!("Removed upgraded file: {}", replaces),
+            Err(e) => warn!("Failed to remove upgraded file {}: {}", replaces, e),
+        }
+        let parent = Path::new(&replaces).parent().and_then(|p| p.to_str());
+        if let Err(e) = media_server.refresh_library(parent).await {
+            warn!("Failed to refresh media library: {}", e);
+        }
+    }
+
+    if let Err(e) = upgrade_db.remove(&hash).await {
+        error!("Failed to remove upgrade: {}", e);
     }
 }
 
@@ -254,21 +1368,19 @@ async fn monitor_torrents(
     client: &Api,
     config: &Data<AppConfig>,
     db: &Data<DBConnection>,
+    media_server: &Arc<dyn MediaServer>,
+    hooks: &CompletionHooks,
     torrents_filtered: &mut HashSet<String>,
-    stalled_torrents: &mut HashMap<String, (State, DateTime<Local>)>,
+    stalled_torrents: &mut HashMap<String, (State, DateTime<Local>, DateTime<Local>)>,
     auto_torrents: &mut HashSet<String>,
-) {
-    let torrents = match client.get_torrent_list().await {
-        Ok(t) => t,
-        Err(_) => {
-            return;
-        }
-    };
+) -> anyhow::Result<()> {
+    let torrents = client.get_torrent_list().await?;
 
+    let db_conn = db;
     let db = DownloadDatabase::new(db);
     if torrents.is_empty() {
         let _ = db.remove_all().await;
-        return;
+        return Ok(());
     }
 
     let hashes = torrents
@@ -276,16 +1388,133 @@ async fn monitor_torrents(
         .map(|x| x.hash())
         .collect::<Vec<&Hash>>();
     let _ = db.remove_all_finished().await;
-    let _ = db.remove_manually_removed(&hashes).await;
+    let history_db = HistoryDatabase::new(db_conn);
+    let attempt_db = AttemptDatabase::new(db_conn);
+    if let Ok(removed) = db.remove_manually_removed(&hashes).await {
+        for hash in removed {
+            if let Err(e) = history_db.insert_event(&hash, HistoryEvent::Deleted, None).await {
+                error!("Failed to record download history: {}", e);
+            }
+            if let Err(e) = attempt_db.set_outcome(&hash, AttemptOutcome::Cancelled, Some("Removed from qBittorrent")).await {
+                error!("Failed to record download attempt: {}", e);
+            }
+            hooks.progress.forget(&hash);
+        }
+    }
 
-    let completed = torrents
+    let mut completed = torrents
         .iter()
         .filter(|t| matches!(t.state(), State::PausedUP))
+        .collect::<Vec<&Torrent>>();
+
+    // Broken or mislabelled releases are thrown away before anything else sees them
+    if hooks.validate {
+        let mut rejected = HashSet::new();
+        for torrent in completed.iter() {
+            if validate_download(client, config, db_conn, hooks, torrent).await.not() {
+                rejected.insert(torrent.hash().clone().inner());
+            }
+        }
+        completed.retain(|t| rejected.contains(t.hash().as_str()).not());
+        for hash in rejected {
+            torrents_filtered.remove(&hash);
+            stalled_torrents.remove(&hash);
+        }
+    }
+
+    for torrent in completed.iter() {
+        let hash = torrent.hash().as_str().to_lowercase();
+        if let Err(e) = history_db.insert_event(&hash, HistoryEvent::Completed, None).await {
+            error!("Failed to record download history: {}", e);
+        }
+        if let Err(e) = attempt_db.set_outcome(&hash, AttemptOutcome::Completed, None).await {
+            error!("Failed to record download attempt: {}", e);
+        }
+        // With a media server the import monitor marks it available once it shows up there
+        let (state, detail) = match config.has_media_server() {
+            true => (WatchlistState::Downloading, Some("Downloaded, waiting for the media server")),
+            false => (WatchlistState::Available, None),
+        };
+        let watched = match WatchlistStateDatabase::new(db_conn).set_for_download(&hash, state, detail).await {
+            Ok(t) => t,
+            Err(e) => {
+                error!("Failed to update watchlist state: {}", e);
+                false
+            }
+        };
+        hooks.notifier.notify_download(db_conn, &hash, DownloadEvent::Completed, None).await;
+        if watched && state == WatchlistState::Available {
+            announce_available(&hash, db_conn, &hooks.notifier).await;
+        }
+        hooks.notifier.forget(&hash);
+        hooks.progress.forget(&hash);
+    }
+
+    // Kept in the downloads list until the import has been verified
+    if config.has_media_server() {
+        for torrent in completed.iter() {
+            if let Err(e) = db.mark_completed(&torrent.hash().as_str().to_lowercase()).await {
+                error!("DB Error marking download completed: {}", e);
+            }
+        }
+    }
+
+    let mut imported = false;
+    if let Some(importer) = &hooks.importer {
+        for torrent in torrents.iter().filter(|t| importer.ready(t)) {
+            let contents = match client.contents(torrent).await {
+                Ok(c) => c,
+                Err(e) => {
+                    warn!("Failed to fetch torrent contents for import: {}", e);
+                    continue;
+                }
+            };
+            let files = contents
+                .iter()
+                .filter(|c| config.file_filter.wanted(c.name(), *c.size()))
+                .map(|c| c.name().to_owned())
+                .collect::<Vec<String>>();
+
+            match importer.import(torrent, &files).await {
+                Ok(_) => {
+                    info!("Imported {} into {}", torrent.hash(), importer.destination());
+                    imported = true;
+                }
+                Err(e) => error!("Failed to import {}: {}", torrent.hash(), e),
+            }
+        }
+    }
+
+    // Let the media server pick up the files before the torrent is removed
+    for torrent in completed.iter() {
+        let path = match &hooks.importer {
+            Some(importer) => importer.destination(),
+            None => torrent.save_path(),
+        };
+        if let Err(e) = media_server.refresh_library(Some(path)).await {
+            warn!("Failed to refresh media library: {}", e);
+        }
+        replace_upgraded_file(client, db_conn, media_server, &hooks.recycle_bin, torrent).await;
+    }
+
+    if let Some(kodi) = &hooks.kodi {
+        if !completed.is_empty() || imported {
+            if let Err(e) = kodi.scan_library().await {
+                warn!("Failed to start Kodi library scan: {}", e);
+            }
+        }
+    }
+
+    let completed = completed
+        .into_iter()
         .map(|t| {
             let hash = t.hash().clone().inner();
             torrents_filtered.remove(&hash);
             stalled_torrents.remove(&hash);
             auto_torrents.remove(t.magnet_uri());
+            if let Some(importer) = &hooks.importer {
+                importer.forget(t.hash().as_str());
+            }
             t.hash()
         })
         .collect::<Vec<&Hash>>();
@@ -299,48 +1528,111 @@ async fn monitor_torrents(
 
     // Updating Database items
     for torrent in torrents.iter() {
+        let hash = torrent.hash().as_str().to_lowercase();
+        let progress = *torrent.progress();
+        if progress < 1.0 && hooks.notifier.is_enabled() && hooks.notifier.passed_milestone(&hash, progress) {
+            hooks.notifier.notify_download(db_conn, &hash, DownloadEvent::Progress, None).await;
+        }
+        // qBittorrent reports 8640000 (100 days) when it can't estimate
+        let eta = match *torrent.eta() {
+            t if (0..8_640_000).contains(&t) => Some(t),
+            _ => None,
+        };
         match db
             .update(
                 torrent.hash().as_str(),
                 torrent.state().as_ref(),
                 *torrent.progress(),
+                *torrent.dlspeed(),
+                eta,
             )
             .await
         {
             Ok(_) => (),
             Err(e) => error!("DB Error updating download: {}", e),
         }
+        hooks.progress.publish(ProgressUpdate {
+            magnet_hash: hash,
+            state: torrent.state().as_ref().to_string(),
+            progress,
+            download_speed: *torrent.dlspeed(),
+            eta,
+        });
+    }
+    if let Err(e) = WatchlistStateDatabase::new(db_conn).mark_downloading().await {
+        error!("Failed to update watchlist states: {}", e);
     }
-
-    // TODO: Find better way of doing this
-    let filtered_clone = torrents_filtered.clone();
-    let torrents = torrents.par_iter().filter(|t| {
-        let hash = t.hash().as_str();
-        let contains = auto_torrents.contains(hash);
-        contains && filtered_clone.contains(hash).not()
-    } && matches!(t.state(), State::Downloading | State::StalledDL | State::ForceDL)).collect::<Vec<&Torrent>>();
 
     let mut thirty_minutes_ago: DateTime<Local> = Local::now();
     thirty_minutes_ago = thirty_minutes_ago
         .checked_sub_signed(chrono::Duration::minutes(30))
         .unwrap();
+    let stalled_cutoff = Local::now() - chrono::Duration::hours(config.stalled_timeout_hours);
 
     let mut torrents_to_reannounce = vec![];
+    let mut torrents_to_retry = vec![];
 
-    for torrent in torrents {
-        stalled_torrents
+    // Downloads started before a restart aren't in auto_torrents anymore
+    let tracked = db.fetch_active_hashes().await.unwrap_or_default();
+
+    // Stalled torrents are reannounced every 30 minutes, until they've been stuck without seeds too long and are replaced
+    for torrent in torrents.iter().filter(|t| {
+        auto_torrents.contains(t.hash().as_str()) || tracked.contains(&t.hash().as_str().to_lowercase())
+    }) {
+        if matches!(torrent.state(), State::Error | State::MissingFiles) {
+            torrents_to_retry.push((torrent, "Torrent errored".to_string()));
+            continue;
+        }
+        if !matches!(torrent.state(), State::Downloading | State::StalledDL | State::ForceDL) {
+            continue;
+        }
+
+        let now = Local::now();
+        let (state, since, announced) = stalled_torrents
             .entry(torrent.hash().clone().inner())
-            .and_modify(|(state, time)| {
-                if *state != *torrent.state() {
-                    *state = torrent.state().clone();
-                    *time = Local::now();
-                } else if *state == State::StalledDL && *time <= thirty_minutes_ago {
-                    *time = Local::now();
-                    torrents_to_reannounce.push(torrent.hash());
-                }
-            })
-            .or_insert((torrent.state().clone(), Local::now()));
+            .or_insert((torrent.state().clone(), now, now));
+        if *state != *torrent.state() {
+            *state = torrent.state().clone();
+            *since = now;
+            *announced = now;
+        } else if config.replace_stalled
+            && *state == State::StalledDL
+            && *torrent.num_seeds() == 0
+            && *since <= stalled_cutoff
+        {
+            let reason = format!("Stalled with no seeds for over {} hours", config.stalled_timeout_hours);
+            torrents_to_retry.push((torrent, reason));
+        } else if *state == State::StalledDL && *announced <= thirty_minutes_ago {
+            *announced = now;
+            torrents_to_reannounce.push(torrent.hash());
+        }
+    }
 
+    for (torrent, reason) in torrents_to_retry {
+        let hash = torrent.hash().as_str().to_lowercase();
+        match db.fetch_by_hash(&hash).await {
+            Ok(Some(download)) => reject_download(client, db_conn, hooks, torrent, &download, &reason).await,
+            Ok(None) => continue, // Only downloads roundup knows how to search for again
+            Err(e) => {
+                error!("Failed to fetch download: {}", e);
+                continue;
+            }
+        }
+        let hash = torrent.hash().clone().inner();
+        torrents_filtered.remove(&hash);
+        stalled_torrents.remove(&hash);
+        auto_torrents.remove(&hash);
+    }
+
+    // TODO: Find better way of doing this
+    let filtered_clone = torrents_filtered.clone();
+    let torrents = torrents.par_iter().filter(|t| {
+        let hash = t.hash().as_str();
+        let contains = auto_torrents.contains(hash);
+        contains && filtered_clone.contains(hash).not()
+    } && matches!(t.state(), State::Downloading | State::StalledDL | State::ForceDL)).collect::<Vec<&Torrent>>();
+
+    for torrent in torrents {
         let contents = match client.contents(torrent).await {
             Ok(c) => c,
             Err(_) => {
@@ -348,13 +1640,11 @@ async fn monitor_torrents(
             }
         };
 
-        let mut files_to_remove: Vec<i64> = Vec::new();
-        let valid_file_types = &config.valid_file_types;
-        for content in contents {
-            if !valid_file_types.iter().any(|t| content.name().ends_with(t)) {
-                files_to_remove.push(*content.index());
-            }
-        }
+        let files = contents
+            .iter()
+            .map(|c| (*c.index(), c.name().to_owned(), *c.size()))
+            .collect::<Vec<(i64, String, i64)>>();
+        let files_to_remove = config.file_filter.files_to_skip(&files);
 
         if files_to_remove.is_empty() {
             continue;
@@ -377,4 +1667,6 @@ async fn monitor_torrents(
             Err(e) => error!("Failed to reannounce torrents: {}", e),
         }
     }
+
+    Ok(())
 }
@@ -0,0 +1,144 @@
+use std::ops::Deref;
+
+use actix_web::{delete, Error, get, HttpResponse, post};
+use actix_web::error::{ErrorBadRequest, ErrorForbidden, ErrorInternalServerError};
+use actix_web::web::{Data, Form, Query};
+use serde::Deserialize;
+
+use crate::AppConfig;
+use crate::db::aliases::AliasDatabase;
+use crate::db::DBConnection;
+use crate::server::escape_html;
+
+#[derive(Deserialize)]
+pub struct AliasesQuery {
+    id: String,
+}
+
+#[derive(Deserialize)]
+pub struct UpdateAliasQuery {
+    id: String,
+    title: String,
+}
+
+#[get("/aliases")]
+pub async fn list_aliases(
+    params: Query<AliasesQuery>,
+    db: Data<DBConnection>,
+    app_config: Data<AppConfig>,
+) -> Result<HttpResponse<String>, Error> {
+    let id = alias_id(&app_config, &params.id);
+    let output = match create_aliases_list(db.deref(), &id, app_config.read_only).await {
+        Ok(t) => t,
+        Err(e) => return Err(ErrorInternalServerError(e)),
+    };
+
+    Ok(HttpResponse::Ok().message_body(output).unwrap())
+}
+
+#[post("/aliases")]
+pub async fn add_alias(
+    params: Form<UpdateAliasQuery>,
+    db: Data<DBConnection>,
+    app_config: Data<AppConfig>,
+) -> Result<HttpResponse<String>, Error> {
+    if app_config.read_only {
+        return Err(ErrorForbidden("Read-only mode"));
+    }
+    if params.title.trim().is_empty() {
+        return Err(ErrorBadRequest("Missing title"));
+    }
+
+    let id = alias_id(&app_config, &params.id);
+    match AliasDatabase::new(db.deref())
+        .insert_many(&id, &[params.title.to_owned()], "user")
+        .await
+    {
+        Ok(_) => (),
+        Err(e) => return Err(ErrorInternalServerError(e)),
+    };
+
+    let output = match create_aliases_list(db.deref(), &id, app_config.read_only).await {
+        Ok(t) => t,
+        Err(e) => return Err(ErrorInternalServerError(e)),
+    };
+
+    Ok(HttpResponse::Ok().message_body(output).unwrap())
+}
+
+#[delete("/aliases")]
+pub async fn remove_alias(
+    params: Query<UpdateAliasQuery>,
+    db: Data<DBConnection>,
+    app_config: Data<AppConfig>,
+) -> Result<HttpResponse<String>, Error> {
+    if app_config.read_only {
+        return Err(ErrorForbidden("Read-only mode"));
+    }
+
+    let id = alias_id(&app_config, &params.id);
+    match AliasDatabase::new(db.deref())
+        .remove(&id, &params.title)
+        .await
+    {
+        Ok(_) => (),
+        Err(e) => return Err(ErrorInternalServerError(e)),
+    };
+
+    let output = match create_aliases_list(db.deref(), &id, app_config.read_only).await {
+        Ok(t) => t,
+        Err(e) => return Err(ErrorInternalServerError(e)),
+    };
+
+    Ok(HttpResponse::Ok().message_body(output).unwrap())
+}
+
+// Aliases are stored against the same ID the downloads use, IMDb IDs always have the tt prefix
+fn alias_id(app_config: &AppConfig, id: &str) -> String {
+    match app_config.tmdb_api_key.is_empty() && !id.starts_with("tt") {
+        true => format!("tt{}", id),
+        false => id.to_owned(),
+    }
+}
+
+async fn create_aliases_list(db: &DBConnection, id: &str, read_only: bool) -> anyhow::Result<String> {
+    let aliases = AliasDatabase::new(db).fetch(id).await?;
+
+    let items = aliases
+        .iter()
+        .map(|title| {
+            let remove_button = match read_only {
+                true => String::new(),
+                false => format!(
+                    "<button type=\"button\" class=\"btn btn-sm btn-outline-danger\" hx-delete=\"/aliases?id={}&title={}\" hx-target=\"#aliases-list\" hx-swap=\"outerHTML\">Remove</button>",
+                    escape_html(&urlencoding::encode(id)),
+                    escape_html(&urlencoding::encode(title))
+                ),
+            };
+            format!(
+                "<li class=\"list-group-item d-flex justify-content-between align-items-center\">{}{}</li>",
+                escape_html(title),
+                remove_button
+            )
+        })
+        .collect::<Vec<String>>();
+
+    let list = match items.is_empty() {
+        true => "<p>No alternate titles yet, they are fetched when searching for downloads.</p>".to_string(),
+        false => format!("<ul class=\"list-group mb-2\">{}</ul>", items.join("")),
+    };
+
+    let form = match read_only {
+        true => String::new(),
+        false => format!(
+            "<form class=\"input-group\" hx-post=\"/aliases\" hx-target=\"#aliases-list\" hx-swap=\"outerHTML\">\
+                <input type=\"hidden\" name=\"id\" value=\"{}\">\
+                <input type=\"text\" class=\"form-control\" name=\"title\" placeholder=\"Alternate title\" required>\
+                <button type=\"submit\" class=\"btn btn-outline-secondary\">Add</button>\
+            </form>",
+            escape_html(id)
+        ),
+    };
+
+    Ok(format!("<div id=\"aliases-list\">{}{}</div>", list, form))
+}
\ No newline at end of file
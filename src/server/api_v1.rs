@@ -0,0 +1,930 @@
+use std::ops::{Deref, Not};
+
+use actix_web::{delete, Error, get, HttpResponse, post, put};
+use actix_web::error::{ErrorBadRequest, ErrorForbidden, ErrorInternalServerError, ErrorNotFound};
+use actix_web::web::{Data, Json, Path, Query};
+use anyhow::format_err;
+use chrono::Local;
+use serde::{Deserialize, Serialize};
+use tokio::sync::Mutex;
+use utoipa::{IntoParams, OpenApi, ToSchema};
+
+use crate::{AppConfig, QueryCache};
+use crate::api::imdb::{IMDB, IMDBItem, ItemType, SearchType};
+use crate::api::list_imports::{ListProvider, WatchlistImportJob, WatchlistImportRequest};
+use crate::api::jobs::{Job, JobQueue};
+use crate::api::moviedb::{MovieDB, MovieDBItem};
+use crate::api::notify::Notifier;
+use crate::api::profiles::QualityProfile;
+use crate::api::progress::ProgressFeed;
+use crate::api::settings::{RuntimeSettings, SharedSettings};
+use crate::api::torrent::{MediaQuality, normalise_magnet, Torrenter, TorrentItem};
+use crate::api::watchlist::WatchlistRecheck;
+use crate::db::{DBConnection, Pagination};
+use crate::db::attempts::{AttemptDatabase, DownloadAttempt};
+use crate::db::blocklist::BlocklistDatabase;
+use crate::db::downloads::{ActiveDownloadIMDBItem, DownloadDatabase, DownloadFilter, DownloadSort};
+use crate::db::imdb::IMDBDatabase;
+use crate::db::jobs::{JobDatabase, JobRecord};
+use crate::db::moviedb::MovieDBDatabase;
+use crate::db::profiles::QualityProfileDatabase;
+use crate::db::watchlist_imports::{WatchlistImport, WatchlistImportDatabase};
+use crate::db::watchlist_options::{WatchlistOptions, WatchlistOptionsDatabase};
+use crate::db::watchlist_states::watchlist_key;
+use crate::server::assets::read_asset_text;
+use crate::server::download::{cancel_download, RecheckWatchlistQuery, set_watchlist_state, start_queries, TorrentQuery};
+use crate::server::query::{
+    check_cache_then_search_imdb, check_cache_then_search_moviedb, get_cached_item_imdb, get_cached_item_moviedb,
+    parse_search_type, SearchRequests,
+};
+use crate::server::settings::apply_settings;
+
+// JSON versions of the HTMX endpoints, for scripts and other frontends.
+// Ids are IMDb IDs or TheMovieDB IDs depending on which metadata source is in use.
+
+#[derive(OpenApi)]
+#[openapi(
+    info(title = "Roundup", version = "1"),
+    paths(
+        api_search,
+        api_item,
+        api_item_torrents,
+        api_item_attempts,
+        api_watchlist,
+        api_add_to_watchlist,
+        api_remove_from_watchlist,
+        api_recheck_watchlist,
+        api_bulk_add_to_watchlist,
+        api_bulk_remove_from_watchlist,
+        api_import_watchlist,
+        api_watchlist_import,
+        api_downloads,
+        api_start_downloads,
+        api_cancel_download,
+        api_settings,
+        api_update_settings,
+        api_profiles,
+        api_create_profile,
+        api_update_profile,
+        api_remove_profile,
+        api_set_watchlist_profile,
+        api_watchlist_options,
+        api_set_watchlist_options,
+        api_jobs,
+    ),
+    components(schemas(
+        IMDBItem,
+        MovieDBItem,
+        ItemType,
+        TorrentItem,
+        TorrentQuery,
+        MediaQuality,
+        ActiveDownloadIMDBItem,
+        StartedDownloads,
+        BulkWatchlistResult,
+        BulkWatchlistFailure,
+        WatchlistImportRequest,
+        ListProvider,
+        WatchlistImport,
+        RuntimeSettings,
+        QualityProfile,
+        WatchlistProfile,
+        WatchlistOptions,
+        JobRecord,
+        DownloadAttempt,
+    ))
+)]
+pub struct ApiDoc;
+
+#[get("/api/openapi.json")]
+pub async fn openapi_spec() -> Result<HttpResponse<String>, Error> {
+    let json = match ApiDoc::openapi().to_json() {
+        Ok(t) => t,
+        Err(e) => return Err(ErrorInternalServerError(e)),
+    };
+
+    Ok(HttpResponse::Ok()
+        .content_type("application/json")
+        .message_body(json)
+        .unwrap())
+}
+
+// Swagger UI for the spec above
+#[get("/api/docs")]
+pub async fn api_docs(app_config: Data<AppConfig>) -> Result<HttpResponse<String>, Error> {
+    let html = read_asset_text(&app_config, "api_docs.html")?;
+
+    Ok(HttpResponse::Ok()
+        .content_type("text/html; charset=utf-8")
+        .message_body(html)
+        .unwrap())
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ApiSearchQuery {
+    // movie or tv
+    #[serde(rename = "type", default)]
+    #[param(rename = "type")]
+    _type: String,
+    // popular, latest, watchlist or query
+    #[serde(default)]
+    mode: String,
+    // Search text, only used with mode=query
+    query: Option<String>,
+    // Starts at 0
+    page: Option<i64>,
+    // Results per page, 48 by default and at most 200
+    limit: Option<i64>,
+}
+
+// Items are IMDBItem when using IMDb and MovieDBItem when using TheMovieDB
+#[utoipa::path(
+    params(ApiSearchQuery),
+    responses(
+        (status = 200, description = "Search results", body = [IMDBItem]),
+        (status = 400, description = "Missing query or downloads mode"),
+    )
+)]
+#[get("/api/v1/search")]
+pub async fn api_search(
+    params: Query<ApiSearchQuery>,
+    cache_update: Data<Mutex<QueryCache>>,
+    searches: Data<SearchRequests>,
+    db: Data<DBConnection>,
+    app_config: Data<AppConfig>,
+) -> Result<HttpResponse<String>, Error> {
+    let mode = parse_search_type(&params._type, &params.mode, params.query.as_ref())?;
+    if mode == SearchType::Downloads {
+        return Err(ErrorBadRequest("Use /api/v1/downloads"));
+    }
+    let pagination = Pagination::new(params.page, params.limit);
+
+    match app_config.tmdb_api_key.is_empty() {
+        true => json_response(&check_cache_then_search_imdb(mode, pagination, db, cache_update, searches).await?),
+        false => json_response(&check_cache_then_search_moviedb(mode, pagination, db, cache_update, searches, app_config).await?),
+    }
+}
+
+#[utoipa::path(
+    params(("id" = String, Path, description = "IMDb ID or TheMovieDB ID")),
+    responses(
+        (status = 200, description = "Cached item", body = IMDBItem),
+        (status = 404, description = "Item hasn't been searched for"),
+    )
+)]
+#[get("/api/v1/items/{id}")]
+pub async fn api_item(
+    id: Path<String>,
+    db: Data<DBConnection>,
+    app_config: Data<AppConfig>,
+) -> Result<HttpResponse<String>, Error> {
+    match app_config.tmdb_api_key.is_empty() {
+        true => match get_cached_item_imdb(&id, db).await {
+            Ok(t) => json_response(&t),
+            Err(_) => Err(ErrorNotFound("Item not found, search for it first")),
+        },
+        false => {
+            if id.parse::<i32>().is_err() {
+                return Err(ErrorBadRequest("Invalid TheMovieDB id"));
+            }
+            match get_cached_item_moviedb(&id, db).await {
+                Ok(t) => json_response(&t),
+                Err(_) => Err(ErrorNotFound("Item not found, search for it first")),
+            }
+        }
+    }
+}
+
+// Releases that can be passed to POST /api/v1/downloads, blocklisted releases are left out
+#[utoipa::path(
+    params(("id" = String, Path, description = "IMDb ID or TheMovieDB ID")),
+    responses(
+        (status = 200, description = "Available releases", body = [TorrentItem]),
+        (status = 404, description = "Item hasn't been searched for"),
+    )
+)]
+#[get("/api/v1/items/{id}/torrents")]
+pub async fn api_item_torrents(
+    id: Path<String>,
+    torrenter: Data<Torrenter>,
+    db: Data<DBConnection>,
+    app_config: Data<AppConfig>,
+) -> Result<HttpResponse<String>, Error> {
+    let (title, search_id) = match app_config.tmdb_api_key.is_empty() {
+        true => match get_cached_item_imdb(&id, Data::clone(&db)).await {
+            Ok(t) => (t.title, t.id),
+            Err(_) => return Err(ErrorNotFound("Item not found, search for it first")),
+        },
+        false => {
+            if id.parse::<i32>().is_err() {
+                return Err(ErrorBadRequest("Invalid TheMovieDB id"));
+            }
+            match get_cached_item_moviedb(&id, Data::clone(&db)).await {
+                Ok(t) => (t.title, t.imdb_id),
+                Err(_) => return Err(ErrorNotFound("Item not found, search for it first")),
+            }
+        }
+    };
+
+    let mut torrents = match torrenter.find_torrent(title, Some(search_id), None).await {
+        Ok(t) => t,
+        Err(e) => return Err(ErrorInternalServerError(e)),
+    };
+    let blocked = match BlocklistDatabase::new(&db).fetch_hashes().await {
+        Ok(t) => t,
+        Err(e) => return Err(ErrorInternalServerError(e)),
+    };
+    torrents.retain(|t| blocked.contains(&t.magnet_hash()).not());
+    // Downloads are recorded against the id used by roundup rather than the indexer's
+    for torrent in torrents.iter_mut() {
+        torrent.imdb_id = id.to_string();
+    }
+
+    json_response(&torrents)
+}
+
+// Every release started for the item and how it went, newest first
+#[utoipa::path(
+    params(("id" = String, Path, description = "IMDb ID or TheMovieDB ID")),
+    responses((status = 200, description = "Download attempts", body = [DownloadAttempt]))
+)]
+#[get("/api/v1/items/{id}/attempts")]
+pub async fn api_item_attempts(id: Path<String>, db: Data<DBConnection>) -> Result<HttpResponse<String>, Error> {
+    let imdb_id = match id.starts_with("tt") {
+        true => id.to_string(),
+        false => format!("tt{}", id),
+    };
+
+    match AttemptDatabase::new(&db).fetch_for_item(&imdb_id).await {
+        Ok(t) => json_response(&t),
+        Err(e) => Err(ErrorInternalServerError(e)),
+    }
+}
+
+#[utoipa::path(responses((status = 200, description = "Watchlist items", body = [IMDBItem])))]
+#[get("/api/v1/watchlist")]
+pub async fn api_watchlist(
+    db: Data<DBConnection>,
+    app_config: Data<AppConfig>,
+) -> Result<HttpResponse<String>, Error> {
+    match app_config.tmdb_api_key.is_empty() {
+        true => match IMDBDatabase::new(db.deref()).fetch_watchlist().await {
+            Ok(t) => json_response(&t),
+            Err(e) => Err(ErrorInternalServerError(e)),
+        },
+        false => match MovieDBDatabase::new(db.deref()).fetch_watchlist().await {
+            Ok(t) => json_response(&t),
+            Err(e) => Err(ErrorInternalServerError(e)),
+        },
+    }
+}
+
+#[utoipa::path(
+    params(("id" = String, Path, description = "IMDb ID or TheMovieDB ID")),
+    responses((status = 204, description = "Added"), (status = 403, description = "Read-only mode"))
+)]
+#[put("/api/v1/watchlist/{id}")]
+pub async fn api_add_to_watchlist(
+    id: Path<String>,
+    db: Data<DBConnection>,
+    notifier: Data<Notifier>,
+    app_config: Data<AppConfig>,
+) -> Result<HttpResponse, Error> {
+    update_watchlist(&id, true, &db, &notifier, &app_config).await
+}
+
+#[utoipa::path(
+    params(("id" = String, Path, description = "IMDb ID or TheMovieDB ID")),
+    responses((status = 204, description = "Removed"), (status = 403, description = "Read-only mode"))
+)]
+#[delete("/api/v1/watchlist/{id}")]
+pub async fn api_remove_from_watchlist(
+    id: Path<String>,
+    db: Data<DBConnection>,
+    notifier: Data<Notifier>,
+    app_config: Data<AppConfig>,
+) -> Result<HttpResponse, Error> {
+    update_watchlist(&id, false, &db, &notifier, &app_config).await
+}
+
+const BULK_WATCHLIST_LIMIT: usize = 200;
+
+#[derive(Serialize, ToSchema)]
+struct BulkWatchlistResult {
+    updated: Vec<String>,
+    failed: Vec<BulkWatchlistFailure>,
+}
+
+#[derive(Serialize, ToSchema)]
+struct BulkWatchlistFailure {
+    id: String,
+    error: String,
+}
+
+// Takes IMDb IDs, or TheMovieDB IDs when using TheMovieDB. Items that haven't been searched for are
+// looked up first, with TheMovieDB that only works for IMDb IDs.
+#[utoipa::path(
+    request_body = [String],
+    responses(
+        (status = 200, description = "Ids that were added and ones that failed", body = BulkWatchlistResult),
+        (status = 400, description = "Too many ids"),
+        (status = 403, description = "Read-only mode"),
+    )
+)]
+#[post("/api/v1/watchlist")]
+pub async fn api_bulk_add_to_watchlist(
+    ids: Json<Vec<String>>,
+    db: Data<DBConnection>,
+    notifier: Data<Notifier>,
+    app_config: Data<AppConfig>,
+) -> Result<HttpResponse<String>, Error> {
+    bulk_update_watchlist(ids.into_inner(), true, &db, &notifier, &app_config).await
+}
+
+#[utoipa::path(
+    request_body = [String],
+    responses(
+        (status = 200, description = "Ids that were removed and ones that failed", body = BulkWatchlistResult),
+        (status = 400, description = "Too many ids"),
+        (status = 403, description = "Read-only mode"),
+    )
+)]
+#[delete("/api/v1/watchlist")]
+pub async fn api_bulk_remove_from_watchlist(
+    ids: Json<Vec<String>>,
+    db: Data<DBConnection>,
+    notifier: Data<Notifier>,
+    app_config: Data<AppConfig>,
+) -> Result<HttpResponse<String>, Error> {
+    bulk_update_watchlist(ids.into_inner(), false, &db, &notifier, &app_config).await
+}
+
+async fn bulk_update_watchlist(
+    ids: Vec<String>,
+    state: bool,
+    db: &DBConnection,
+    notifier: &Notifier,
+    app_config: &AppConfig,
+) -> Result<HttpResponse<String>, Error> {
+    if app_config.read_only {
+        return Err(ErrorForbidden("Read-only mode"));
+    }
+    if ids.len() > BULK_WATCHLIST_LIMIT {
+        return Err(ErrorBadRequest(format!("At most {} ids at a time", BULK_WATCHLIST_LIMIT)));
+    }
+
+    let mut result = BulkWatchlistResult {
+        updated: Vec::new(),
+        failed: Vec::new(),
+    };
+    // Shared between IMDb lookups so it's only fetched once
+    let mut query_key = None;
+
+    for id in ids {
+        let id = id.trim().to_string();
+        let outcome = match resolve_watchlist_id(&id, state, db, app_config, &mut query_key).await {
+            Ok(t) => set_watchlist_state(app_config, db, notifier, &t, state).await,
+            Err(e) => Err(e),
+        };
+
+        match outcome {
+            Ok(_) => result.updated.push(id),
+            Err(e) => result.failed.push(BulkWatchlistFailure {
+                id,
+                error: e.to_string(),
+            }),
+        }
+    }
+
+    json_response(&result)
+}
+
+// Returns the id the watchlist is stored under, fetching the item's metadata if it's being added
+// and isn't cached yet
+pub async fn resolve_watchlist_id(
+    id: &str,
+    fetch_missing: bool,
+    db: &DBConnection,
+    app_config: &AppConfig,
+    query_key: &mut Option<String>,
+) -> anyhow::Result<String> {
+    let is_imdb_id = id.strip_prefix("tt").is_some_and(|t| t.len() >= 7 && t.chars().all(|c| c.is_ascii_digit()));
+
+    match app_config.tmdb_api_key.is_empty() {
+        true => {
+            if is_imdb_id.not() {
+                return Err(format_err!("Invalid IMDb id"));
+            }
+            let imdb_db = IMDBDatabase::new(db);
+            if imdb_db.fetch_item_by_id(id).await?.is_empty().not() || fetch_missing.not() {
+                return Ok(id.to_string());
+            }
+
+            if query_key.is_none() {
+                *query_key = Some(IMDB::update_query_key(None).await?);
+            }
+            let mut item = IMDB::update_media_data(id, query_key.clone(), None).await?;
+            item.created_at = Local::now();
+            item.updated_at = Local::now();
+            imdb_db.insert_or_update(&item).await?;
+            // Fails when there's no rating, runtime or plot, which is fine
+            let _ = imdb_db.update_metadata(&item).await;
+
+            Ok(item.id)
+        }
+        false => {
+            let movie_db = MovieDBDatabase::new(db);
+            if is_imdb_id.not() {
+                let tmdb_id = id.parse::<i32>().map_err(|_| format_err!("Invalid IMDb or TheMovieDB id"))?;
+                return match movie_db.fetch_item_by_id(tmdb_id).await?.is_empty() {
+                    false => Ok(id.to_string()),
+                    // Could be a movie or a show, so can't be looked up
+                    true => Err(format_err!("Unknown TheMovieDB id, search for it first or use its IMDb id")),
+                };
+            }
+
+            if let Some(item) = movie_db.fetch_item_by_imdb_id(id).await?.into_iter().next() {
+                return Ok(item.id.to_string());
+            }
+            if fetch_missing.not() {
+                return Err(format_err!("Not in the watchlist"));
+            }
+
+            let item = MovieDB::new(&app_config.tmdb_api_key).fetch_by_imdb_id(id).await?;
+            movie_db.insert_or_update(&item).await?;
+
+            Ok(item.id.to_string())
+        }
+    }
+}
+
+// Adds every title on a public IMDb or Letterboxd list or watchlist, or an exported CSV, in the background
+#[utoipa::path(
+    request_body = WatchlistImportRequest,
+    responses(
+        (status = 200, description = "Import queued, follow it with GET /api/v1/watchlist/import/{id}", body = WatchlistImport),
+        (status = 400, description = "Missing or invalid url, or a Letterboxd import without TheMovieDB"),
+        (status = 403, description = "Read-only mode"),
+    )
+)]
+#[post("/api/v1/watchlist/import")]
+pub async fn api_import_watchlist(
+    body: Json<WatchlistImportRequest>,
+    db: Data<DBConnection>,
+    jobs: Data<JobQueue>,
+    app_config: Data<AppConfig>,
+) -> Result<HttpResponse<String>, Error> {
+    if app_config.read_only {
+        return Err(ErrorForbidden("Read-only mode"));
+    }
+    if let Err(e) = body.validate(&app_config) {
+        return Err(ErrorBadRequest(e));
+    }
+
+    let import_db = WatchlistImportDatabase::new(&db);
+    let import_id = match import_db.insert(&body.source()).await {
+        Ok(t) => t,
+        Err(e) => return Err(ErrorInternalServerError(e)),
+    };
+    let job = Job::WatchlistImport(WatchlistImportJob {
+        import_id,
+        request: body.into_inner(),
+    });
+    if let Err(e) = jobs.enqueue(&db, &job).await {
+        return Err(ErrorInternalServerError(e));
+    }
+
+    let import = match import_db.fetch(import_id).await {
+        Ok(Some(t)) => t,
+        Ok(None) => return Err(ErrorNotFound("Import not found")),
+        Err(e) => return Err(ErrorInternalServerError(e)),
+    };
+    json_response(&import)
+}
+
+#[utoipa::path(
+    params(("id" = i64, Path, description = "Id returned when the import was started")),
+    responses(
+        (status = 200, description = "Progress of the import", body = WatchlistImport),
+        (status = 404, description = "Import not found"),
+    )
+)]
+#[get("/api/v1/watchlist/import/{id}")]
+pub async fn api_watchlist_import(id: Path<i64>, db: Data<DBConnection>) -> Result<HttpResponse<String>, Error> {
+    match WatchlistImportDatabase::new(&db).fetch(id.into_inner()).await {
+        Ok(Some(t)) => json_response(&t),
+        Ok(None) => Err(ErrorNotFound("Import not found")),
+        Err(e) => Err(ErrorInternalServerError(e)),
+    }
+}
+
+// Checks the watchlist for new releases now instead of waiting for the next interval
+#[utoipa::path(
+    params(("imdb_id" = Option<String>, Query, description = "Only check this item")),
+    responses((status = 202, description = "Check started"), (status = 403, description = "Read-only mode"))
+)]
+#[post("/api/v1/watchlist/recheck")]
+pub async fn api_recheck_watchlist(
+    query: Query<RecheckWatchlistQuery>,
+    recheck: Data<WatchlistRecheck>,
+    app_config: Data<AppConfig>,
+) -> Result<HttpResponse, Error> {
+    if app_config.read_only {
+        return Err(ErrorForbidden("Read-only mode"));
+    }
+
+    recheck.trigger(query.into_inner().imdb_id.filter(|t| t.is_empty().not()));
+    Ok(HttpResponse::Accepted().finish())
+}
+
+async fn update_watchlist(
+    id: &str,
+    state: bool,
+    db: &DBConnection,
+    notifier: &Notifier,
+    app_config: &AppConfig,
+) -> Result<HttpResponse, Error> {
+    if app_config.read_only {
+        return Err(ErrorForbidden("Read-only mode"));
+    }
+
+    match set_watchlist_state(app_config, db, notifier, id, state).await {
+        Ok(_) => Ok(HttpResponse::NoContent().finish()),
+        Err(e) => Err(ErrorInternalServerError(e)),
+    }
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ApiDownloadsQuery {
+    // all, downloading, stalled, completed or failed
+    state: Option<String>,
+    // movie or tv, anything else lists both
+    #[serde(rename = "type")]
+    #[param(rename = "type")]
+    _type: Option<String>,
+    // eg 720p, 1080p or 2160p
+    quality: Option<String>,
+    // added, age (oldest first), progress or title
+    sort: Option<String>,
+    page: Option<i64>,
+}
+
+#[utoipa::path(
+    params(ApiDownloadsQuery),
+    responses(
+        (status = 200, description = "Downloads", body = [ActiveDownloadIMDBItem]),
+        (status = 400, description = "Using TheMovieDB"),
+    )
+)]
+#[get("/api/v1/downloads")]
+pub async fn api_downloads(
+    params: Query<ApiDownloadsQuery>,
+    db: Data<DBConnection>,
+    app_config: Data<AppConfig>,
+) -> Result<HttpResponse<String>, Error> {
+    // Download details are joined from the IMDb cache, same as the downloads page
+    if app_config.tmdb_api_key.is_empty().not() {
+        return Err(ErrorBadRequest("Downloads are only listed when using IMDb"));
+    }
+    let filter = DownloadFilter::from_params(params.state.as_deref(), params._type.as_deref(), params.quality.as_deref());
+    let sort = DownloadSort::from_param(params.sort.as_deref().unwrap_or_default());
+    let page = params.page.unwrap_or(0).max(0);
+
+    match DownloadDatabase::new(&db).fetch_downloads_with_imdb_data(&filter, sort, page).await {
+        Ok(t) => json_response(&t),
+        Err(e) => Err(ErrorInternalServerError(e)),
+    }
+}
+
+#[derive(Serialize, ToSchema)]
+struct StartedDownloads {
+    magnet_hashes: Vec<String>,
+}
+
+// Takes the same fields as the HTMX form, with plain (not url encoded) magnet URIs
+#[utoipa::path(
+    request_body = [TorrentQuery],
+    responses(
+        (status = 200, description = "Downloads started", body = StartedDownloads),
+        (status = 400, description = "Invalid magnet URI, or replaces was set"),
+        (status = 403, description = "Read-only mode"),
+    )
+)]
+#[post("/api/v1/downloads")]
+pub async fn api_start_downloads(
+    queries: Json<Vec<TorrentQuery>>,
+    torrenter: Data<Torrenter>,
+    db: Data<DBConnection>,
+    app_config: Data<AppConfig>,
+) -> Result<HttpResponse<String>, Error> {
+    if app_config.read_only {
+        return Err(ErrorForbidden("Read-only mode"));
+    }
+    let mut queries = queries.into_inner();
+    for query in queries.iter_mut() {
+        // Upgrades delete a library file once imported, so they can only be started from the item page
+        if query.replaces.is_some() {
+            return Err(ErrorBadRequest("replaces can't be set through the API"));
+        }
+        let (magnet_uri, name) = match normalise_magnet(&query.magnet_uri) {
+            Ok(t) => t,
+            Err(e) => return Err(ErrorBadRequest(e)),
+        };
+        query.magnet_uri = magnet_uri;
+        if query.name.is_empty() {
+            query.name = name;
+        }
+        // Downloads are stored with a tt prefix for TheMovieDB ids too
+        if query.imdb_id.starts_with("tt").not() {
+            query.imdb_id = format!("tt{}", query.imdb_id);
+        }
+    }
+
+    match start_queries(&queries, &torrenter, &db).await {
+        Ok(_) => (),
+        Err(e) => return Err(ErrorInternalServerError(e)),
+    };
+
+    json_response(&StartedDownloads {
+        magnet_hashes: queries.iter().map(|q| q.magnet_hash()).collect(),
+    })
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ApiCancelDownloadQuery {
+    // Also delete what has been downloaded so far
+    delete_files: Option<bool>,
+}
+
+#[utoipa::path(
+    params(("hash" = String, Path, description = "Magnet hash"), ApiCancelDownloadQuery),
+    responses((status = 204, description = "Removed"), (status = 403, description = "Read-only mode"))
+)]
+#[delete("/api/v1/downloads/{hash}")]
+pub async fn api_cancel_download(
+    hash: Path<String>,
+    query: Query<ApiCancelDownloadQuery>,
+    torrenter: Data<Torrenter>,
+    progress: Data<ProgressFeed>,
+    db: Data<DBConnection>,
+    app_config: Data<AppConfig>,
+) -> Result<HttpResponse, Error> {
+    if app_config.read_only {
+        return Err(ErrorForbidden("Read-only mode"));
+    }
+
+    match cancel_download(&hash, query.delete_files.unwrap_or(false), &torrenter, &progress, &db).await {
+        Ok(_) => Ok(HttpResponse::NoContent().finish()),
+        Err(e) => Err(ErrorInternalServerError(e)),
+    }
+}
+
+pub fn json_response<T: Serialize>(value: &T) -> Result<HttpResponse<String>, Error> {
+    let json = match serde_json::to_string(value) {
+        Ok(t) => t,
+        Err(e) => return Err(ErrorInternalServerError(e)),
+    };
+
+    Ok(HttpResponse::Ok()
+        .content_type("application/json")
+        .message_body(json)
+        .unwrap())
+}
+
+#[utoipa::path(
+    responses(
+        (status = 200, description = "Current settings", body = RuntimeSettings),
+    )
+)]
+#[get("/api/v1/settings")]
+pub async fn api_settings(settings: Data<SharedSettings>) -> Result<HttpResponse<String>, Error> {
+    json_response(settings.load().as_ref())
+}
+
+// Replaces every runtime setting, they're saved to the database and apply without a restart
+#[utoipa::path(
+    request_body = RuntimeSettings,
+    responses(
+        (status = 200, description = "Settings saved", body = RuntimeSettings),
+        (status = 400, description = "Invalid setting"),
+        (status = 403, description = "Read-only mode"),
+    )
+)]
+#[put("/api/v1/settings")]
+pub async fn api_update_settings(
+    updated: Json<RuntimeSettings>,
+    settings: Data<SharedSettings>,
+    db: Data<DBConnection>,
+    app_config: Data<AppConfig>,
+) -> Result<HttpResponse<String>, Error> {
+    apply_settings(updated.into_inner(), &settings, &db, &app_config).await?;
+    json_response(settings.load().as_ref())
+}
+
+#[utoipa::path(
+    responses(
+        (status = 200, description = "Quality profiles", body = [QualityProfile]),
+    )
+)]
+#[get("/api/v1/profiles")]
+pub async fn api_profiles(db: Data<DBConnection>) -> Result<HttpResponse<String>, Error> {
+    match QualityProfileDatabase::new(&db).fetch_all().await {
+        Ok(t) => json_response(&t),
+        Err(e) => Err(ErrorInternalServerError(e)),
+    }
+}
+
+// Making a profile the default takes it away from the previous default
+#[utoipa::path(
+    request_body = QualityProfile,
+    responses(
+        (status = 200, description = "Profile created", body = QualityProfile),
+        (status = 400, description = "Invalid profile"),
+        (status = 403, description = "Read-only mode"),
+    )
+)]
+#[post("/api/v1/profiles")]
+pub async fn api_create_profile(
+    profile: Json<QualityProfile>,
+    db: Data<DBConnection>,
+    app_config: Data<AppConfig>,
+) -> Result<HttpResponse<String>, Error> {
+    if app_config.read_only {
+        return Err(ErrorForbidden("Read-only mode"));
+    }
+
+    let mut profile = profile.into_inner();
+    if let Err(e) = profile.validate() {
+        return Err(ErrorBadRequest(e));
+    }
+
+    profile.id = match QualityProfileDatabase::new(&db).insert(&profile).await {
+        Ok(t) => t,
+        Err(e) => return Err(ErrorInternalServerError(e)),
+    };
+    json_response(&profile)
+}
+
+#[utoipa::path(
+    params(("id" = i32, Path, description = "Profile ID")),
+    request_body = QualityProfile,
+    responses(
+        (status = 200, description = "Profile updated", body = QualityProfile),
+        (status = 400, description = "Invalid profile"),
+        (status = 403, description = "Read-only mode"),
+        (status = 404, description = "No such profile"),
+    )
+)]
+#[put("/api/v1/profiles/{id}")]
+pub async fn api_update_profile(
+    id: Path<i32>,
+    profile: Json<QualityProfile>,
+    db: Data<DBConnection>,
+    app_config: Data<AppConfig>,
+) -> Result<HttpResponse<String>, Error> {
+    if app_config.read_only {
+        return Err(ErrorForbidden("Read-only mode"));
+    }
+
+    let mut profile = profile.into_inner();
+    if let Err(e) = profile.validate() {
+        return Err(ErrorBadRequest(e));
+    }
+
+    profile.id = id.into_inner();
+    match QualityProfileDatabase::new(&db).update(profile.id, &profile).await {
+        Ok(true) => json_response(&profile),
+        Ok(false) => Err(ErrorNotFound("Quality profile not found")),
+        Err(e) => Err(ErrorInternalServerError(e)),
+    }
+}
+
+// Watchlist items using the profile go back to the default profile
+#[utoipa::path(
+    params(("id" = i32, Path, description = "Profile ID")),
+    responses(
+        (status = 204, description = "Removed"),
+        (status = 403, description = "Read-only mode"),
+        (status = 404, description = "No such profile"),
+    )
+)]
+#[delete("/api/v1/profiles/{id}")]
+pub async fn api_remove_profile(
+    id: Path<i32>,
+    db: Data<DBConnection>,
+    app_config: Data<AppConfig>,
+) -> Result<HttpResponse, Error> {
+    if app_config.read_only {
+        return Err(ErrorForbidden("Read-only mode"));
+    }
+
+    match QualityProfileDatabase::new(&db).remove(id.into_inner()).await {
+        Ok(true) => Ok(HttpResponse::NoContent().finish()),
+        Ok(false) => Err(ErrorNotFound("Quality profile not found")),
+        Err(e) => Err(ErrorInternalServerError(e)),
+    }
+}
+
+#[derive(Deserialize, ToSchema)]
+struct WatchlistProfile {
+    // Null for the default profile
+    profile_id: Option<i32>,
+}
+
+#[utoipa::path(
+    params(("id" = String, Path, description = "IMDb ID or TheMovieDB ID")),
+    request_body = WatchlistProfile,
+    responses(
+        (status = 204, description = "Profile set"),
+        (status = 403, description = "Read-only mode"),
+        (status = 404, description = "No such profile"),
+    )
+)]
+#[put("/api/v1/watchlist/{id}/profile")]
+pub async fn api_set_watchlist_profile(
+    id: Path<String>,
+    body: Json<WatchlistProfile>,
+    db: Data<DBConnection>,
+    app_config: Data<AppConfig>,
+) -> Result<HttpResponse, Error> {
+    if app_config.read_only {
+        return Err(ErrorForbidden("Read-only mode"));
+    }
+
+    let profile_db = QualityProfileDatabase::new(&db);
+    if let Some(profile_id) = body.profile_id {
+        match profile_db.fetch(profile_id).await {
+            Ok(Some(_)) => (),
+            Ok(None) => return Err(ErrorNotFound("Quality profile not found")),
+            Err(e) => return Err(ErrorInternalServerError(e)),
+        }
+    }
+
+    match profile_db.set_for_item(&id, body.profile_id).await {
+        Ok(_) => Ok(HttpResponse::NoContent().finish()),
+        Err(e) => Err(ErrorInternalServerError(e)),
+    }
+}
+// Most recent first, including jobs that finished in the last 7 days
+#[utoipa::path(
+    responses(
+        (status = 200, description = "Recent background jobs", body = [JobRecord]),
+    )
+)]
+#[get("/api/v1/jobs")]
+pub async fn api_jobs(db: Data<DBConnection>) -> Result<HttpResponse<String>, Error> {
+    match JobDatabase::new(&db).fetch_recent(50).await {
+        Ok(t) => json_response(&t),
+        Err(e) => Err(ErrorInternalServerError(e)),
+    }
+}
+
+#[utoipa::path(
+    params(("id" = String, Path, description = "IMDb ID or TheMovieDB ID")),
+    responses((status = 200, description = "Watchlist overrides for the item", body = WatchlistOptions))
+)]
+#[get("/api/v1/watchlist/{id}/options")]
+pub async fn api_watchlist_options(id: Path<String>, db: Data<DBConnection>) -> Result<HttpResponse<String>, Error> {
+    match WatchlistOptionsDatabase::new(&db).fetch(&watchlist_key(&id)).await {
+        Ok(t) => json_response(&t),
+        Err(e) => Err(ErrorInternalServerError(e)),
+    }
+}
+
+// future_seasons_from is set by the server when only_future_seasons is first turned on
+#[utoipa::path(
+    params(("id" = String, Path, description = "IMDb ID or TheMovieDB ID")),
+    request_body = WatchlistOptions,
+    responses(
+        (status = 200, description = "Options saved", body = WatchlistOptions),
+        (status = 403, description = "Read-only mode"),
+    )
+)]
+#[put("/api/v1/watchlist/{id}/options")]
+pub async fn api_set_watchlist_options(
+    id: Path<String>,
+    body: Json<WatchlistOptions>,
+    db: Data<DBConnection>,
+    app_config: Data<AppConfig>,
+) -> Result<HttpResponse<String>, Error> {
+    if app_config.read_only {
+        return Err(ErrorForbidden("Read-only mode"));
+    }
+
+    if body.recheck_interval_hours.is_some_and(|h| h < 1) {
+        return Err(ErrorBadRequest("recheck_interval_hours has to be at least 1"));
+    }
+
+    let id = watchlist_key(&id);
+    let options_db = WatchlistOptionsDatabase::new(&db);
+    if let Err(e) = options_db.set(&id, &body).await {
+        return Err(ErrorInternalServerError(e));
+    }
+    match options_db.fetch(&id).await {
+        Ok(t) => json_response(&t),
+        Err(e) => Err(ErrorInternalServerError(e)),
+    }
+}
\ No newline at end of file
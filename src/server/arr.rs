@@ -0,0 +1,440 @@
+use std::ops::{Deref, Not};
+
+use actix_web::{Error, get, HttpResponse, post};
+use actix_web::error::{ErrorBadRequest, ErrorForbidden, ErrorInternalServerError, ErrorNotFound};
+use actix_web::web::{Data, Json, Query};
+use chrono::Datelike;
+use serde::{Deserialize, Serialize};
+use tokio::sync::Mutex;
+
+use crate::{AppConfig, QueryCache};
+use crate::api::imdb::{IMDBItem, ItemType, SearchType};
+use crate::api::moviedb::MovieDBItem;
+use crate::api::notify::Notifier;
+use crate::db::{DBConnection, Pagination};
+use crate::db::downloads::{DownloadDatabase, DownloadFilter, DownloadSort, DOWNLOADS_PAGE_SIZE, IMPORT_FAILED};
+use crate::db::imdb::IMDBDatabase;
+use crate::db::moviedb::MovieDBDatabase;
+use crate::server::api_v1::json_response;
+use crate::server::download::set_watchlist_state;
+use crate::server::query::{
+    check_cache_then_search_imdb, check_cache_then_search_moviedb, get_cached_item_imdb, get_cached_item_moviedb,
+    SearchRequests,
+};
+
+// Just enough of the Radarr/Sonarr v3 API for apps like nzb360 and LunaSea, movies and series are the
+// watchlist and "monitored" means on the watchlist. Numeric ids are the IMDb id without the tt prefix, or the
+// TheMovieDB id.
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ArrSystemStatus {
+    app_name: &'static str,
+    instance_name: &'static str,
+    version: &'static str,
+    roundup_version: &'static str,
+    is_windows: bool,
+    is_linux: bool,
+    url_base: &'static str,
+    authentication: &'static str,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ArrImage {
+    cover_type: &'static str,
+    url: String,
+    remote_url: String,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ArrItem {
+    id: i32,
+    title: String,
+    sort_title: String,
+    title_slug: String,
+    year: i64,
+    overview: String,
+    imdb_id: String,
+    tmdb_id: i32,
+    runtime: i64,
+    monitored: bool,
+    has_file: bool,
+    status: &'static str,
+    images: Vec<ArrImage>,
+    // Only for series
+    #[serde(skip_serializing_if = "Option::is_none")]
+    seasons: Option<Vec<serde_json::Value>>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ArrQueuePage {
+    page: i64,
+    page_size: i64,
+    sort_key: &'static str,
+    sort_direction: &'static str,
+    total_records: i64,
+    records: Vec<ArrQueueItem>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ArrQueueItem {
+    id: i32,
+    movie_id: Option<i32>,
+    series_id: Option<i32>,
+    title: String,
+    status: &'static str,
+    tracked_download_status: &'static str,
+    tracked_download_state: &'static str,
+    size: i64,
+    sizeleft: i64,
+    timeleft: Option<String>,
+    protocol: &'static str,
+    download_client: &'static str,
+    indexer: String,
+    download_id: String,
+}
+
+#[derive(Deserialize)]
+pub struct ArrLookupQuery {
+    #[serde(default)]
+    term: String,
+}
+
+#[derive(Deserialize)]
+pub struct ArrQueueQuery {
+    page: Option<i64>,
+}
+
+// The app sends back what it got from lookup, only the ids matter
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ArrAddItem {
+    imdb_id: Option<String>,
+    tmdb_id: Option<i32>,
+}
+
+#[get("/api/v3/system/status")]
+pub async fn arr_system_status() -> Result<HttpResponse<String>, Error> {
+    json_response(&ArrSystemStatus {
+        app_name: "Roundup",
+        instance_name: "Roundup",
+        version: "3.0.0",
+        roundup_version: env!("CARGO_PKG_VERSION"),
+        is_windows: cfg!(windows),
+        is_linux: cfg!(target_os = "linux"),
+        url_base: "",
+        authentication: "none",
+    })
+}
+
+#[get("/api/v3/movie")]
+pub async fn arr_movies(db: Data<DBConnection>, app_config: Data<AppConfig>) -> Result<HttpResponse<String>, Error> {
+    json_response(&fetch_watchlist(ItemType::Movie, &db, &app_config).await?)
+}
+
+#[get("/api/v3/series")]
+pub async fn arr_series(db: Data<DBConnection>, app_config: Data<AppConfig>) -> Result<HttpResponse<String>, Error> {
+    json_response(&fetch_watchlist(ItemType::TvShow, &db, &app_config).await?)
+}
+
+#[get("/api/v3/movie/lookup")]
+pub async fn arr_movie_lookup(
+    params: Query<ArrLookupQuery>,
+    cache_update: Data<Mutex<QueryCache>>,
+    searches: Data<SearchRequests>,
+    db: Data<DBConnection>,
+    app_config: Data<AppConfig>,
+) -> Result<HttpResponse<String>, Error> {
+    json_response(&lookup(ItemType::Movie, &params.term, cache_update, searches, db, app_config).await?)
+}
+
+#[get("/api/v3/series/lookup")]
+pub async fn arr_series_lookup(
+    params: Query<ArrLookupQuery>,
+    cache_update: Data<Mutex<QueryCache>>,
+    searches: Data<SearchRequests>,
+    db: Data<DBConnection>,
+    app_config: Data<AppConfig>,
+) -> Result<HttpResponse<String>, Error> {
+    json_response(&lookup(ItemType::TvShow, &params.term, cache_update, searches, db, app_config).await?)
+}
+
+#[post("/api/v3/movie")]
+pub async fn arr_add_movie(
+    body: Json<ArrAddItem>,
+    db: Data<DBConnection>,
+    notifier: Data<Notifier>,
+    app_config: Data<AppConfig>,
+) -> Result<HttpResponse<String>, Error> {
+    json_response(&add_to_watchlist(&body, db, notifier, app_config).await?)
+}
+
+#[post("/api/v3/series")]
+pub async fn arr_add_series(
+    body: Json<ArrAddItem>,
+    db: Data<DBConnection>,
+    notifier: Data<Notifier>,
+    app_config: Data<AppConfig>,
+) -> Result<HttpResponse<String>, Error> {
+    json_response(&add_to_watchlist(&body, db, notifier, app_config).await?)
+}
+
+#[get("/api/v3/queue")]
+pub async fn arr_queue(
+    params: Query<ArrQueueQuery>,
+    db: Data<DBConnection>,
+    app_config: Data<AppConfig>,
+) -> Result<HttpResponse<String>, Error> {
+    // Pages start at 1 here
+    let page = params.page.unwrap_or(1).max(1);
+
+    // Download details are joined from the IMDb cache, so the queue is always empty with TheMovieDB
+    let (downloads, total_records) = match app_config.tmdb_api_key.is_empty() {
+        true => {
+            let download_db = DownloadDatabase::new(&db);
+            let downloads = match download_db
+                .fetch_downloads_with_imdb_data(&DownloadFilter::from_params(None, None, None), DownloadSort::Added, page - 1)
+                .await
+            {
+                Ok(t) => t,
+                Err(e) => return Err(ErrorInternalServerError(e)),
+            };
+            match download_db.count().await {
+                Ok(t) => (downloads, t),
+                Err(e) => return Err(ErrorInternalServerError(e)),
+            }
+        }
+        false => (Vec::new(), 0),
+    };
+
+    let records = downloads
+        .into_iter()
+        .map(|d| {
+            let id = numeric_id(&d.imdb_id);
+            let size = d.size.unwrap_or(0);
+            let title = match (d.season, d.episode) {
+                (Some(s), Some(e)) => format!("{} S{:02}E{:02}", d.title, s, e),
+                (Some(s), None) => format!("{} S{:02}", d.title, s),
+                _ => format!("{} ({})", d.title, d.year),
+            };
+            let failed = d.import_state.as_deref() == Some(IMPORT_FAILED);
+            let status = match d.state.as_str() {
+                _ if d.progress >= 1.0 => "completed",
+                "pausedDL" => "paused",
+                "queuedDL" | "metaDL" | "checkingDL" | "allocating" => "queued",
+                "error" | "missingFiles" => "failed",
+                _ => "downloading",
+            };
+
+            ArrQueueItem {
+                id: d.id,
+                movie_id: matches!(d._type, ItemType::Movie).then_some(id),
+                series_id: matches!(d._type, ItemType::TvShow).then_some(id),
+                title: d.release_name.unwrap_or(title),
+                status,
+                tracked_download_status: match failed || d.state == "stalledDL" {
+                    true => "warning",
+                    false => "ok",
+                },
+                tracked_download_state: match (failed, d.progress >= 1.0) {
+                    (true, _) => "failed",
+                    (false, true) => "imported",
+                    (false, false) => "downloading",
+                },
+                size,
+                sizeleft: (size as f64 * (1.0 - d.progress).max(0.0)) as i64,
+                timeleft: d.eta.map(|t| format!("{:02}:{:02}:{:02}", t / 3600, t % 3600 / 60, t % 60)),
+                protocol: "torrent",
+                download_client: "qBittorrent",
+                indexer: d.source.unwrap_or_default(),
+                download_id: d.magnet_hash.to_ascii_uppercase(),
+            }
+        })
+        .collect::<Vec<ArrQueueItem>>();
+
+    json_response(&ArrQueuePage {
+        page,
+        page_size: DOWNLOADS_PAGE_SIZE,
+        sort_key: "timeleft",
+        sort_direction: "ascending",
+        total_records,
+        records,
+    })
+}
+
+async fn fetch_watchlist(_type: ItemType, db: &DBConnection, app_config: &AppConfig) -> Result<Vec<ArrItem>, Error> {
+    let items = match app_config.tmdb_api_key.is_empty() {
+        true => match IMDBDatabase::new(db).fetch_watchlist().await {
+            Ok(t) => t.into_iter().map(ArrItem::from).collect::<Vec<ArrItem>>(),
+            Err(e) => return Err(ErrorInternalServerError(e)),
+        },
+        false => match MovieDBDatabase::new(db).fetch_watchlist().await {
+            Ok(t) => t.into_iter().map(ArrItem::from).collect::<Vec<ArrItem>>(),
+            Err(e) => return Err(ErrorInternalServerError(e)),
+        },
+    };
+
+    Ok(items.into_iter().filter(|i| i.is_type(&_type)).collect())
+}
+
+// Accepts plain search text, or imdb:tt0133093 / tmdb:603 for an item that's already been searched for
+async fn lookup(
+    _type: ItemType,
+    term: &str,
+    cache_update: Data<Mutex<QueryCache>>,
+    searches: Data<SearchRequests>,
+    db: Data<DBConnection>,
+    app_config: Data<AppConfig>,
+) -> Result<Vec<ArrItem>, Error> {
+    let term = term.trim();
+    if term.is_empty() {
+        return Err(ErrorBadRequest("Missing term"));
+    }
+
+    if let Some(id) = term.strip_prefix("imdb:").or(term.strip_prefix("tmdb:")) {
+        let item = match app_config.tmdb_api_key.is_empty() {
+            true => get_cached_item_imdb(id.trim(), db).await.map(ArrItem::from),
+            false => match id.trim().parse::<i32>() {
+                Ok(_) => get_cached_item_moviedb(id.trim(), db).await.map(ArrItem::from),
+                Err(_) => return Err(ErrorBadRequest("Invalid TheMovieDB id")),
+            },
+        };
+        return Ok(item.into_iter().collect());
+    }
+
+    let mode = SearchType::Query(term.to_string());
+    let items = match app_config.tmdb_api_key.is_empty() {
+        true => check_cache_then_search_imdb(mode, Pagination::new(None, None), db, cache_update, searches)
+            .await?
+            .into_iter()
+            .map(ArrItem::from)
+            .collect::<Vec<ArrItem>>(),
+        false => check_cache_then_search_moviedb(mode, Pagination::new(None, None), db, cache_update, searches, app_config)
+            .await?
+            .into_iter()
+            .map(ArrItem::from)
+            .collect::<Vec<ArrItem>>(),
+    };
+
+    Ok(items.into_iter().filter(|i| i.is_type(&_type)).collect())
+}
+
+async fn add_to_watchlist(
+    body: &ArrAddItem,
+    db: Data<DBConnection>,
+    notifier: Data<Notifier>,
+    app_config: Data<AppConfig>,
+) -> Result<ArrItem, Error> {
+    if app_config.read_only {
+        return Err(ErrorForbidden("Read-only mode"));
+    }
+
+    let id = match (app_config.tmdb_api_key.is_empty(), &body.imdb_id, body.tmdb_id) {
+        (true, Some(t), _) if t.is_empty().not() => match t.starts_with("tt") {
+            true => t.to_string(),
+            false => format!("tt{}", t),
+        },
+        (false, _, Some(t)) if t > 0 => t.to_string(),
+        (true, _, _) => return Err(ErrorBadRequest("Missing imdbId")),
+        (false, _, _) => return Err(ErrorBadRequest("Missing tmdbId")),
+    };
+
+    let item = match app_config.tmdb_api_key.is_empty() {
+        true => get_cached_item_imdb(&id, Data::clone(&db)).await.map(ArrItem::from),
+        false => get_cached_item_moviedb(&id, Data::clone(&db)).await.map(ArrItem::from),
+    };
+    let mut item = match item {
+        Ok(t) => t,
+        Err(_) => return Err(ErrorNotFound("Item not found, look it up first")),
+    };
+
+    match set_watchlist_state(&app_config, db.deref(), &notifier, &id, true).await {
+        Ok(_) => (),
+        Err(e) => return Err(ErrorInternalServerError(e)),
+    };
+    item.monitored = true;
+
+    Ok(item)
+}
+
+fn numeric_id(id: &str) -> i32 {
+    id.trim_start_matches("tt").parse::<i32>().unwrap_or(0)
+}
+
+fn slug(title: &str, year: i64) -> String {
+    let slug = title
+        .to_ascii_lowercase()
+        .split(|c: char| c.is_ascii_alphanumeric().not())
+        .filter(|s| s.is_empty().not())
+        .collect::<Vec<&str>>()
+        .join("-");
+    format!("{}-{}", slug, year)
+}
+
+fn images(poster_url: Option<String>, backdrop_url: Option<String>) -> Vec<ArrImage> {
+    [("poster", poster_url), ("fanart", backdrop_url)]
+        .into_iter()
+        .filter_map(|(cover_type, url)| url.filter(|u| u.is_empty().not()).map(|url| (cover_type, url)))
+        .map(|(cover_type, url)| ArrImage {
+            cover_type,
+            remote_url: url.clone(),
+            url,
+        })
+        .collect()
+}
+
+impl ArrItem {
+    fn is_type(&self, _type: &ItemType) -> bool {
+        match _type {
+            ItemType::Movie => self.seasons.is_none(),
+            ItemType::TvShow => self.seasons.is_some(),
+        }
+    }
+}
+
+impl From<IMDBItem> for ArrItem {
+    fn from(item: IMDBItem) -> Self {
+        ArrItem {
+            id: numeric_id(&item.id),
+            sort_title: item.title.to_ascii_lowercase(),
+            title_slug: slug(&item.title, item.year),
+            title: item.title,
+            year: item.year,
+            overview: item.plot.unwrap_or_default(),
+            imdb_id: item.id,
+            tmdb_id: 0,
+            runtime: item.runtime.unwrap_or(0) / 60,
+            monitored: item.watchlist,
+            has_file: false,
+            status: "released",
+            images: images(item.poster_url.or(Some(item.image_url)), item.backdrop_url),
+            seasons: matches!(item._type, ItemType::TvShow).then(Vec::new),
+        }
+    }
+}
+
+impl From<MovieDBItem> for ArrItem {
+    fn from(item: MovieDBItem) -> Self {
+        let year = item.release_date.year() as i64;
+        ArrItem {
+            id: item.id,
+            sort_title: item.title.to_ascii_lowercase(),
+            title_slug: slug(&item.title, year),
+            title: item.title,
+            year,
+            overview: item.plot,
+            imdb_id: item.imdb_id,
+            tmdb_id: item.id,
+            runtime: item.runtime.unwrap_or(0),
+            monitored: item.watchlist,
+            has_file: false,
+            status: "released",
+            images: images(item.poster_url.or(item.image_url), item.backdrop_url),
+            seasons: matches!(item._type, ItemType::TvShow).then(Vec::new),
+        }
+    }
+}
\ No newline at end of file
@@ -0,0 +1,53 @@
+use std::borrow::Cow;
+use std::fs;
+use std::ops::Not;
+use std::path::{Component, Path};
+
+use actix_web::{Error, get, HttpResponse};
+use actix_web::error::ErrorNotFound;
+use actix_web::web::{self, Data};
+
+use crate::AppConfig;
+
+// Everything under ./static, compiled in by build.rs so the binary runs from any working directory
+include!(concat!(env!("OUT_DIR"), "/static_assets.rs"));
+
+// Reads from ROUNDUP_STATIC_DIR when it's set, so the pages can be edited without rebuilding
+pub fn read_asset(app_config: &AppConfig, path: &str) -> Option<Cow<'static, [u8]>> {
+    // Only plain names, so ".." or an absolute path like /etc/passwd can't reach outside the static directory
+    if Path::new(path).components().all(|c| matches!(c, Component::Normal(_))).not() {
+        return None;
+    }
+
+    match app_config.static_dir.is_empty() {
+        true => STATIC_ASSETS
+            .iter()
+            .find(|(name, _)| *name == path)
+            .map(|(_, data)| Cow::Borrowed(*data)),
+        false => fs::read(Path::new(&app_config.static_dir).join(path)).ok().map(Cow::Owned),
+    }
+}
+
+pub fn read_asset_text(app_config: &AppConfig, path: &str) -> Result<String, Error> {
+    match read_asset(app_config, path) {
+        Some(t) => Ok(String::from_utf8_lossy(&t).into_owned()),
+        None => Err(ErrorNotFound(format!("{} not found", path))),
+    }
+}
+
+#[get("/static/{path:.*}")]
+pub async fn static_file(path: web::Path<String>, app_config: Data<AppConfig>) -> Result<HttpResponse, Error> {
+    let data = match read_asset(&app_config, &path) {
+        Some(t) => t,
+        None => return Err(ErrorNotFound("Not found")),
+    };
+
+    let extension = path.rsplit_once('.').map(|(_, e)| e).unwrap_or_default();
+    let mut response = HttpResponse::Ok();
+    response.content_type(actix_files::file_extension_to_mime(extension));
+
+    Ok(match data {
+        Cow::Borrowed(t) => response.body(t),
+        Cow::Owned(t) => response.body(t),
+    })
+}
\ No newline at end of file
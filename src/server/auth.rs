@@ -0,0 +1,310 @@
+use std::collections::HashMap;
+use std::future::{ready, Ready};
+use std::ops::Not;
+use std::rc::Rc;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use actix_web::{delete, Error, get, HttpResponse, post};
+use actix_web::body::EitherBody;
+use actix_web::dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform};
+use actix_web::error::{ErrorBadRequest, ErrorForbidden, ErrorInternalServerError, ErrorNotFound};
+use actix_web::http::Method;
+use actix_web::web::{Data, Json, Path};
+use futures::future::LocalBoxFuture;
+use log::warn;
+use serde::{Deserialize, Serialize};
+
+use crate::AppConfig;
+use crate::db::DBConnection;
+use crate::db::api_keys::{ApiKeyDatabase, ApiKeyScope};
+use crate::server::api_v1::json_response;
+
+pub const API_KEY_HEADER: &str = "X-Api-Key";
+// How long a checked key is trusted before the database is asked again, last_used_at is only updated then
+const API_KEY_CACHE_TTL: Duration = Duration::from_secs(60);
+
+// Recently checked keys by hash, so API clients polling every few seconds don't each cost a database write
+pub struct ApiKeyCache {
+    entries: Mutex<HashMap<String, (ApiKeyScope, Instant)>>,
+}
+
+impl ApiKeyCache {
+    pub fn new() -> Self {
+        Self {
+            entries: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn get(&self, key_hash: &str) -> Option<ApiKeyScope> {
+        let mut entries = self.entries.lock().unwrap();
+        match entries.get(key_hash) {
+            Some((scope, checked_at)) if checked_at.elapsed() < API_KEY_CACHE_TTL => Some(*scope),
+            Some(_) => {
+                entries.remove(key_hash);
+                None
+            }
+            None => None,
+        }
+    }
+
+    fn insert(&self, key_hash: String, scope: ApiKeyScope) {
+        let mut entries = self.entries.lock().unwrap();
+        entries.retain(|_, (_, checked_at)| checked_at.elapsed() < API_KEY_CACHE_TTL);
+        entries.insert(key_hash, (scope, Instant::now()));
+    }
+
+    // Keys are revoked by id, so forget them all rather than keep a revoked key working until it expires
+    pub fn clear(&self) {
+        self.entries.lock().unwrap().clear();
+    }
+}
+
+pub fn generate_api_key() -> String {
+    let mut bytes = [0u8; 32];
+    ring::rand::SecureRandom::fill(&ring::rand::SystemRandom::new(), &mut bytes).unwrap();
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+pub fn hash_api_key(key: &str) -> String {
+    let digest = ring::digest::digest(&ring::digest::SHA256, key.as_bytes());
+    digest.as_ref().iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+// Requires a valid X-Api-Key header on /api/v1 and /api/v3 when require_api_key is set. Managing keys under /api/keys
+// always needs an admin key, apart from creating the first one, and manual imports always need a download key
+pub struct ApiKeyAuth;
+
+impl<S, B> Transform<S, ServiceRequest> for ApiKeyAuth
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Transform = ApiKeyAuthMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(ApiKeyAuthMiddleware { service: Rc::new(service) }))
+    }
+}
+
+pub struct ApiKeyAuthMiddleware<S> {
+    service: Rc<S>,
+}
+
+impl<S, B> Service<ServiceRequest> for ApiKeyAuthMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let service = Rc::clone(&self.service);
+
+        Box::pin(async move {
+            let (required, managing_keys) = match req.app_data::<Data<AppConfig>>() {
+                Some(t) => {
+                    let path = req.path().strip_prefix(t.base_path.as_str()).unwrap_or(req.path());
+                    let managing_keys = path.starts_with("/api/keys");
+                    let api = path.starts_with("/api/v1") || path.starts_with("/api/v3");
+                    let importing = path == "/import";
+                    (managing_keys || importing || (t.require_api_key && api), managing_keys)
+                }
+                None => (false, false),
+            };
+            if required.not() {
+                return service.call(req).await.map(|r| r.map_into_left_body());
+            }
+            // Otherwise there'd be no way to get the first admin key
+            if managing_keys && req.method() == Method::POST && has_no_keys(&req).await {
+                return service.call(req).await.map(|r| r.map_into_left_body());
+            }
+
+            let key = request_api_key(&req).unwrap_or_default();
+
+            let key_hash = hash_api_key(&key);
+            let cache = req.app_data::<Data<ApiKeyCache>>().cloned();
+            let cached = cache.as_ref().and_then(|c| c.get(&key_hash));
+
+            let scope = match (cached, key.is_empty(), req.app_data::<Data<DBConnection>>()) {
+                (Some(t), _, _) => Some(t),
+                (None, false, Some(db)) => match ApiKeyDatabase::new(db).find_by_hash(&key_hash).await {
+                    Ok(t) => {
+                        if let (Some(scope), Some(cache)) = (t, &cache) {
+                            cache.insert(key_hash, scope);
+                        }
+                        t
+                    }
+                    Err(e) => {
+                        warn!("Failed to check API key: {}", e);
+                        None
+                    }
+                },
+                _ => None,
+            };
+
+            let response = match scope {
+                Some(t) if t >= required_scope(&req) => {
+                    return service.call(req).await.map(|r| r.map_into_left_body())
+                }
+                Some(_) => HttpResponse::Forbidden()
+                    .content_type("application/json")
+                    .body(r#"{"error":"API key scope doesn't allow this request"}"#),
+                None => HttpResponse::Unauthorized()
+                    .content_type("application/json")
+                    .body(r#"{"error":"Missing or invalid API key"}"#),
+            };
+            Ok(req.into_response(response).map_into_right_body())
+        })
+    }
+}
+
+async fn has_no_keys(req: &ServiceRequest) -> bool {
+    let db = match req.app_data::<Data<DBConnection>>() {
+        Some(t) => t,
+        None => return false,
+    };
+    match ApiKeyDatabase::new(db).count().await {
+        Ok(t) => t == 0,
+        Err(e) => {
+            warn!("Failed to count API keys: {}", e);
+            false
+        }
+    }
+}
+
+// Reading needs any key, changing settings needs an admin key and every other change a download key. Anything under
+// /api/keys needs an admin key, listing them included
+fn required_scope(req: &ServiceRequest) -> ApiKeyScope {
+    let path = req.path();
+    if path.contains("/api/keys") {
+        return ApiKeyScope::Admin;
+    }
+    if matches!(*req.method(), Method::GET | Method::HEAD | Method::OPTIONS) {
+        return ApiKeyScope::Read;
+    }
+    match path.ends_with("/api/v1/settings") || path.contains("/api/v1/profiles") {
+        true => ApiKeyScope::Admin,
+        false => ApiKeyScope::Download,
+    }
+}
+
+pub fn request_api_key(req: &ServiceRequest) -> Option<String> {
+    req.headers()
+        .get(API_KEY_HEADER)
+        .and_then(|h| h.to_str().ok())
+        .map(|h| h.trim().to_string())
+        .or(api_key_param(req.query_string()))
+        .filter(|k| k.is_empty().not())
+}
+
+// Radarr/Sonarr clients can send the key as ?apikey= instead
+fn api_key_param(query: &str) -> Option<String> {
+    query
+        .split('&')
+        .find_map(|p| p.strip_prefix("apikey="))
+        .and_then(|k| urlencoding::decode(k).ok())
+        .map(|k| k.trim().to_string())
+}
+
+#[derive(Deserialize)]
+pub struct NewApiKey {
+    name: String,
+    // read, download or admin, defaults to read. The first key is created without one so it defaults to admin
+    scope: Option<String>,
+}
+
+#[derive(Serialize)]
+struct CreatedApiKey {
+    id: i32,
+    name: String,
+    scope: &'static str,
+    key: String,
+}
+
+#[get("/api/keys")]
+pub async fn list_api_keys(db: Data<DBConnection>) -> Result<HttpResponse<String>, Error> {
+    let keys = match ApiKeyDatabase::new(&db).fetch_all().await {
+        Ok(t) => t,
+        Err(e) => return Err(ErrorInternalServerError(e)),
+    };
+
+    json_response(&keys)
+}
+
+// The key is only ever shown in this response
+#[post("/api/keys")]
+pub async fn create_api_key(
+    body: Json<NewApiKey>,
+    db: Data<DBConnection>,
+    app_config: Data<AppConfig>,
+) -> Result<HttpResponse<String>, Error> {
+    if app_config.read_only {
+        return Err(ErrorForbidden("Read-only mode"));
+    }
+
+    let name = body.name.trim();
+    if name.is_empty() {
+        return Err(ErrorBadRequest("Name is required"));
+    }
+
+    let key_db = ApiKeyDatabase::new(&db);
+    let first_key = match key_db.count().await {
+        Ok(t) => t == 0,
+        Err(e) => return Err(ErrorInternalServerError(e)),
+    };
+    let scope = match body.scope.as_deref() {
+        Some(t) => match ApiKeyScope::from_param(t) {
+            Some(t) => t,
+            None => return Err(ErrorBadRequest("Scope must be read, download or admin")),
+        },
+        None if first_key => ApiKeyScope::Admin,
+        None => ApiKeyScope::Read,
+    };
+    // Only admin keys can create more, so anything else would lock key management out
+    if first_key && scope != ApiKeyScope::Admin {
+        return Err(ErrorBadRequest("The first key has to be an admin key"));
+    }
+
+    let key = generate_api_key();
+    let id = match key_db.insert(name, &hash_api_key(&key), scope).await {
+        Ok(t) => t,
+        Err(e) => return Err(ErrorInternalServerError(e)),
+    };
+
+    json_response(&CreatedApiKey {
+        id,
+        name: name.to_string(),
+        scope: scope.as_str(),
+        key,
+    })
+}
+
+#[delete("/api/keys/{id}")]
+pub async fn revoke_api_key(
+    id: Path<i32>,
+    db: Data<DBConnection>,
+    cache: Data<ApiKeyCache>,
+    app_config: Data<AppConfig>,
+) -> Result<HttpResponse, Error> {
+    if app_config.read_only {
+        return Err(ErrorForbidden("Read-only mode"));
+    }
+
+    match ApiKeyDatabase::new(&db).remove(id.into_inner()).await {
+        Ok(true) => {
+            cache.clear();
+            Ok(HttpResponse::NoContent().finish())
+        }
+        Ok(false) => Err(ErrorNotFound("API key not found")),
+        Err(e) => Err(ErrorInternalServerError(e)),
+    }
+}
\ No newline at end of file
@@ -0,0 +1,116 @@
+use std::collections::HashMap;
+
+use actix_web::{Error, get, HttpResponse};
+use actix_web::error::ErrorInternalServerError;
+use actix_web::web::Data;
+use chrono::{Datelike, Duration, NaiveDate, Utc};
+
+use crate::AppConfig;
+use crate::api::imdb::ItemType;
+use crate::db::DBConnection;
+use crate::db::episodes::EpisodeDatabase;
+use crate::db::imdb::IMDBDatabase;
+use crate::db::moviedb::MovieDBDatabase;
+
+// Upcoming episodes of watchlist shows as an iCalendar feed, subscribe to it from Google Calendar, Home Assistant etc.
+// Air dates are stored when a show is searched for downloads, so a newly added show appears after the next watchlist check
+#[get("/calendar.ics")]
+pub async fn calendar(db: Data<DBConnection>, app_config: Data<AppConfig>) -> Result<HttpResponse<String>, Error> {
+    let shows = match fetch_watchlist_shows(&db, &app_config).await {
+        Ok(t) => t,
+        Err(e) => return Err(ErrorInternalServerError(e)),
+    };
+
+    let ids = shows.keys().cloned().collect::<Vec<String>>();
+    let episodes = match EpisodeDatabase::new(&db).fetch_upcoming(&ids).await {
+        Ok(t) => t,
+        Err(e) => return Err(ErrorInternalServerError(e)),
+    };
+
+    let stamp = Utc::now().format("%Y%m%dT%H%M%SZ").to_string();
+    let mut output = String::new();
+    push_line(&mut output, "BEGIN:VCALENDAR");
+    push_line(&mut output, "VERSION:2.0");
+    push_line(&mut output, "PRODID:-//Roundup//Upcoming Episodes//EN");
+    push_line(&mut output, "CALSCALE:GREGORIAN");
+    push_line(&mut output, "X-WR-CALNAME:Roundup");
+
+    for (show_id, season, episode, air_date) in episodes {
+        let title = match shows.get(&show_id) {
+            Some(t) => t,
+            None => continue,
+        };
+
+        push_line(&mut output, "BEGIN:VEVENT");
+        push_line(&mut output, &format!("UID:{}-s{}e{}@roundup", show_id, season, episode));
+        push_line(&mut output, &format!("DTSTAMP:{}", stamp));
+        push_line(&mut output, &format!("DTSTART;VALUE=DATE:{}", format_date(air_date)));
+        push_line(&mut output, &format!("DTEND;VALUE=DATE:{}", format_date(air_date + Duration::days(1))));
+        push_line(&mut output, &format!("SUMMARY:{}", escape_text(&format!("{} S{:02}E{:02}", title, season, episode))));
+        push_line(&mut output, "TRANSP:TRANSPARENT");
+        push_line(&mut output, "END:VEVENT");
+    }
+
+    push_line(&mut output, "END:VCALENDAR");
+
+    Ok(HttpResponse::Ok()
+        .content_type("text/calendar; charset=utf-8")
+        .message_body(output)
+        .unwrap())
+}
+
+// Show id to title, ids match what the episodes were stored under
+async fn fetch_watchlist_shows(db: &DBConnection, app_config: &AppConfig) -> anyhow::Result<HashMap<String, String>> {
+    let shows = match app_config.tmdb_api_key.is_empty() {
+        true => IMDBDatabase::new(db)
+            .fetch_watchlist()
+            .await?
+            .into_iter()
+            .filter(|i| matches!(i._type, ItemType::TvShow))
+            .map(|i| (i.id, i.title))
+            .collect(),
+        false => MovieDBDatabase::new(db)
+            .fetch_watchlist()
+            .await?
+            .into_iter()
+            .filter(|i| matches!(i._type, ItemType::TvShow))
+            .map(|i| (i.id.to_string(), i.title))
+            .collect(),
+    };
+
+    Ok(shows)
+}
+
+fn format_date(date: NaiveDate) -> String {
+    format!("{:04}{:02}{:02}", date.year(), date.month(), date.day())
+}
+
+// RFC 5545 text escaping
+fn escape_text(input: &str) -> String {
+    let mut output = String::with_capacity(input.len());
+    for c in input.chars() {
+        match c {
+            '\\' => output.push_str("\\\\"),
+            ';' => output.push_str("\\;"),
+            ',' => output.push_str("\\,"),
+            '\n' => output.push_str("\\n"),
+            '\r' => (),
+            _ => output.push(c),
+        }
+    }
+    output
+}
+
+// Lines end in CRLF and are folded at 75 octets, continuation lines start with a space
+fn push_line(output: &mut String, line: &str) {
+    let mut length = 0;
+    for c in line.chars() {
+        if length + c.len_utf8() > 75 {
+            output.push_str("\r\n ");
+            length = 1;
+        }
+        output.push(c);
+        length += c.len_utf8();
+    }
+    output.push_str("\r\n");
+}
\ No newline at end of file
@@ -0,0 +1,178 @@
+use std::future::{ready, Ready};
+use std::io::Write;
+use std::rc::Rc;
+
+use actix_web::body::{BodySize, BoxBody, EitherBody, MessageBody};
+use actix_web::dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform};
+use actix_web::Error;
+use actix_web::error::ErrorInternalServerError;
+use actix_web::http::header::{self, HeaderValue};
+use futures::future::LocalBoxFuture;
+
+// Smaller responses aren't worth the CPU
+const MIN_COMPRESS_SIZE: u64 = 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Encoding {
+    Brotli,
+    Gzip,
+}
+
+// Compresses HTML, JSON and other text responses with brotli or gzip, whichever the client accepts.
+// A level of 0 disables that encoding, streamed responses (websocket, event stream) are left alone.
+#[derive(Clone, Copy)]
+pub struct Compression {
+    gzip_level: u32,
+    brotli_level: u32,
+}
+
+impl Compression {
+    pub fn new(gzip_level: u32, brotli_level: u32) -> Self {
+        Self {
+            gzip_level: gzip_level.min(9),
+            brotli_level: brotli_level.min(11),
+        }
+    }
+
+    fn choose_encoding(&self, accept_encoding: &str) -> Option<Encoding> {
+        let accepted = accept_encoding
+            .split(',')
+            .filter_map(|e| {
+                let mut parts = e.split(';');
+                let name = parts.next()?.trim().to_ascii_lowercase();
+                // "gzip;q=0" means the client doesn't want it
+                let refused = parts.any(|p| matches!(p.trim(), "q=0" | "q=0.0" | "q=0.00" | "q=0.000"));
+                match refused {
+                    true => None,
+                    false => Some(name),
+                }
+            })
+            .collect::<Vec<String>>();
+
+        if self.brotli_level > 0 && accepted.iter().any(|e| e == "br") {
+            return Some(Encoding::Brotli);
+        }
+        if self.gzip_level > 0 && accepted.iter().any(|e| e == "gzip") {
+            return Some(Encoding::Gzip);
+        }
+        None
+    }
+
+    fn compress(&self, encoding: Encoding, input: &[u8]) -> std::io::Result<Vec<u8>> {
+        match encoding {
+            Encoding::Brotli => {
+                let mut writer = brotli::CompressorWriter::new(Vec::new(), 4096, self.brotli_level, 22);
+                writer.write_all(input)?;
+                Ok(writer.into_inner())
+            }
+            Encoding::Gzip => {
+                let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::new(self.gzip_level));
+                encoder.write_all(input)?;
+                encoder.finish()
+            }
+        }
+    }
+}
+
+impl<S, B> Transform<S, ServiceRequest> for Compression
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Transform = CompressionMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(CompressionMiddleware { service: Rc::new(service), compression: *self }))
+    }
+}
+
+pub struct CompressionMiddleware<S> {
+    service: Rc<S>,
+    compression: Compression,
+}
+
+impl<S, B> Service<ServiceRequest> for CompressionMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let service = Rc::clone(&self.service);
+        let compression = self.compression;
+
+        let encoding = req
+            .headers()
+            .get(header::ACCEPT_ENCODING)
+            .and_then(|h| h.to_str().ok())
+            .and_then(|h| compression.choose_encoding(h));
+
+        Box::pin(async move {
+            let res = service.call(req).await?;
+            let encoding = match encoding {
+                Some(t) if should_compress(&res) => t,
+                _ => return Ok(res.map_into_left_body()),
+            };
+
+            let (req, res) = res.into_parts();
+            let (mut res, body) = res.into_parts();
+            let body = match actix_web::body::to_bytes(body).await {
+                Ok(t) => t,
+                Err(e) => return Err(ErrorInternalServerError(e.into())),
+            };
+            let compressed = match compression.compress(encoding, &body) {
+                Ok(t) => t,
+                Err(e) => return Err(ErrorInternalServerError(e)),
+            };
+
+            let headers = res.headers_mut();
+            headers.insert(
+                header::CONTENT_ENCODING,
+                HeaderValue::from_static(match encoding {
+                    Encoding::Brotli => "br",
+                    Encoding::Gzip => "gzip",
+                }),
+            );
+            headers.append(header::VARY, HeaderValue::from_static("accept-encoding"));
+            headers.remove(header::CONTENT_LENGTH);
+
+            let res = res.set_body(BoxBody::new(compressed));
+            Ok(ServiceResponse::new(req, res).map_into_right_body())
+        })
+    }
+}
+
+fn should_compress<B: MessageBody>(res: &ServiceResponse<B>) -> bool {
+    if res.headers().contains_key(header::CONTENT_ENCODING) {
+        return false;
+    }
+
+    // Only complete bodies, buffering a stream would hold it back until it ends
+    match res.response().body().size() {
+        BodySize::Sized(t) if t >= MIN_COMPRESS_SIZE => (),
+        _ => return false,
+    }
+
+    let content_type = res
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|h| h.to_str().ok())
+        .unwrap_or_default()
+        .to_ascii_lowercase();
+
+    // Responses without a content type are the HTMX fragments
+    content_type.is_empty()
+        || content_type.starts_with("text/")
+        || content_type.contains("json")
+        || content_type.contains("xml")
+        || content_type.contains("javascript")
+}
\ No newline at end of file
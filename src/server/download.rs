@@ -1,21 +1,40 @@
 use std::ops::{Deref, Not};
 use std::sync::Arc;
 
-use actix_web::{Error, get, HttpResponse, post, web};
-use actix_web::error::ErrorInternalServerError;
-use actix_web::web::{Data, Json, Query};
+use actix_web::{delete, Error, get, HttpRequest, HttpResponse, post, web};
+use actix_web::error::{ErrorBadRequest, ErrorForbidden, ErrorInternalServerError, ErrorNotFound};
+use actix_web::web::{Data, Form, Json, Path, Query};
+use anyhow::format_err;
+use chrono::Datelike;
+use log::{error, warn};
 use rayon::prelude::*;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
 
 use crate::api::imdb::{IMDB, IMDBEpisode, ItemType};
 use crate::api::moviedb::MovieDB;
-use crate::api::plex::Plex;
-use crate::api::torrent::{MediaQuality, Torrenter, TorrentItem};
+use crate::api::media_server::{LibraryFile, MediaLookup, MediaServer};
+use crate::api::notify::{DownloadEvent, Notifier};
+use crate::api::profiles::QualityProfile;
+use crate::api::progress::ProgressFeed;
+use crate::api::torrent::{MediaQuality, normalise_magnet, Torrenter, TorrentItem};
+use crate::api::watchlist::WatchlistRecheck;
 use crate::AppConfig;
 use crate::db::DBConnection;
+use crate::db::aliases::AliasDatabase;
+use crate::db::attempts::{AttemptDatabase, AttemptOutcome, DownloadAttempt};
+use crate::db::blocklist::BlocklistDatabase;
 use crate::db::downloads::DownloadDatabase;
+use crate::db::episodes::EpisodeDatabase;
+use crate::db::history::{HistoryDatabase, HistoryEvent};
 use crate::db::imdb::IMDBDatabase;
 use crate::db::moviedb::MovieDBDatabase;
+use crate::db::profiles::QualityProfileDatabase;
+use crate::db::upgrades::UpgradeDatabase;
+use crate::db::watchlist_states::WatchlistStateDatabase;
+use crate::server::escape_html;
+use crate::server::preferences::load_preferences;
+use crate::server::query::{get_cached_item_imdb, get_cached_item_moviedb};
 
 #[derive(Deserialize)]
 pub struct DownloadQueryParams {
@@ -24,32 +43,61 @@ pub struct DownloadQueryParams {
     #[serde(rename = "type")]
     _type: String,
     ignore_already_exists: Option<bool>,
+    // Quality profile to filter and rank releases with, 0 for every release. Defaults to the watchlist profile
+    profile: Option<i32>,
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Serialize, Debug, ToSchema)]
 pub struct TorrentQuery {
     pub imdb_id: String,
-    #[serde(default)]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub season: Option<i32>,
-    #[serde(default)]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub episode: Option<i32>,
     pub quality: MediaQuality,
     pub magnet_uri: String,
+    // Library file to remove once this download has been imported. Only the server sets it, start_download looks the
+    // file up again rather than using the path it was sent
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub replaces: Option<String>,
+    // Release details, shown on the downloads page and kept for the download history
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub name: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub source: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub size: Option<i64>,
 }
 
 #[get("/find_download")]
 pub async fn find_download(
+    req: HttpRequest,
     params: Query<DownloadQueryParams>,
-    plex: Data<Plex>,
+    media_server: Data<dyn MediaServer>,
     db: Data<DBConnection>,
     torrenter: Data<Torrenter>,
     app_config: Data<AppConfig>,
 ) -> Result<HttpResponse<String>, Error> {
+    if app_config.read_only {
+        return Ok(HttpResponse::Ok()
+            .message_body("<b>Downloads are disabled</b>".to_string())
+            .unwrap());
+    }
+
+    let item_type = match params._type.as_str() {
+        "tv" => ItemType::TvShow,
+        _ => ItemType::Movie,
+    };
+    let lookup = media_lookup(&app_config, &params.imdb_id, &params.title);
+    let aliases = fetch_aliases(&app_config, db.deref(), &lookup, &item_type).await;
+    let lookup = lookup.with_aliases(aliases);
+
     let missing_tv_episodes = match params._type.as_str() {
         "tv" => {
             match find_missing_tv_shows(
-                plex.clone().into_inner(),
-                app_config,
+                media_server.clone().into_inner(),
+                Data::clone(&app_config),
+                db.deref(),
                 &params.imdb_id,
                 &params.title,
             )
@@ -63,7 +111,10 @@ pub async fn find_download(
     };
     
     let already_exists = missing_tv_episodes.is_none()
-        && match plex.exists_in_library(&params.title, false).await {
+        && match media_server
+            .exists_in_library(&lookup, false)
+            .await
+        {
             Ok(b) => b,
             Err(e) => return Err(ErrorInternalServerError(e)),
         };
@@ -84,10 +135,21 @@ pub async fn find_download(
         Err(e) => return Err(ErrorInternalServerError(e)),
     };
 
+    // In upgrade mode a movie that's already in the library can still be replaced with a better release
+    let mut upgrade_from = None;
     if already_exists && !params.ignore_already_exists.is_some_and(|x| x) {
-        return Ok(HttpResponse::Ok()
-            .message_body("<b>Content already exists</b>".to_string())
-            .unwrap());
+        if app_config.upgrade_quality {
+            upgrade_from = match media_server.library_file(&lookup).await {
+                Ok(t) => t,
+                Err(e) => return Err(ErrorInternalServerError(e)),
+            };
+        }
+
+        if upgrade_from.is_none() {
+            return Ok(HttpResponse::Ok()
+                .message_body("<b>Content already exists</b>".to_string())
+                .unwrap());
+        }
     }
 
     if is_downloading
@@ -99,12 +161,224 @@ pub async fn find_download(
             .unwrap());
     }
 
+    let profile_db = QualityProfileDatabase::new(db.deref());
+    let profiles = match profile_db.fetch_all().await {
+        Ok(t) => t,
+        Err(e) => return Err(ErrorInternalServerError(e)),
+    };
+    let watchlist_id = match app_config.tmdb_api_key.is_empty() {
+        true => &imdb_id,
+        false => &params.imdb_id,
+    };
+    let profile = match params.profile {
+        Some(0) => None,
+        Some(id) => match profiles.iter().find(|p| p.id == id) {
+            Some(t) => Some(t.clone()),
+            None => return Err(ErrorNotFound("Quality profile not found")),
+        },
+        None => match profile_db.fetch_for_item(watchlist_id).await {
+            Ok(t) => t,
+            Err(e) => return Err(ErrorInternalServerError(e)),
+        },
+    };
+    let profile_select = create_profile_select(&profiles, profile.as_ref().map(|p| p.id), &params);
+
+    if let (Some(existing), Some(profile)) = (&upgrade_from, &profile) {
+        if profile.meets_cutoff(existing.quality) {
+            return Ok(HttpResponse::Ok()
+                .message_body(format!(
+                    "<div id=\"download_results\">{}<b>Content already exists ({}), it meets the {} profile</b></div>",
+                    profile_select,
+                    existing.quality,
+                    escape_html(&profile.name)
+                ))
+                .unwrap());
+        }
+    }
+
+    let searchable_episodes = missing_tv_episodes.clone().unwrap_or_default();
+
     // Find Torrent on first platform that has a download
     let torrents = match torrenter
         .find_torrent(
             params.title.to_owned(),
             Some(params.imdb_id.to_owned()),
-            missing_tv_episodes,
+            missing_tv_episodes.clone(),
+        )
+        .await
+    {
+        Ok(t) => Ok(t),
+        Err(e) => match lookup.aliases.is_empty() {
+            true => Err(e),
+            false => {
+                torrenter
+                    .find_torrent_by_aliases(&lookup.aliases, params.imdb_id.to_owned(), missing_tv_episodes)
+                    .await
+            }
+        },
+    };
+    let mut torrents = match torrents {
+        Ok(t) => t,
+        Err(e) => {
+            return Ok(HttpResponse::Ok()
+                .message_body(format!("<b>{}</b>", e))
+                .unwrap())
+        }
+    };
+
+    // Releases that failed validation before
+    match BlocklistDatabase::new(db.deref()).fetch_hashes().await {
+        Ok(blocked) => torrents.retain(|t| blocked.contains(&t.magnet_hash()).not()),
+        Err(e) => return Err(ErrorInternalServerError(e)),
+    }
+
+    if let Some(profile) = &profile {
+        torrents.retain(|t| profile.allows(t));
+        if torrents.is_empty() {
+            return Ok(HttpResponse::Ok()
+                .message_body(format!(
+                    "<div id=\"download_results\">{}<b>No releases match the {} profile</b></div>",
+                    profile_select,
+                    escape_html(&profile.name)
+                ))
+                .unwrap());
+        }
+    }
+
+    if let Some(existing) = &upgrade_from {
+        torrents.retain(|t| t.quality > existing.quality);
+        if torrents.is_empty() {
+            return Ok(HttpResponse::Ok()
+                .message_body(format!("<b>Content already exists ({}), no better quality available</b>", existing.quality))
+                .unwrap());
+        }
+    }
+
+    if app_config.verify_torrent_health {
+        torrenter.verify_health(&mut torrents).await;
+    }
+
+    // TV results stay grouped by season
+    if matches!(item_type, ItemType::Movie) {
+        match &profile {
+            Some(profile) => profile.sort(&mut torrents),
+            None => {
+                if let Some(quality) = load_preferences(&req, &db).await.quality() {
+                    torrents.sort_by_key(|t| t.quality != quality);
+                }
+            }
+        }
+    }
+
+    let output = match &upgrade_from {
+        Some(existing) => create_upgrade_modal_options(torrents, existing),
+        None => create_download_modal_options(
+            torrents,
+            &searchable_episodes,
+            &params.imdb_id,
+            &params.title,
+        ),
+    };
+    let attempts = attempts_summary(db.deref(), &imdb_id).await;
+    let output = format!("<div id=\"download_results\">{}{}{}</div>", profile_select, attempts, output);
+
+    Ok(HttpResponse::Ok().message_body(output).unwrap())
+}
+
+// Lets the modal say "Tried 4 releases, all failed" before another one is picked
+async fn attempts_summary(db: &DBConnection, imdb_id: &str) -> String {
+    let attempts = match AttemptDatabase::new(db).fetch_for_item(imdb_id).await {
+        Ok(t) => t,
+        Err(e) => {
+            warn!("Failed to fetch download attempts for {}: {}", imdb_id, e);
+            return String::new();
+        }
+    };
+    let failed = attempts.iter().filter(|a| a.outcome == AttemptOutcome::Failed.as_str()).collect::<Vec<&DownloadAttempt>>();
+    let last = match failed.first() {
+        Some(t) => t,
+        None => return String::new(),
+    };
+
+    let summary = match failed.len() == attempts.len() {
+        true => format!("Tried {} releases, all failed", attempts.len()),
+        false => format!("{} of {} releases tried have failed", failed.len(), attempts.len()),
+    };
+    let reason = match &last.detail {
+        Some(t) => format!(", the last because: {}", escape_html(t)),
+        None => String::new(),
+    };
+    format!("<p class=\"text-body-secondary\">{}{}</p>", summary, reason)
+}
+
+// Switching profile reloads the results in place
+fn create_profile_select(profiles: &[QualityProfile], selected: Option<i32>, params: &DownloadQueryParams) -> String {
+    if profiles.is_empty() {
+        return String::new();
+    }
+
+    let options = profiles
+        .iter()
+        .map(|p| {
+            let is_selected = match selected == Some(p.id) {
+                true => " selected",
+                false => "",
+            };
+            format!("<option value=\"{}\"{}>{}</option>", p.id, is_selected, escape_html(&p.name))
+        })
+        .collect::<Vec<String>>()
+        .join("");
+    let any_selected = match selected.is_none() {
+        true => " selected",
+        false => "",
+    };
+
+    format!(
+        "<select class=\"form-select mb-2\" name=\"profile\" aria-label=\"Quality profile\" hx-get=\"find_download?imdb_id={}&title={}&type={}&ignore_already_exists={}\" hx-target=\"#download_results\" hx-swap=\"outerHTML\">\
+            <option value=\"0\"{}>Any quality</option>{}\
+        </select>",
+        urlencoding::encode(&params.imdb_id),
+        urlencoding::encode(&params.title),
+        urlencoding::encode(&params._type),
+        params.ignore_already_exists.unwrap_or(false),
+        any_selected,
+        options
+    )
+}
+
+#[derive(Deserialize)]
+pub struct EpisodeDownloadQueryParams {
+    imdb_id: String,
+    title: String,
+    season: i32,
+    episode: i32,
+}
+
+#[get("/find_episode_download")]
+pub async fn find_episode_download(
+    params: Query<EpisodeDownloadQueryParams>,
+    db: Data<DBConnection>,
+    torrenter: Data<Torrenter>,
+    app_config: Data<AppConfig>,
+) -> Result<HttpResponse<String>, Error> {
+    if app_config.read_only {
+        return Ok(HttpResponse::Ok()
+            .message_body("<b>Downloads are disabled</b>".to_string())
+            .unwrap());
+    }
+
+    let episode = IMDBEpisode {
+        id: String::new(),
+        season: params.season,
+        episode: params.episode,
+        air_date: None,
+    };
+
+    let mut torrents = match torrenter
+        .find_torrent(
+            params.title.to_owned(),
+            Some(params.imdb_id.to_owned()),
+            Some(vec![episode]),
         )
         .await
     {
@@ -116,11 +390,35 @@ pub async fn find_download(
         }
     };
 
-    let output = create_download_modal_options(torrents);
+    // Season packs for this season can still be returned, only keep the requested episode
+    torrents.retain(|t| t.season == Some(params.season) && t.episode == Some(params.episode));
+    match BlocklistDatabase::new(db.deref()).fetch_hashes().await {
+        Ok(blocked) => torrents.retain(|t| blocked.contains(&t.magnet_hash()).not()),
+        Err(e) => return Err(ErrorInternalServerError(e)),
+    }
+    if app_config.verify_torrent_health {
+        torrenter.verify_health(&mut torrents).await;
+    }
+
+    let buttons = torrents
+        .iter()
+        .map(create_download_episode_modal_button)
+        .collect::<Vec<String>>();
+
+    let output = match buttons.is_empty() {
+        true => format!("<b>No torrents found for Season {} Episode {}</b>", params.season, params.episode),
+        false => buttons.join(""),
+    };
 
     Ok(HttpResponse::Ok().message_body(output).unwrap())
 }
-fn create_download_modal_options(items: Vec<TorrentItem>) -> String {
+
+fn create_download_modal_options(
+    items: Vec<TorrentItem>,
+    missing_episodes: &[IMDBEpisode],
+    imdb_id: &str,
+    title: &str,
+) -> String {
     let _type = match items.first() {
         Some(t) => t._type.clone(),
         None => ItemType::Movie,
@@ -132,7 +430,7 @@ fn create_download_modal_options(items: Vec<TorrentItem>) -> String {
         ItemType::Movie => {
             let select = items
                 .par_iter()
-                .map(create_download_movie_modal_button)
+                .map(|item| create_download_movie_modal_button(item, None))
                 .collect::<Vec<String>>()
                 .join("");
 
@@ -172,31 +470,41 @@ fn create_download_modal_options(items: Vec<TorrentItem>) -> String {
                 );
 
                 for item in season {
-                    let btn_colour = button_colour_for_quality(&item.quality);
-
-                    let imdb_id = match item.imdb_id.starts_with("tt") {
-                        true => item.imdb_id.clone(),
-                        false => format!("tt{}", item.imdb_id),
-                    };
-                    
-                    let episode = item.episode.as_ref().unwrap();
-                    if *episode == -1 {
-                        let button = format!("\
-                <button class=\"download-button btn btn-{}\" hx-post=\"/start_download\" hx-vals='{{\"queries\":[{{\"imdb_id\": \"{}\", \"season\": {}, \"quality\": \"{}\", \"magnet_uri\": \"{}\"}}]}}' hx-ext='json-enc' hx-swap=\"outerHTML\" hx-disabled-elt=\"closest button\" hx-confirm=\"Start download?\">\
-                    Entire Season {} - {}\
-                </button>", btn_colour, imdb_id, item.season.as_ref().unwrap(), item.quality, urlencoding::encode(&item.magnet_uri), item.season.as_ref().unwrap(), item.quality);
-                        output.push_str(&button);
-                    } else {
-                        let button = format!("\
-                <button class=\"download-button btn btn-{}\" hx-post=\"/start_download\" hx-vals='{{\"queries\":[{{\"imdb_id\": \"{}\", \"season\": {}, \"episode\": {}, \"quality\": \"{}\", \"magnet_uri\": \"{}\"}}]}}' hx-ext='json-enc' hx-swap=\"outerHTML\" hx-disabled-elt=\"closest button\" hx-confirm=\"Start download?\">\
-                    Season: {} Episode: {} - {}\
-                </button>", btn_colour, imdb_id, item.season.as_ref().unwrap(), episode, item.quality, urlencoding::encode(&item.magnet_uri), item.season.as_ref().unwrap(), episode, item.quality);
-
-                        output.push_str(&button);
-                        
-                    }
+                    output.push_str(&create_download_episode_modal_button(item));
                 }
 
+                generate_episode_search_buttons(season_number, missing_episodes, imdb_id, title, &mut output);
+
+                output.push_str("</div>");
+                output.push_str(
+                    "</div>\
+        </div>\
+    </div>",
+                );
+            }
+
+            // Seasons where nothing was found can still be searched an episode at a time
+            let mut empty_seasons = missing_episodes
+                .iter()
+                .map(|e| e.season)
+                .filter(|s| items.iter().any(|i| i.season == Some(*s)).not())
+                .collect::<Vec<i32>>();
+            empty_seasons.sort();
+            empty_seasons.dedup();
+
+            for season_number in empty_seasons {
+                let accordion_item = format!("<div class=\"accordion-item\">\
+        <h3 class=\"accordion-header\">\
+            <button class=\"accordion-button collapsed\" type=\"button\" data-bs-toggle=\"collapse\" data-bs-target=\"#collapseSeason{}\" aria-expanded=\"false\" aria-controls=\"collapseSeason{}\">\
+                Season {} (No results)\
+            </button>\
+        </h3>\
+        <div id=\"collapseSeason{}\" class=\"accordion-collapse collapse\" data-bs-parent=\"#season_accordion\">", season_number, season_number, season_number, season_number);
+                output.push_str(&accordion_item);
+                output.push_str("<div style=\"display: flex; flex-direction: column;\">");
+
+                generate_episode_search_buttons(season_number, missing_episodes, imdb_id, title, &mut output);
+
                 output.push_str("</div>");
                 output.push_str(
                     "</div>\
@@ -212,6 +520,22 @@ fn create_download_modal_options(items: Vec<TorrentItem>) -> String {
     }
 }
 
+fn create_upgrade_modal_options(items: Vec<TorrentItem>, existing: &LibraryFile) -> String {
+    let select = items
+        .par_iter()
+        .map(|item| create_download_movie_modal_button(item, Some(&existing.path)))
+        .collect::<Vec<String>>()
+        .join("");
+
+    format!(
+        "<div id=\"download_selection\" style=\"display: flex; flex-direction: column;\">\
+    <p>In library at {}, the old file is removed once the upgrade has been imported.</p>\
+    {}\
+</div>",
+        existing.quality, select
+    )
+}
+
 fn generate_season_download_buttons(
     items: &[TorrentItem],
     qualities: &[MediaQuality],
@@ -221,40 +545,133 @@ fn generate_season_download_buttons(
         let all_matching_quality = all_torrents_for_quality(items, *quality);
 
         if all_matching_quality.is_empty().not() {
-            let vals = all_matching_quality.par_iter().filter(|x| x.episode.as_ref().unwrap().ge(&0) ).map(|v| {
-                let imdb_id = match v.imdb_id.starts_with("tt") {
-                    true => v.imdb_id.clone(),
-                    false => format!("tt{}", v.imdb_id),
-                };
-                format!("{{\"imdb_id\": \"{}\", \"season\": {}, \"episode\": {}, \"quality\": \"{}\", \"magnet_uri\": \"{}\"}}", imdb_id, v.season.unwrap(), v.episode.unwrap(), v.quality, urlencoding::encode(&v.magnet_uri))
-            }).collect::<Vec<String>>().join(",");
+            let vals = all_matching_quality
+                .par_iter()
+                .filter(|x| x.episode.as_ref().unwrap().ge(&0))
+                .map(|v| TorrentQuery::from_torrent_item(v))
+                .collect::<Vec<TorrentQuery>>();
 
             let download_all_button = format!("\
-                <button class=\"download-button-all btn btn-success btn-lg\" hx-post=\"/start_download\" hx-vals='{{\"queries\":[{}]}}' hx-ext='json-enc'  hx-disabled-elt=\"this\" hx-confirm=\"Start download?\">\
+                <button class=\"download-button-all btn btn-success btn-lg\" hx-post=\"/start_download\" {} hx-ext='json-enc'  hx-disabled-elt=\"this\" hx-confirm=\"Start download?\">\
                     Download All ({})
-                </button>", vals, quality);
+                </button>", create_hx_vals(vals), quality);
             output.push_str(download_all_button.as_str());
         }
     }
 }
 
+fn generate_episode_search_buttons(
+    season_number: i32,
+    missing_episodes: &[IMDBEpisode],
+    imdb_id: &str,
+    title: &str,
+    output: &mut String,
+) {
+    let mut episodes = missing_episodes
+        .iter()
+        .filter(|e| e.season == season_number)
+        .map(|e| e.episode)
+        .collect::<Vec<i32>>();
+    if episodes.is_empty() {
+        return;
+    }
+    episodes.sort();
+
+    output.push_str("<div style=\"display: flex; flex-direction: row; flex-wrap: wrap; margin-top: 0.5rem;\">");
+    for episode in episodes {
+        let button = format!("\
+            <button class=\"btn btn-sm btn-outline-secondary\" style=\"margin: 0.2rem;\" hx-get=\"/find_episode_download?imdb_id={}&title={}&season={}&episode={}\" hx-swap=\"outerHTML\" hx-disabled-elt=\"this\">\
+                Search E{}\
+            </button>", urlencoding::encode(imdb_id), urlencoding::encode(title), season_number, episode, episode);
+        output.push_str(&button);
+    }
+    output.push_str("</div>");
+}
+
 fn all_torrents_for_quality(items: &[TorrentItem], quality: MediaQuality) -> Vec<&TorrentItem> {
     items.par_iter().filter(|i| i.quality == quality).collect()
 }
 
-fn create_download_movie_modal_button(item: &TorrentItem) -> String {
-    let imdb_id = match item.imdb_id.starts_with("tt") {
-        true => item.imdb_id.clone(),
-        false => format!("tt{}", item.imdb_id),
-    };
-    let value = format!(
-        "hx-vals='{{\"queries\":[{{\"imdb_id\": \"{}\", \"quality\": \"{}\", \"magnet_uri\": \"{}\"}}]}}'",
-        imdb_id, item.quality, urlencoding::encode(&item.magnet_uri)
-    );
+fn create_download_movie_modal_button(item: &TorrentItem, replaces: Option<&str>) -> String {
+    let mut query = TorrentQuery::from_torrent_item(item);
+    query.replaces = replaces.map(|r| r.to_owned());
+    let value = create_hx_vals(vec![query]);
+
+    let btn_colour = button_colour_for_item(item);
+
+    format!("<button class=\"download-button btn btn-{}\" hx-post=\"/start_download\" hx-ext='json-enc' hx-confirm=\"Start download?\" hx-swap=\"outerHTML\" hx-target=\"#download_selection\" {}>{}{}</button>", btn_colour, value, item.quality, health_label(item))
+}
+
+fn create_download_episode_modal_button(item: &TorrentItem) -> String {
+    let btn_colour = button_colour_for_item(item);
 
-    let btn_colour = button_colour_for_quality(&item.quality);
+    let mut query = TorrentQuery::from_torrent_item(item);
+    let episode = item.episode.as_ref().unwrap();
+    if *episode == -1 {
+        query.episode = None; // Season packs don't have an episode
+        format!("\
+                <button class=\"download-button btn btn-{}\" hx-post=\"/start_download\" {} hx-ext='json-enc' hx-swap=\"outerHTML\" hx-disabled-elt=\"closest button\" hx-confirm=\"Start download?\">\
+                    Entire Season {} - {}{}\
+                </button>", btn_colour, create_hx_vals(vec![query]), item.season.as_ref().unwrap(), item.quality, health_label(item))
+    } else {
+        format!("\
+                <button class=\"download-button btn btn-{}\" hx-post=\"/start_download\" {} hx-ext='json-enc' hx-swap=\"outerHTML\" hx-disabled-elt=\"closest button\" hx-confirm=\"Start download?\">\
+                    Season: {} Episode: {} - {}{}\
+                </button>", btn_colour, create_hx_vals(vec![query]), item.season.as_ref().unwrap(), episode, item.quality, health_label(item))
+    }
+}
+
+// Serialises the queries as JSON so any quotes in the values can't break out of the attribute
+fn create_hx_vals(queries: Vec<TorrentQuery>) -> String {
+    let vals = serde_json::to_string(&TorrentQueries { queries }).unwrap();
+    format!("hx-vals=\"{}\"", escape_html(&vals))
+}
+
+impl TorrentQuery {
+    fn from_torrent_item(item: &TorrentItem) -> Self {
+        let imdb_id = match item.imdb_id.starts_with("tt") {
+            true => item.imdb_id.clone(),
+            false => format!("tt{}", item.imdb_id),
+        };
+
+        Self {
+            imdb_id,
+            season: item.season,
+            episode: item.episode,
+            quality: item.quality,
+            magnet_uri: urlencoding::encode(&item.magnet_uri).to_string(), // Decoded again in start_download_post
+            replaces: None,
+            name: item.name.clone(),
+            source: item.source.clone(),
+            size: item.size,
+        }
+    }
+
+    pub fn magnet_hash(&self) -> String {
+        self.magnet_uri
+            .split_at(20)
+            .1
+            .split_once('&')
+            .unwrap()
+            .0
+            .to_lowercase()
+    }
+}
+
+// Dead torrents are greyed out but can still be started in case the trackers are wrong
+fn button_colour_for_item(item: &TorrentItem) -> &'static str {
+    match item.verified && item.seeds == Some(0) {
+        true => "outline-secondary",
+        false => button_colour_for_quality(&item.quality),
+    }
+}
 
-    format!("<button class=\"download-button btn btn-{}\" hx-post=\"/start_download\" hx-ext='json-enc' hx-confirm=\"Start download?\" hx-swap=\"outerHTML\" hx-target=\"#download_selection\"{}>{}</button>", btn_colour, value, item.quality)
+fn health_label(item: &TorrentItem) -> String {
+    match (item.verified, item.seeds) {
+        (true, Some(0)) => " (Dead)".to_string(),
+        (true, Some(t)) => format!(" ({} seeds)", t),
+        _ => String::new(),
+    }
 }
 
 fn button_colour_for_quality(quality: &MediaQuality) -> &'static str {
@@ -271,9 +688,92 @@ fn button_colour_for_quality(quality: &MediaQuality) -> &'static str {
     }
 }
 
+// id is an IMDb ID or a TMDB ID depending on which metadata source is in use
+fn media_lookup(app_config: &AppConfig, id: &str, title: &str) -> MediaLookup {
+    match app_config.tmdb_api_key.is_empty() {
+        true => {
+            let imdb_id = match id.starts_with("tt") {
+                true => id.to_owned(),
+                false => format!("tt{}", id),
+            };
+            MediaLookup::new(title, Some(imdb_id), None)
+        }
+        false => MediaLookup::new(title, None, Some(id.to_owned())),
+    }
+}
+
+// Lookups are keyed by IMDb ID or TMDB ID depending on which metadata source is in use
+fn alias_key(lookup: &MediaLookup) -> &str {
+    match (&lookup.imdb_id, &lookup.tmdb_id) {
+        (Some(id), _) => id,
+        (None, Some(id)) => id,
+        (None, None) => "",
+    }
+}
+
+// Lookup with any aliases already in the database, without asking the metadata source
+pub async fn stored_media_lookup(
+    app_config: &AppConfig,
+    db: &DBConnection,
+    id: &str,
+    title: &str,
+) -> anyhow::Result<MediaLookup> {
+    let lookup = media_lookup(app_config, id, title);
+    let aliases = AliasDatabase::new(db).fetch(alias_key(&lookup)).await?;
+    Ok(lookup.with_aliases(aliases))
+}
+
+// Stored aliases for the item, the metadata source is only asked the first time
+async fn fetch_aliases(
+    app_config: &AppConfig,
+    db: &DBConnection,
+    lookup: &MediaLookup,
+    _type: &ItemType,
+) -> Vec<String> {
+    let alias_db = AliasDatabase::new(db);
+    let id = alias_key(lookup);
+
+    let aliases = match alias_db.fetch(id).await {
+        Ok(t) => t,
+        Err(e) => {
+            error!("Failed to fetch aliases for {}: {}", id, e);
+            return Vec::new();
+        }
+    };
+    if aliases.is_empty().not() {
+        return aliases;
+    }
+
+    let aliases = match app_config.tmdb_api_key.is_empty() {
+        true => IMDB::fetch_alternate_titles(id, None, None).await,
+        false => match id.parse::<i32>() {
+            Ok(tmdb_id) => {
+                MovieDB::new(&app_config.tmdb_api_key)
+                    .fetch_alternative_titles(tmdb_id, _type)
+                    .await
+            }
+            Err(e) => Err(e.into()),
+        },
+    };
+    let aliases = match aliases {
+        Ok(t) => t,
+        Err(e) => {
+            error!("Failed to fetch alternate titles for {}: {}", id, e);
+            return Vec::new();
+        }
+    };
+
+    if let Err(e) = alias_db.insert_many(id, &aliases, "metadata").await {
+        error!("Failed to store aliases for {}: {}", id, e);
+    }
+
+    aliases
+}
+
 pub async fn find_missing_tv_shows(
-    plex: Arc<Plex>,
+    media_server: Arc<dyn MediaServer>,
     app_config: Data<AppConfig>,
+    db: &DBConnection,
     imdb_id: &str,
     title: &str,
 ) -> anyhow::Result<Option<Vec<IMDBEpisode>>> {
@@ -296,6 +796,7 @@ pub async fn find_missing_tv_shows(
                         id: "".to_string(),
                         season: x.season,
                         episode: x.episode,
+                        air_date: x.air_date,
                     })
                     .collect(),
                 Err(e) => return Err(e),
@@ -303,7 +804,15 @@ pub async fn find_missing_tv_shows(
         }
     };
 
-    let existing_episodes = match plex.tvshow_exists_in_library(title).await {
+    // Keep track of every episode, but only treat the aired ones as missing
+    let episode_db = EpisodeDatabase::new(db);
+    if let Err(e) = episode_db.insert_or_update_many(imdb_id, &all_episodes).await {
+        error!("Failed to store episodes for {}: {}", imdb_id, e);
+    }
+    all_episodes.retain(|e| e.has_aired());
+
+    let lookup = stored_media_lookup(&app_config, db, imdb_id, title).await?;
+    let existing_episodes = match media_server.list_episodes(&lookup).await {
         Ok(t) => t,
         Err(e) => return Err(e),
     };
@@ -326,11 +835,15 @@ pub async fn find_missing_tv_shows(
 pub async fn start_download(
     params: Query<TorrentQuery>,
     torrenter: Data<Torrenter>,
+    app_config: Data<AppConfig>,
 ) -> Result<HttpResponse, Error> {
+    if app_config.read_only {
+        return Err(ErrorForbidden("Read-only mode"));
+    }
     for magnet in params.magnet_uri.split(',') {
         let torrent_item = TorrentItem::new(
             params.imdb_id.clone(),
-            "".to_string(),
+            params.name.clone(),
             magnet.to_string(),
             params.quality,
             match params.season {
@@ -351,7 +864,7 @@ pub async fn start_download(
     Ok(HttpResponse::Ok().body("<b>Download Started!<b>"))
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Serialize, Debug)]
 // #[serde(transparent)]
 struct TorrentQueries {
     queries: Vec<TorrentQuery>,
@@ -361,14 +874,148 @@ struct TorrentQueries {
 pub async fn start_download_post(
     params: Json<TorrentQueries>,
     torrenter: Data<Torrenter>,
+    media_server: Data<dyn MediaServer>,
     db: Data<DBConnection>,
+    app_config: Data<AppConfig>,
 ) -> Result<HttpResponse, Error> {
+    if app_config.read_only {
+        return Err(ErrorForbidden("Read-only mode"));
+    }
     let mut params = params;
-    for data in params.queries.as_slice() {
+    params.queries.par_iter_mut().for_each(|torrent| {
+        let uri = torrent.magnet_uri.clone();
+        let magnet = urlencoding::decode(&uri).unwrap();
+        torrent.magnet_uri.clear();
+        torrent.magnet_uri.push_str(&magnet);
+    });
+    if let Err(e) = resolve_replaced_files(&mut params.queries, media_server.get_ref(), &db, &app_config).await {
+        return Err(ErrorInternalServerError(e));
+    }
+
+    match start_queries(&params.queries, &torrenter, &db).await {
+        Ok(_) => (),
+        Err(e) => return Err(ErrorInternalServerError(e)),
+    };
+
+    Ok(HttpResponse::Ok().body("<b>Download Started!<b>"))
+}
+
+#[derive(Deserialize)]
+pub struct MagnetSubmission {
+    // IMDb ID or TheMovieDB ID, with or without the tt prefix
+    imdb_id: String,
+    magnet_uri: String,
+    // Left empty for movies
+    #[serde(default)]
+    season: String,
+    #[serde(default)]
+    episode: String,
+}
+
+// For releases the indexers missed
+#[post("/downloads/magnet")]
+pub async fn submit_magnet(
+    form: Form<MagnetSubmission>,
+    torrenter: Data<Torrenter>,
+    db: Data<DBConnection>,
+    app_config: Data<AppConfig>,
+) -> Result<HttpResponse<String>, Error> {
+    if app_config.read_only {
+        return Err(ErrorForbidden("Read-only mode"));
+    }
+
+    let (magnet_uri, name) = match normalise_magnet(&form.magnet_uri) {
+        Ok(t) => t,
+        Err(e) => return Err(ErrorBadRequest(e)),
+    };
+    let season = parse_optional_number(&form.season)?;
+    let episode = parse_optional_number(&form.episode)?;
+    if episode.is_some() && season.is_none() {
+        return Err(ErrorBadRequest("Episode given without a season"));
+    }
+
+    // The downloads page shows the cached item's details, so it has to have been searched for
+    let id = form.imdb_id.trim().trim_start_matches("tt");
+    let known = match app_config.tmdb_api_key.is_empty() {
+        true => get_cached_item_imdb(&format!("tt{}", id), Data::clone(&db)).await.is_ok(),
+        false => id.parse::<i32>().is_ok() && get_cached_item_moviedb(id, Data::clone(&db)).await.is_ok(),
+    };
+    if known.not() {
+        return Err(ErrorNotFound("Item not found, search for it first"));
+    }
+
+    let query = TorrentQuery {
+        imdb_id: format!("tt{}", id),
+        season,
+        episode,
+        quality: MediaQuality::from_release_name(&name),
+        magnet_uri,
+        replaces: None,
+        name,
+        source: String::from("manual"),
+        size: None,
+    };
+    match start_queries(&[query], &torrenter, &db).await {
+        Ok(_) => (),
+        Err(e) => return Err(ErrorInternalServerError(e)),
+    };
+
+    Ok(HttpResponse::Ok()
+        .message_body("<b>Download Started!</b>".to_string())
+        .unwrap())
+}
+
+fn parse_optional_number(input: &str) -> Result<Option<i32>, Error> {
+    match input.trim().is_empty() {
+        true => Ok(None),
+        false => match input.trim().parse::<i32>() {
+            Ok(t) if t >= 0 => Ok(Some(t)),
+            _ => Err(ErrorBadRequest(format!("Invalid number: {}", input))),
+        },
+    }
+}
+
+// The browser only says that a download is an upgrade, the file it replaces is looked up in the media server again so
+// a request can't pick which file gets deleted
+async fn resolve_replaced_files(
+    queries: &mut [TorrentQuery],
+    media_server: &dyn MediaServer,
+    db: &Data<DBConnection>,
+    app_config: &AppConfig,
+) -> anyhow::Result<()> {
+    for query in queries.iter_mut() {
+        if query.replaces.take().is_none() || query.season.is_some() {
+            continue;
+        }
+
+        let id = query.imdb_id.trim_start_matches("tt");
+        let title = match app_config.tmdb_api_key.is_empty() {
+            true => {
+                let item = get_cached_item_imdb(&query.imdb_id, Data::clone(db)).await?;
+                format!("{} ({})", item.title, item.year)
+            }
+            false => {
+                if id.parse::<i32>().is_err() {
+                    continue;
+                }
+                let item = get_cached_item_moviedb(id, Data::clone(db)).await?;
+                format!("{} ({})", item.title, item.release_date.year())
+            }
+        };
+        let lookup = stored_media_lookup(app_config, db, id, &title).await?;
+        query.replaces = media_server.library_file(&lookup).await?.map(|f| f.path);
+    }
+
+    Ok(())
+}
+
+// Starts the downloads and records them, magnet URIs must already be decoded
+pub async fn start_queries(queries: &[TorrentQuery], torrenter: &Torrenter, db: &DBConnection) -> anyhow::Result<()> {
+    for data in queries {
         let torrent_item = TorrentItem::new(
             data.imdb_id.clone(),
-            "".to_string(),
-            urlencoding::decode(&data.magnet_uri).unwrap().to_string(),
+            data.name.clone(),
+            data.magnet_uri.clone(),
             MediaQuality::Unknown,
             ItemType::Movie,
             None,
@@ -376,28 +1023,107 @@ pub async fn start_download_post(
             None,
         );
 
-        match torrenter.start_download(torrent_item).await {
-            Ok(_) => (),
-            Err(e) => return Err(ErrorInternalServerError(e)),
-        };
+        torrenter.start_download(torrent_item).await?;
     }
 
-    params.queries.par_iter_mut().for_each(|torrent| {
-        let uri = torrent.magnet_uri.clone();
-        let magnet = urlencoding::decode(&uri).unwrap();
-        torrent.magnet_uri.clear();
-        torrent.magnet_uri.push_str(&magnet);
-    });
+    DownloadDatabase::new(db).insert_many(queries).await?;
 
-    match DownloadDatabase::new(&db)
-        .insert_many(params.queries.as_slice())
-        .await
-    {
+    let history_db = HistoryDatabase::new(db);
+    let attempt_db = AttemptDatabase::new(db);
+    for torrent in queries.iter() {
+        if let Err(e) = history_db.insert_grab(torrent).await {
+            error!("Failed to record download history: {}", e);
+        }
+        if let Err(e) = attempt_db.insert(torrent).await {
+            error!("Failed to record download attempt: {}", e);
+        }
+    }
+
+    let upgrade_db = UpgradeDatabase::new(db);
+    for torrent in queries.iter() {
+        if let Some(replaces) = &torrent.replaces {
+            upgrade_db.insert(&torrent.magnet_hash(), replaces).await?;
+        }
+    }
+
+    Ok(())
+}
+
+#[derive(Deserialize)]
+struct DismissDownloadQuery {
+    id: i32,
+}
+
+// Clears a download that failed to import from the downloads list
+#[get("/downloads/dismiss")]
+pub async fn dismiss_download(
+    query: Query<DismissDownloadQuery>,
+    db: Data<DBConnection>,
+    app_config: Data<AppConfig>,
+) -> Result<HttpResponse<String>, Error> {
+    if app_config.read_only {
+        return Err(ErrorForbidden("Read-only mode"));
+    }
+
+    match DownloadDatabase::new(&db).remove(query.id).await {
         Ok(_) => (),
         Err(e) => return Err(ErrorInternalServerError(e)),
+    };
+
+    Ok(HttpResponse::Ok().message_body(String::new()).unwrap())
+}
+
+#[derive(Deserialize)]
+struct RemoveDownloadQuery {
+    delete_files: Option<bool>,
+}
+
+// Cancels a download from roundup, optionally deleting what has been downloaded so far
+#[delete("/downloads/{hash}")]
+pub async fn remove_download(
+    hash: Path<String>,
+    query: Query<RemoveDownloadQuery>,
+    torrenter: Data<Torrenter>,
+    progress: Data<ProgressFeed>,
+    db: Data<DBConnection>,
+    app_config: Data<AppConfig>,
+) -> Result<HttpResponse<String>, Error> {
+    if app_config.read_only {
+        return Err(ErrorForbidden("Read-only mode"));
     }
+    match cancel_download(&hash, query.delete_files.unwrap_or(false), &torrenter, &progress, &db).await {
+        Ok(_) => (),
+        Err(e) => return Err(ErrorInternalServerError(e)),
+    };
 
-    Ok(HttpResponse::Ok().body("<b>Download Started!<b>"))
+    Ok(HttpResponse::Ok().message_body(String::new()).unwrap())
+}
+
+pub async fn cancel_download(
+    hash: &str,
+    delete_files: bool,
+    torrenter: &Torrenter,
+    progress: &ProgressFeed,
+    db: &DBConnection,
+) -> anyhow::Result<()> {
+    let hash = hash.to_lowercase();
+
+    torrenter.remove_download(&hash, delete_files).await?;
+    DownloadDatabase::new(db).remove_by_hash(&hash).await?;
+    progress.forget(&hash);
+
+    let detail = match delete_files {
+        true => "Removed with data from roundup",
+        false => "Removed from roundup",
+    };
+    if let Err(e) = HistoryDatabase::new(db).insert_event(&hash, HistoryEvent::Deleted, Some(detail)).await {
+        error!("Failed to record download history: {}", e);
+    }
+    if let Err(e) = AttemptDatabase::new(db).set_outcome(&hash, AttemptOutcome::Cancelled, Some(detail)).await {
+        error!("Failed to record download attempt: {}", e);
+    }
+
+    Ok(())
 }
 
 #[derive(Deserialize)]
@@ -410,38 +1136,79 @@ struct UpdateWatchlistQuery {
 pub async fn update_watchlist(
     query: Query<UpdateWatchlistQuery>,
     db: web::Data<DBConnection>,
+    notifier: Data<Notifier>,
     app_config: Data<AppConfig>,
 ) -> Result<HttpResponse<String>, Error> {
-    let button = match app_config.tmdb_api_key.is_empty() {
-        true => {
-            let imdb_db = IMDBDatabase::new(db.deref());
+    if app_config.read_only {
+        return Err(ErrorForbidden("Read-only mode"));
+    }
 
-            match imdb_db
-                .update_watchlist_item(&query.imdb_id, query.state)
-                .await
-            {
-                Ok(_) => (),
-                Err(e) => return Err(ErrorInternalServerError(e)),
-            };
+    match set_watchlist_state(&app_config, &db, &notifier, &query.imdb_id, query.state).await {
+        Ok(_) => (),
+        Err(e) => return Err(ErrorInternalServerError(e)),
+    };
+    let button = create_watchlist_button(&query.imdb_id, query.state);
 
-            create_watchlist_button(&query.imdb_id, query.state)
-        }
-        false => {
-            let movie_db = MovieDBDatabase::new(db.deref());
+    Ok(HttpResponse::Ok().message_body(button).unwrap())
+}
 
-            match movie_db
-                .update_watchlist_item(query.imdb_id.parse::<i32>().unwrap(), query.state)
-                .await
-            {
-                Ok(_) => (),
-                Err(e) => return Err(ErrorInternalServerError(e)),
-            };
+#[derive(Deserialize)]
+pub struct RecheckWatchlistQuery {
+    pub imdb_id: Option<String>,
+}
+
+// Starts a watchlist check now, for a single item when imdb_id is given
+#[post("/watchlist/recheck")]
+pub async fn recheck_watchlist(
+    query: Query<RecheckWatchlistQuery>,
+    recheck: Data<WatchlistRecheck>,
+    app_config: Data<AppConfig>,
+) -> Result<HttpResponse<String>, Error> {
+    if app_config.read_only {
+        return Err(ErrorForbidden("Read-only mode"));
+    }
 
-            create_watchlist_button(&query.imdb_id, query.state)
+    recheck.trigger(query.into_inner().imdb_id.filter(|t| t.is_empty().not()));
+
+    Ok(HttpResponse::Ok()
+        .message_body("<button type=\"button\" class=\"btn btn-outline-secondary\" disabled>Checking...</button>".to_string())
+        .unwrap())
+}
+
+// id is an IMDb ID or a TMDB ID depending on which metadata source is in use
+pub async fn set_watchlist_state(
+    app_config: &AppConfig,
+    db: &DBConnection,
+    notifier: &Notifier,
+    id: &str,
+    state: bool,
+) -> anyhow::Result<()> {
+    let notify_id = match app_config.tmdb_api_key.is_empty() {
+        true => {
+            IMDBDatabase::new(db).update_watchlist_item(id, state).await?;
+            id.to_string()
+        }
+        false => {
+            let id = id.parse::<i32>().map_err(|_| format_err!("Invalid TheMovieDB id: {}", id))?;
+            MovieDBDatabase::new(db).update_watchlist_item(id, state).await?;
+            format!("tt{}", id)
         }
     };
 
-    Ok(HttpResponse::Ok().message_body(button).unwrap())
+    let state_db = WatchlistStateDatabase::new(db);
+    let result = match state {
+        true => state_db.added(&notify_id).await,
+        false => state_db.remove(&notify_id).await,
+    };
+    if let Err(e) = result {
+        error!("Failed to update watchlist state: {}", e);
+    }
+
+    if state {
+        notifier.notify(DownloadEvent::WatchlistAdded, Some(&notify_id), None, None).await;
+    }
+
+    Ok(())
 }
 
 pub fn create_watchlist_button(imdb_id: &str, state: bool) -> String {
@@ -451,7 +1218,12 @@ pub fn create_watchlist_button(imdb_id: &str, state: bool) -> String {
     } else {
         button.push_str("Add to watchlist");
     }
-    button.push_str("</button></div>");
+    button.push_str("</button>");
+    if state {
+        let recheck = format!(" <button type=\"button\" class=\"btn btn-outline-secondary\" hx-post=\"/watchlist/recheck?imdb_id={}\" hx-swap=\"outerHTML\">Check now</button>", imdb_id);
+        button.push_str(&recheck);
+    }
+    button.push_str("</div>");
 
     button
 }
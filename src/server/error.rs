@@ -0,0 +1,136 @@
+use std::future::{ready, Ready};
+use std::ops::Not;
+use std::rc::Rc;
+
+use actix_web::{Error, HttpRequest, HttpResponse};
+use actix_web::body::EitherBody;
+use actix_web::dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform};
+use actix_web::http::{header, StatusCode};
+use futures::future::LocalBoxFuture;
+use log::error;
+use serde::Serialize;
+
+use crate::server::escape_html;
+
+// Set on error fragments so the page knows to swap them in, HTMX ignores error responses by default
+pub static ERROR_HEADER: &str = "X-Roundup-Error";
+
+#[derive(Serialize)]
+struct ErrorBody<'a> {
+    error: &'a str,
+}
+
+// Handlers return actix errors wrapping whatever failed (sqlx, reqwest, ...), this turns them into a
+// message that's safe to show. Server errors are logged in full and replaced with a generic message,
+// client errors keep theirs. The API gets JSON, everything else an alert the page can swap in.
+#[derive(Clone)]
+pub struct ErrorPages {
+    base_path: String,
+}
+
+impl ErrorPages {
+    pub fn new(base_path: &str) -> Self {
+        ErrorPages { base_path: base_path.to_string() }
+    }
+
+    fn render(&self, req: &HttpRequest, status: StatusCode, err: &Error) -> HttpResponse {
+        let message = match status.is_server_error() {
+            true => {
+                error!("{} {} failed: {}", req.method(), req.path(), err);
+                "Something went wrong, check the roundup logs for details".to_string()
+            }
+            false => err.to_string(),
+        };
+
+        let mut response = HttpResponse::build(status);
+        match self.wants_json(req) {
+            true => {
+                let json = serde_json::to_string(&ErrorBody { error: &message }).unwrap_or_default();
+                response.content_type("application/json").body(json)
+            }
+            false => response
+                .content_type("text/html; charset=utf-8")
+                .insert_header((ERROR_HEADER, "1"))
+                .body(format!(
+                    "<div class=\"alert alert-danger\" role=\"alert\">{}</div>",
+                    escape_html(&message)
+                )),
+        }
+    }
+
+    fn wants_json(&self, req: &HttpRequest) -> bool {
+        let path = req.path().strip_prefix(self.base_path.as_str()).unwrap_or(req.path());
+        if path.starts_with("/api/") {
+            return true;
+        }
+
+        req.headers().contains_key("HX-Request").not()
+            && req
+                .headers()
+                .get(header::ACCEPT)
+                .and_then(|h| h.to_str().ok())
+                .is_some_and(|h| h.contains("application/json"))
+    }
+}
+
+impl<S, B> Transform<S, ServiceRequest> for ErrorPages
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Transform = ErrorPagesMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(ErrorPagesMiddleware { service: Rc::new(service), pages: self.clone() }))
+    }
+}
+
+pub struct ErrorPagesMiddleware<S> {
+    service: Rc<S>,
+    pages: ErrorPages,
+}
+
+impl<S, B> Service<ServiceRequest> for ErrorPagesMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let service = Rc::clone(&self.service);
+        let pages = self.pages.clone();
+        // Kept for errors returned from inner middleware, which don't hand the request back
+        let http_req = req.request().clone();
+
+        Box::pin(async move {
+            match service.call(req).await {
+                Ok(res) => {
+                    // Only responses built from an error are replaced, handlers that set their own body keep it
+                    let status = res.status();
+                    let response = match res.response().error() {
+                        Some(err) if status.is_client_error() || status.is_server_error() => {
+                            pages.render(res.request(), status, err)
+                        }
+                        _ => return Ok(res.map_into_left_body()),
+                    };
+                    let (req, _) = res.into_parts();
+                    Ok(ServiceResponse::new(req, response).map_into_right_body())
+                }
+                Err(err) => {
+                    let status = err.as_response_error().status_code();
+                    let response = pages.render(&http_req, status, &err);
+                    Ok(ServiceResponse::new(http_req, response).map_into_right_body())
+                }
+            }
+        })
+    }
+}
\ No newline at end of file
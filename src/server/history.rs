@@ -0,0 +1,176 @@
+use actix_web::{Error, get, HttpRequest, HttpResponse};
+use actix_web::error::{ErrorBadRequest, ErrorInternalServerError};
+use actix_web::web::{Data, Query};
+use serde::Deserialize;
+
+use crate::db::DBConnection;
+use crate::db::history::{HISTORY_PAGE_SIZE, HistoryDatabase, HistoryItem};
+use crate::server::{escape_html, format_size};
+
+const HISTORY_FEED_SIZE: i64 = 50;
+
+#[derive(Deserialize)]
+pub struct HistoryQuery {
+    format: Option<String>,
+    page: Option<i64>,
+}
+
+#[get("/history")]
+pub async fn download_history(
+    params: Query<HistoryQuery>,
+    db: Data<DBConnection>,
+) -> Result<HttpResponse<String>, Error> {
+    let page = params.page.unwrap_or(0).max(0);
+    if page.checked_mul(HISTORY_PAGE_SIZE).is_none() {
+        return Err(ErrorBadRequest("Invalid page"));
+    }
+    let items = match HistoryDatabase::new(&db).fetch(page).await {
+        Ok(t) => t,
+        Err(e) => return Err(ErrorInternalServerError(e)),
+    };
+
+    match params.format.as_deref() {
+        Some("html") => {
+            // Later pages are appended in place of the load more trigger, so only send the rows
+            let html = match page {
+                0 => create_history_table(&items),
+                _ => create_history_rows(&items, page),
+            };
+            Ok(HttpResponse::Ok().message_body(html).unwrap())
+        }
+        _ => {
+            let json = match serde_json::to_string(&items) {
+                Ok(t) => t,
+                Err(e) => return Err(ErrorInternalServerError(e)),
+            };
+            Ok(HttpResponse::Ok()
+                .content_type("application/json")
+                .message_body(json)
+                .unwrap())
+        }
+    }
+}
+
+// RSS 2.0 feed of grabs and completions for feed readers
+#[get("/history/feed.xml")]
+pub async fn history_feed(req: HttpRequest, db: Data<DBConnection>) -> Result<HttpResponse<String>, Error> {
+    let items = match HistoryDatabase::new(&db).fetch_activity(HISTORY_FEED_SIZE).await {
+        Ok(t) => t,
+        Err(e) => return Err(ErrorInternalServerError(e)),
+    };
+
+    let info = req.connection_info();
+    let link = format!("{}://{}/", info.scheme(), info.host());
+
+    let entries = items
+        .iter()
+        .map(|item| {
+            let event = match item.event.as_str() {
+                "grabbed" => "Grabbed",
+                "completed" => "Completed",
+                "available" => "Available",
+                t => t,
+            };
+            let mut description = item.release_name.as_ref().unwrap_or(&item.magnet_hash).to_owned();
+            if let Some(source) = &item.source {
+                description.push_str(&format!(" from {}", source));
+            }
+            if let Some(size) = item.size {
+                description.push_str(&format!(" ({})", format_size(size)));
+            }
+
+            format!(
+                "<item><title>{}: {}</title><link>{}</link><description>{}</description><guid isPermaLink=\"false\">roundup-history-{}</guid><pubDate>{}</pubDate></item>",
+                event,
+                escape_html(&history_title(item)),
+                escape_html(&link),
+                escape_html(&description),
+                item.id,
+                item.created_at.to_rfc2822(),
+            )
+        })
+        .collect::<Vec<String>>()
+        .join("");
+
+    let feed = format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?><rss version=\"2.0\"><channel><title>Roundup</title><link>{}</link><description>Grabbed and completed downloads</description>{}</channel></rss>",
+        escape_html(&link),
+        entries
+    );
+
+    Ok(HttpResponse::Ok()
+        .content_type("application/rss+xml; charset=utf-8")
+        .message_body(feed)
+        .unwrap())
+}
+
+fn create_history_table(items: &[HistoryItem]) -> String {
+    match items.is_empty() {
+        true => "<b>No download history yet</b>".to_string(),
+        false => format!("<div class=\"table-responsive\" style=\"width: 100%; padding: 0.5rem;\">\
+    <table class=\"table table-sm\">\
+        <thead>\
+            <tr><th>Date</th><th>Title</th><th>Event</th><th>Release</th><th>Indexer</th><th>Details</th></tr>\
+        </thead>\
+        <tbody>{}</tbody>\
+    </table>\
+</div>", create_history_rows(items, 0)),
+    }
+}
+
+fn create_history_rows(items: &[HistoryItem], page: i64) -> String {
+    let mut output = items
+        .iter()
+        .map(|item| {
+            format!(
+                "<tr><td>{}</td><td>{}</td><td><span class=\"badge text-bg-{}\">{}</span></td><td><small>{}</small></td><td>{}</td><td><small>{}</small></td></tr>",
+                item.created_at.format("%Y-%m-%d %H:%M"),
+                escape_html(&history_title(item)),
+                badge_colour_for_event(&item.event),
+                escape_html(&item.event),
+                escape_html(item.release_name.as_ref().unwrap_or(&item.magnet_hash)),
+                release_details(item),
+                escape_html(item.detail.as_deref().unwrap_or_default()),
+            )
+        })
+        .collect::<Vec<String>>()
+        .join("");
+
+    // Fetch the next page when the end of the table scrolls into view
+    if items.len() as i64 == HISTORY_PAGE_SIZE {
+        output.push_str(&format!(
+            "<tr hx-get=\"/history?format=html&page={}\" hx-trigger=\"revealed\" hx-swap=\"outerHTML\"><td colspan=\"6\"><div class=\"spinner-border spinner-border-sm\"></div></td></tr>",
+            page + 1
+        ));
+    }
+
+    output
+}
+
+fn history_title(item: &HistoryItem) -> String {
+    let mut title = item.title.as_ref().unwrap_or(&item.imdb_id).to_owned();
+    match (item.season, item.episode) {
+        (Some(s), Some(e)) => title.push_str(&format!(" S{:02}E{:02}", s, e)),
+        (Some(s), None) => title.push_str(&format!(" Season {}", s)),
+        _ => (),
+    }
+    title
+}
+
+fn release_details(item: &HistoryItem) -> String {
+    let source = escape_html(item.source.as_deref().unwrap_or_default());
+    match item.size {
+        Some(t) => format!("{} <small class=\"text-body-secondary\">{}</small>", source, format_size(t)),
+        None => source,
+    }
+}
+
+fn badge_colour_for_event(event: &str) -> &'static str {
+    match event {
+        "grabbed" => "primary",
+        "completed" => "success",
+        "available" => "info",
+        "failed" => "danger",
+        _ => "secondary",
+    }
+}
\ No newline at end of file
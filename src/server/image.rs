@@ -0,0 +1,84 @@
+use std::ops::Not;
+
+use actix_web::{Error, get, HttpResponse, web};
+use actix_web::error::ErrorInternalServerError;
+use actix_web::http::header;
+use actix_web::web::Data;
+use log::warn;
+
+use crate::AppConfig;
+use crate::api::posters::{imdb_poster_url, PosterCache, tmdb_poster_url};
+use crate::db::DBConnection;
+use crate::db::imdb::IMDBDatabase;
+use crate::db::moviedb::MovieDBDatabase;
+use crate::server::assets;
+
+// Posters don't change for an id, so browsers can keep them for a month
+static POSTER_CACHE_CONTROL: &str = "public, max-age=2592000, immutable";
+// Short enough that a poster missing now is picked up once it's available
+static PLACEHOLDER_CACHE_CONTROL: &str = "public, max-age=3600";
+
+// Serves an item's poster through roundup instead of hot-linking IMDb/TMDB, anything missing or
+// broken gets the placeholder image
+#[get("/image/{id}")]
+pub async fn poster(
+    path: web::Path<String>,
+    db: Data<DBConnection>,
+    posters: Data<PosterCache>,
+    app_config: Data<AppConfig>,
+) -> Result<HttpResponse, Error> {
+    let id = path.into_inner();
+    let url = match poster_url(&id, &db, &app_config).await {
+        Ok(t) => t,
+        Err(e) => return Err(ErrorInternalServerError(e)),
+    };
+
+    let url = match url {
+        Some(t) => t,
+        None => return placeholder(&app_config),
+    };
+
+    match posters.fetch(&id, &url).await {
+        Ok((bytes, content_type)) => Ok(HttpResponse::Ok()
+            .content_type(content_type)
+            .insert_header((header::CACHE_CONTROL, POSTER_CACHE_CONTROL))
+            .body(bytes)),
+        Err(e) => {
+            warn!("{}", e);
+            placeholder(&app_config)
+        }
+    }
+}
+
+// In TheMovieDB mode the id is the TMDB id, optionally with a tt prefix as stored for downloads, or an IMDb id
+async fn poster_url(id: &str, db: &DBConnection, app_config: &AppConfig) -> anyhow::Result<Option<String>> {
+    match app_config.tmdb_api_key.is_empty() {
+        true => {
+            let item = IMDBDatabase::new(db).fetch_item_by_id(id).await?.into_iter().next();
+            Ok(item
+                .map(|i| i.image_url)
+                .filter(|u| u.is_empty().not() && u.contains("No_image_available").not())
+                .map(|u| imdb_poster_url(&u)))
+        }
+        false => {
+            let movie_db = MovieDBDatabase::new(db);
+            let mut items = match id.trim_start_matches("tt").parse::<i32>() {
+                Ok(t) => movie_db.fetch_item_by_id(t).await?,
+                Err(_) => Vec::new(),
+            };
+            if items.is_empty() {
+                items = movie_db.fetch_item_by_imdb_id(id).await?;
+            }
+            Ok(items.into_iter().next().and_then(|i| i.image_url).map(|p| tmdb_poster_url(&p)))
+        }
+    }
+}
+
+fn placeholder(app_config: &AppConfig) -> Result<HttpResponse, Error> {
+    let svg = assets::read_asset_text(app_config, "placeholder.svg")?;
+
+    Ok(HttpResponse::Ok()
+        .content_type("image/svg+xml")
+        .insert_header((header::CACHE_CONTROL, PLACEHOLDER_CACHE_CONTROL))
+        .body(svg))
+}
\ No newline at end of file
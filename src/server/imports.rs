@@ -0,0 +1,254 @@
+use std::ops::{Deref, Not};
+use std::path::{Path, PathBuf};
+
+use actix_web::{Error, HttpResponse, post};
+use actix_web::error::{ErrorBadRequest, ErrorForbidden, ErrorInternalServerError};
+use actix_web::web::{Data, Json};
+use chrono::Datelike;
+use log::{error, info, warn};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+use anyhow::format_err;
+
+use crate::AppConfig;
+use crate::api::imdb::ItemType;
+use crate::api::importer::{ImportMode, Importer};
+use crate::api::jobs::{Job, JobQueue};
+use crate::api::media_server::MediaServer;
+use crate::api::notify::{DownloadEvent, Notification, Notifier};
+use crate::api::validation;
+use crate::db::DBConnection;
+use crate::db::history::HistoryDatabase;
+use crate::db::imdb::IMDBDatabase;
+use crate::db::moviedb::MovieDBDatabase;
+
+#[derive(Deserialize, Serialize, Debug)]
+pub struct ManualImportRequest {
+    path: String,
+    imdb_id: String,
+}
+
+struct ImportItem {
+    title: String,
+    year: i64,
+    _type: ItemType,
+}
+
+// Imports files downloaded outside of roundup, renamed the way the library expects. Copying can take a while
+// so it's done by the job worker
+#[post("/import")]
+pub async fn manual_import(
+    params: Json<ManualImportRequest>,
+    jobs: Data<JobQueue>,
+    db: Data<DBConnection>,
+    app_config: Data<AppConfig>,
+) -> Result<HttpResponse<String>, Error> {
+    if app_config.read_only {
+        return Err(ErrorForbidden("Read-only mode"));
+    }
+    if app_config.import_path.is_empty() {
+        return Err(ErrorBadRequest("import_path isn't set"));
+    }
+    // TheMovieDB ids are numbers, with or without the tt prefix used everywhere else
+    if app_config.tmdb_api_key.is_empty().not() && params.imdb_id.trim_start_matches("tt").parse::<i32>().is_err() {
+        return Err(ErrorBadRequest("Invalid TheMovieDB id"));
+    }
+
+    match find_item(&app_config, db.deref(), &params.imdb_id).await {
+        Ok(Some(_)) => (),
+        Ok(None) => return Err(ErrorBadRequest("Unknown item, search for it first")),
+        Err(e) => return Err(ErrorInternalServerError(e)),
+    };
+    // Missing paths get the same error, so this can't be used to find out what's on the server
+    let path = match import_source(&app_config, &params.path) {
+        Some(t) => t,
+        None => return Err(ErrorBadRequest("Path has to be inside download_path or library_paths")),
+    };
+
+    let mut params = params.into_inner();
+    params.path = path.to_string_lossy().to_string();
+    let id = match jobs.enqueue(&db, &Job::Import(params)).await {
+        Ok(t) => t,
+        Err(e) => return Err(ErrorInternalServerError(e)),
+    };
+
+    Ok(HttpResponse::Accepted()
+        .message_body(format!("<b>Import queued (job {})</b>, imported files are added to the download history", id))
+        .unwrap())
+}
+
+// Run by the job worker. Fails when nothing could be imported so the job is tried again
+pub async fn import_files(
+    params: &ManualImportRequest,
+    media_server: &dyn MediaServer,
+    notifier: &Notifier,
+    db: &DBConnection,
+    app_config: &AppConfig,
+) -> anyhow::Result<()> {
+    let item = match find_item(app_config, db, &params.imdb_id).await? {
+        Some(t) => t,
+        None => return Err(format_err!("Unknown item {}", params.imdb_id)),
+    };
+
+    // Checked again as queued jobs outlive config changes
+    let root = match import_source(app_config, &params.path) {
+        Some(t) => t,
+        None => return Err(format_err!("{} isn't inside download_path or library_paths", params.path)),
+    };
+    let valid_file_types = app_config.valid_file_types.clone();
+    let files = tokio::task::spawn_blocking(move || find_video_files(&root, &valid_file_types)).await?;
+    if files.is_empty() {
+        return Err(format_err!("No video files found in {}", params.path));
+    }
+
+    let imdb_id = match params.imdb_id.starts_with("tt") {
+        true => params.imdb_id.to_owned(),
+        false => format!("tt{}", params.imdb_id),
+    };
+    let importer = Importer::new(ImportMode::from_config(&app_config.import_mode), &app_config.import_path);
+    let history_db = HistoryDatabase::new(db);
+    let single_file = files.len() == 1;
+
+    let mut imported = Vec::new();
+    for file in files.iter() {
+        let (target, season, episode) = library_name(&item, file, single_file);
+        if let Err(e) = importer.import_as(file, &target).await {
+            warn!("Failed to import {}: {}", file.display(), e);
+            continue;
+        }
+
+        let file_name = file.file_name().unwrap_or_default().to_string_lossy();
+        let target = target.to_string_lossy();
+        if let Err(e) = history_db.insert_manual_import(&imdb_id, season, episode, &file_name, &target).await {
+            error!("Failed to record download history: {}", e);
+        }
+        notifier
+            .dispatch(Notification {
+                event: DownloadEvent::Imported,
+                magnet_hash: String::new(),
+                imdb_id: Some(imdb_id.clone()),
+                title: Some(item.title.clone()),
+                season,
+                episode,
+                quality: None,
+                source: Some("manual".to_string()),
+                progress: None,
+                detail: Some(target.to_string()),
+            })
+            .await;
+        imported.push(target.to_string());
+    }
+
+    if imported.is_empty() {
+        return Err(format_err!("None of the {} files in {} could be imported", files.len(), params.path));
+    }
+
+    info!("Imported {} of {} files from {}", imported.len(), files.len(), params.path);
+    if let Err(e) = media_server.refresh_library(Some(importer.destination())).await {
+        warn!("Failed to refresh media library: {}", e);
+    }
+    Ok(())
+}
+
+// Files can only be imported from qBittorrent's download folder or the library, symlinks and ".." are resolved first
+fn import_source(app_config: &AppConfig, path: &str) -> Option<PathBuf> {
+    let path = std::fs::canonicalize(path).ok()?;
+
+    let mut roots = vec![&app_config.download_path];
+    roots.extend(app_config.library_paths.iter());
+    roots
+        .into_iter()
+        .filter(|r| r.is_empty().not())
+        .filter_map(|r| std::fs::canonicalize(r).ok())
+        .any(|r| path.starts_with(r))
+        .then_some(path)
+}
+
+async fn find_item(app_config: &AppConfig, db: &DBConnection, id: &str) -> anyhow::Result<Option<ImportItem>> {
+    let item = match app_config.tmdb_api_key.is_empty() {
+        true => IMDBDatabase::new(db)
+            .fetch_item_by_id(id)
+            .await?
+            .into_iter()
+            .next()
+            .map(|i| ImportItem {
+                title: i.title,
+                year: i.year,
+                _type: i._type,
+            }),
+        false => MovieDBDatabase::new(db)
+            .fetch_item_by_id(id.trim_start_matches("tt").parse()?)
+            .await?
+            .into_iter()
+            .next()
+            .map(|i| ImportItem {
+                title: i.title,
+                year: i.release_date.year() as i64,
+                _type: i._type,
+            }),
+    };
+
+    Ok(item)
+}
+
+// Accepts a single file or a folder, folders are searched recursively
+fn find_video_files(path: &Path, valid_file_types: &[String]) -> Vec<PathBuf> {
+    if path.is_file() {
+        return match validation::is_video_file(&path.to_string_lossy(), valid_file_types) {
+            true => vec![path.to_path_buf()],
+            false => Vec::new(),
+        };
+    }
+
+    let mut files = Vec::new();
+    let entries = match std::fs::read_dir(path) {
+        Ok(t) => t,
+        Err(_) => return files,
+    };
+    for entry in entries.flatten() {
+        files.extend(find_video_files(&entry.path(), valid_file_types));
+    }
+    files.sort();
+    files
+}
+
+// "Movie (Year)/Movie (Year).mkv" and "Show/Season 01/Show - S01E02.mkv", files that can't be matched keep their name
+fn library_name(item: &ImportItem, file: &Path, single_file: bool) -> (PathBuf, Option<i32>, Option<i32>) {
+    let file_name = file.file_name().unwrap_or_default().to_string_lossy().to_string();
+    let extension = file.extension().unwrap_or_default().to_string_lossy().to_string();
+    let title = sanitise_file_name(&item.title);
+
+    match item._type {
+        ItemType::Movie => {
+            let folder = format!("{} ({})", title, item.year);
+            let name = match single_file {
+                true => format!("{}.{}", folder, extension),
+                false => file_name,
+            };
+            (Path::new(&folder).join(name), None, None)
+        }
+        ItemType::TvShow => {
+            let episode_regexp = Regex::new(r"(?i)s(\d{1,2})\s?e(\d{1,3})").unwrap();
+            match episode_regexp.captures(&file_name) {
+                Some(captures) => {
+                    let season = captures[1].parse::<i32>().unwrap_or_default();
+                    let episode = captures[2].parse::<i32>().unwrap_or_default();
+                    let name = format!("{} - S{:02}E{:02}.{}", title, season, episode, extension);
+                    let path = Path::new(&title).join(format!("Season {:02}", season)).join(name);
+                    (path, Some(season), Some(episode))
+                }
+                None => (Path::new(&title).join(file_name), None, None),
+            }
+        }
+    }
+}
+
+// Characters Windows doesn't allow in file names
+fn sanitise_file_name(name: &str) -> String {
+    name.chars()
+        .filter(|c| !matches!(c, '<' | '>' | ':' | '"' | '/' | '\\' | '|' | '?' | '*'))
+        .collect::<String>()
+        .trim()
+        .to_string()
+}
\ No newline at end of file
@@ -0,0 +1,92 @@
+use std::time::Duration;
+
+use actix_web::{Error, get, HttpRequest, HttpResponse, web};
+use actix_web::web::Data;
+use actix_ws::Message;
+use futures::StreamExt;
+use log::warn;
+use tokio::sync::broadcast::error::RecvError;
+
+use crate::api::notify::EventStream;
+use crate::api::progress::ProgressFeed;
+
+static KEEP_ALIVE_INTERVAL: Duration = Duration::from_secs(30);
+
+// Pushes download progress as JSON text messages while the socket is open
+#[get("/ws")]
+pub async fn progress_socket(
+    req: HttpRequest,
+    body: web::Payload,
+    feed: Data<ProgressFeed>,
+) -> Result<HttpResponse, Error> {
+    let (response, mut session, mut stream) = actix_ws::handle(&req, body)?;
+    let mut updates = feed.subscribe();
+
+    actix_web::rt::spawn(async move {
+        loop {
+            tokio::select! {
+                update = updates.recv() => match update {
+                    Ok(update) => {
+                        let json = match serde_json::to_string(&update) {
+                            Ok(t) => t,
+                            Err(e) => {
+                                warn!("Failed to serialise progress update: {}", e);
+                                continue;
+                            }
+                        };
+                        if session.text(json).await.is_err() {
+                            break;
+                        }
+                    }
+                    // The next update for each download is still sent, so missing some is fine
+                    Err(RecvError::Lagged(_)) => continue,
+                    Err(RecvError::Closed) => break,
+                },
+                message = stream.next() => match message {
+                    Some(Ok(Message::Ping(bytes))) => {
+                        if session.pong(&bytes).await.is_err() {
+                            break;
+                        }
+                    }
+                    Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+                    _ => (),
+                },
+            }
+        }
+
+        let _ = session.close(None).await;
+    });
+
+    Ok(response)
+}
+
+// Server-Sent Events stream of grabs, completions, failures, watchlist matches and background errors
+#[get("/events")]
+pub async fn event_stream(events: Data<EventStream>) -> HttpResponse {
+    let receiver = events.subscribe();
+    let keep_alive = tokio::time::interval(KEEP_ALIVE_INTERVAL);
+
+    let stream = futures::stream::unfold((receiver, keep_alive), |(mut receiver, mut keep_alive)| async move {
+        let message = loop {
+            tokio::select! {
+                notification = receiver.recv() => match notification {
+                    Ok(notification) => match serde_json::to_string(&notification) {
+                        Ok(json) => break format!("data: {}\n\n", json),
+                        Err(e) => warn!("Failed to serialise event: {}", e),
+                    },
+                    Err(RecvError::Lagged(_)) => continue,
+                    Err(RecvError::Closed) => return None,
+                },
+                // Stops proxies from closing an idle connection
+                _ = keep_alive.tick() => break String::from(": keep-alive\n\n"),
+            }
+        };
+
+        Some((Ok::<_, Error>(web::Bytes::from(message)), (receiver, keep_alive)))
+    });
+
+    HttpResponse::Ok()
+        .content_type("text/event-stream")
+        .insert_header(("Cache-Control", "no-cache"))
+        .streaming(stream)
+}
\ No newline at end of file
@@ -1,12 +1,80 @@
-use std::path::PathBuf;
-use actix_files::NamedFile;
-use actix_web::{Error, get};
+use actix_web::{Error, get, HttpRequest, HttpResponse};
+use actix_web::web::Data;
 
+use crate::AppConfig;
+use crate::db::DBConnection;
+
+pub mod assets;
 pub mod query;
 pub mod download;
+pub mod plex;
+pub mod aliases;
+pub mod stats;
+pub mod history;
+pub mod image;
+pub mod imports;
+pub mod api_v1;
+pub mod arr;
+pub mod auth;
+pub mod live;
+pub mod calendar;
+pub mod compress;
+pub mod error;
+pub mod preferences;
+pub mod rate_limit;
+pub mod settings;
 
 #[get("/")]
-pub async fn index() -> Result<NamedFile, Error> {
-    let path: PathBuf = "./static/index.html".parse().unwrap();
-    Ok(NamedFile::open(path)?)
+pub async fn index(
+    req: HttpRequest,
+    db: Data<DBConnection>,
+    app_config: Data<AppConfig>,
+) -> Result<HttpResponse<String>, Error> {
+    let html = assets::read_asset_text(&app_config, "index.html")?;
+
+    // Relative links and every HTMX request in the page are resolved against <base>
+    let base = format!("<base href=\"{}/\">", escape_html(&app_config.base_path));
+    let html = html.replacen("<base href=\"/\">", &base, 1);
+
+    let preferences = preferences::load_preferences(&req, &db).await;
+    let html = html
+        .replacen("data-theme=\"auto\"", &format!("data-theme=\"{}\"", escape_html(&preferences.theme)), 1)
+        .replacen(
+            "hx-get=\"/search?type=movie&mode=popular\" hx-trigger=\"load\"",
+            &format!("hx-get=\"/search?{}\" hx-trigger=\"load\"", preferences.search_query()),
+            1,
+        );
+
+    let mut response = HttpResponse::Ok();
+    if preferences::client_id(&req).is_none() {
+        response.cookie(preferences::new_client_cookie());
+    }
+
+    Ok(response
+        .content_type("text/html; charset=utf-8")
+        .message_body(html)
+        .unwrap())
+}
+
+// Escapes text for use inside HTML content or a double-quoted attribute
+pub fn escape_html(input: &str) -> String {
+    let mut output = String::with_capacity(input.len());
+    for c in input.chars() {
+        match c {
+            '&' => output.push_str("&amp;"),
+            '<' => output.push_str("&lt;"),
+            '>' => output.push_str("&gt;"),
+            '"' => output.push_str("&quot;"),
+            '\'' => output.push_str("&#39;"),
+            _ => output.push(c),
+        }
+    }
+    output
+}
+
+pub fn format_size(bytes: i64) -> String {
+    match bytes {
+        t if t >= 1_073_741_824 => format!("{:.2} GB", t as f64 / 1_073_741_824.0),
+        t => format!("{:.0} MB", t as f64 / 1_048_576.0),
+    }
 }
\ No newline at end of file
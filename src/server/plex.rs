@@ -0,0 +1,93 @@
+use actix_web::{Error, get, HttpResponse};
+use actix_web::error::{ErrorForbidden, ErrorInternalServerError};
+use actix_web::web::{Data, Query};
+use serde::Deserialize;
+
+use crate::AppConfig;
+use crate::api::plex::Plex;
+use crate::db::DBConnection;
+use crate::db::plex::PlexAuthDatabase;
+use crate::server::escape_html;
+
+#[get("/plex/login")]
+pub async fn plex_login(plex: Option<Data<Plex>>, app_config: Data<AppConfig>) -> Result<HttpResponse<String>, Error> {
+    if app_config.read_only {
+        return Err(ErrorForbidden("Read-only mode"));
+    }
+    let plex = match plex {
+        Some(t) => t,
+        None => {
+            return Ok(HttpResponse::Ok()
+                .message_body("<b>Plex is not the configured media server</b>".to_string())
+                .unwrap())
+        }
+    };
+
+    let pin = match plex.create_pin().await {
+        Ok(t) => t,
+        Err(e) => return Err(ErrorInternalServerError(e)),
+    };
+
+    // Polls until the PIN has been approved on plex.tv
+    let html = format!("<!DOCTYPE html>\
+<html lang=\"en\">\
+<head>\
+    <meta charset=\"UTF-8\">\
+    <title>Roundup - Plex Sign In</title>\
+    <script src=\"https://unpkg.com/htmx.org@1.9.10\" integrity=\"sha384-D1Kt99CQMDuVetoL1lrYwg5t+9QdHe7NLX/SoJYkXDFfX37iInKRy5xLSi8nO7UC\" crossorigin=\"anonymous\"></script>\
+    <link href=\"https://cdn.jsdelivr.net/npm/bootstrap@5.3.3/dist/css/bootstrap.min.css\" rel=\"stylesheet\" integrity=\"sha384-QWTKZyjpPEjISv5WaRU9OFeRpok6YctnYmDr5pNlyT2bRjXh0JMhjY6hW+ALEwIH\" crossorigin=\"anonymous\">\
+</head>\
+<body style=\"padding: 2rem;\">\
+    <h2>Sign in to Plex</h2>\
+    <p><a class=\"btn btn-primary\" href=\"{}\" target=\"_blank\" rel=\"noopener\">Open plex.tv</a></p>\
+    <div hx-get=\"login/check?id={}\" hx-trigger=\"every 2s\" hx-swap=\"outerHTML\">\
+        <div class=\"spinner-border\"></div> Waiting for approval...\
+    </div>\
+</body>\
+</html>", escape_html(&plex.auth_url(&pin.code)), pin.id);
+
+    Ok(HttpResponse::Ok().message_body(html).unwrap())
+}
+
+#[derive(Deserialize)]
+pub struct PlexLoginCheckQuery {
+    id: i64,
+}
+
+#[get("/plex/login/check")]
+pub async fn plex_login_check(
+    params: Query<PlexLoginCheckQuery>,
+    plex: Option<Data<Plex>>,
+    db: Data<DBConnection>,
+    app_config: Data<AppConfig>,
+) -> Result<HttpResponse<String>, Error> {
+    if app_config.read_only {
+        return Err(ErrorForbidden("Read-only mode"));
+    }
+    let plex = match plex {
+        Some(t) => t,
+        None => return Err(ErrorInternalServerError("Plex is not the configured media server")),
+    };
+
+    let token = match plex.check_pin(params.id).await {
+        Ok(Some(t)) => t,
+        Ok(None) => {
+            // Returning the same element keeps htmx polling
+            let html = format!("<div hx-get=\"login/check?id={}\" hx-trigger=\"every 2s\" hx-swap=\"outerHTML\">\
+        <div class=\"spinner-border\"></div> Waiting for approval...\
+    </div>", params.id);
+            return Ok(HttpResponse::Ok().message_body(html).unwrap());
+        }
+        Err(e) => return Err(ErrorInternalServerError(e)),
+    };
+
+    match PlexAuthDatabase::new(&db).update_token(&token).await {
+        Ok(_) => (),
+        Err(e) => return Err(ErrorInternalServerError(e)),
+    };
+    plex.set_token(&token);
+
+    Ok(HttpResponse::Ok()
+        .message_body("<div><b>Signed in to Plex!</b> <a href=\"../\">Back to Roundup</a></div>".to_string())
+        .unwrap())
+}
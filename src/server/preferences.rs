@@ -0,0 +1,230 @@
+use std::ops::Not;
+
+use actix_web::{Error, get, HttpRequest, HttpResponse, post};
+use actix_web::cookie::{Cookie, SameSite};
+use actix_web::cookie::time::Duration;
+use actix_web::error::{ErrorBadRequest, ErrorInternalServerError};
+use actix_web::web::{Data, Form, Query};
+use log::warn;
+use serde::Deserialize;
+
+use crate::api::torrent::MediaQuality;
+use crate::db::{DBConnection, MAX_PAGE_SIZE};
+use crate::db::preferences::{PreferencesDatabase, UserPreferences};
+use crate::server::api_v1::json_response;
+use crate::server::escape_html;
+
+// There are no user accounts, each browser gets a random id the first time it opens the page
+pub const CLIENT_COOKIE: &str = "roundup_client";
+
+// Preference key, /search query and button label
+pub const SEARCH_MODES: [(&str, &str, &str); 6] = [
+    ("popular_movies", "type=movie&mode=popular", "Popular Movies"),
+    ("latest_movies", "type=movie&mode=latest", "Latest Movies"),
+    ("popular_tv", "type=tv&mode=popular", "Popular TV"),
+    ("latest_tv", "type=tv&mode=latest", "Latest TV"),
+    ("watchlist", "type=movie&mode=watchlist", "Watchlist"),
+    ("downloads", "type=all&mode=downloads", "Downloads"),
+];
+
+const THEMES: [&str; 3] = ["auto", "light", "dark"];
+
+const PREFERRED_QUALITIES: [MediaQuality; 5] = [
+    MediaQuality::_480p,
+    MediaQuality::_720p,
+    MediaQuality::_1080p,
+    MediaQuality::_2160p,
+    MediaQuality::_4320p,
+];
+
+impl UserPreferences {
+    // The /search query to load when the page opens
+    pub fn search_query(&self) -> &'static str {
+        SEARCH_MODES
+            .iter()
+            .find(|(key, _, _)| *key == self.search_mode)
+            .unwrap_or(&SEARCH_MODES[0])
+            .1
+    }
+
+    pub fn quality(&self) -> Option<MediaQuality> {
+        MediaQuality::from_label(&self.preferred_quality)
+    }
+}
+
+pub fn client_id(req: &HttpRequest) -> Option<String> {
+    req.cookie(CLIENT_COOKIE)
+        .map(|c| c.value().to_string())
+        .filter(|id| id.len() == 32 && id.chars().all(|c| c.is_ascii_hexdigit()))
+}
+
+pub fn new_client_cookie() -> Cookie<'static> {
+    let mut bytes = [0u8; 16];
+    ring::rand::SecureRandom::fill(&ring::rand::SystemRandom::new(), &mut bytes).unwrap();
+    let id = bytes.iter().map(|b| format!("{:02x}", b)).collect::<String>();
+
+    Cookie::build(CLIENT_COOKIE, id)
+        .path("/")
+        .http_only(true)
+        .same_site(SameSite::Lax)
+        .max_age(Duration::days(365 * 5))
+        .finish()
+}
+
+// Falls back to the defaults for browsers without a cookie or saved preferences
+pub async fn load_preferences(req: &HttpRequest, db: &DBConnection) -> UserPreferences {
+    let id = match client_id(req) {
+        Some(t) => t,
+        None => return UserPreferences::default(),
+    };
+
+    match PreferencesDatabase::new(db).fetch(&id).await {
+        Ok(t) => t.unwrap_or_default(),
+        Err(e) => {
+            warn!("Failed to load preferences, {}", e);
+            UserPreferences::default()
+        }
+    }
+}
+
+#[derive(Deserialize)]
+pub struct PreferencesQuery {
+    format: Option<String>,
+}
+
+#[get("/preferences")]
+pub async fn get_preferences(
+    req: HttpRequest,
+    params: Query<PreferencesQuery>,
+    db: Data<DBConnection>,
+) -> Result<HttpResponse<String>, Error> {
+    let preferences = load_preferences(&req, &db).await;
+
+    match params.format.as_deref() {
+        Some("html") => Ok(HttpResponse::Ok().message_body(create_preferences_form(&preferences, None)).unwrap()),
+        _ => json_response(&preferences),
+    }
+}
+
+#[derive(Deserialize)]
+pub struct PreferencesForm {
+    search_mode: String,
+    page_size: i64,
+    preferred_quality: String,
+    theme: String,
+}
+
+#[post("/preferences")]
+pub async fn update_preferences(
+    req: HttpRequest,
+    form: Form<PreferencesForm>,
+    db: Data<DBConnection>,
+) -> Result<HttpResponse<String>, Error> {
+    let form = form.into_inner();
+    if SEARCH_MODES.iter().any(|(key, _, _)| *key == form.search_mode).not() {
+        return Err(ErrorBadRequest("Unknown search mode"));
+    }
+    if THEMES.contains(&form.theme.as_str()).not() {
+        return Err(ErrorBadRequest("Unknown theme"));
+    }
+    if (1..=MAX_PAGE_SIZE).contains(&form.page_size).not() {
+        return Err(ErrorBadRequest(format!("Items per page must be between 1 and {}", MAX_PAGE_SIZE)));
+    }
+    let preferred_quality = match form.preferred_quality.is_empty() {
+        true => String::new(),
+        false => match MediaQuality::from_label(&form.preferred_quality) {
+            Some(t) => t.to_string(),
+            None => return Err(ErrorBadRequest("Unknown quality")),
+        },
+    };
+
+    let preferences = UserPreferences {
+        search_mode: form.search_mode,
+        page_size: form.page_size,
+        preferred_quality,
+        theme: form.theme,
+    };
+
+    // Pages opened before the cookie existed get one now
+    let (id, cookie) = match client_id(&req) {
+        Some(t) => (t, None),
+        None => {
+            let cookie = new_client_cookie();
+            (cookie.value().to_string(), Some(cookie))
+        }
+    };
+    if let Err(e) = PreferencesDatabase::new(&db).insert_or_update(&id, &preferences).await {
+        return Err(ErrorInternalServerError(e));
+    }
+
+    let message = "<div class=\"alert alert-success\" role=\"alert\">Preferences saved</div>";
+    let mut response = HttpResponse::Ok();
+    // Picked up by index.html to switch theme without a reload
+    response.insert_header(("HX-Trigger", format!("{{\"themeChanged\":\"{}\"}}", preferences.theme)));
+    if let Some(cookie) = cookie {
+        response.cookie(cookie);
+    }
+
+    Ok(response
+        .message_body(create_preferences_form(&preferences, Some(message)))
+        .unwrap())
+}
+
+fn create_preferences_form(preferences: &UserPreferences, message: Option<&str>) -> String {
+    let search_modes = SEARCH_MODES
+        .iter()
+        .map(|(key, _, label)| create_option(key, label, *key == preferences.search_mode))
+        .collect::<Vec<String>>()
+        .join("");
+
+    let mut qualities = create_option("", "No preference", preferences.preferred_quality.is_empty());
+    for quality in PREFERRED_QUALITIES {
+        let label = quality.to_string();
+        qualities.push_str(&create_option(&label, &label, label == preferences.preferred_quality));
+    }
+
+    let themes = THEMES
+        .iter()
+        .map(|t| create_option(t, t, *t == preferences.theme))
+        .collect::<Vec<String>>()
+        .join("");
+
+    format!("<div id=\"preferences\" style=\"max-width: 40rem; margin: 0.5rem auto; padding: 0.5rem;\">\
+    {}\
+    <p class=\"text-body-secondary\">Saved for this browser only.</p>\
+    <form hx-post=\"/preferences\" hx-target=\"#preferences\" hx-swap=\"outerHTML\">\
+        <div class=\"mb-3\">\
+            <label class=\"form-label\" for=\"search_mode\">Show on start</label>\
+            <select class=\"form-select\" id=\"search_mode\" name=\"search_mode\">{}</select>\
+        </div>\
+        <div class=\"mb-3\">\
+            <label class=\"form-label\" for=\"page_size\">Items per page</label>\
+            <input class=\"form-control\" type=\"number\" min=\"1\" max=\"{}\" id=\"page_size\" name=\"page_size\" value=\"{}\">\
+        </div>\
+        <div class=\"mb-3\">\
+            <label class=\"form-label\" for=\"preferred_quality\">Preferred quality (listed first when downloading movies)</label>\
+            <select class=\"form-select\" id=\"preferred_quality\" name=\"preferred_quality\">{}</select>\
+        </div>\
+        <div class=\"mb-3\">\
+            <label class=\"form-label\" for=\"theme\">Theme</label>\
+            <select class=\"form-select\" id=\"theme\" name=\"theme\">{}</select>\
+        </div>\
+        <button type=\"submit\" class=\"btn btn-primary\">Save</button>\
+    </form>\
+</div>",
+        message.unwrap_or_default(),
+        search_modes,
+        MAX_PAGE_SIZE,
+        preferences.page_size,
+        qualities,
+        themes,
+    )
+}
+
+fn create_option(value: &str, label: &str, selected: bool) -> String {
+    let selected = match selected {
+        true => " selected",
+        false => "",
+    };
+    format!("<option value=\"{}\"{}>{}</option>", escape_html(value), selected, escape_html(label))
+}
\ No newline at end of file
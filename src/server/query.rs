@@ -1,23 +1,32 @@
+use std::collections::HashMap;
 use std::ops::{Deref, Div, Not};
 
-use actix_web::{Error, get, HttpResponse, web};
-use actix_web::error::{ErrorBadRequest, ErrorInternalServerError};
+use actix_web::{Error, get, HttpRequest, HttpResponse, post, web};
+use actix_web::error::{ErrorBadRequest, ErrorForbidden, ErrorInternalServerError, ErrorNotFound};
 use actix_web::web::{Data, Query};
 use anyhow::format_err;
 use chrono::{Datelike, Duration, Local};
-use log::error;
+use log::{error, warn};
 use rayon::prelude::*;
 use serde::Deserialize;
 use tokio::sync::Mutex;
 
 use crate::{AppConfig, QueryCache};
+use crate::api::coalesce::Coalescer;
+use crate::api::disk::{DiskMonitor, VolumeUsage};
+use crate::api::fanart::Fanart;
 use crate::api::imdb::{IMDB, IMDBItem, ItemType, SearchType};
+use crate::api::torrent::MediaQuality;
 use crate::api::moviedb::{MovieDB, MovieDBItem};
 use crate::api::youtube::Youtube;
-use crate::db::DBConnection;
-use crate::db::downloads::{ActiveDownloadIMDBItem, DownloadDatabase};
+use crate::db::{DBConnection, Pagination};
+use crate::db::downloads::{ActiveDownloadIMDBItem, DOWNLOADS_PAGE_SIZE, DownloadDatabase, DownloadFilter, DownloadSort, DownloadStateFilter, IMPORT_FAILED, IMPORT_PENDING};
 use crate::db::imdb::IMDBDatabase;
 use crate::db::moviedb::MovieDBDatabase;
+use crate::db::similar::SimilarDatabase;
+use crate::db::watchlist_states::{WatchlistStateDatabase, WatchlistStatus};
+use crate::server::{escape_html, format_size};
+use crate::server::preferences::load_preferences;
 
 #[derive(Deserialize)]
 pub struct SearchQueryParams {
@@ -25,21 +34,142 @@ pub struct SearchQueryParams {
     _type: String,
     mode: String,
     query: Option<String>,
+    page: Option<i64>,
+    limit: Option<i64>,
+    state: Option<String>,
+    quality: Option<String>,
+    sort: Option<String>,
 }
 #[get("/search")]
 pub async fn search(
+    req: HttpRequest,
     params: Query<SearchQueryParams>,
     cache_update: web::Data<Mutex<QueryCache>>,
+    searches: web::Data<SearchRequests>,
     db: web::Data<DBConnection>,
+    disk: Data<DiskMonitor>,
     app_config: Data<AppConfig>,
 ) -> Result<HttpResponse<String>, Error> {
-    let _type = match params._type.to_ascii_lowercase().as_str() {
+    let mode = parse_search_type(&params._type, &params.mode, params.query.as_ref())?;
+    let page_size = match params.limit {
+        Some(t) => t,
+        None => load_preferences(&req, &db).await.page_size,
+    };
+    let pagination = Pagination::new(params.page, Some(page_size));
+
+    match app_config.tmdb_api_key.is_empty() {
+        true => {
+            if mode == SearchType::Downloads {
+                // The type param narrows the list here, the downloads button sends type=all
+                let filter = DownloadFilter::from_params(params.state.as_deref(), Some(&params._type), params.quality.as_deref());
+                let sort = DownloadSort::from_param(params.sort.as_deref().unwrap_or_default());
+                let page = params.page.unwrap_or(0).max(0);
+
+                let db = DownloadDatabase::new(&db);
+                let items = match db.fetch_downloads_with_imdb_data(&filter, sort, page).await {
+                    Ok(t) => t,
+                    Err(e) => return Err(ErrorInternalServerError(e)),
+                };
+
+                // Later pages are appended in place of the load more trigger, so only send the cards
+                let html = match page {
+                    0 => generate_active_downloads_html(items, &filter, sort, &disk),
+                    _ => generate_active_downloads_page(items, &filter, sort, page),
+                };
+                return Ok(HttpResponse::Ok().message_body(html).unwrap());
+            }
+
+            let is_watchlist = mode == SearchType::Watchlist;
+            let results = check_cache_then_search_imdb(mode, pagination, db.clone(), cache_update, searches).await?;
+            let next_page = next_search_page(&params, pagination, results.len());
+            let states = match is_watchlist {
+                true => fetch_watchlist_states(&db, results.iter().map(|i| i.id.clone()).collect()).await?,
+                false => HashMap::new(),
+            };
+
+            let html = generate_search_page(generate_search_html_imdb(results, &states), next_page, pagination);
+            Ok(HttpResponse::Ok().message_body(html).unwrap())
+        }
+        false => {
+            let is_watchlist = mode == SearchType::Watchlist;
+            let results =
+                check_cache_then_search_moviedb(mode, pagination, db.clone(), cache_update, searches, app_config).await?;
+            let next_page = next_search_page(&params, pagination, results.len());
+            let states = match is_watchlist {
+                true => fetch_watchlist_states(&db, results.iter().map(|i| format!("tt{}", i.id)).collect()).await?,
+                false => HashMap::new(),
+            };
+
+            let html = generate_search_page(generate_search_html_moviedb(results, &states), next_page, pagination);
+            Ok(HttpResponse::Ok().message_body(html).unwrap())
+        }
+    }
+}
+
+async fn fetch_watchlist_states(db: &DBConnection, ids: Vec<String>) -> Result<HashMap<String, WatchlistStatus>, Error> {
+    match WatchlistStateDatabase::new(db).fetch_many(&ids).await {
+        Ok(t) => Ok(t),
+        Err(e) => Err(ErrorInternalServerError(e)),
+    }
+}
+
+// Shown under the title on the watchlist page, hovering shows why a check didn't grab anything
+fn generate_watchlist_state_html(status: Option<&WatchlistStatus>) -> String {
+    let status = match status {
+        Some(t) => t,
+        None => return String::new(),
+    };
+    let class = match status.state.as_str() {
+        "available" => "text-success",
+        "grabbed" | "downloading" => "text-info",
+        _ => "text-body-secondary",
+    };
+    let title = status.detail.as_deref().map(escape_html).unwrap_or_default();
+
+    format!("<p class=\"card-text\"><small class=\"{}\" title=\"{}\">{}</small></p>", class, title, status.state)
+}
+
+// Only a full page can have more after it
+fn next_search_page(params: &SearchQueryParams, pagination: Pagination, results: usize) -> Option<String> {
+    if (results as i64) < pagination.limit {
+        return None;
+    }
+
+    Some(format!(
+        "/search?type={}&mode={}&query={}&limit={}&page={}",
+        urlencoding::encode(&params._type),
+        urlencoding::encode(&params.mode),
+        urlencoding::encode(params.query.as_deref().unwrap_or_default()),
+        pagination.limit,
+        pagination.page + 1
+    ))
+}
+
+// Later pages are appended in place of the load more trigger, so only send the cards
+fn generate_search_page(items: String, next_page: Option<String>, pagination: Pagination) -> String {
+    let load_more = match next_page {
+        Some(url) => format!(
+            "<div class=\"spinner-border\" style=\"margin: 0.5rem;\" hx-get=\"{}\" hx-trigger=\"revealed\" hx-swap=\"outerHTML\"></div>",
+            escape_html(&url)
+        ),
+        None => String::new(),
+    };
+
+    match pagination.page {
+        0 => format!("<div class=\"results-container\">{}{}</div>", items, load_more),
+        _ => format!("{}{}", items, load_more),
+    }
+}
+
+// Shared by the HTMX and JSON search endpoints
+pub fn parse_search_type(_type: &str, mode: &str, query: Option<&String>) -> Result<SearchType, Error> {
+    let _type = match _type.to_ascii_lowercase().as_str() {
         "movie" | "film" => ItemType::Movie,
         "tv" | "show" | "series" => ItemType::TvShow,
         _ => ItemType::Movie,
     };
 
-    let mode = match params.mode.to_ascii_lowercase().as_str() {
+    let mode = match mode.to_ascii_lowercase().as_str() {
         "latest" | "recent" | "release" => match _type {
             ItemType::Movie => SearchType::MovieLatestRelease,
             ItemType::TvShow => SearchType::TVLatestRelease,
@@ -49,7 +179,7 @@ pub async fn search(
             ItemType::TvShow => SearchType::TVPopular,
         },
         "query" => {
-            match &params.query {
+            match query {
                 Some(t) => {
                     if t.is_empty() {
                         SearchType::MoviePopular // for when the text box is emptied, show the default page
@@ -68,46 +198,133 @@ pub async fn search(
         },
     };
 
-    match app_config.tmdb_api_key.is_empty() {
-        true => {
-            if mode == SearchType::Downloads {
-                let db = DownloadDatabase::new(&db);
-                let items = match db.fetch_downloads_with_imdb_data().await {
-                    Ok(t) => t,
-                    Err(e) => return Err(ErrorInternalServerError(e)),
-                };
+    Ok(mode)
+}
 
-                let html = generate_active_downloads_html(items);
-                return Ok(HttpResponse::Ok().message_body(html).unwrap());
-            }
+fn generate_active_downloads_html(
+    items: Vec<ActiveDownloadIMDBItem>,
+    filter: &DownloadFilter,
+    sort: DownloadSort,
+    disk: &DiskMonitor,
+) -> String {
+    let mut output = String::new();
 
-            let results = check_cache_then_search_imdb(mode, db, cache_update).await?;
+    output.push_str(&generate_active_downloads_controls(filter, sort));
+    output.push_str(&generate_disk_usage_html(disk.usage(), disk.is_low()));
+    output.push_str(
+        "<div style=\"display: flex; flex-direction: row; align-items: center; flex-wrap: wrap;\">",
+    );
+    let items = generate_active_downloads_page(items, filter, sort, 0);
+    output.push_str(&items);
+    output.push_str("</div>");
+    output
+}
 
-            let html = generate_search_html_imdb(results);
-            Ok(HttpResponse::Ok().message_body(html).unwrap())
-        }
-        false => {
-            let results =
-                check_cache_then_search_moviedb(mode, db, cache_update, app_config).await?;
+fn generate_active_downloads_controls(filter: &DownloadFilter, sort: DownloadSort) -> String {
+    let mut output = String::new();
+    output.push_str("<div style=\"display: flex; flex-direction: row; flex-wrap: wrap; margin: 0.5rem;\">");
+
+    output.push_str("<div class=\"btn-group\" role=\"group\" style=\"margin-right: 1rem;\">");
+    for state in [
+        DownloadStateFilter::All,
+        DownloadStateFilter::Downloading,
+        DownloadStateFilter::Stalled,
+        DownloadStateFilter::Completed,
+        DownloadStateFilter::ImportFailed,
+    ] {
+        let option = DownloadFilter { state, ..filter.clone() };
+        output.push_str(&generate_downloads_control_button(&option, sort, state == filter.state, state.as_str()));
+    }
+    output.push_str("</div>");
 
-            let html = generate_search_html_moviedb(results);
-            Ok(HttpResponse::Ok().message_body(html).unwrap())
-        }
+    output.push_str("<div class=\"btn-group\" role=\"group\" style=\"margin-right: 1rem;\">");
+    for (item_type, label) in [(None, "all"), (Some(ItemType::Movie), "movies"), (Some(ItemType::TvShow), "tv")] {
+        let option = DownloadFilter { item_type, ..filter.clone() };
+        let selected = option.as_query() == filter.as_query();
+        output.push_str(&generate_downloads_control_button(&option, sort, selected, label));
+    }
+    output.push_str("</div>");
+
+    output.push_str("<div class=\"btn-group\" role=\"group\" style=\"margin-right: 1rem;\">");
+    for quality in [None, Some(MediaQuality::_720p), Some(MediaQuality::_1080p), Some(MediaQuality::_2160p)] {
+        let label = match quality {
+            Some(t) => t.to_string(),
+            None => "any quality".to_string(),
+        };
+        let option = DownloadFilter { quality, ..filter.clone() };
+        output.push_str(&generate_downloads_control_button(&option, sort, quality == filter.quality, &label));
     }
+    output.push_str("</div>");
+
+    output.push_str("<div class=\"btn-group\" role=\"group\">");
+    for order in [DownloadSort::Added, DownloadSort::Age, DownloadSort::Progress, DownloadSort::Title] {
+        let label = format!("Sort: {}", order.as_str());
+        output.push_str(&generate_downloads_control_button(filter, order, order == sort, &label));
+    }
+    output.push_str("</div>");
+
+    output.push_str("</div>");
+    output
+}
+
+fn generate_downloads_control_button(filter: &DownloadFilter, sort: DownloadSort, selected: bool, label: &str) -> String {
+    let class = match selected {
+        true => "btn-secondary",
+        false => "btn-outline-secondary",
+    };
+    format!(
+        "<button type=\"button\" class=\"btn btn-sm {}\" hx-get=\"/search?mode=downloads&{}&sort={}\" hx-target=\"#search-results\" hx-indicator=\"#load-spinner-results\">{}</button>",
+        class, filter.as_query(), sort.as_str(), label
+    )
 }
 
-fn generate_active_downloads_html(items: Vec<ActiveDownloadIMDBItem>) -> String {
+fn generate_disk_usage_html(usage: Vec<VolumeUsage>, is_low: bool) -> String {
     let mut output = String::new();
+    if usage.is_empty() {
+        return output;
+    }
 
-    output.push_str(
-        "<div style=\"display: flex; flex-direction: row; align-items: center; flex-wrap: wrap;\">",
-    );
-    let items = generate_active_downloads_items(items);
-    output.push_str(&items);
+    if is_low {
+        output.push_str("<div class=\"alert alert-warning\" role=\"alert\" style=\"margin: 0.5rem;\">Low disk space, automatic downloads are paused</div>");
+    }
+
+    output.push_str("<div style=\"display: flex; flex-direction: row; flex-wrap: wrap; margin: 0.5rem;\">");
+    for volume in usage {
+        let used = match volume.total {
+            0 => 0.0,
+            t => (t - volume.available) as f64 / t as f64 * 100.0,
+        };
+        let text = format!(
+            "<span class=\"badge text-bg-secondary\" style=\"margin-right: 0.5rem;\" title=\"{:.0}% used\">{}: {:.1} GB free of {:.1} GB</span>",
+            used, escape_html(&volume.path), volume.available_gb(), volume.total_gb()
+        );
+        output.push_str(&text);
+    }
     output.push_str("</div>");
     output
 }
 
+fn generate_active_downloads_page(
+    items: Vec<ActiveDownloadIMDBItem>,
+    filter: &DownloadFilter,
+    sort: DownloadSort,
+    page: i64,
+) -> String {
+    let is_full_page = items.len() as i64 == DOWNLOADS_PAGE_SIZE;
+    let mut output = generate_active_downloads_items(items);
+
+    // Fetch the next page when the end of the list scrolls into view
+    if is_full_page {
+        let load_more = format!(
+            "<div class=\"spinner-border\" style=\"margin: 0.5rem;\" hx-get=\"/search?mode=downloads&{}&sort={}&page={}\" hx-trigger=\"revealed\" hx-swap=\"outerHTML\"></div>",
+            filter.as_query(), sort.as_str(), page + 1
+        );
+        output.push_str(&load_more);
+    }
+
+    output
+}
+
 fn generate_active_downloads_items(items: Vec<ActiveDownloadIMDBItem>) -> String {
     let mut output = String::new();
 
@@ -118,8 +335,8 @@ fn generate_active_downloads_items(items: Vec<ActiveDownloadIMDBItem>) -> String
         output.push_str("<div class=\"row\">");
         output.push_str("<div class=\"col\">");
         let image = format!(
-            "<img src=\"{}\" alt=\"imdb_image\" style=\"max-height: 14rem;\"/>",
-            item.image_url
+            "<img src=\"image/{}\" alt=\"imdb_image\" style=\"max-height: 14rem;\"/>",
+            item.imdb_id
         );
         output.push_str(&image);
         output.push_str("</div>");
@@ -142,16 +359,63 @@ fn generate_active_downloads_items(items: Vec<ActiveDownloadIMDBItem>) -> String
             None => String::new(),
         };
 
+        let state = match item.import_state.as_deref() {
+            Some(IMPORT_FAILED) => format!(
+                "<span class=\"text-danger\">Import failed</span> <button type=\"button\" class=\"btn btn-sm btn-outline-secondary\" hx-get=\"/downloads/dismiss?id={}\" hx-target=\"closest .card\" hx-swap=\"outerHTML\">Dismiss</button>",
+                item.id
+            ),
+            Some(IMPORT_PENDING) => "Waiting for import".to_string(),
+            // Kept up to date by the progress socket in index.html
+            _ if item.progress < 1.0 && item.download_speed > 0 => format!(
+                "<span class=\"download-progress\" data-hash=\"{}\">{} | {:.2}%<br><small>{}{}</small></span>",
+                item.magnet_hash,
+                item.state,
+                item.progress * 100.00,
+                format_speed(item.download_speed),
+                item.eta.map(|t| format!(" | ETA {}", format_eta(t))).unwrap_or_default()
+            ),
+            _ => format!(
+                "<span class=\"download-progress\" data-hash=\"{}\">{} | {:.2}%</span>",
+                item.magnet_hash,
+                item.state,
+                item.progress * 100.00
+            ),
+        };
+
+        let remove_buttons = format!(
+            "<button type=\"button\" class=\"btn btn-sm btn-outline-danger\" hx-delete=\"/downloads/{}\" hx-confirm=\"Remove this download?\" hx-target=\"closest .card\" hx-swap=\"outerHTML\">Remove</button> \
+            <button type=\"button\" class=\"btn btn-sm btn-outline-danger\" hx-delete=\"/downloads/{}?delete_files=true\" hx-confirm=\"Remove this download and delete its files?\" hx-target=\"closest .card\" hx-swap=\"outerHTML\">Remove with data</button>",
+            item.magnet_hash, item.magnet_hash
+        );
+
+        let release = match &item.release_name {
+            Some(name) => {
+                let details = [item.source.clone(), item.size.map(format_size)]
+                    .into_iter()
+                    .flatten()
+                    .collect::<Vec<String>>()
+                    .join(" | ");
+                format!(
+                    "<p class=\"text-truncate\" style=\"max-width: 11rem;\" title=\"{}\"><small>{}<br/>{}</small></p>",
+                    escape_html(name), escape_html(name), escape_html(&details)
+                )
+            }
+            None => String::new(),
+        };
+
         let heading = format!(
             "<div class=\"card-text\">\
     <p><small>{}</small></p>\
     {}\
-    <p>{} | {:.2}%</p>\
+    {}\
+    <p>{}</p>\
+    <p>{}</p>\
     </div>",
             subheading,
+            release,
             season_episode_text,
-            item.state,
-            item.progress * 100.00
+            state,
+            remove_buttons
         );
         output.push_str(&heading);
         output.push_str("</div>");
@@ -163,11 +427,35 @@ fn generate_active_downloads_items(items: Vec<ActiveDownloadIMDBItem>) -> String
     output
 }
 
+fn format_speed(bytes_per_second: i64) -> String {
+    match bytes_per_second {
+        t if t >= 1_048_576 => format!("{:.1} MB/s", t as f64 / 1_048_576.0),
+        t => format!("{:.0} KB/s", t as f64 / 1024.0),
+    }
+}
+
+fn format_eta(seconds: i64) -> String {
+    match (seconds / 3600, (seconds % 3600) / 60) {
+        (0, 0) => "< 1m".to_string(),
+        (0, m) => format!("{}m", m),
+        (h, m) => format!("{}h {}m", h, m),
+    }
+}
+
+// Upstream searches in progress, so the same search from several clients only hits IMDb or TheMovieDB once
+#[derive(Default)]
+pub struct SearchRequests {
+    imdb: Coalescer<SearchType, Result<Vec<IMDBItem>, String>>,
+    moviedb: Coalescer<SearchType, Result<Vec<MovieDBItem>, String>>,
+}
+
 // IMDB FUNCTIONS
-async fn check_cache_then_search_imdb(
+pub async fn check_cache_then_search_imdb(
     search_type: SearchType,
+    pagination: Pagination,
     db: web::Data<DBConnection>,
     cache_update: web::Data<Mutex<QueryCache>>,
+    searches: web::Data<SearchRequests>,
 ) -> Result<Vec<IMDBItem>, Error> {
     let imdb_db = IMDBDatabase::new(db.deref());
     let mut twelve_hour_ago: chrono::DateTime<Local> = Local::now();
@@ -190,15 +478,16 @@ async fn check_cache_then_search_imdb(
     };
     drop(cache_update);
 
-    let mut output: Vec<IMDBItem> = match imdb_db.fetch(search_type.to_owned()).await {
+    let mut output: Vec<IMDBItem> = match imdb_db.fetch(search_type.to_owned(), pagination).await {
         Ok(t) => t,
         Err(_) => return Err(ErrorInternalServerError("Failed to fetch from cache")),
     };
 
-    if updated_at.le(&twelve_hour_ago) || output.is_empty() {
+    // An empty page past the first just means there's nothing more
+    if updated_at.le(&twelve_hour_ago) || (output.is_empty() && pagination.page == 0) {
         match &search_type {
             SearchType::Query(_) | SearchType::Watchlist => {
-                if let Ok(a) = imdb_db.fetch(search_type.to_owned()).await {
+                if let Ok(a) = imdb_db.fetch(search_type.to_owned(), pagination).await {
                     if a.is_empty().not() {
                         return Ok(a);
                     }
@@ -208,56 +497,58 @@ async fn check_cache_then_search_imdb(
         };
 
         // Grab new results
-        let imdb: IMDB = IMDB::new(search_type, None);
-        let items = match imdb.search().await {
+        let items = searches
+            .imdb
+            .run(search_type.to_owned(), || async {
+                let imdb: IMDB = IMDB::new(search_type, None);
+                let items = imdb.search().await.map_err(|e| e.to_string())?;
+
+                // Update DB
+                match imdb_db.insert_or_update_many(&items).await {
+                    Ok(_) => Ok(items),
+                    Err(_) => Err("Failed to insert or update IMDB items".to_string()),
+                }
+            })
+            .await;
+        let items = match items {
             Ok(i) => i,
             Err(e) => return Err(ErrorInternalServerError(e)),
         };
-
-        // Update DB
-        match imdb_db.insert_or_update_many(&items).await {
-            Ok(_) => (),
-            Err(_) => {
-                return Err(ErrorInternalServerError(
-                    "Failed to insert or update IMDB items",
-                ))
-            }
-        };
-        output = items;
+        output = pagination.apply(items);
     };
 
     Ok(output)
 }
 
-fn generate_search_html_imdb(results: Vec<IMDBItem>) -> String {
-    let items = results
+fn generate_search_html_imdb(results: Vec<IMDBItem>, states: &HashMap<String, WatchlistStatus>) -> String {
+    results
         .par_iter()
-        .map(generate_item_html_imdb)
+        .map(|i| generate_item_html_imdb(i, states.get(&i.id)))
         .collect::<Vec<String>>()
-        .join("");
-
-    format!("<div class=\"results-container\">{}</div>", items)
+        .join("")
 }
 
-fn generate_item_html_imdb(item: &IMDBItem) -> String {
+fn generate_item_html_imdb(item: &IMDBItem, status: Option<&WatchlistStatus>) -> String {
     let _type = match item._type {
         ItemType::Movie => "movie",
         ItemType::TvShow => "tv",
     };
 
     format!("<div id=\"{}\" onclick=\"htmx.trigger('.htmx-request', 'htmx:abort')\" class=\"card\" style=\"width: 8rem; cursor: pointer;\" hx-get=\"/modal_metadata?id={}\" hx-target=\"#download-select\" hx-swap=\"outerHTML\" hx-indicator=\"#download-select\" hx-sync=\"#download-select:replace\" data-bs-toggle=\"modal\" data-bs-target=\"#download-modal\">\
-                <img src={} alt=\"media-image\" hx-trigger=\"intersect once\"/>\
+                <img src=\"image/{}\" alt=\"media-image\" loading=\"lazy\"/>\
                 <div class=\"card-body\">\
-                    <p class=\"card-text\">{} ({})</p>\
+                    <p class=\"card-text\">{} ({})</p>{}\
                 </div>\
-            </div>", &item.id, &item.id, item.image_url, item.title, item.year)
+            </div>", &item.id, &item.id, &item.id, item.title, item.year, generate_watchlist_state_html(status))
 }
 
 // THE MOVIE DB FUNCTIONS
-async fn check_cache_then_search_moviedb(
+pub async fn check_cache_then_search_moviedb(
     search_type: SearchType,
+    pagination: Pagination,
     db: web::Data<DBConnection>,
     cache_update: web::Data<Mutex<QueryCache>>,
+    searches: web::Data<SearchRequests>,
     app_config: Data<AppConfig>,
 ) -> Result<Vec<MovieDBItem>, Error> {
     let movie_db = MovieDBDatabase::new(db.deref());
@@ -281,31 +572,36 @@ async fn check_cache_then_search_moviedb(
     };
     drop(cache_update);
 
-    let mut output: Vec<MovieDBItem> = match movie_db.fetch(search_type.to_owned()).await {
+    let mut output: Vec<MovieDBItem> = match movie_db.fetch(search_type.to_owned(), pagination).await {
         Ok(t) => t,
         Err(e) => {
             return Err(ErrorInternalServerError(e));
         }
     };
 
-    if updated_at.le(&twelve_hour_ago) || output.is_empty() {
+    // An empty page past the first just means there's nothing more
+    if updated_at.le(&twelve_hour_ago) || (output.is_empty() && pagination.page == 0) {
         match &search_type {
             SearchType::Watchlist => (), // We already checked it, so no point rechecking
             _ => {
                 // Grab new results
-                let moviedb: MovieDB = MovieDB::new(&app_config.tmdb_api_key);
-                let items = match moviedb.search(search_type).await {
-                    Ok(i) => i,
+                let items = searches
+                    .moviedb
+                    .run(search_type.to_owned(), || async {
+                        let moviedb: MovieDB = MovieDB::new(&app_config.tmdb_api_key);
+                        let items = moviedb.search(search_type).await.map_err(|e| e.to_string())?;
+
+                        // Update DB
+                        match movie_db.insert_or_update_many(&items).await {
+                            Ok(_) => Ok(items),
+                            Err(e) => Err(e.to_string()), //"Failed to insert or update MovieDB items"
+                        }
+                    })
+                    .await;
+                match items {
+                    Ok(i) => output = pagination.apply(i),
                     Err(e) => return Err(ErrorInternalServerError(e)),
                 };
-
-                // Update DB
-                match movie_db.insert_or_update_many(&items).await {
-                    Ok(_) => {
-                        output = items;
-                    }
-                    Err(e) => return Err(ErrorInternalServerError(e)), //"Failed to insert or update MovieDB items"
-                };
             }
         };
     };
@@ -313,37 +609,28 @@ async fn check_cache_then_search_moviedb(
     Ok(output)
 }
 
-fn generate_search_html_moviedb(results: Vec<MovieDBItem>) -> String {
-    let items = results
+fn generate_search_html_moviedb(results: Vec<MovieDBItem>, states: &HashMap<String, WatchlistStatus>) -> String {
+    results
         .par_iter()
-        .map(generate_item_html_moviedb)
+        .map(|i| generate_item_html_moviedb(i, states.get(&format!("tt{}", i.id))))
         .collect::<Vec<String>>()
-        .join("");
-
-    format!("<div class=\"results-container\">{}</div>", items)
+        .join("")
 }
 
-fn generate_item_html_moviedb(item: &MovieDBItem) -> String {
+fn generate_item_html_moviedb(item: &MovieDBItem, status: Option<&WatchlistStatus>) -> String {
     let _type = match item._type {
         ItemType::Movie => "movie",
         ItemType::TvShow => "tv",
     };
 
-    let image_url = match &item.image_url {
-        Some(t) => format!("https://image.tmdb.org/t/p/w200{}", t),
-        None => {
-            "https://upload.wikimedia.org/wikipedia/commons/0/0a/No-image-available.png".to_string()
-        }
-    };
-
     let year = item.release_date.year();
 
     format!("<div id=\"{}\" onclick=\"htmx.trigger('.htmx-request', 'htmx:abort')\" class=\"card\" style=\"width: 8rem; cursor: pointer;\" hx-get=\"/modal_metadata?id={}\" hx-target=\"#download-select\" hx-swap=\"outerHTML\" hx-indicator=\"#download-select\" data-bs-toggle=\"modal\" data-bs-target=\"#download-modal\">\
-                <img src={} alt=\"media-image\" hx-trigger=\"intersect once\"/>\
+                <img src=\"image/{}\" alt=\"media-image\" loading=\"lazy\"/>\
                 <div class=\"card-body\">\
-                    <p class=\"card-text\">{} ({})</p>\
+                    <p class=\"card-text\">{} ({})</p>{}\
                 </div>\
-            </div>", &item.id, &item.id, image_url, item.title, year)
+            </div>", &item.id, &item.id, &item.id, item.title, year, generate_watchlist_state_html(status))
 }
 
 #[derive(Deserialize)]
@@ -360,76 +647,200 @@ pub async fn modal_metadata(
 ) -> Result<HttpResponse<String>, Error> {
     let body = match app_config.tmdb_api_key.is_empty() {
         true => {
-            let mut cached_item = match get_cached_item_imdb(&params.id, Data::clone(&db)).await {
+            let cached_item = match get_cached_item_imdb(&params.id, Data::clone(&db)).await {
                 Ok(t) => t,
                 Err(e) => return Err(ErrorInternalServerError(e)),
             };
 
-            let mut made_changes = false;
-
-            if cached_item.video_url.is_none() {
-                let query = format!("{} ({}) Trailer", cached_item.title, cached_item.year);
-                let video_url = match yt.search(&query).await {
-                    Ok(t) => t
-                        .par_iter()
-                        .find_first(|(title, _)| {
-                            let title = title.to_lowercase();
-                            let cache_title = cached_item.title.to_lowercase();
-                            title.contains(&cache_title) && title.contains("trailer")
-                        })
-                        .map(|(_, id)| id.to_string()),
-                    Err(e) => {
-                        error!("{}", e);
-                        None
+            imdb_modal_body(cached_item, false, &db, &yt, &app_config).await?
+        }
+        false => {
+            let mut cached_item = match get_cached_item_moviedb(&params.id, Data::clone(&db)).await {
+                Ok(t) => t,
+                Err(e) => return Err(ErrorInternalServerError(e)),
+            };
+
+            if app_config.fanart_api_key.is_empty().not()
+                && cached_item.poster_url.is_none()
+                && cached_item.backdrop_url.is_none()
+            {
+                let fanart_id = match cached_item._type {
+                    ItemType::Movie => Some(cached_item.id.to_string()),
+                    ItemType::TvShow => {
+                        match MovieDB::fetch_tvdb_id(&app_config.tmdb_api_key, cached_item.id).await {
+                            Ok(t) => t.map(|x| x.to_string()),
+                            Err(e) => {
+                                error!("{}", e);
+                                None
+                            }
+                        }
                     }
                 };
-                if video_url.is_some() {
-                    made_changes = true;
-                }
 
-                cached_item.video_url = video_url;
+                if let Some(fanart_id) = fanart_id {
+                    let fanart = Fanart::new(&app_config.fanart_api_key);
+                    match fanart.fetch_artwork(&fanart_id, &cached_item._type).await {
+                        Ok(t) => {
+                            if t.poster_url.is_some() || t.backdrop_url.is_some() {
+                                let movie_db = MovieDBDatabase::new(db.deref());
+                                match movie_db
+                                    .update_artwork(cached_item.id, t.poster_url.as_deref(), t.backdrop_url.as_deref())
+                                    .await
+                                {
+                                    Ok(_) => (),
+                                    Err(e) => return Err(ErrorInternalServerError(e)),
+                                };
+                            }
+                            cached_item.poster_url = t.poster_url;
+                            cached_item.backdrop_url = t.backdrop_url;
+                        }
+                        Err(e) => error!("{}", e),
+                    };
+                }
             }
 
-            if cached_item.plot.is_none() {
-                let metadata = match IMDB::update_media_data(&params.id, None, None).await {
-                    Ok(t) => t,
-                    Err(e) => return Err(ErrorInternalServerError(e)),
-                };
+            create_modal_body_moviedb(&cached_item, app_config.read_only)
+        }
+    };
 
-                if metadata.plot.is_some() {
-                    made_changes = true;
-                }
+    Ok(HttpResponse::Ok().message_body(body).unwrap())
+}
 
-                cached_item.plot = metadata.plot;
-                cached_item.rating = metadata.rating;
-                cached_item.runtime = metadata.runtime;
-            }
+#[derive(Deserialize)]
+struct RefreshItemPath {
+    id: String,
+}
 
-            if made_changes {
-                let imdb_db = IMDBDatabase::new(db.deref());
-                match imdb_db.update_metadata(&cached_item).await {
-                    Ok(_) => (),
-                    Err(e) => return Err(ErrorInternalServerError(e)),
-                };
-            }
+// Fetches plot, rating and runtime again without waiting for metadata_refresh_days, returns the updated modal
+#[post("/item/{id}/refresh")]
+pub async fn refresh_item(
+    path: web::Path<RefreshItemPath>,
+    db: web::Data<DBConnection>,
+    yt: web::Data<Youtube>,
+    app_config: Data<AppConfig>,
+) -> Result<HttpResponse<String>, Error> {
+    if app_config.read_only {
+        return Err(ErrorForbidden("Read-only mode"));
+    }
+    if app_config.tmdb_api_key.is_empty().not() {
+        return Err(ErrorBadRequest("Only IMDb metadata can be refreshed"));
+    }
 
-            create_modal_body_imdb(&cached_item)
-        }
-        false => {
-            let cached_item = match get_cached_item_moviedb(&params.id, Data::clone(&db)).await {
-                Ok(t) => t,
-                Err(e) => return Err(ErrorInternalServerError(e)),
-            };
+    let cached_item = match get_cached_item_imdb(&path.id, Data::clone(&db)).await {
+        Ok(t) => t,
+        Err(_) => return Err(ErrorNotFound("Item not found, search for it first")),
+    };
+
+    let body = imdb_modal_body(cached_item, true, &db, &yt, &app_config).await?;
+    Ok(HttpResponse::Ok().message_body(body).unwrap())
+}
 
-            create_modal_body_moviedb(&cached_item)
+// Fills in the trailer, metadata and artwork that are missing or stale before rendering. force_refresh fetches the
+// metadata again whatever its age, for the refresh button
+async fn imdb_modal_body(
+    mut cached_item: IMDBItem,
+    force_refresh: bool,
+    db: &DBConnection,
+    yt: &Youtube,
+    app_config: &AppConfig,
+) -> Result<String, Error> {
+    let mut made_changes = false;
+
+    if cached_item.video_url.is_none() {
+        let query = format!("{} ({}) Trailer", cached_item.title, cached_item.year);
+        let video_url = match yt.search(&query).await {
+            Ok(t) => t
+                .par_iter()
+                .find_first(|(title, _)| {
+                    let title = title.to_lowercase();
+                    let cache_title = cached_item.title.to_lowercase();
+                    title.contains(&cache_title) && title.contains("trailer")
+                })
+                .map(|(_, id)| id.to_string()),
+            Err(e) => {
+                error!("{}", e);
+                None
+            }
+        };
+        if video_url.is_some() {
+            made_changes = true;
         }
+
+        cached_item.video_url = video_url;
+    }
+
+    let imdb_db = IMDBDatabase::new(db);
+    let stale = match imdb_db.is_metadata_stale(&cached_item.id, app_config.metadata_refresh_days).await {
+        Ok(t) => t,
+        Err(e) => return Err(ErrorInternalServerError(e)),
     };
+    if cached_item.plot.is_none() || force_refresh {
+        match refresh_imdb_metadata(&mut cached_item, db).await {
+            Ok(t) => made_changes |= t,
+            Err(e) => return Err(ErrorInternalServerError(e)),
+        };
+    } else if stale {
+        // The cached plot is still worth showing if IMDb can't be reached
+        match refresh_imdb_metadata(&mut cached_item, db).await {
+            Ok(t) => made_changes |= t,
+            Err(e) => warn!("Failed to refresh metadata for {}: {}", cached_item.id, e),
+        };
+    }
 
-    Ok(HttpResponse::Ok().message_body(body).unwrap())
+    if made_changes {
+        match imdb_db.update_metadata(&cached_item).await {
+            Ok(_) => (),
+            Err(e) => return Err(ErrorInternalServerError(e)),
+        };
+    }
+
+    // fanart.tv only indexes TV shows by TheTVDB id, which IMDb doesn't provide
+    if app_config.fanart_api_key.is_empty().not()
+        && matches!(cached_item._type, ItemType::Movie)
+        && cached_item.poster_url.is_none()
+        && cached_item.backdrop_url.is_none()
+    {
+        let fanart = Fanart::new(&app_config.fanart_api_key);
+        match fanart.fetch_artwork(&cached_item.id, &cached_item._type).await {
+            Ok(t) => {
+                if t.poster_url.is_some() || t.backdrop_url.is_some() {
+                    match imdb_db
+                        .update_artwork(&cached_item.id, t.poster_url.as_deref(), t.backdrop_url.as_deref())
+                        .await
+                    {
+                        Ok(_) => (),
+                        Err(e) => return Err(ErrorInternalServerError(e)),
+                    };
+                }
+                cached_item.poster_url = t.poster_url;
+                cached_item.backdrop_url = t.backdrop_url;
+            }
+            Err(e) => error!("{}", e),
+        };
+    }
+
+    Ok(create_modal_body_imdb(&cached_item, app_config.read_only))
+}
+
+// Returns whether IMDb had a plot, the caller saves the changes
+async fn refresh_imdb_metadata(item: &mut IMDBItem, db: &DBConnection) -> anyhow::Result<bool> {
+    let metadata = IMDB::update_media_data(&item.id, None, None).await?;
+    if let Err(e) = IMDBDatabase::new(db).mark_metadata_updated(&item.id).await {
+        warn!("Failed to record metadata refresh for {}: {}", item.id, e);
+    }
+
+    let made_changes = metadata.plot.is_some();
+    item.plot = metadata.plot.or(item.plot.take());
+    if metadata.rating.is_empty().not() && metadata.rating != "TBD" {
+        item.rating = metadata.rating;
+    }
+    item.runtime = metadata.runtime.or(item.runtime);
+
+    Ok(made_changes)
 }
 
 // IMDB Functions
-async fn get_cached_item_imdb(id: &str, db: Data<DBConnection>) -> anyhow::Result<IMDBItem> {
+pub async fn get_cached_item_imdb(id: &str, db: Data<DBConnection>) -> anyhow::Result<IMDBItem> {
     let imdb_db = IMDBDatabase::new(db.deref());
     let items = imdb_db.fetch_item_by_id(id).await?;
     if items.is_empty() {
@@ -440,7 +851,7 @@ async fn get_cached_item_imdb(id: &str, db: Data<DBConnection>) -> anyhow::Resul
     Ok(item)
 }
 
-fn create_modal_body_imdb(item: &IMDBItem) -> String {
+fn create_modal_body_imdb(item: &IMDBItem, read_only: bool) -> String {
     let title = &item.title;
     let mut subheading = format!("{} | {}", item.year, &item.rating);
 
@@ -452,24 +863,50 @@ fn create_modal_body_imdb(item: &IMDBItem) -> String {
     let default_plot = "".to_string();
     let plot = item.plot.as_ref().unwrap_or(&default_plot);
 
+    let artwork = create_artwork_header(item.poster_url.as_ref(), item.backdrop_url.as_ref());
+
+    let refresh_button = format!(
+        "<button type=\"button\" class=\"btn btn-sm btn-outline-secondary\" hx-post=\"/item/{}/refresh\" hx-target=\"#download-select\" hx-swap=\"outerHTML\" hx-disabled-elt=\"this\">Refresh</button>",
+        item.id
+    );
+
     let heading = format!(
         "<div>\
+    {}\
     <h2>{}</h2>\
-    <p><small>{}</small></p>\
+    <p><small>{}</small> {}</p>\
     <p>{}</p>\
     </div>",
-        title, subheading, plot
+        artwork, title, subheading, refresh_button, plot
     );
 
-    let watchlist_button = super::download::create_watchlist_button(&item.id, item.watchlist);
-    let accordion = create_accordion_imdb(item);
+    let watchlist_button = match read_only {
+        true => String::new(),
+        false => super::download::create_watchlist_button(&item.id, item.watchlist),
+    };
+    let accordion = create_accordion_imdb(item, read_only);
 
     let html = format!("<div id=\"download-select\">{}{}<div id=\"modal_accordion\" class=\"accordion\">{}</div></div>", heading, watchlist_button, accordion);
 
     html
 }
 
-fn create_accordion_imdb(item: &IMDBItem) -> String {
+// Prefers the wide backdrop, falling back to the high-res poster
+fn create_artwork_header(poster_url: Option<&String>, backdrop_url: Option<&String>) -> String {
+    match (backdrop_url, poster_url) {
+        (Some(t), _) => format!(
+            "<img src=\"{}\" alt=\"backdrop\" class=\"img-fluid rounded\" style=\"width: 100%; margin-bottom: 0.5rem;\"/>",
+            t
+        ),
+        (None, Some(t)) => format!(
+            "<img src=\"{}\" alt=\"poster\" class=\"img-fluid rounded\" style=\"max-height: 20rem; margin-bottom: 0.5rem;\"/>",
+            t
+        ),
+        (None, None) => String::new(),
+    }
+}
+
+fn create_accordion_imdb(item: &IMDBItem, read_only: bool) -> String {
     let video_url = match &item.video_url {
         Some(t) => t.to_string(),
         None => "".to_string(),
@@ -510,15 +947,19 @@ fn create_accordion_imdb(item: &IMDBItem) -> String {
         <div id=\"collapseDownload\" class=\"accordion-collapse collapse\" data-bs-parent=\"#modal_accordion\">\
             <div class=\"accordion-body\">\
                 <div id=\"load-spinner-accordion\" class=\"htmx-indicator spinner-border\" hx-get=\"/find_download?imdb_id={}&title={}&type={}\" hx-swap=\"outerHTML\" hx-trigger=\"load\" hx-indicator=\"#load-spinner-accordion\"></div>
+                {}\
             </div>\
         </div>\
-    </div>", id, title_encoded, _type);
+    </div>", id, title_encoded, _type, create_magnet_form(id, &item._type, read_only));
+
+    let similar_segment = create_similar_segment(&item.id);
+    let aliases_segment = create_aliases_segment(&item.id);
 
-    format!("{}{}", trailer_segment, download_segment)
+    format!("{}{}{}{}", trailer_segment, download_segment, similar_segment, aliases_segment)
 }
 
 // MovieDB Functions
-async fn get_cached_item_moviedb(id: &str, db: Data<DBConnection>) -> anyhow::Result<MovieDBItem> {
+pub async fn get_cached_item_moviedb(id: &str, db: Data<DBConnection>) -> anyhow::Result<MovieDBItem> {
     let movie_db = MovieDBDatabase::new(db.deref());
     let id = id.parse::<i32>().unwrap();
     let items = movie_db.fetch_item_by_id(id).await?;
@@ -530,7 +971,7 @@ async fn get_cached_item_moviedb(id: &str, db: Data<DBConnection>) -> anyhow::Re
     Ok(item)
 }
 
-fn create_modal_body_moviedb(item: &MovieDBItem) -> String {
+fn create_modal_body_moviedb(item: &MovieDBItem, read_only: bool) -> String {
     let year = item.release_date.year();
     let rating = match &item.certification {
         Some(t) => t.to_string(),
@@ -547,26 +988,32 @@ fn create_modal_body_moviedb(item: &MovieDBItem) -> String {
         None => (),
     };
 
+    let artwork = create_artwork_header(item.poster_url.as_ref(), item.backdrop_url.as_ref());
+
     let heading = format!(
         "<div>\
+    {}\
     <h2>{}</h2>\
     <p><small>{}</small></p>\
     <p>{}</p>\
     </div>",
-        &item.title, subheading, &item.plot
+        artwork, &item.title, subheading, &item.plot
     );
 
     let id = item.id.to_string();
 
-    let watchlist_button = super::download::create_watchlist_button(&id, item.watchlist);
-    let accordion = create_accordion_moviedb(&item);
+    let watchlist_button = match read_only {
+        true => String::new(),
+        false => super::download::create_watchlist_button(&id, item.watchlist),
+    };
+    let accordion = create_accordion_moviedb(&item, read_only);
 
     let html = format!("<div id=\"download-select\">{}{}<div id=\"modal_accordion\" class=\"accordion\">{}</div></div>", heading, watchlist_button, accordion);
 
     html
 }
 
-fn create_accordion_moviedb(item: &MovieDBItem) -> String {
+fn create_accordion_moviedb(item: &MovieDBItem, read_only: bool) -> String {
     let video_id = match &item.video_id {
         Some(t) => t.to_string(),
         None => "".to_string(),
@@ -602,9 +1049,175 @@ fn create_accordion_moviedb(item: &MovieDBItem) -> String {
         <div id=\"collapseDownload\" class=\"accordion-collapse collapse\" data-bs-parent=\"#modal_accordion\">\
             <div class=\"accordion-body\">\
                 <div id=\"load-spinner-accordion\" class=\"htmx-indicator spinner-border\" hx-get=\"/find_download?imdb_id={}&title={}&type={}\" hx-swap=\"outerHTML\" hx-trigger=\"load\" hx-indicator=\"#load-spinner-accordion\"></div>
+                {}\
+            </div>\
+        </div>\
+    </div>", item.id, title_encoded, _type, create_magnet_form(&item.id.to_string(), &item._type, read_only));
+
+    let similar_segment = create_similar_segment(&item.id.to_string());
+    let aliases_segment = create_aliases_segment(&item.id.to_string());
+
+    format!("{}{}{}{}", trailer_segment, download_segment, similar_segment, aliases_segment)
+}
+
+fn create_similar_segment(id: &str) -> String {
+    format!("<div class=\"accordion-item\">\
+        <h3 class=\"accordion-header\">\
+            <button class=\"accordion-button collapsed\" type=\"button\" data-bs-toggle=\"collapse\" data-bs-target=\"#collapseSimilar\" aria-expanded=\"false\" aria-controls=\"collapseSimilar\">\
+                More like this\
+            </button>\
+        </h3>\
+        <div id=\"collapseSimilar\" class=\"accordion-collapse collapse\" data-bs-parent=\"#modal_accordion\">\
+            <div class=\"accordion-body\">\
+                <div id=\"load-spinner-similar\" class=\"htmx-indicator spinner-border\" hx-get=\"/more_like_this?id={}\" hx-swap=\"outerHTML\" hx-trigger=\"intersect once\" hx-indicator=\"#load-spinner-similar\"></div>
+            </div>\
+        </div>\
+    </div>", id)
+}
+
+// For adding a release the indexers didn't find
+fn create_magnet_form(id: &str, _type: &ItemType, read_only: bool) -> String {
+    if read_only {
+        return String::new();
+    }
+
+    let episode_inputs = match _type {
+        ItemType::Movie => "",
+        ItemType::TvShow => "<input type=\"number\" name=\"season\" class=\"form-control\" placeholder=\"Season\" min=\"0\" style=\"max-width: 6rem;\">\
+            <input type=\"number\" name=\"episode\" class=\"form-control\" placeholder=\"Episode\" min=\"0\" style=\"max-width: 6rem;\">",
+    };
+
+    format!("<form hx-post=\"/downloads/magnet\" hx-target=\"find .magnet-result\" hx-disabled-elt=\"find button\" style=\"margin-top: 0.5rem;\">\
+        <input type=\"hidden\" name=\"imdb_id\" value=\"{}\">\
+        <div class=\"input-group input-group-sm\">\
+            <input type=\"text\" name=\"magnet_uri\" class=\"form-control\" placeholder=\"magnet:?xt=urn:btih:...\" required>\
+            {}\
+            <button type=\"submit\" class=\"btn btn-outline-secondary\">Add magnet</button>\
+        </div>\
+        <div class=\"magnet-result\"></div>\
+    </form>", escape_html(id), episode_inputs)
+}
+
+fn create_aliases_segment(id: &str) -> String {
+    format!("<div class=\"accordion-item\">\
+        <h3 class=\"accordion-header\">\
+            <button class=\"accordion-button collapsed\" type=\"button\" data-bs-toggle=\"collapse\" data-bs-target=\"#collapseAliases\" aria-expanded=\"false\" aria-controls=\"collapseAliases\">\
+                Alternate titles\
+            </button>\
+        </h3>\
+        <div id=\"collapseAliases\" class=\"accordion-collapse collapse\" data-bs-parent=\"#modal_accordion\">\
+            <div class=\"accordion-body\">\
+                <div id=\"load-spinner-aliases\" class=\"htmx-indicator spinner-border\" hx-get=\"/aliases?id={}\" hx-swap=\"outerHTML\" hx-trigger=\"intersect once\" hx-indicator=\"#load-spinner-aliases\"></div>\
             </div>\
         </div>\
-    </div>", item.id, title_encoded, _type);
+    </div>", id)
+}
 
-    format!("{}{}", trailer_segment, download_segment)
+#[get("/more_like_this")]
+pub async fn more_like_this(
+    params: Query<ModalMetadataQuery>,
+    db: web::Data<DBConnection>,
+    app_config: Data<AppConfig>,
+) -> Result<HttpResponse<String>, Error> {
+    let similar_db = SimilarDatabase::new(db.deref());
+
+    let cards = match app_config.tmdb_api_key.is_empty() {
+        true => {
+            let mut items = match similar_db.fetch_imdb(&params.id).await {
+                Ok(t) => t,
+                Err(e) => return Err(ErrorInternalServerError(e)),
+            };
+
+            if items.is_empty() {
+                items = match IMDB::fetch_more_like_this(&params.id, None, None).await {
+                    Ok(t) => t,
+                    Err(e) => {
+                        return Ok(HttpResponse::Ok()
+                            .message_body(format!("<b>{}</b>", e))
+                            .unwrap())
+                    }
+                };
+
+                let imdb_db = IMDBDatabase::new(db.deref());
+                match imdb_db.insert_or_update_many(&items).await {
+                    Ok(_) => (),
+                    Err(e) => return Err(ErrorInternalServerError(e)),
+                };
+
+                let ids = items.iter().map(|x| x.id.to_owned()).collect::<Vec<String>>();
+                match similar_db.insert_many(&params.id, &ids).await {
+                    Ok(_) => (),
+                    Err(e) => return Err(ErrorInternalServerError(e)),
+                };
+            }
+
+            items
+                .par_iter()
+                .map(|x| generate_similar_item_html(&x.id, &x.title, x.year))
+                .collect::<Vec<String>>()
+        }
+        false => {
+            let mut items = match similar_db.fetch_moviedb(&params.id).await {
+                Ok(t) => t,
+                Err(e) => return Err(ErrorInternalServerError(e)),
+            };
+
+            if items.is_empty() {
+                let cached_item = match get_cached_item_moviedb(&params.id, Data::clone(&db)).await {
+                    Ok(t) => t,
+                    Err(e) => return Err(ErrorInternalServerError(e)),
+                };
+
+                let moviedb = MovieDB::new(&app_config.tmdb_api_key);
+                items = match moviedb.fetch_recommendations(cached_item.id, &cached_item._type).await {
+                    Ok(t) => t,
+                    Err(e) => {
+                        return Ok(HttpResponse::Ok()
+                            .message_body(format!("<b>{}</b>", e))
+                            .unwrap())
+                    }
+                };
+
+                let movie_db = MovieDBDatabase::new(db.deref());
+                match movie_db.insert_or_update_many(&items).await {
+                    Ok(_) => (),
+                    Err(e) => return Err(ErrorInternalServerError(e)),
+                };
+
+                let ids = items.iter().map(|x| x.id.to_string()).collect::<Vec<String>>();
+                match similar_db.insert_many(&params.id, &ids).await {
+                    Ok(_) => (),
+                    Err(e) => return Err(ErrorInternalServerError(e)),
+                };
+            }
+
+            items
+                .par_iter()
+                .map(|x| generate_similar_item_html(&x.id.to_string(), &x.title, x.release_date.year() as i64))
+                .collect::<Vec<String>>()
+        }
+    };
+
+    if cards.is_empty() {
+        return Ok(HttpResponse::Ok()
+            .message_body("<b>No similar titles found</b>".to_string())
+            .unwrap());
+    }
+
+    let html = format!(
+        "<div style=\"display: flex; flex-direction: row; flex-wrap: wrap; gap: 0.5rem;\">{}</div>",
+        cards.join("")
+    );
+
+    Ok(HttpResponse::Ok().message_body(html).unwrap())
+}
+
+// Doesn't toggle the modal like the search cards, as it is already open
+fn generate_similar_item_html(id: &str, title: &str, year: i64) -> String {
+    format!("<div class=\"card\" style=\"width: 6rem; cursor: pointer;\" hx-get=\"/modal_metadata?id={}\" hx-target=\"#download-select\" hx-swap=\"outerHTML\" hx-indicator=\"#download-select\" hx-sync=\"#download-select:replace\">\
+                <img src=\"image/{}\" alt=\"media-image\" loading=\"lazy\"/>\
+                <div class=\"card-body\" style=\"padding: 0.25rem;\">\
+                    <p class=\"card-text\"><small>{} ({})</small></p>\
+                </div>\
+            </div>", id, id, title, year)
 }
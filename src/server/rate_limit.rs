@@ -0,0 +1,174 @@
+use std::collections::HashMap;
+use std::future::{ready, Ready};
+use std::net::IpAddr;
+use std::ops::Not;
+use std::rc::Rc;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+use actix_web::{Error, HttpResponse};
+use actix_web::body::EitherBody;
+use actix_web::dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform};
+use actix_web::http::header;
+use futures::future::LocalBoxFuture;
+use log::warn;
+
+use crate::server::auth::{hash_api_key, request_api_key};
+
+// Once this many clients are tracked, full buckets are dropped since they'd behave the same as a new one
+const MAX_TRACKED_CLIENTS: usize = 10_000;
+
+struct Bucket {
+    tokens: f64,
+    updated_at: Instant,
+}
+
+// Token bucket per client IP, and per API key when one is sent, on the endpoints that scrape IMDb or the indexers.
+// Each client can make `burst` requests at once, refilled at `per_minute`.
+#[derive(Clone)]
+pub struct RateLimit {
+    per_minute: u32,
+    burst: u32,
+    // Only requests from these addresses have their X-Forwarded-For or Forwarded header believed
+    trusted_proxies: Vec<IpAddr>,
+    base_path: String,
+    buckets: Arc<Mutex<HashMap<String, Bucket>>>,
+}
+
+impl RateLimit {
+    pub fn new(per_minute: u32, burst: u32, trusted_proxies: &[String], base_path: &str) -> Self {
+        let trusted_proxies = trusted_proxies
+            .iter()
+            .filter_map(|p| match p.trim().parse::<IpAddr>() {
+                Ok(t) => Some(t),
+                Err(_) => {
+                    warn!("Ignoring trusted proxy {}, it isn't an IP address", p);
+                    None
+                }
+            })
+            .collect();
+
+        RateLimit {
+            per_minute,
+            burst: burst.max(1),
+            trusted_proxies,
+            base_path: base_path.to_string(),
+            buckets: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    fn client_ip(&self, req: &ServiceRequest) -> String {
+        match req.peer_addr().map(|a| a.ip()) {
+            Some(ip) if self.trusted_proxies.contains(&ip) => req
+                .connection_info()
+                .realip_remote_addr()
+                .map(|t| t.to_string())
+                .unwrap_or(ip.to_string()),
+            Some(ip) => ip.to_string(),
+            None => "unknown".to_string(),
+        }
+    }
+
+    fn is_enabled(&self) -> bool {
+        self.per_minute > 0
+    }
+
+    fn is_limited(&self, path: &str) -> bool {
+        let path = path.strip_prefix(self.base_path.as_str()).unwrap_or(path);
+        matches!(
+            path,
+            "/search" | "/find_download" | "/find_episode_download" | "/api/v1/search" | "/api/v3/movie/lookup" | "/api/v3/series/lookup"
+        ) || (path.starts_with("/api/v1/items/") && path.ends_with("/torrents"))
+    }
+
+    // Takes a token from every bucket, returns the seconds until one is available if any are empty
+    fn acquire(&self, keys: &[String]) -> Result<(), u64> {
+        let now = Instant::now();
+        let rate = self.per_minute as f64 / 60.0;
+        let capacity = self.burst as f64;
+        let mut buckets = self.buckets.lock().unwrap();
+
+        if buckets.len() >= MAX_TRACKED_CLIENTS {
+            buckets.retain(|_, b| b.tokens + now.duration_since(b.updated_at).as_secs_f64() * rate < capacity);
+        }
+
+        let mut wait = 0.0_f64;
+        for key in keys {
+            let bucket = buckets.entry(key.to_owned()).or_insert(Bucket { tokens: capacity, updated_at: now });
+            bucket.tokens = (bucket.tokens + now.duration_since(bucket.updated_at).as_secs_f64() * rate).min(capacity);
+            bucket.updated_at = now;
+            if bucket.tokens < 1.0 {
+                wait = wait.max((1.0 - bucket.tokens) / rate);
+            }
+        }
+
+        if wait > 0.0 {
+            return Err(wait.ceil() as u64);
+        }
+        for key in keys {
+            if let Some(bucket) = buckets.get_mut(key) {
+                bucket.tokens -= 1.0;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<S, B> Transform<S, ServiceRequest> for RateLimit
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Transform = RateLimitMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(RateLimitMiddleware { service: Rc::new(service), limit: self.clone() }))
+    }
+}
+
+pub struct RateLimitMiddleware<S> {
+    service: Rc<S>,
+    limit: RateLimit,
+}
+
+impl<S, B> Service<ServiceRequest> for RateLimitMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let service = Rc::clone(&self.service);
+
+        if self.limit.is_enabled().not() || self.limit.is_limited(req.path()).not() {
+            return Box::pin(async move { service.call(req).await.map(|r| r.map_into_left_body()) });
+        }
+
+        let mut keys = vec![format!("ip:{}", self.limit.client_ip(&req))];
+        if let Some(key) = request_api_key(&req) {
+            keys.push(format!("key:{}", hash_api_key(&key)));
+        }
+        let result = self.limit.acquire(&keys);
+
+        Box::pin(async move {
+            match result {
+                Ok(_) => service.call(req).await.map(|r| r.map_into_left_body()),
+                Err(retry_after) => {
+                    let response = HttpResponse::TooManyRequests()
+                        .insert_header((header::RETRY_AFTER, retry_after.to_string()))
+                        .body(format!("<b>Too many requests, try again in {} seconds</b>", retry_after));
+                    Ok(req.into_response(response).map_into_right_body())
+                }
+            }
+        })
+    }
+}
\ No newline at end of file
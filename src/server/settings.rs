@@ -0,0 +1,230 @@
+use std::ops::Not;
+
+use actix_web::{Error, get, HttpResponse, post};
+use actix_web::error::{ErrorBadRequest, ErrorForbidden, ErrorInternalServerError};
+use actix_web::web::{Data, Form, Query};
+use serde::Deserialize;
+
+use crate::AppConfig;
+use crate::api::settings::{INDEXERS, RuntimeSettings, SharedSettings};
+use crate::api::torrent::MediaQuality;
+use crate::db::DBConnection;
+use crate::server::api_v1::json_response;
+use crate::server::escape_html;
+
+const QUALITIES: [MediaQuality; 8] = [
+    MediaQuality::Unknown,
+    MediaQuality::Cam,
+    MediaQuality::Telesync,
+    MediaQuality::_480p,
+    MediaQuality::_720p,
+    MediaQuality::_1080p,
+    MediaQuality::_2160p,
+    MediaQuality::_4320p,
+];
+
+#[derive(Deserialize)]
+pub struct SettingsQuery {
+    format: Option<String>,
+}
+
+#[get("/settings")]
+pub async fn get_settings(
+    params: Query<SettingsQuery>,
+    settings: Data<SharedSettings>,
+) -> Result<HttpResponse<String>, Error> {
+    let current = settings.load();
+
+    match params.format.as_deref() {
+        Some("html") => Ok(HttpResponse::Ok().message_body(create_settings_form(&current, None)).unwrap()),
+        _ => json_response(current.as_ref()),
+    }
+}
+
+// Lists are one entry per line, unticked indexer boxes aren't sent at all
+#[derive(Deserialize)]
+pub struct SettingsForm {
+    minimum_quality: usize,
+    trackers: String,
+    watchlist_recheck_interval_hours: i64,
+    notification_webhooks: String,
+    #[serde(default, rename = "indexer_YTS")]
+    yts: Option<String>,
+    #[serde(default, rename = "indexer_EZTV")]
+    eztv: Option<String>,
+    #[serde(default, rename = "indexer_TheRARBG")]
+    therarbg: Option<String>,
+}
+
+#[post("/settings")]
+pub async fn update_settings(
+    form: Form<SettingsForm>,
+    settings: Data<SharedSettings>,
+    db: Data<DBConnection>,
+    app_config: Data<AppConfig>,
+) -> Result<HttpResponse<String>, Error> {
+    let form = form.into_inner();
+    let minimum_quality = match QUALITIES.get(form.minimum_quality) {
+        Some(t) => *t,
+        None => return Err(ErrorBadRequest("Unknown quality")),
+    };
+    let enabled = [form.yts.is_some(), form.eztv.is_some(), form.therarbg.is_some()];
+
+    let updated = RuntimeSettings {
+        minimum_quality,
+        trackers: split_lines(&form.trackers),
+        watchlist_recheck_interval_hours: form.watchlist_recheck_interval_hours,
+        disabled_indexers: INDEXERS
+            .iter()
+            .zip(enabled)
+            .filter(|(_, enabled)| enabled.not())
+            .map(|(name, _)| name.to_string())
+            .collect(),
+        notification_webhooks: split_lines(&form.notification_webhooks),
+    };
+
+    let message = match apply_settings(updated, &settings, &db, &app_config).await {
+        Ok(_) => "<div class=\"alert alert-success\" role=\"alert\">Settings saved</div>".to_string(),
+        Err(e) => format!("<div class=\"alert alert-danger\" role=\"alert\">{}</div>", escape_html(&e.to_string())),
+    };
+
+    Ok(HttpResponse::Ok()
+        .message_body(create_settings_form(&settings.load(), Some(&message)))
+        .unwrap())
+}
+
+// Same as saving the config file, which is picked up within a few seconds anyway
+#[post("/settings/reload")]
+pub async fn reload_settings(
+    settings: Data<SharedSettings>,
+    db: Data<DBConnection>,
+    app_config: Data<AppConfig>,
+) -> Result<HttpResponse<String>, Error> {
+    if app_config.read_only {
+        return Err(ErrorForbidden("Read-only mode"));
+    }
+
+    let message = match settings.reload(&db).await {
+        Ok(t) if t.is_empty() => {
+            "<div class=\"alert alert-info\" role=\"alert\">The config file doesn't change any settings</div>".to_string()
+        }
+        Ok(t) => format!(
+            "<div class=\"alert alert-success\" role=\"alert\">Reloaded {} from the config file</div>",
+            escape_html(&t.join(", "))
+        ),
+        Err(e) => format!("<div class=\"alert alert-danger\" role=\"alert\">{}</div>", escape_html(&e.to_string())),
+    };
+
+    Ok(HttpResponse::Ok()
+        .message_body(create_settings_form(&settings.load(), Some(&message)))
+        .unwrap())
+}
+
+// Validates, stores and swaps in new settings, shared with the JSON API
+pub async fn apply_settings(
+    updated: RuntimeSettings,
+    settings: &SharedSettings,
+    db: &DBConnection,
+    app_config: &AppConfig,
+) -> Result<(), Error> {
+    if app_config.read_only {
+        return Err(ErrorForbidden("Read-only mode"));
+    }
+    if updated.watchlist_recheck_interval_hours < 1 {
+        return Err(ErrorBadRequest("Watchlist interval must be at least 1 hour"));
+    }
+    if let Some(t) = updated
+        .disabled_indexers
+        .iter()
+        .find(|i| INDEXERS.iter().any(|n| n.eq_ignore_ascii_case(i)).not())
+    {
+        return Err(ErrorBadRequest(format!("Unknown indexer {}", t)));
+    }
+    if let Some(t) = updated
+        .notification_webhooks
+        .iter()
+        .find(|u| (u.starts_with("http://") || u.starts_with("https://")).not())
+    {
+        return Err(ErrorBadRequest(format!("Webhook {} isn't an http(s) URL", t)));
+    }
+
+    if let Err(e) = updated.save_to_db(db).await {
+        return Err(ErrorInternalServerError(e));
+    }
+    settings.store(updated);
+    Ok(())
+}
+
+fn split_lines(input: &str) -> Vec<String> {
+    input
+        .lines()
+        .map(|l| l.trim())
+        .filter(|l| l.is_empty().not())
+        .map(|l| l.to_string())
+        .collect()
+}
+
+fn create_settings_form(settings: &RuntimeSettings, message: Option<&str>) -> String {
+    let qualities = QUALITIES
+        .iter()
+        .enumerate()
+        .map(|(i, q)| {
+            let selected = match *q == settings.minimum_quality {
+                true => " selected",
+                false => "",
+            };
+            format!("<option value=\"{}\"{}>{}</option>", i, selected, q)
+        })
+        .collect::<Vec<String>>()
+        .join("");
+
+    let indexers = INDEXERS
+        .iter()
+        .map(|name| {
+            let checked = match settings.indexer_enabled(name) {
+                true => " checked",
+                false => "",
+            };
+            format!(
+                "<div class=\"form-check form-check-inline\"><input class=\"form-check-input\" type=\"checkbox\" id=\"indexer_{0}\" name=\"indexer_{0}\"{1}><label class=\"form-check-label\" for=\"indexer_{0}\">{0}</label></div>",
+                name, checked
+            )
+        })
+        .collect::<Vec<String>>()
+        .join("");
+
+    format!("<div id=\"settings\" style=\"max-width: 40rem; margin: 0.5rem auto; padding: 0.5rem;\">\
+    {}\
+    <form hx-post=\"/settings\" hx-target=\"#settings\" hx-swap=\"outerHTML\">\
+        <div class=\"mb-3\">\
+            <label class=\"form-label\" for=\"minimum_quality\">Minimum quality</label>\
+            <select class=\"form-select\" id=\"minimum_quality\" name=\"minimum_quality\">{}</select>\
+        </div>\
+        <div class=\"mb-3\">\
+            <label class=\"form-label\" for=\"watchlist_recheck_interval_hours\">Watchlist check interval (hours, at least 6 are used)</label>\
+            <input class=\"form-control\" type=\"number\" min=\"1\" id=\"watchlist_recheck_interval_hours\" name=\"watchlist_recheck_interval_hours\" value=\"{}\">\
+        </div>\
+        <div class=\"mb-3\">\
+            <label class=\"form-label\">Indexers</label>\
+            <div>{}</div>\
+        </div>\
+        <div class=\"mb-3\">\
+            <label class=\"form-label\" for=\"trackers\">Extra trackers (one per line)</label>\
+            <textarea class=\"form-control\" rows=\"4\" id=\"trackers\" name=\"trackers\">{}</textarea>\
+        </div>\
+        <div class=\"mb-3\">\
+            <label class=\"form-label\" for=\"notification_webhooks\">Notification webhooks (one per line)</label>\
+            <textarea class=\"form-control\" rows=\"3\" id=\"notification_webhooks\" name=\"notification_webhooks\">{}</textarea>\
+        </div>\
+        <button type=\"submit\" class=\"btn btn-primary\">Save</button>\
+        <button type=\"button\" class=\"btn btn-secondary\" hx-post=\"/settings/reload\" hx-target=\"#settings\" hx-swap=\"outerHTML\">Reload config file</button>\
+    </form>\
+</div>",
+        message.unwrap_or_default(),
+        qualities,
+        settings.watchlist_recheck_interval_hours,
+        indexers,
+        escape_html(&settings.trackers.join("\n")),
+        escape_html(&settings.notification_webhooks.join("\n")),
+    )
+}
\ No newline at end of file
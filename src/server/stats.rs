@@ -0,0 +1,244 @@
+use std::ops::{Deref, Not};
+
+use actix_web::{Error, get, HttpResponse};
+use actix_web::error::ErrorInternalServerError;
+use actix_web::web::{Data, Query};
+use chrono::Datelike;
+use log::warn;
+use serde::{Deserialize, Serialize};
+
+use crate::AppConfig;
+use crate::api::imdb::ItemType;
+use crate::api::media_server::{LibraryStats, MediaServer};
+use crate::db::DBConnection;
+use crate::db::episodes::EpisodeDatabase;
+use crate::db::imdb::IMDBDatabase;
+use crate::db::moviedb::MovieDBDatabase;
+use crate::db::stats::{DownloadStats, StatsDatabase};
+use crate::server::download::stored_media_lookup;
+use crate::server::{escape_html, format_size};
+
+#[derive(Deserialize)]
+pub struct LibraryStatsQuery {
+    format: Option<String>,
+}
+
+#[get("/stats")]
+pub async fn download_stats(
+    params: Query<LibraryStatsQuery>,
+    db: Data<DBConnection>,
+) -> Result<HttpResponse<String>, Error> {
+    let stats = match StatsDatabase::new(&db).fetch().await {
+        Ok(t) => t,
+        Err(e) => return Err(ErrorInternalServerError(e)),
+    };
+
+    match params.format.as_deref() {
+        Some("html") => Ok(HttpResponse::Ok().message_body(create_download_stats_html(&stats)).unwrap()),
+        _ => {
+            let json = match serde_json::to_string(&stats) {
+                Ok(t) => t,
+                Err(e) => return Err(ErrorInternalServerError(e)),
+            };
+            Ok(HttpResponse::Ok()
+                .content_type("application/json")
+                .message_body(json)
+                .unwrap())
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct LibraryStatsResponse {
+    #[serde(flatten)]
+    library: LibraryStats,
+    missing_episodes: i64,
+}
+
+#[get("/stats/library")]
+pub async fn library_stats(
+    params: Query<LibraryStatsQuery>,
+    media_server: Data<dyn MediaServer>,
+    db: Data<DBConnection>,
+    app_config: Data<AppConfig>,
+) -> Result<HttpResponse<String>, Error> {
+    let library = match media_server.library_stats().await {
+        Ok(t) => t,
+        Err(e) => return Err(ErrorInternalServerError(e)),
+    };
+
+    let missing_episodes = match count_missing_episodes(media_server.get_ref(), db.deref(), &app_config).await {
+        Ok(t) => t,
+        Err(e) => return Err(ErrorInternalServerError(e)),
+    };
+
+    let stats = LibraryStatsResponse {
+        library,
+        missing_episodes,
+    };
+
+    match params.format.as_deref() {
+        Some("html") => Ok(HttpResponse::Ok().message_body(create_stats_html(&stats)).unwrap()),
+        _ => {
+            let json = match serde_json::to_string(&stats) {
+                Ok(t) => t,
+                Err(e) => return Err(ErrorInternalServerError(e)),
+            };
+            Ok(HttpResponse::Ok()
+                .content_type("application/json")
+                .message_body(json)
+                .unwrap())
+        }
+    }
+}
+
+// Aired episodes of watchlist shows that aren't in the library, episodes are stored when a show is searched for downloads
+async fn count_missing_episodes(
+    media_server: &dyn MediaServer,
+    db: &DBConnection,
+    app_config: &AppConfig,
+) -> anyhow::Result<i64> {
+    let shows = match app_config.tmdb_api_key.is_empty() {
+        true => IMDBDatabase::new(db)
+            .fetch_watchlist()
+            .await?
+            .into_iter()
+            .filter(|i| matches!(i._type, ItemType::TvShow))
+            .map(|i| (i.id.to_owned(), format!("{} ({})", i.title, i.year)))
+            .collect::<Vec<(String, String)>>(),
+        false => MovieDBDatabase::new(db)
+            .fetch_watchlist()
+            .await?
+            .into_iter()
+            .filter(|i| matches!(i._type, ItemType::TvShow))
+            .map(|i| (i.id.to_string(), format!("{} ({})", i.title, i.release_date.year())))
+            .collect::<Vec<(String, String)>>(),
+    };
+
+    let episode_db = EpisodeDatabase::new(db);
+    let mut missing = 0;
+    for (id, title) in shows {
+        let aired = episode_db.fetch_aired(&id).await?;
+        if aired.is_empty() {
+            continue;
+        }
+
+        let lookup = stored_media_lookup(app_config, db, &id, &title).await?;
+        let existing = match media_server.list_episodes(&lookup).await {
+            Ok(t) => t,
+            Err(e) => {
+                warn!("Failed to list episodes for {}: {}", title, e);
+                continue;
+            }
+        };
+
+        missing += aired
+            .iter()
+            .filter(|(season, episode)| existing.iter().any(|e| e.season == *season && e.episode == *episode).not())
+            .count() as i64;
+    }
+
+    Ok(missing)
+}
+
+fn create_stats_html(stats: &LibraryStatsResponse) -> String {
+    let library = &stats.library;
+
+    let qualities = library
+        .movie_qualities
+        .iter()
+        .map(|(quality, count)| format!("<li class=\"list-group-item d-flex justify-content-between\">{}<span>{}</span></li>", escape_html(quality), count))
+        .collect::<Vec<String>>()
+        .join("");
+
+    let recent = library
+        .recently_added
+        .iter()
+        .map(|item| {
+            let year = match item.year {
+                Some(t) => format!(" ({})", t),
+                None => String::new(),
+            };
+            format!(
+                "<li class=\"list-group-item d-flex justify-content-between\">{}{}<small>{}</small></li>",
+                escape_html(&item.title),
+                year,
+                item.added_at.format("%Y-%m-%d")
+            )
+        })
+        .collect::<Vec<String>>()
+        .join("");
+
+    format!("<div style=\"display: flex; flex-direction: row; flex-wrap: wrap; justify-content: center;\">\
+    <div class=\"card\" style=\"width: 18rem; margin: 0.5rem;\">\
+        <div class=\"card-body\">\
+            <h5 class=\"card-title\">Library</h5>\
+            <p>Movies: <b>{}</b></p>\
+            <p>TV Shows: <b>{}</b> ({} episodes)</p>\
+            <p>Missing episodes: <b>{}</b></p>\
+        </div>\
+    </div>\
+    <div class=\"card\" style=\"width: 18rem; margin: 0.5rem;\">\
+        <div class=\"card-body\">\
+            <h5 class=\"card-title\">Movie quality</h5>\
+            <ul class=\"list-group\">{}</ul>\
+        </div>\
+    </div>\
+    <div class=\"card\" style=\"width: 24rem; margin: 0.5rem;\">\
+        <div class=\"card-body\">\
+            <h5 class=\"card-title\">Recently added</h5>\
+            <ul class=\"list-group\">{}</ul>\
+        </div>\
+    </div>\
+</div>", library.movies, library.shows, library.episodes, stats.missing_episodes, qualities, recent)
+}
+
+fn create_download_stats_html(stats: &DownloadStats) -> String {
+    let indexers = stats
+        .indexers
+        .iter()
+        .map(|i| {
+            format!(
+                "<li class=\"list-group-item d-flex justify-content-between\">{}<span>{} ({} grabs)</span></li>",
+                escape_html(&i.source),
+                format_size(i.size),
+                i.grabs
+            )
+        })
+        .collect::<Vec<String>>()
+        .join("");
+
+    let average_completion = match stats.average_completion_seconds {
+        Some(t) if t >= 3600 => format!("{}h {}m", t / 3600, (t % 3600) / 60),
+        Some(t) => format!("{}m", t / 60),
+        None => "-".to_string(),
+    };
+    let hit_rate = match stats.watchlist_hit_rate {
+        Some(t) => format!("{:.0}% of {} checks", t * 100.0, stats.watchlist_checks),
+        None => "-".to_string(),
+    };
+
+    format!("<div style=\"display: flex; flex-direction: row; flex-wrap: wrap; justify-content: center;\">\
+    <div class=\"card\" style=\"width: 18rem; margin: 0.5rem;\">\
+        <div class=\"card-body\">\
+            <h5 class=\"card-title\">This week</h5>\
+            <p>Grabbed: <b>{}</b></p>\
+            <p>Completed: <b>{}</b></p>\
+            <p>Failed: <b>{}</b></p>\
+        </div>\
+    </div>\
+    <div class=\"card\" style=\"width: 18rem; margin: 0.5rem;\">\
+        <div class=\"card-body\">\
+            <h5 class=\"card-title\">Downloads</h5>\
+            <p>Average time to complete: <b>{}</b></p>\
+            <p>Watchlist hit rate (30 days): <b>{}</b></p>\
+        </div>\
+    </div>\
+    <div class=\"card\" style=\"width: 24rem; margin: 0.5rem;\">\
+        <div class=\"card-body\">\
+            <h5 class=\"card-title\">Grabbed per indexer</h5>\
+            <ul class=\"list-group\">{}</ul>\
+        </div>\
+    </div>\
+</div>", stats.grabbed_this_week, stats.completed_this_week, stats.failed_this_week, average_completion, hit_rate, indexers)
+}
\ No newline at end of file